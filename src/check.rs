@@ -0,0 +1,439 @@
+//! Integrity check of backup destinations.
+//!
+//! A plain tree destination is compared against the manifest it stores, or
+//! against the source directory: files can be missing, extra (present only
+//! at the destination), of the wrong size or, when their data is read back,
+//! of the wrong contents. Repositories are checked through their snapshots
+//! (see `repository::Repository::check`).
+//!
+//! Reading data back is the expensive part of a check, so it can be limited
+//! to a random subset of the files (`--read-data-subset 10%`).
+
+use crate::compress::{AutoDecompress, compressed_name, is_compressed_file};
+use crate::copy::flush_logger;
+use crate::crypto::ENCRYPTED_EXTENSION;
+use crate::destination::TEMP_SUFFIX;
+use crate::manifest::{METADATA_PREFIX, Manifest, hash_file, manifest_key};
+use crate::output::{LogContext, log_output};
+use crate::repository::Repository;
+use crate::utils::{Messages, is_excluded};
+use chacha20poly1305::aead::OsRng;
+use chacha20poly1305::aead::rand_core::RngCore;
+use rayon::prelude::*;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Write};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// How much of the stored data a check reads back.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReadData {
+    /// Only look at names and sizes.
+    Off,
+    /// Read every file (or object).
+    All,
+    /// Read a random share of the files, between 0 and 1.
+    Subset(f64),
+}
+
+impl ReadData {
+    /// Parse a `--read-data-subset` value such as `10%`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rbackup::check::ReadData;
+    /// assert_eq!(ReadData::parse_subset("25%"), Ok(ReadData::Subset(0.25)));
+    /// assert!(ReadData::parse_subset("150%").is_err());
+    /// ```
+    pub fn parse_subset(spec: &str) -> Result<Self, String> {
+        spec.trim()
+            .strip_suffix('%')
+            .and_then(|p| p.trim().parse::<f64>().ok())
+            .filter(|p| *p > 0.0 && *p <= 100.0)
+            .map(|p| ReadData::Subset(p / 100.0))
+            .ok_or_else(|| {
+                format!(
+                    "invalid subset '{}' (expected a percentage such as 10%)",
+                    spec
+                )
+            })
+    }
+
+    /// Choose the keys whose data is read back.
+    ///
+    /// Subsets are drawn at random on every run, so that repeated checks
+    /// eventually cover the whole backup.
+    pub fn select<'a>(&self, keys: impl Iterator<Item = &'a str>) -> HashSet<&'a str> {
+        match *self {
+            ReadData::Off => HashSet::new(),
+            ReadData::All => keys.collect(),
+            ReadData::Subset(fraction) => {
+                let mut seed = [0u8; 32];
+                OsRng.fill_bytes(&mut seed);
+                let mut keyed: Vec<(blake3::Hash, &str)> = keys
+                    .map(|k| (blake3::keyed_hash(&seed, k.as_bytes()), k))
+                    .collect();
+                let wanted = (keyed.len() as f64 * fraction).ceil() as usize;
+                keyed.sort_by(|a, b| a.0.as_bytes().cmp(b.0.as_bytes()));
+                keyed.into_iter().take(wanted).map(|(_, k)| k).collect()
+            }
+        }
+    }
+}
+
+/// Outcome of `check_tree`. Paths are relative to the source, `/`-separated,
+/// except for extra files which are relative to the destination.
+#[derive(Debug, Default)]
+pub struct TreeReport {
+    /// Number of files expected at the destination.
+    pub files: usize,
+    /// Number of files whose data was read back.
+    pub read: usize,
+    /// Expected files not found at the destination.
+    pub missing: Vec<String>,
+    /// Files found at the destination but not expected.
+    pub extra: Vec<String>,
+    /// Files whose stored size differs from the expected one.
+    pub size_mismatch: Vec<String>,
+    /// Files whose contents differ from the expected ones, or cannot be read.
+    pub hash_mismatch: Vec<String>,
+}
+
+impl TreeReport {
+    /// Return true if no problem was found.
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty()
+            && self.extra.is_empty()
+            && self.size_mismatch.is_empty()
+            && self.hash_mismatch.is_empty()
+    }
+}
+
+/// A file the destination should contain.
+struct Expected {
+    key: String,
+    size: u64,
+    /// Recorded hash, or the source file to hash when comparing to a source.
+    hash: Option<String>,
+    source: Option<PathBuf>,
+}
+
+/// Result of checking a single expected file.
+enum FileStatus {
+    Ok,
+    Missing,
+    SizeMismatch,
+    HashMismatch,
+}
+
+/// Check the tree destination `dest_dir`.
+///
+/// The expected files come from `source_dir` when given, leaving out those
+/// excluded by `options.exclude_matcher` as a copy does, otherwise from the
+/// manifest stored in the destination. Encrypted destinations are read with
+/// `options.encryption`. Returns an error when there is nothing to compare
+/// against.
+pub fn check_tree(
+    dest_dir: &Path,
+    source_dir: Option<&Path>,
+    read: ReadData,
+    options: &LogContext,
+) -> io::Result<TreeReport> {
+    if !dest_dir.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} is not a directory", dest_dir.display()),
+        ));
+    }
+
+    let expected: Vec<Expected> = match source_dir {
+        Some(src) => WalkDir::new(src)
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|e| e.file_type().is_file())
+            .filter_map(|e| {
+                let rel = e.path().strip_prefix(src).ok()?;
+                if is_excluded(options, e.path(), rel) {
+                    return None;
+                }
+                Some(Expected {
+                    key: manifest_key(rel),
+                    size: e.metadata().ok()?.len(),
+                    hash: None,
+                    source: Some(e.path().to_owned()),
+                })
+            })
+            .collect(),
+        None => {
+            let manifest =
+                Manifest::load(dest_dir, options.encryption.as_ref())?.ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::NotFound,
+                        "no manifest in the destination; run copy with --manifest or pass --source",
+                    )
+                })?;
+            manifest
+                .entries
+                .into_iter()
+                .map(|(key, entry)| Expected {
+                    key,
+                    size: entry.size,
+                    hash: Some(entry.hash),
+                    source: None,
+                })
+                .collect()
+        }
+    };
+
+    let to_read = read.select(expected.iter().map(|e| e.key.as_str()));
+
+    let results: Vec<(&Expected, Option<PathBuf>, FileStatus)> = expected
+        .par_iter()
+        .map(|e| match locate(dest_dir, &e.key, options) {
+            None => (e, None, FileStatus::Missing),
+            Some((path, transformed)) => {
+                let read = to_read.contains(e.key.as_str());
+                let status = verify(&path, transformed, e, read, options);
+                (e, Some(path), status)
+            }
+        })
+        .collect();
+
+    let mut report = TreeReport {
+        files: expected.len(),
+        read: to_read.len(),
+        ..Default::default()
+    };
+    let mut found: HashSet<PathBuf> = HashSet::new();
+    for (e, stored, status) in results {
+        if let Some(path) = stored {
+            found.insert(path);
+        }
+        match status {
+            FileStatus::Ok => {}
+            FileStatus::Missing => report.missing.push(e.key.clone()),
+            FileStatus::SizeMismatch => report.size_mismatch.push(e.key.clone()),
+            FileStatus::HashMismatch => report.hash_mismatch.push(e.key.clone()),
+        }
+    }
+
+    report.extra = WalkDir::new(dest_dir)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|e| e.file_type().is_file())
+        .filter(|e| {
+            let name = e.file_name().to_string_lossy();
+            !name.starts_with(METADATA_PREFIX) && !name.ends_with(TEMP_SUFFIX)
+        })
+        .filter(|e| !found.contains(e.path()))
+        .filter_map(|e| e.path().strip_prefix(dest_dir).ok().map(manifest_key))
+        .collect();
+    report.extra.sort();
+
+    Ok(report)
+}
+
+/// Find the stored copy of the file recorded under `key`, accounting for
+/// encryption and compression.
+///
+/// Returns the path of the stored file and whether it was transformed
+/// (compressed or encrypted) on the way to the destination.
+pub(crate) fn locate(dest_dir: &Path, key: &str, options: &LogContext) -> Option<(PathBuf, bool)> {
+    let rel = Path::new(key);
+    let candidates = match &options.encryption {
+        Some(enc) if enc.encrypt_names => vec![enc.dest_rel_path(rel)],
+        Some(enc) => vec![
+            enc.dest_rel_path(rel),
+            enc.dest_rel_path(&compressed_name(rel)),
+        ],
+        None => vec![rel.to_owned(), compressed_name(rel)],
+    };
+    candidates
+        .into_iter()
+        .map(|p| dest_dir.join(p))
+        .enumerate()
+        .find(|(i, p)| {
+            // A `.zst` file next to a missing plain one only counts when
+            // rBackup compressed it; it may also be a source file of its own.
+            p.is_file()
+                && (*i == 0
+                    || options.encryption.is_some()
+                    || is_compressed_file(p).unwrap_or(false))
+        })
+        .map(|(i, p)| (p, i > 0 || options.encryption.is_some()))
+}
+
+/// Compare a stored file with what it should contain.
+///
+/// Files stored as they are can be compared by size without reading them;
+/// compressed or encrypted files only when their data is read.
+fn verify(
+    stored: &Path,
+    transformed: bool,
+    expected: &Expected,
+    read: bool,
+    options: &LogContext,
+) -> FileStatus {
+    if !transformed && fs::metadata(stored).map(|m| m.len()).ok() != Some(expected.size) {
+        return FileStatus::SizeMismatch;
+    }
+    if !read {
+        return FileStatus::Ok;
+    }
+
+    let (size, hash) = match decoded_contents(stored, options) {
+        Ok(d) => d,
+        Err(_) => return FileStatus::HashMismatch,
+    };
+    if size != expected.size {
+        return FileStatus::SizeMismatch;
+    }
+    let wanted = match (&expected.hash, &expected.source) {
+        (Some(h), _) => Some(h.clone()),
+        (None, Some(src)) => hash_file(src).ok(),
+        (None, None) => None,
+    };
+    if wanted.as_deref() == Some(hash.as_str()) {
+        FileStatus::Ok
+    } else {
+        FileStatus::HashMismatch
+    }
+}
+
+/// Size and hash of the original contents of a stored file.
+fn decoded_contents(stored: &Path, options: &LogContext) -> io::Result<(u64, String)> {
+    let mut out = HashWriter::default();
+    let mut reader = BufReader::new(File::open(stored)?);
+    let encrypted = stored
+        .extension()
+        .is_some_and(|ext| ext == ENCRYPTED_EXTENSION);
+    match &options.encryption {
+        Some(enc) if encrypted => {
+            enc.decrypt_stream(reader, |_| Ok(AutoDecompress::new(&mut out)))?;
+        }
+        _ if is_compressed_file(stored)? => {
+            io::copy(
+                &mut zstd::stream::read::Decoder::with_buffer(reader)?,
+                &mut out,
+            )?;
+        }
+        _ => {
+            io::copy(&mut reader, &mut out)?;
+        }
+    }
+    Ok((out.size, out.hasher.finalize().to_hex().to_string()))
+}
+
+/// Writer that only hashes and counts what is written to it.
+#[derive(Default)]
+struct HashWriter {
+    hasher: blake3::Hasher,
+    size: u64,
+}
+
+impl Write for HashWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.hasher.update(buf);
+        self.size += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Check the destination `dest_dir` and print a report.
+///
+/// Repositories are checked through their snapshots, other destinations
+/// with `check_tree`. Mirrors `copy::execute_copy`: every problem is printed
+/// and logged, followed by a summary; the process exits with a non-zero
+/// status when problems are found or the destination cannot be read.
+pub fn execute_check(
+    msg: &Messages,
+    ctx: &LogContext,
+    dest_dir: &Path,
+    source_dir: Option<&Path>,
+    read: ReadData,
+) {
+    log_output(&msg.check_init, ctx);
+    log_output(
+        &format!("{} {}\n", msg.checking_repository, dest_dir.display()),
+        ctx,
+    );
+
+    let result = if Repository::is_repository(dest_dir) {
+        Repository::open(dest_dir)
+            .and_then(|repo| repo.check(read))
+            .map(|report| {
+                let mut lines = Vec::new();
+                for id in &report.unreadable_snapshots {
+                    lines.push(format!("{}: {}", msg.unreadable_snapshot, id));
+                }
+                for id in &report.missing_objects {
+                    lines.push(format!("{}: {}", msg.missing_object, id));
+                }
+                for id in &report.corrupt_objects {
+                    lines.push(format!("{}: {}", msg.corrupt_object, id));
+                }
+                let summary = format!(
+                    "{}, {}",
+                    msg.snapshots_checked
+                        .replace("{}", &report.snapshots.to_string()),
+                    msg.objects_checked
+                        .replace("{}", &report.objects.to_string())
+                );
+                (lines, summary, report.is_ok())
+            })
+    } else {
+        check_tree(dest_dir, source_dir, read, ctx).map(|report| {
+            let mut lines = Vec::new();
+            for (label, paths) in [
+                (&msg.missing_file, &report.missing),
+                (&msg.extra_file, &report.extra),
+                (&msg.size_mismatch, &report.size_mismatch),
+                (&msg.hash_mismatch, &report.hash_mismatch),
+            ] {
+                for path in paths {
+                    lines.push(format!("{}: {}", label, path));
+                }
+            }
+            let summary = format!(
+                "{}, {}",
+                msg.files_checked.replace("{}", &report.files.to_string()),
+                msg.files_read.replace("{}", &report.read.to_string())
+            );
+            (lines, summary, report.is_ok())
+        })
+    };
+
+    match result {
+        Ok((problems, summary, ok)) => {
+            for line in &problems {
+                log_output(line, ctx);
+            }
+            let outcome = if ok {
+                msg.check_ok.clone()
+            } else {
+                msg.check_failed.replace("{}", &problems.len().to_string())
+            };
+            log_output(
+                &format!("\n{} ({}). {}", msg.check_ended, summary, outcome),
+                ctx,
+            );
+            flush_logger(ctx);
+            if !ok {
+                std::process::exit(1);
+            }
+        }
+        Err(e) => {
+            let mut ctx = ctx.clone();
+            ctx.quiet = false;
+            log_output(&format!("{}: {}", msg.generic_error, e), &ctx);
+            flush_logger(&ctx);
+            std::process::exit(1);
+        }
+    }
+}
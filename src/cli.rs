@@ -3,6 +3,8 @@
 //! This module defines the clap-powered `Cli` parser and the `Commands` enum
 //! describing the supported subcommands and their options.
 
+use crate::check::ReadData;
+use crate::compress::Compression;
 use clap::{ArgAction, Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
 
@@ -17,6 +19,39 @@ pub enum ShowSkippedArg {
     All,
 }
 
+/// Control how progress is displayed during the copy.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ProgressArg {
+    /// Fancy on a terminal, plain otherwise (cron, CI, pipes).
+    Auto,
+    /// Full-screen view with a progress bar.
+    Fancy,
+    /// One line per event and periodic percentages, no cursor movement.
+    Plain,
+    /// Multi-pane dashboard with per-worker activity and keyboard controls.
+    Dashboard,
+    /// No progress output.
+    None,
+}
+
+/// Naming of the destination subdirectories of multiple sources.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum SourceNamesArg {
+    /// Last component of the source path.
+    Basename,
+    /// Whole absolute source path.
+    FullPath,
+}
+
+/// System scheduler used by `schedule`.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ScheduleBackendArg {
+    /// User systemd service and timer.
+    Systemd,
+    /// Entry in the user crontab.
+    Cron,
+}
+
 /// Parsed command-line arguments for the `rbackup` binary.
 ///
 /// Use `Cli::parse()` (provided by clap) to obtain a populated instance.
@@ -34,6 +69,11 @@ pub enum ShowSkippedArg {
 /// match cli.command {
 ///     Some(rbackup::cli::Commands::Copy { .. }) => println!("Copy command chosen"),
 ///     Some(rbackup::cli::Commands::Config { .. }) => println!("Config command chosen"),
+///     Some(rbackup::cli::Commands::Restore { .. }) => println!("Restore command chosen"),
+///     Some(rbackup::cli::Commands::Check { .. }) => println!("Check command chosen"),
+///     Some(rbackup::cli::Commands::Diff { .. }) => println!("Diff command chosen"),
+///     Some(rbackup::cli::Commands::Schedule { .. }) => println!("Schedule command chosen"),
+///     Some(rbackup::cli::Commands::Daemon { .. }) => println!("Daemon command chosen"),
 ///     None => println!("No subcommand provided"),
 /// }
 /// ```
@@ -59,16 +99,29 @@ pub struct Cli {
 pub enum Commands {
     /// Perform an incremental backup
     Copy {
-        /// Source directory to backup
+        /// Source directories to backup
         ///
-        /// This is interpreted as a filesystem path. The program will traverse
-        /// the directory recursively and copy new or modified files to the
-        /// destination.
-        source: PathBuf,
+        /// These are interpreted as filesystem paths. The program will
+        /// traverse each directory recursively and copy new or modified files
+        /// to the destination; with several sources, each one is copied into
+        /// its own subdirectory (see `--source-names`).
+        #[arg(required = true, num_args = 1.., value_name = "SOURCE")]
+        sources: Vec<PathBuf>,
 
         /// Destination directory
         destination: PathBuf,
 
+        /// Additional destination directories
+        ///
+        /// The sources are scanned once and copied to `destination` and to
+        /// each of these, with results reported per destination.
+        #[arg(
+            long = "also-to",
+            value_name = "DESTINATION",
+            help = "Also copy to this destination (repeatable); the sources are scanned once and each destination reports its own results"
+        )]
+        also_to: Vec<PathBuf>,
+
         /// Copy only changed/new items (delta mode). When enabled, skipped items are hidden by default.
         #[arg(short = 'd', long = "delta", action = ArgAction::SetTrue)]
         delta: bool,
@@ -117,6 +170,298 @@ pub enum Commands {
             help = "(Advanced) Display skipped items: never, summary, or all (overrides --delta default)"
         )]
         show_skipped: Option<ShowSkippedArg>,
+
+        /// How progress is displayed
+        #[arg(
+            long = "progress",
+            value_enum,
+            default_value = "auto",
+            help = "Progress display: auto (detect terminal), fancy, plain (for logs and CI), dashboard or none"
+        )]
+        progress: ProgressArg,
+
+        /// Write a manifest of the run (path, size, mtime, hash) to the destination
+        #[arg(
+            long = "manifest",
+            action = ArgAction::SetTrue,
+            help = "Write a manifest (path, size, mtime, hash) of the backed-up files to the destination"
+        )]
+        manifest: bool,
+
+        /// Keep a local index of the backed-up files to skip unchanged ones
+        #[arg(
+            long = "state",
+            action = ArgAction::SetTrue,
+            help = "Record the backed-up files in ~/.rbackup/state and skip comparing unchanged ones with the destination"
+        )]
+        state: bool,
+
+        /// Compare every file with the destination and rebuild the state index
+        #[arg(
+            long = "rescan",
+            action = ArgAction::SetTrue,
+            requires = "state",
+            help = "Compare every file with the destination again and rebuild the state index (requires --state)"
+        )]
+        rescan: bool,
+
+        /// Encrypt file contents written to the destination
+        #[arg(
+            long = "encrypt",
+            action = ArgAction::SetTrue,
+            help = "Encrypt backed-up files (ChaCha20-Poly1305); the passphrase is read from $RBACKUP_PASSPHRASE or prompted"
+        )]
+        encrypt: bool,
+
+        /// Also hide file and directory names in the destination
+        #[arg(
+            long = "encrypt-names",
+            action = ArgAction::SetTrue,
+            requires = "encrypt",
+            help = "Replace destination file names with keyed hashes (requires --encrypt)"
+        )]
+        encrypt_names: bool,
+
+        /// Use the contents of a key file instead of a passphrase
+        #[arg(
+            long = "key-file",
+            value_name = "FILE",
+            requires = "encrypt",
+            help = "Derive the encryption key from a key file instead of a passphrase"
+        )]
+        key_file: Option<PathBuf>,
+
+        /// Store files compressed (zstd) with a `.zst` suffix
+        #[arg(
+            long = "compress",
+            value_name = "ALGO[:LEVEL]",
+            value_parser = Compression::parse,
+            help = "Compress each stored file: zstd[:level] (default level 3); already compressed formats are skipped"
+        )]
+        compress: Option<Compression>,
+
+        /// Additional extensions that must not be compressed (can be used multiple times)
+        #[arg(
+            long = "compress-skip-ext",
+            value_name = "EXT",
+            action = ArgAction::Append,
+            requires = "compress",
+            help = "Store files with this extension uncompressed, in addition to the built-in list (repeatable)"
+        )]
+        compress_skip_ext: Vec<String>,
+
+        /// Store the backup in a deduplicating repository instead of a plain tree
+        #[arg(
+            long = "repository",
+            action = ArgAction::SetTrue,
+            conflicts_with = "encrypt",
+            help = "Store file contents once by hash in a repository at the destination and record a snapshot of the run"
+        )]
+        repository: bool,
+
+        /// Split files into content-defined chunks when creating the repository
+        #[arg(
+            long = "chunking",
+            action = ArgAction::SetTrue,
+            requires = "repository",
+            help = "Split files into content-defined chunks (only applies when the repository is created)"
+        )]
+        chunking: bool,
+
+        /// Keep running and back up changes as they happen
+        #[arg(
+            long = "watch",
+            action = ArgAction::SetTrue,
+            conflicts_with = "dry_run",
+            help = "After the initial copy, keep watching the source and back up changed files as they change"
+        )]
+        watch: bool,
+
+        /// Seconds without changes before a batch is copied
+        #[arg(
+            long = "watch-debounce",
+            value_name = "SECS",
+            default_value_t = crate::watch::DEFAULT_DEBOUNCE_SECS,
+            requires = "watch",
+            help = "Seconds without filesystem events before the changed files are copied (requires --watch)"
+        )]
+        watch_debounce: u64,
+
+        /// Seconds between two full rescans of the source
+        #[arg(
+            long = "watch-rescan",
+            value_name = "SECS",
+            default_value_t = crate::watch::DEFAULT_RESCAN_SECS,
+            value_parser = clap::value_parser!(u64).range(1..),
+            requires = "watch",
+            help = "Seconds between full rescans catching changes missed by the watcher (requires --watch)"
+        )]
+        watch_rescan: u64,
+
+        /// Wait for another run holding the lock instead of failing
+        #[arg(
+            long = "wait",
+            action = ArgAction::SetTrue,
+            overrides_with = "no_wait",
+            help = "Wait until another run on the same destination (or profile) has finished"
+        )]
+        wait: bool,
+
+        /// Fail at once when another run holds the lock (default)
+        #[arg(
+            long = "no-wait",
+            action = ArgAction::SetTrue,
+            overrides_with = "wait",
+            help = "Fail immediately when another run holds the lock (default)"
+        )]
+        no_wait: bool,
+
+        /// How sources are named below the destination
+        #[arg(
+            long = "source-names",
+            value_enum,
+            help = "Subdirectory of the destination each source is copied into: basename (default with several sources) or full-path; a single source is copied into the destination itself unless this is set"
+        )]
+        source_names: Option<SourceNamesArg>,
+
+        /// Run on behalf of a configuration profile
+        #[arg(
+            long = "profile",
+            value_name = "NAME",
+            help = "Run as the configuration profile NAME: hold its lock and run its hooks (set by schedule and daemon)"
+        )]
+        profile: Option<String>,
+    },
+
+    /// Restore files from a backup destination
+    Restore {
+        /// Backup directory to restore from
+        backup: PathBuf,
+
+        /// Directory where files are restored
+        target: PathBuf,
+
+        /// Key file used to encrypt the backup (otherwise the passphrase is used)
+        #[arg(long = "key-file", value_name = "FILE")]
+        key_file: Option<PathBuf>,
+
+        /// Snapshot to restore from a repository (default: the latest)
+        #[arg(long = "snapshot", value_name = "ID")]
+        snapshot: Option<String>,
+
+        /// Suppress all output to stdout
+        #[arg(short, long, action = ArgAction::SetTrue)]
+        quiet: bool,
+
+        /// Print timestamps in logs
+        #[arg(short, long, action = ArgAction::SetTrue)]
+        timestamp: bool,
+
+        /// File path to write logs
+        #[arg(long, value_name = "FILE")]
+        log: Option<PathBuf>,
+
+        /// Check the backup can be restored without writing any file
+        #[arg(long = "dry-run", action = ArgAction::SetTrue, help = "Verify and list files without restoring them")]
+        dry_run: bool,
+    },
+
+    /// Verify the integrity of a backup destination or repository
+    Check {
+        /// Destination directory (or repository) to check
+        destination: PathBuf,
+
+        /// Compare the destination against this source directory instead of its manifest
+        #[arg(long = "source", value_name = "DIR")]
+        source: Option<PathBuf>,
+
+        /// Read back all stored data and verify it
+        #[arg(long = "read-data", action = ArgAction::SetTrue, help = "Read back all stored data and verify it against its hash")]
+        read_data: bool,
+
+        /// Read back only a random share of the stored data
+        #[arg(
+            long = "read-data-subset",
+            value_name = "PERCENT",
+            value_parser = ReadData::parse_subset,
+            conflicts_with = "read_data",
+            help = "Read back and verify a random share of the stored data, e.g. 10%"
+        )]
+        read_data_subset: Option<ReadData>,
+
+        /// Exclude files matching the given glob pattern from the `--source` comparison (can be used multiple times)
+        #[arg(short = 'x', long = "exclude", value_name = "PATTERN", action = ArgAction::Append)]
+        exclude: Vec<String>,
+
+        /// Match exclude patterns against absolute paths instead of relative
+        #[arg(long = "absolute-exclude", action = ArgAction::SetTrue, help = "Match exclude patterns against absolute source paths")]
+        absolute_exclude: bool,
+
+        /// Case-insensitive matching for exclude patterns
+        #[arg(long = "ignore-case", action = ArgAction::SetTrue, help = "Perform case-insensitive matching for exclude patterns")]
+        ignore_case: bool,
+
+        /// Key file used to encrypt the backup (otherwise the passphrase is used)
+        #[arg(long = "key-file", value_name = "FILE")]
+        key_file: Option<PathBuf>,
+
+        /// Suppress all output to stdout
+        #[arg(short, long, action = ArgAction::SetTrue)]
+        quiet: bool,
+
+        /// Print timestamps in logs
+        #[arg(short, long, action = ArgAction::SetTrue)]
+        timestamp: bool,
+
+        /// File path to write logs
+        #[arg(long, value_name = "FILE")]
+        log: Option<PathBuf>,
+    },
+
+    /// Show what the next copy would change, without copying anything
+    Diff {
+        /// Source directory
+        source: PathBuf,
+
+        /// Destination directory (or repository)
+        destination: PathBuf,
+
+        /// Print the differences as JSON
+        #[arg(long = "json", action = ArgAction::SetTrue, help = "Print the differences as JSON")]
+        json: bool,
+
+        /// Exclude files matching the given glob pattern (can be used multiple times)
+        #[arg(short = 'x', long = "exclude", value_name = "PATTERN", action = ArgAction::Append)]
+        exclude: Vec<String>,
+
+        /// Match exclude patterns against absolute paths instead of relative
+        #[arg(long = "absolute-exclude", action = ArgAction::SetTrue, help = "Match exclude patterns against absolute source paths")]
+        absolute_exclude: bool,
+
+        /// Case-insensitive matching for exclude patterns
+        #[arg(long = "ignore-case", action = ArgAction::SetTrue, help = "Perform case-insensitive matching for exclude patterns")]
+        ignore_case: bool,
+
+        /// Key file used to encrypt the backup (otherwise the passphrase is used)
+        #[arg(long = "key-file", value_name = "FILE")]
+        key_file: Option<PathBuf>,
+    },
+
+    /// Install, list or remove scheduled backups (systemd timers or cron)
+    Schedule {
+        #[command(subcommand)]
+        action: ScheduleAction,
+    },
+
+    /// Run the scheduled profiles of the configuration file (runs until stopped)
+    Daemon {
+        /// Log file for run summaries (default: daemon.log next to the configuration file)
+        #[arg(long, value_name = "FILE")]
+        log: Option<PathBuf>,
+
+        /// Size in bytes above which the log file is rotated
+        #[arg(long = "log-max-size", value_name = "BYTES", default_value_t = crate::daemon::DEFAULT_LOG_MAX_BYTES)]
+        log_max_size: u64,
     },
 
     /// Manage the configuration file (view or edit)
@@ -149,3 +494,54 @@ pub enum Commands {
         editor: Option<String>,
     },
 }
+
+/// Actions of the `schedule` subcommand.
+#[derive(Debug, Subcommand)]
+pub enum ScheduleAction {
+    /// Install (or replace) a scheduled backup
+    Add {
+        /// Schedule name (letters, digits, '-' and '_')
+        name: String,
+
+        /// Cron expression, e.g. "0 2 * * *" or "@daily"
+        #[arg(long = "cron", value_name = "EXPR")]
+        cron: String,
+
+        /// Run the profile with this name from the configuration file
+        #[arg(long = "profile", value_name = "NAME", conflicts_with = "copy_args")]
+        profile: Option<String>,
+
+        /// Scheduler to use (default: systemd when available, otherwise cron)
+        #[arg(long = "backend", value_enum)]
+        backend: Option<ScheduleBackendArg>,
+
+        /// Print the unit files or crontab line instead of installing them
+        #[arg(long = "print", action = ArgAction::SetTrue)]
+        print: bool,
+
+        /// Arguments of the scheduled `copy` (after `--`): SOURCE DESTINATION [OPTIONS]
+        #[arg(
+            last = true,
+            value_name = "COPY_ARGS",
+            required_unless_present = "profile"
+        )]
+        copy_args: Vec<String>,
+    },
+
+    /// List the installed scheduled backups
+    List {
+        /// Scheduler to query (default: systemd when available, otherwise cron)
+        #[arg(long = "backend", value_enum)]
+        backend: Option<ScheduleBackendArg>,
+    },
+
+    /// Remove a scheduled backup
+    Remove {
+        /// Schedule name
+        name: String,
+
+        /// Scheduler to use (default: systemd when available, otherwise cron)
+        #[arg(long = "backend", value_enum)]
+        backend: Option<ScheduleBackendArg>,
+    },
+}
@@ -1,17 +1,32 @@
 //! Command handlers invoked by the CLI dispatcher.
 //!
 //! This module contains the high-level functions that implement the behavior
-//! of the `config` and `copy` subcommands. They adapt CLI arguments and the
-//! loaded configuration into the lower-level utilities responsible for I/O,
-//! logging and copying.
+//! of the `config`, `copy`, `restore`, `check`, `diff`, `schedule` and
+//! `daemon` subcommands. They adapt CLI arguments and the loaded
+//! configuration into the lower-level utilities responsible for I/O, logging
+//! and copying.
 
-use crate::cli::Commands;
+use crate::check::{ReadData, execute_check};
+use crate::cli::{Cli, Commands, ScheduleAction, ScheduleBackendArg, SourceNamesArg};
 use crate::config::Config;
-use crate::copy::{execute_copy, start_copy_message};
-use crate::output::{LogContext, ShowSkipped, log_output};
+use crate::copy::SourceNaming;
+use crate::crypto::{Encryption, KeySource, PASSPHRASE_ENV, prompt_passphrase};
+use crate::daemon::{RotatingLog, run_daemon};
+use crate::diff::{diff_tree, format_json, format_plain};
+use crate::error::Error;
+use crate::job::{BackupJob, Compare, Output};
+use crate::notifications::{Notice, notify_all};
+use crate::output::{LogContext, ProgressMode, ShowSkipped, log_output};
+use crate::repository::Repository;
+use crate::restore::execute_restore;
+use crate::schedule::{self, Backend, CronExpr, UNIT_PREFIX, systemd_dir};
 use crate::utils::{Messages, build_exclude_matcher, create_logger};
-use rayon::ThreadPoolBuilder;
+use crate::watch::WatchOptions;
+use clap::Parser;
+use std::env;
 use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 /// Handle the `config` subcommand.
 ///
@@ -42,17 +57,10 @@ pub fn handle_conf(
     {
         println!();
         let ctx = LogContext {
-            logger: None,
-            quiet: false,
-            with_timestamp: false,
             timestamp_format: Some(config.timestamp_format.clone()),
-            row: None,
             on_log: false,
-            exclude_matcher: None,
-            exclude_match_absolute: false,
-            dry_run: false,
-            exclude_patterns: None,
             show_skipped: ShowSkipped::Summary,
+            ..Default::default()
         };
 
         if *print_config {
@@ -123,8 +131,9 @@ pub fn handle_conf(
 
 /// Handle the `copy` subcommand.
 ///
-/// This function prepares logging, exclude matchers and the `LogContext`,
-/// then starts the copy operation and prints the start/finish messages.
+/// This function describes the copy as a `job::BackupJob`, with the hooks,
+/// locks, encryption and terminal output of the command, runs it and sends
+/// the notifications of its result.
 ///
 /// # Parameters
 /// - `cmd`: the parsed CLI command (expected to be `Commands::Copy`).
@@ -139,15 +148,17 @@ pub fn handle_conf(
 ///
 /// # Returns
 /// - `Ok(())` on success.
-/// - `Err(...)` if exclude pattern parsing fails or other I/O errors occur.
+/// - `Err(...)` if the job is invalid, or the run or one of its destinations
+///   failed.
 pub fn handle_copy(
     cmd: &Commands,
     msg: &Messages,
     config: &Config,
 ) -> Result<(), Box<dyn std::error::Error>> {
     if let Commands::Copy {
-        source,
+        sources,
         destination,
+        also_to,
         quiet,
         timestamp,
         log,
@@ -158,117 +169,313 @@ pub fn handle_copy(
         jobs,
         delta,
         show_skipped,
+        progress,
+        manifest,
+        state,
+        rescan,
+        encrypt,
+        encrypt_names,
+        key_file,
+        compress,
+        compress_skip_ext,
+        repository,
+        chunking,
+        watch,
+        watch_debounce,
+        watch_rescan,
+        wait,
+        no_wait: _,
+        source_names,
+        profile,
     } = cmd
     {
-        // Determine effective number of worker threads:
-        // precedence: CLI `--jobs` if present, otherwise value from config (default 4).
-        let effective_jobs: usize = if let Some(n) = jobs { *n } else { config.jobs };
-
-        if effective_jobs == 0 {
-            let ctx = LogContext {
-                logger: None,
-                quiet: false,
-                with_timestamp: false,
-                timestamp_format: Some(config.timestamp_format.clone()),
-                row: None,
-                on_log: false,
-                exclude_matcher: None,
-                exclude_match_absolute: false,
-                dry_run: false,
-                exclude_patterns: None,
-                show_skipped: ShowSkipped::Summary,
-            };
-            log_output(
-                &format!("Invalid value for jobs: {} (must be > 0)", effective_jobs),
-                &ctx,
+        // create_logger now returns io::Result<Option<Logger>>
+        let logger = match create_logger(log.as_deref()) {
+            Ok(l) => l,
+            Err(e) => {
+                // Create a temporary ctx to report the error
+                let ctx = LogContext {
+                    with_timestamp: true,
+                    timestamp_format: Some(config.timestamp_format.clone()),
+                    on_log: false,
+                    show_skipped: ShowSkipped::Summary,
+                    ..Default::default()
+                };
+                log_output(&format!("Failed to create log file: {}", e), &ctx);
+                None
+            }
+        };
+        // The lines of the command itself; the job writes its own.
+        let ctx = LogContext {
+            logger: logger.clone(),
+            quiet: *quiet,
+            with_timestamp: *timestamp,
+            timestamp_format: Some(config.timestamp_format.clone()),
+            ..Default::default()
+        };
+        let err_ctx = LogContext {
+            with_timestamp: false,
+            on_log: false,
+            ..ctx.clone()
+        };
+
+        // The hooks of the profile take precedence over the global ones.
+        let hooks = match profile {
+            Some(name) => match config.profiles.get(name) {
+                Some(p) => config.hooks.merged(&p.hooks),
+                None => {
+                    log_output(
+                        format!("\u{274C} {} {}", msg.profile_not_found, name).as_str(),
+                        &ctx,
+                    );
+                    return Err(Box::new(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("profile '{}' not found", name),
+                    )));
+                }
+            },
+            None => config.hooks.clone(),
+        };
+
+        let output = Output {
+            quiet: *quiet,
+            timestamp: timestamp.then(|| config.timestamp_format.clone()),
+            logger,
+            progress: match progress {
+                crate::cli::ProgressArg::Auto => ProgressMode::Auto,
+                crate::cli::ProgressArg::Fancy => ProgressMode::Fancy,
+                crate::cli::ProgressArg::Plain => ProgressMode::Plain,
+                crate::cli::ProgressArg::Dashboard => ProgressMode::Dashboard,
+                crate::cli::ProgressArg::None => ProgressMode::None,
+            },
+            show_skipped: match (*delta, show_skipped.as_ref()) {
+                (_, Some(crate::cli::ShowSkippedArg::Never)) => ShowSkipped::Never,
+                (_, Some(crate::cli::ShowSkippedArg::Summary)) => ShowSkipped::Summary,
+                (_, Some(crate::cli::ShowSkippedArg::All)) => ShowSkipped::All,
+                (true, None) => ShowSkipped::Never,
+                (false, None) => ShowSkipped::All,
+            },
+        };
+        let mut job = BackupJob::new(&sources[0], destination)
+            .ignore_case(*ignore_case)
+            .absolute_exclude(*absolute_exclude)
+            .compare(match delta {
+                true => Compare::Delta,
+                false => Compare::Newer,
+            })
+            .dry_run(*dry_run)
+            // CLI `--jobs` if present, otherwise the value from the config.
+            .jobs(jobs.unwrap_or(config.jobs))
+            .manifest(*manifest)
+            .repository(*repository)
+            .chunking(*chunking)
+            .wait(*wait)
+            .hooks(hooks)
+            .output(output)
+            .messages(msg.clone())
+            .config(config.clone());
+        for pattern in exclude {
+            job = job.exclude(pattern);
+        }
+        for source in &sources[1..] {
+            job = job.source(source);
+        }
+        for dest in also_to {
+            job = job.also_to(dest);
+        }
+        if let Some(naming) = source_names {
+            job = job.source_naming(match naming {
+                SourceNamesArg::Basename => SourceNaming::Basename,
+                SourceNamesArg::FullPath => SourceNaming::FullPath,
+            });
+        }
+        if let Some(compress) = compress {
+            let mut compress = compress.clone();
+            compress.skip_extensions.extend(
+                compress_skip_ext
+                    .iter()
+                    .map(|e| e.trim_start_matches('.').to_lowercase()),
             );
-            return Err(Box::new(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "jobs must be > 0",
-            )));
+            job = job.compression(compress);
+        }
+        if *state {
+            job = job.state(crate::state::default_dir()).rescan(*rescan);
+        }
+        if let Some(name) = profile {
+            job = job.profile(name);
+        }
+        if let Some(path) = log {
+            job = job.report(path);
+        }
+        if *watch {
+            job = job.watch(WatchOptions {
+                debounce: Duration::from_secs(*watch_debounce),
+                rescan: Duration::from_secs(*watch_rescan),
+            });
         }
 
-        // Try to set a global Rayon thread pool; ignore errors if already set
-        match ThreadPoolBuilder::new()
-            .num_threads(effective_jobs)
-            .build_global()
-        {
-            Ok(_) => {
-                // successfully set global pool
+        let destinations: Vec<PathBuf> = std::iter::once(destination)
+            .chain(also_to)
+            .cloned()
+            .collect();
+        if *encrypt {
+            // Asked once for all the destinations.
+            let confirm = destinations.iter().any(|d| !Encryption::is_initialized(d));
+            match key_source(key_file.as_deref(), confirm, msg) {
+                Ok(key) => job = job.encryption(key, *encrypt_names),
+                Err(e) => {
+                    log_output(format!("\u{274C} {}", e).as_str(), &err_ctx);
+                    return Err(Box::new(e));
+                }
             }
+        }
+
+        let summary = match job.run() {
+            Ok(summary) => summary,
+            Err(e) => {
+                let line = match &e {
+                    Error::Pattern { .. } => {
+                        format!("\u{274C} {}: {}", msg.error_exclude_parsing, e)
+                    }
+                    // The message of a held lock names it.
+                    Error::Lock { source, .. } => format!("\u{274C} {}", source),
+                    e => format!("\u{274C} {}: {}", msg.generic_error, e),
+                };
+                log_output(&line, &err_ctx);
+                return Err(Box::new(e));
+            }
+        };
+
+        let result = summary.run_result(log.clone());
+        if !*dry_run {
+            let notice = Notice::new(&result, sources, &destinations, profile.as_deref());
+            notify_all(&config.notifications, &notice, msg, &ctx);
+        }
+        // Failures were already reported; `main` exits with an error status
+        // once the logger is dropped.
+        if let Some(error) = result.error {
+            return Err(Box::new(io::Error::other(error)));
+        }
+    }
+    Ok(())
+}
+
+/// Handle the `restore` subcommand.
+///
+/// Prepares logging and, when the backup is encrypted, derives the keys from
+/// the key file or passphrase before restoring every file into the target.
+/// Repositories are restored from the requested snapshot, or the latest one.
+///
+/// # Returns
+/// - `Ok(())` on success.
+/// - `Err(...)` if the keys cannot be derived (wrong passphrase, missing key file).
+pub fn handle_restore(
+    cmd: &Commands,
+    msg: &Messages,
+    config: &Config,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Commands::Restore {
+        backup,
+        target,
+        key_file,
+        snapshot,
+        quiet,
+        timestamp,
+        log,
+        dry_run,
+    } = cmd
+    {
+        let logger = match create_logger(log.as_deref()) {
+            Ok(l) => l,
             Err(e) => {
-                // If building global pool fails (already set), log a hint but continue
                 let ctx = LogContext {
-                    logger: None,
-                    quiet: false,
-                    with_timestamp: false,
+                    with_timestamp: true,
                     timestamp_format: Some(config.timestamp_format.clone()),
-                    row: None,
                     on_log: false,
-                    exclude_matcher: None,
-                    exclude_match_absolute: false,
-                    dry_run: false,
-                    exclude_patterns: None,
                     show_skipped: ShowSkipped::Summary,
+                    ..Default::default()
                 };
-                log_output(
-                    format!(
-                        "Warning: could not set Rayon thread pool to {}: {}",
-                        effective_jobs, e
-                    )
-                    .as_str(),
-                    &ctx,
-                );
+                log_output(&format!("Failed to create log file: {}", e), &ctx);
+                None
+            }
+        };
+
+        let mut ctx = LogContext {
+            logger,
+            quiet: *quiet,
+            with_timestamp: *timestamp,
+            timestamp_format: Some(config.timestamp_format.clone()),
+            dry_run: *dry_run,
+            ..Default::default()
+        };
+
+        if Encryption::is_initialized(backup) {
+            match open_encryption(backup, key_file.as_deref(), None, false, msg) {
+                Ok(enc) => ctx.encryption = Some(enc),
+                Err(e) => {
+                    ctx.quiet = false;
+                    ctx.on_log = false;
+                    log_output(format!("\u{274C} {}", e).as_str(), &ctx);
+                    return Err(Box::new(e));
+                }
             }
         }
 
-        // create_logger now returns io::Result<Option<Logger>>
+        execute_restore(msg, &ctx, backup, target, snapshot.as_deref());
+    }
+    Ok(())
+}
+
+/// Handle the `check` subcommand.
+///
+/// Prepares logging and, when the destination is encrypted, derives the keys
+/// needed to read its manifest and data, then verifies the destination and
+/// prints every problem found. The process exits with a non-zero status when
+/// the check fails.
+pub fn handle_check(
+    cmd: &Commands,
+    msg: &Messages,
+    config: &Config,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Commands::Check {
+        destination,
+        source,
+        read_data,
+        read_data_subset,
+        exclude,
+        absolute_exclude,
+        ignore_case,
+        key_file,
+        quiet,
+        timestamp,
+        log,
+    } = cmd
+    {
         let logger = match create_logger(log.as_deref()) {
             Ok(l) => l,
             Err(e) => {
-                // Create a temporary ctx to report the error
                 let ctx = LogContext {
-                    logger: None,
-                    quiet: false,
                     with_timestamp: true,
                     timestamp_format: Some(config.timestamp_format.clone()),
-                    row: None,
                     on_log: false,
-                    exclude_matcher: None,
-                    exclude_match_absolute: false,
-                    dry_run: false,
-                    exclude_patterns: None,
                     show_skipped: ShowSkipped::Summary,
+                    ..Default::default()
                 };
                 log_output(&format!("Failed to create log file: {}", e), &ctx);
                 None
             }
         };
 
-        // Build a mutable LogContext and pass it to copy functions to reduce arg count
         let mut ctx = LogContext {
-            logger: logger.clone(),
+            logger,
             quiet: *quiet,
             with_timestamp: *timestamp,
             timestamp_format: Some(config.timestamp_format.clone()),
-            row: None,
-            on_log: true,
-            exclude_matcher: None,
             exclude_match_absolute: *absolute_exclude,
-            dry_run: *dry_run,
-            exclude_patterns: None,
-            show_skipped: match (*delta, show_skipped.as_ref()) {
-                (true, Some(v)) | (false, Some(v)) => match v {
-                    crate::cli::ShowSkippedArg::Never => ShowSkipped::Never,
-                    crate::cli::ShowSkippedArg::Summary => ShowSkipped::Summary,
-                    crate::cli::ShowSkippedArg::All => ShowSkipped::All,
-                },
-                (true, None) => ShowSkipped::Never,
-                (false, None) => ShowSkipped::All,
-            },
+            ..Default::default()
         };
 
-        // Build exclude matcher here (avoid duplication)
+        // Files a copy excluded are not expected in the destination.
         if !exclude.is_empty() {
             match build_exclude_matcher(exclude, *ignore_case) {
                 Ok(matcher) => {
@@ -276,8 +483,7 @@ pub fn handle_copy(
                     ctx.exclude_patterns = Some(exclude.clone());
                 }
                 Err(e) => {
-                    // report and return error
-                    ctx.with_timestamp = false;
+                    ctx.quiet = false;
                     ctx.on_log = false;
                     log_output(
                         format!("\u{274C} {}: {}", msg.error_exclude_parsing, e).as_str(),
@@ -288,9 +494,306 @@ pub fn handle_copy(
             }
         }
 
-        start_copy_message(msg, &ctx, source, destination);
+        if Encryption::is_initialized(destination) {
+            match open_encryption(destination, key_file.as_deref(), None, false, msg) {
+                Ok(enc) => ctx.encryption = Some(enc),
+                Err(e) => {
+                    ctx.quiet = false;
+                    ctx.on_log = false;
+                    log_output(format!("\u{274C} {}", e).as_str(), &ctx);
+                    return Err(Box::new(e));
+                }
+            }
+        }
 
-        execute_copy(msg, &mut ctx, source, destination, *delta);
+        let read = match (read_data, read_data_subset) {
+            (true, _) => ReadData::All,
+            (false, Some(subset)) => *subset,
+            (false, None) => ReadData::Off,
+        };
+        execute_check(msg, &ctx, destination, source.as_deref(), read);
     }
     Ok(())
 }
+
+/// Handle the `diff` subcommand.
+///
+/// Runs the planning phase of a copy with the same excludes and destination
+/// settings, then prints the differences as plain text or JSON on stdout.
+/// The terminal is left untouched so the output can be piped.
+pub fn handle_diff(
+    cmd: &Commands,
+    msg: &Messages,
+    config: &Config,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Commands::Diff {
+        source,
+        destination,
+        json,
+        exclude,
+        absolute_exclude,
+        ignore_case,
+        key_file,
+    } = cmd
+    {
+        let mut ctx = LogContext {
+            timestamp_format: Some(config.timestamp_format.clone()),
+            on_log: false,
+            exclude_match_absolute: *absolute_exclude,
+            dry_run: true,
+            ..Default::default()
+        };
+
+        if !exclude.is_empty() {
+            match build_exclude_matcher(exclude, *ignore_case) {
+                Ok(matcher) => {
+                    ctx.exclude_matcher = Some(matcher);
+                    ctx.exclude_patterns = Some(exclude.clone());
+                }
+                Err(e) => {
+                    log_output(
+                        format!("\u{274C} {}: {}", msg.error_exclude_parsing, e).as_str(),
+                        &ctx,
+                    );
+                    return Err(Box::new(e));
+                }
+            }
+        }
+
+        if Repository::is_repository(destination) {
+            ctx.repository = Some(Repository::open(destination)?);
+        } else if Encryption::is_initialized(destination) {
+            match open_encryption(destination, key_file.as_deref(), None, false, msg) {
+                Ok(enc) => {
+                    ctx.manifest = true;
+                    ctx.encryption = Some(enc);
+                }
+                Err(e) => {
+                    log_output(format!("\u{274C} {}", e).as_str(), &ctx);
+                    return Err(Box::new(e));
+                }
+            }
+        }
+
+        let report = diff_tree(source, destination, &ctx)?;
+        if *json {
+            println!("{}", format_json(&report)?);
+        } else {
+            print!("{}", format_plain(&report, msg));
+        }
+    }
+    Ok(())
+}
+
+/// Handle the `schedule` subcommand.
+///
+/// `add` builds the `copy` command line from a configuration profile or from
+/// the arguments after `--` (validated with the same parser as `copy`), then
+/// installs it with the selected scheduler, or only prints the generated
+/// files with `--print`. `list` and `remove` manage installed schedules.
+pub fn handle_schedule(
+    cmd: &Commands,
+    msg: &Messages,
+    config: &Config,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Commands::Schedule { action } = cmd {
+        let ctx = LogContext {
+            timestamp_format: Some(config.timestamp_format.clone()),
+            on_log: false,
+            ..Default::default()
+        };
+        let backend = |arg: &Option<ScheduleBackendArg>| match arg {
+            Some(ScheduleBackendArg::Systemd) => Backend::Systemd,
+            Some(ScheduleBackendArg::Cron) => Backend::Cron,
+            None => Backend::detect(),
+        };
+
+        match action {
+            ScheduleAction::Add {
+                name,
+                cron,
+                profile,
+                backend: backend_arg,
+                print,
+                copy_args,
+            } => {
+                schedule::validate_name(name)?;
+                let expr = CronExpr::parse(cron)?;
+                let args = match profile {
+                    Some(profile) => match config.profiles.get(profile) {
+                        Some(p) => p.run_args(profile),
+                        None => {
+                            log_output(
+                                format!("\u{274C} {} {}", msg.profile_not_found, profile).as_str(),
+                                &ctx,
+                            );
+                            return Err(Box::new(io::Error::new(
+                                io::ErrorKind::NotFound,
+                                format!("profile '{}' not found", profile),
+                            )));
+                        }
+                    },
+                    None => scheduled_copy_args(copy_args)?,
+                };
+                let exe = env::current_exe()?;
+                let backend = backend(backend_arg);
+
+                if *print {
+                    match backend {
+                        Backend::Systemd => {
+                            let (service, timer) =
+                                schedule::systemd_units(name, &expr, &exe, &args)?;
+                            let dir = systemd_dir();
+                            println!(
+                                "# {}",
+                                dir.join(format!("{UNIT_PREFIX}{name}.service")).display()
+                            );
+                            println!("{}", service);
+                            println!(
+                                "# {}",
+                                dir.join(format!("{UNIT_PREFIX}{name}.timer")).display()
+                            );
+                            print!("{}", timer);
+                        }
+                        Backend::Cron => {
+                            println!("{}", schedule::crontab_line(name, &expr, &exe, &args));
+                        }
+                    }
+                } else {
+                    schedule::install(backend, name, &expr, &exe, &args)?;
+                    log_output(
+                        format!(
+                            "\u{2705} {} {} ({}, {})",
+                            msg.schedule_installed, name, backend, expr
+                        )
+                        .as_str(),
+                        &ctx,
+                    );
+                }
+            }
+            ScheduleAction::List {
+                backend: backend_arg,
+            } => {
+                let entries = schedule::list(backend(backend_arg))?;
+                if entries.is_empty() {
+                    log_output(&msg.schedule_none, &ctx);
+                }
+                for entry in entries {
+                    log_output(
+                        format!(
+                            "{}  [{}]  {}  {}",
+                            entry.name, entry.backend, entry.expression, entry.command
+                        )
+                        .as_str(),
+                        &ctx,
+                    );
+                }
+            }
+            ScheduleAction::Remove {
+                name,
+                backend: backend_arg,
+            } => {
+                if schedule::remove(backend(backend_arg), name)? {
+                    log_output(
+                        format!("\u{2705} {} {}", msg.schedule_removed, name).as_str(),
+                        &ctx,
+                    );
+                } else {
+                    log_output(
+                        format!("\u{26A0}\u{FE0F} {} {}", msg.schedule_not_found, name).as_str(),
+                        &ctx,
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Handle the `daemon` subcommand.
+///
+/// Runs the profiles with a `schedule` until the process is stopped; see
+/// `daemon::run_daemon`.
+pub fn handle_daemon(
+    cmd: &Commands,
+    msg: &Messages,
+    config: &Config,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Commands::Daemon { log, log_max_size } = cmd {
+        let log = log.clone().unwrap_or_else(RotatingLog::default_path);
+        run_daemon(msg, config.clone(), &log, *log_max_size)?;
+    }
+    Ok(())
+}
+
+/// Turn the arguments given after `--` into the arguments of a scheduled
+/// `copy`, with absolute source and destination paths since the scheduler
+/// runs from another directory.
+fn scheduled_copy_args(copy_args: &[String]) -> io::Result<Vec<String>> {
+    let cli = Cli::try_parse_from(
+        ["rbackup", "copy"]
+            .into_iter()
+            .map(String::from)
+            .chain(copy_args.iter().cloned()),
+    )
+    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+    let mut args = copy_args.to_vec();
+    if let Some(Commands::Copy {
+        sources,
+        destination,
+        ..
+    }) = cli.command
+    {
+        for path in sources.into_iter().chain([destination]) {
+            if path.is_relative()
+                && let Some(arg) = args.iter_mut().find(|a| Path::new(a.as_str()) == path)
+            {
+                *arg = std::path::absolute(&path)?.to_string_lossy().into_owned();
+            }
+        }
+    }
+    args.insert(0, "copy".to_string());
+    Ok(args)
+}
+
+/// Derive the encryption keys for `destination`.
+///
+/// The key comes from `key_file` when given, otherwise from the
+/// `RBACKUP_PASSPHRASE` environment variable or an interactive prompt (asked
+/// twice when a new destination is initialized). `encrypt_names` is `None`
+/// when the caller accepts the settings stored in the destination, as
+/// restores do. New parameters are written to the destination only when
+/// `persist` is true.
+///
+/// Errors carry a localized message suitable for display.
+fn open_encryption(
+    destination: &Path,
+    key_file: Option<&Path>,
+    encrypt_names: Option<bool>,
+    persist: bool,
+    msg: &Messages,
+) -> io::Result<Encryption> {
+    let confirm = !Encryption::is_initialized(destination);
+    let source = key_source(key_file, confirm, msg)?;
+    Encryption::open(destination, &source, encrypt_names, persist, msg)
+}
+
+/// Where the encryption key comes from: `key_file` when given, otherwise the
+/// `RBACKUP_PASSPHRASE` environment variable or an interactive prompt, asked
+/// twice when `confirm` is set.
+fn key_source(key_file: Option<&Path>, confirm: bool, msg: &Messages) -> io::Result<KeySource> {
+    if let Some(path) = key_file {
+        return Ok(KeySource::KeyFile(path.to_owned()));
+    }
+    if let Some(pass) = env::var(PASSPHRASE_ENV).ok().filter(|p| !p.is_empty()) {
+        return Ok(KeySource::Passphrase(pass));
+    }
+    let pass = prompt_passphrase(&msg.passphrase_prompt)?;
+    if confirm && prompt_passphrase(&msg.passphrase_confirm)? != pass {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            msg.passphrase_mismatch.clone(),
+        ));
+    }
+    Ok(KeySource::Passphrase(pass))
+}
@@ -0,0 +1,209 @@
+//! Per-file compression of backed-up files.
+//!
+//! With `--compress=zstd[:level]` every file written to a tree destination is
+//! stored zstd-compressed under its original name with `.zst` appended.
+//! Files whose extension denotes an already compressed format are stored as
+//! they are.
+//!
+//! Compressed files start with a zstd skippable frame carrying an rBackup
+//! marker. The file stays readable by the `zstd` tool (decoders ignore
+//! skippable frames), while a restore can tell files compressed by rBackup
+//! apart from `.zst` files that were already compressed in the source.
+
+use crate::crypto::write_atomically;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Extension appended to the name of every compressed file.
+pub const COMPRESSED_EXTENSION: &str = "zst";
+
+/// Compression level used when `--compress=zstd` gives none.
+pub const DEFAULT_LEVEL: i32 = 3;
+
+/// Extensions of formats that are already compressed and are never
+/// recompressed. Matching is case-insensitive.
+pub const DEFAULT_SKIP_EXTENSIONS: &[&str] = &[
+    "zst", "gz", "tgz", "xz", "txz", "bz2", "lz4", "lzma", "br", "zip", "7z", "rar", "jar", "apk",
+    "jpg", "jpeg", "png", "gif", "webp", "heic", "avif", "mp3", "aac", "ogg", "opus", "flac",
+    "m4a", "mp4", "m4v", "mkv", "webm", "avi", "mov", "pdf", "docx", "xlsx", "pptx", "odt", "ods",
+    "odp", "epub", "dmg",
+];
+
+/// Skippable frame (magic 0x184D2A5E, 4 bytes payload) marking files
+/// compressed by rBackup.
+const MARKER: [u8; 12] = [
+    0x5E, 0x2A, 0x4D, 0x18, 0x04, 0x00, 0x00, 0x00, b'R', b'B', b'K', b'Z',
+];
+
+/// Compression settings for a run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Compression {
+    /// zstd compression level (1-22).
+    pub level: i32,
+    /// Lower-case extensions of files stored without compression.
+    pub skip_extensions: Vec<String>,
+}
+
+impl Compression {
+    /// Parse a `--compress` specification such as `zstd` or `zstd:19`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rbackup::compress::Compression;
+    /// assert_eq!(Compression::parse("zstd:19").unwrap().level, 19);
+    /// assert!(Compression::parse("gzip").is_err());
+    /// ```
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (algo, level) = match spec.split_once(':') {
+            Some((a, l)) => (a, Some(l)),
+            None => (spec, None),
+        };
+        if !algo.eq_ignore_ascii_case("zstd") {
+            return Err(format!(
+                "unsupported compression '{}' (supported: zstd[:level])",
+                algo
+            ));
+        }
+        let level = match level {
+            Some(l) => l
+                .parse::<i32>()
+                .ok()
+                .filter(|l| zstd::compression_level_range().contains(l))
+                .ok_or_else(|| format!("invalid zstd level '{}'", l))?,
+            None => DEFAULT_LEVEL,
+        };
+        Ok(Self {
+            level,
+            skip_extensions: DEFAULT_SKIP_EXTENSIONS
+                .iter()
+                .map(|e| e.to_string())
+                .collect(),
+        })
+    }
+
+    /// Return true if the file at `rel_path` should be stored compressed.
+    pub fn applies_to(&self, rel_path: &Path) -> bool {
+        match rel_path.extension() {
+            Some(ext) => {
+                let ext = ext.to_string_lossy().to_lowercase();
+                !self.skip_extensions.contains(&ext)
+            }
+            None => true,
+        }
+    }
+
+    /// Wrap `reader` so that it yields the compressed representation of its
+    /// contents, marker included.
+    pub fn reader<'a, R: Read + 'a>(&self, reader: R) -> io::Result<impl Read + 'a> {
+        let encoder = zstd::stream::read::Encoder::new(reader, self.level)?;
+        Ok(io::Cursor::new(MARKER).chain(encoder))
+    }
+
+    /// Compress `src` into `dest` through a temporary file.
+    pub fn compress_file(&self, src: &Path, dest: &Path) -> io::Result<()> {
+        let mut reader = self.reader(BufReader::new(File::open(src)?))?;
+        write_atomically(dest, |w| io::copy(&mut reader, w).map(|_| ()))
+    }
+}
+
+/// Append the compressed extension to `rel_path`.
+pub fn compressed_name(rel_path: &Path) -> PathBuf {
+    let mut name = rel_path.as_os_str().to_owned();
+    name.push(".");
+    name.push(COMPRESSED_EXTENSION);
+    PathBuf::from(name)
+}
+
+/// Return true if the file at `path` was compressed by rBackup.
+pub fn is_compressed_file(path: &Path) -> io::Result<bool> {
+    let mut head = [0u8; MARKER.len()];
+    let mut file = File::open(path)?;
+    let mut filled = 0;
+    while filled < head.len() {
+        match file.read(&mut head[filled..])? {
+            0 => return Ok(false),
+            n => filled += n,
+        }
+    }
+    Ok(head == MARKER)
+}
+
+/// Decompress the rBackup-compressed file `src` into `dest`.
+pub fn decompress_file(src: &Path, dest: &Path) -> io::Result<()> {
+    let mut decoder = zstd::stream::read::Decoder::new(File::open(src)?)?;
+    write_atomically(dest, |w| io::copy(&mut decoder, w).map(|_| ()))
+}
+
+/// Writer that decompresses its input when it starts with the rBackup
+/// marker and passes it through unchanged otherwise.
+///
+/// Used when restoring encrypted files, whose plaintext may or may not have
+/// been compressed before encryption. Call `flush` once all data has been
+/// written.
+pub struct AutoDecompress<W: Write> {
+    state: State<W>,
+}
+
+enum State<W: Write> {
+    Sniffing(W, Vec<u8>),
+    Plain(W),
+    Zstd(Box<zstd::stream::write::Decoder<'static, W>>),
+    Done,
+}
+
+impl<W: Write> AutoDecompress<W> {
+    /// Wrap `inner`.
+    pub fn new(inner: W) -> Self {
+        Self {
+            state: State::Sniffing(inner, Vec::with_capacity(MARKER.len())),
+        }
+    }
+
+    /// Decide between plain and compressed output once enough bytes (or the
+    /// end of the stream) have been seen.
+    fn settle(&mut self) -> io::Result<()> {
+        if !matches!(self.state, State::Sniffing(..)) {
+            return Ok(());
+        }
+        if let State::Sniffing(inner, head) = std::mem::replace(&mut self.state, State::Done) {
+            self.state = if head.len() >= MARKER.len() && head[..MARKER.len()] == MARKER {
+                let mut decoder = zstd::stream::write::Decoder::new(inner)?;
+                decoder.write_all(&head)?;
+                State::Zstd(Box::new(decoder))
+            } else {
+                let mut inner = inner;
+                inner.write_all(&head)?;
+                State::Plain(inner)
+            };
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for AutoDecompress<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.state {
+            State::Sniffing(_, head) => {
+                head.extend_from_slice(buf);
+                if head.len() >= MARKER.len() {
+                    self.settle()?;
+                }
+                Ok(buf.len())
+            }
+            State::Plain(w) => w.write(buf),
+            State::Zstd(d) => d.write(buf),
+            State::Done => Err(io::Error::other("writer already failed")),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.settle()?;
+        match &mut self.state {
+            State::Plain(w) => w.flush(),
+            State::Zstd(d) => d.flush(),
+            _ => Ok(()),
+        }
+    }
+}
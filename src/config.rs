@@ -3,6 +3,7 @@
 //! This module defines the `Config` structure representing user-configurable
 //! settings, plus helpers to read, write and edit the configuration file.
 
+use std::collections::BTreeMap;
 use std::env;
 use std::fs;
 use std::io::{self};
@@ -11,12 +12,18 @@ use std::process::Command;
 
 use serde::{Deserialize, Serialize};
 
+use crate::error::Error;
+use crate::hooks::Hooks;
+use crate::notifications::Notification;
+use crate::webdav::WebDavLogin;
+
 /// Runtime configuration for the application.
 ///
-/// This struct is deserialized from a YAML configuration file. Fields are kept
-/// minimal: language, timestamp format and number of worker threads. Add fields
+/// This struct is deserialized from a YAML configuration file: language,
+/// timestamp format and number of worker threads, plus the optional backup
+/// profiles, hooks, notification channels and WebDAV credentials. Add fields
 /// here if you extend the configuration schema.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     /// Language code used for messages ("auto", "en", "it", ...)
     pub language: String,
@@ -24,6 +31,57 @@ pub struct Config {
     pub timestamp_format: String,
     /// Number of worker threads to use for parallel operations (Rayon pool)
     pub jobs: usize,
+    /// Named backup profiles (optional)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, Profile>,
+    /// Commands run around every `copy` (optional)
+    #[serde(flatten)]
+    pub hooks: Hooks,
+    /// Channels notified when a `copy` ends (optional)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notifications: Vec<Notification>,
+    /// Credentials of WebDAV destinations (optional)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub webdav: Vec<WebDavLogin>,
+}
+
+/// A named `copy` configuration, e.g. for scheduled backups.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Profile {
+    /// Source directory to backup
+    pub source: PathBuf,
+    /// Destination directory (or repository)
+    pub destination: PathBuf,
+    /// Additional `copy` options, e.g. `["--exclude", "*.tmp", "--manifest"]`
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Cron expression used by `rbackup daemon`, e.g. `"0 2 * * *"` (optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<String>,
+    /// Commands run around the runs of this profile, overriding the global ones
+    #[serde(flatten)]
+    pub hooks: Hooks,
+}
+
+impl Profile {
+    /// Command-line arguments (without the program name) running this profile.
+    pub fn copy_args(&self) -> Vec<String> {
+        let mut args = vec![
+            "copy".to_string(),
+            self.source.to_string_lossy().into_owned(),
+            self.destination.to_string_lossy().into_owned(),
+        ];
+        args.extend(self.args.iter().cloned());
+        args
+    }
+
+    /// Arguments of a scheduled run of the profile `name`: `copy_args` plus
+    /// `--profile`, so that the run holds the profile lock and runs its hooks.
+    pub fn run_args(&self, name: &str) -> Vec<String> {
+        let mut args = self.copy_args();
+        args.extend(["--profile".to_string(), name.to_string()]);
+        args
+    }
 }
 
 /// Default configuration file template (YAML).
@@ -55,8 +113,67 @@ timestamp_format: '%Y-%m-%d %H:%M:%S'
 # Number of worker threads used for parallel copy operations.
 # Set to an integer > 0. Default: 4
 jobs: 4
+
+# Named backup profiles, usable with `rbackup schedule add --profile <NAME>`.
+# `args` lists additional `copy` options; `schedule` is a cron expression
+# used by `rbackup daemon`.
+# profiles:
+#   documents:
+#     source: /home/me/Documents
+#     destination: /mnt/backup/documents
+#     args: ["--exclude", "*.tmp", "--log", "/home/me/rbackup-documents.log"]
+#     schedule: "0 2 * * *"
+#     pre_hook: "pg_dump mydb > /home/me/Documents/mydb.sql"
+
+# Commands run around every `copy` (profiles can override them). `post_hook`
+# and `on_error` receive the results in RBACKUP_COPIED, RBACKUP_SKIPPED,
+# RBACKUP_ERRORS, RBACKUP_EXIT_STATUS and RBACKUP_REPORT. `hook_failure`
+# is `fatal` (a failing hook fails the run) or `warn`.
+# pre_hook: "mount /mnt/backup"
+# post_hook: "umount /mnt/backup"
+# on_error: "notify-send 'rbackup failed'"
+# hook_failure: fatal
+
+# Channels notified when a `copy` ends. `on` is `always` (default), `success`
+# or `failure` (the run failed or some files could not be copied).
+# notifications:
+#   - type: email
+#     on: failure
+#     smtp_host: smtp.example.com
+#     smtp_port: 587
+#     tls: starttls            # starttls, tls or none
+#     username: me@example.com
+#     password_env: RBACKUP_SMTP_PASSWORD
+#     from: rbackup@example.com
+#     to: ["me@example.com"]
+#   - type: webhook
+#     url: https://hooks.example.com/rbackup
+#     headers: { Authorization: "Bearer <token>" }
+#   - type: syslog
+
+# Credentials of WebDAV destinations (`webdav://` or `webdavs://`), used for
+# the destinations starting with `url`.
+# webdav:
+#   - url: webdavs://dms.example.com/backup
+#     username: backup
+#     password_env: RBACKUP_DMS_PASSWORD
 "#;
 
+/// The settings used without a configuration file.
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            language: "auto".to_string(),
+            timestamp_format: "%Y-%m-%d %H:%M:%S".to_string(),
+            jobs: 4,
+            profiles: BTreeMap::new(),
+            hooks: Hooks::default(),
+            notifications: Vec::new(),
+            webdav: Vec::new(),
+        }
+    }
+}
+
 impl Config {
     /// Create the default configuration file on disk.
     ///
@@ -148,24 +265,24 @@ impl Config {
 
     /// Load the configuration from disk.
     ///
-    /// Returns `io::Error` if the file does not exist or if its contents are
-    /// invalid YAML.
-    pub fn load() -> io::Result<Self> {
+    /// Returns an `Error::Config` naming the file if it does not exist or if
+    /// its contents are invalid YAML.
+    pub fn load() -> Result<Self, Error> {
         let path = Config::config_file();
-        let content = fs::read_to_string(&path)?;
+        let config_error = |source| Error::Config {
+            path: Some(path.clone()),
+            source,
+        };
+        let content = fs::read_to_string(&path).map_err(config_error)?;
         let conf: Config = serde_yaml::from_str(&content)
-            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            .map_err(|e| config_error(io::Error::new(io::ErrorKind::InvalidData, e)))?;
         Ok(conf)
     }
 
     /// Load the configuration, falling back to sensible defaults when the
     /// configuration file is missing or invalid.
     pub fn load_or_default() -> Self {
-        Config::load().unwrap_or_else(|_| Config {
-            language: "auto".to_string(),
-            timestamp_format: "%Y-%m-%d %H:%M:%S".to_string(),
-            jobs: 4,
-        })
+        Config::load().unwrap_or_default()
     }
 
     /// Open the configuration file in the user's preferred editor.
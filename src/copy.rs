@@ -5,43 +5,140 @@
 //! `output` modules; this module coordinates UI, logging flush and error
 //! reporting.
 
-use crate::output::{LogContext, log_output};
-use crate::utils::{Messages, clear_terminal, copy_incremental};
+use crate::error::Error;
+use crate::output::{LogContext, ProgressMode, log_output};
+use crate::utils::{CopyCounts, CopyTarget, Messages, clear_terminal, copy_targets};
 use crossterm::terminal;
+use std::collections::HashSet;
+use std::io;
 use std::io::Write;
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
+
+/// How each source of a multi-source run is named below the destination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceNaming {
+    /// Last component of the source path (`~/Documents` -> `Documents`).
+    Basename,
+    /// Whole absolute source path (`/etc` -> `etc`, `C:\Data` -> `C/Data`).
+    FullPath,
+}
+
+/// Map each source to the directory it is copied into.
+///
+/// A single source is copied into `destination` itself unless `naming` is
+/// given; several sources get a subdirectory each, named after their
+/// basename by default. Two sources mapped to the same directory are an
+/// error.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use std::path::{Path, PathBuf};
+/// use rbackup::copy::source_destinations;
+///
+/// let sources = [PathBuf::from("/home/me/Documents"), PathBuf::from("/etc")];
+/// let pairs = source_destinations(&sources, Path::new("/mnt/backup"), None).unwrap();
+/// assert_eq!(pairs[1].1, Path::new("/mnt/backup/etc"));
+/// ```
+pub fn source_destinations(
+    sources: &[PathBuf],
+    destination: &Path,
+    naming: Option<SourceNaming>,
+) -> io::Result<Vec<(PathBuf, PathBuf)>> {
+    let naming = match (naming, sources) {
+        (None, [source]) => return Ok(vec![(source.clone(), destination.to_path_buf())]),
+        (naming, _) => naming.unwrap_or(SourceNaming::Basename),
+    };
+
+    let mut seen = HashSet::new();
+    let mut pairs = Vec::with_capacity(sources.len());
+    for source in sources {
+        // Names come from the resolved path, so that `.` or `..` work too.
+        let resolved = source.canonicalize()?;
+        let name: PathBuf = match naming {
+            SourceNaming::Basename => resolved.file_name().map(PathBuf::from).unwrap_or_default(),
+            SourceNaming::FullPath => resolved
+                .components()
+                .filter_map(|c| match c {
+                    Component::Prefix(p) => {
+                        let drive: String = p
+                            .as_os_str()
+                            .to_string_lossy()
+                            .chars()
+                            .filter(char::is_ascii_alphanumeric)
+                            .collect();
+                        Some(PathBuf::from(drive))
+                    }
+                    Component::Normal(n) => Some(PathBuf::from(n)),
+                    _ => None,
+                })
+                .collect(),
+        };
+        if name.as_os_str().is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{}: no name to copy it under", source.display()),
+            ));
+        }
+        if !seen.insert(name.clone()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{}: several sources would be copied to {}",
+                    source.display(),
+                    destination.join(&name).display()
+                ),
+            ));
+        }
+        pairs.push((source.clone(), destination.join(name)));
+    }
+    Ok(pairs)
+}
 
 /// Print the initial messages shown when a copy operation starts.
 ///
-/// This clears the terminal and prints the localized "backup started" messages
-/// including source and destination paths. It uses the provided `LogContext`
-/// for formatting decisions (quiet / timestamps) but does not mutate it.
+/// This clears the terminal (in fancy progress mode only) and prints the
+/// localized "backup started" messages, with one line per source and
+/// directory it is copied into, for each destination. It uses the provided
+/// `LogContext` for formatting decisions (quiet / timestamps / progress mode)
+/// but does not mutate it.
 ///
 /// # Parameters
 /// - `msg`: localized messages bundle.
 /// - `ctx`: current log/output context.
-/// - `source`: source directory path.
-/// - `destination`: destination directory path.
-pub fn start_copy_message(msg: &Messages, ctx: &LogContext, source: &Path, destination: &Path) {
-    clear_terminal();
+/// - `targets`: destinations and the directories their sources are copied into.
+pub fn start_copy_message(msg: &Messages, ctx: &LogContext, targets: &[CopyTarget]) {
+    let fancy = ctx.progress.resolve(ctx.quiet) == ProgressMode::Fancy;
+    if fancy {
+        clear_terminal();
+    }
 
     // Use provided context directly (don't mutate)
     log_output(&msg.backup_init, ctx);
-    log_output(
-        &format!(
-            "{} {} {} {}\n\n\n\n\n",
-            msg.starting_backup,
-            source.display(),
-            msg.to,
-            destination.display()
-        ),
-        ctx,
-    );
+    let pairs: Vec<&(PathBuf, PathBuf)> = targets.iter().flat_map(|t| &t.pairs).collect();
+    for (i, (source, destination)) in pairs.iter().enumerate() {
+        let padding = if fancy && i + 1 == pairs.len() {
+            "\n\n\n\n\n"
+        } else {
+            ""
+        };
+        log_output(
+            &format!(
+                "{} {} {} {}{}",
+                msg.starting_backup,
+                source.display(),
+                msg.to,
+                destination.display(),
+                padding
+            ),
+            ctx,
+        );
+    }
 }
 
 // Helper function: centralize logger flush to avoid duplication
-// (kept private as internal utility)
-fn flush_logger(ctx: &mut LogContext) {
+// (crate-visible so the restore path can share it)
+pub(crate) fn flush_logger(ctx: &LogContext) {
     if let Some(log) = &ctx.logger {
         match log.lock() {
             Ok(mut guard) => {
@@ -57,59 +154,81 @@ fn flush_logger(ctx: &mut LogContext) {
 
 /// Run the incremental copy operation and print final messages.
 ///
-/// This function sets up the progress row, calls the core `copy_incremental`
-/// helper and prints a summary or a fatal error message. On unrecoverable
-/// errors it attempts to flush the logger and exits the process.
+/// This function sets up the progress row, calls the core `copy_targets`
+/// helper and prints a summary, the errors of single files, then the error
+/// of each destination that failed. Errors are returned per destination after
+/// flushing the logger, so that the caller can run its `on_error` and
+/// `post_hook` hooks before exiting.
 ///
 /// # Parameters
 /// - `msg`: localized messages bundle.
 /// - `ctx`: mutable logging/context object; some fields (row, on_log) are
 ///   updated during execution.
-/// - `source`: source directory path.
-/// - `destination`: destination directory path.
+/// - `targets`: destinations, each with the directories its sources are
+///   copied into; several sources are copied in one run with a combined
+///   summary, and several destinations also get a line each.
 pub fn execute_copy(
     msg: &Messages,
     ctx: &mut LogContext,
-    source: &Path,
-    destination: &Path,
+    targets: &[CopyTarget],
     delta: bool,
-) {
-    let (_cols, rows) = terminal::size().unwrap_or((80, 24));
-    let progress_row = rows.saturating_sub(1);
-
-    // ctx.exclude_matcher is expected to be prepared by the caller (commands::handle_copy)
-
-    ctx.row = Some(progress_row);
+) -> Vec<Result<CopyCounts, Error>> {
+    // ctx.exclude_matcher is expected to be prepared by the caller (job::BackupJob::run)
 
-    match copy_incremental(source, destination, msg, ctx, delta) {
-        Ok((copied, skipped)) => {
-            ctx.row = None;
-            ctx.on_log = true;
-            let done_msg = format!(
-                "\n\n\n{} ({}. {}, {})",
-                &msg.backup_ended,
-                &msg.files_total
-                    .replace("{}", &(copied + skipped).to_string()),
-                &msg.files_copied.replace("{}", &copied.to_string()),
-                &msg.files_skipped.replace("{}", &skipped.to_string())
-            );
+    // Only the fancy view positions output on the bottom row.
+    if ctx.progress.resolve(ctx.quiet) == ProgressMode::Fancy {
+        let (_cols, rows) = terminal::size().unwrap_or((80, 24));
+        ctx.row = Some(rows.saturating_sub(1));
+    }
 
-            log_output(&done_msg, ctx);
+    let results = copy_targets(targets, msg, ctx, delta, None);
+    ctx.row = None;
+    ctx.on_log = true;
 
-            // Flush logger if present
-            flush_logger(ctx);
-        }
-        Err(e) => {
-            ctx.quiet = false;
-            ctx.row = None;
-            ctx.on_log = true;
-            let error_msg = format!("{}: {}", msg.generic_error, e);
-            log_output(&error_msg, ctx);
+    let done: Vec<&CopyCounts> = results.iter().filter_map(|r| r.as_ref().ok()).collect();
+    if !done.is_empty() {
+        let copied: usize = done.iter().map(|c| c.copied).sum();
+        let skipped: usize = done.iter().map(|c| c.skipped).sum();
+        let done_msg = format!(
+            "\n\n\n{} ({}. {}, {})",
+            &msg.backup_ended,
+            &msg.files_total
+                .replace("{}", &(copied + skipped).to_string()),
+            &msg.files_copied.replace("{}", &copied.to_string()),
+            &msg.files_skipped.replace("{}", &skipped.to_string())
+        );
 
-            // Try to flush logger before exiting
-            flush_logger(ctx);
+        log_output(&done_msg, ctx);
+    }
+    // Files that failed (or could not be listed) do not stop the run; they
+    // are listed once it ends.
+    for e in done.iter().flat_map(|c| &c.errors) {
+        log_output(&format!("{}: {}", msg.generic_error, e), ctx);
+    }
 
-            std::process::exit(1);
+    // With several destinations, a failing one must not hide the others.
+    let several = targets.len() > 1;
+    for (target, result) in targets.iter().zip(&results) {
+        match result {
+            Ok(counts) if several => log_output(
+                &format!(
+                    "{}: {}, {}",
+                    target.destination.display(),
+                    msg.files_copied.replace("{}", &counts.copied.to_string()),
+                    msg.files_skipped.replace("{}", &counts.skipped.to_string())
+                ),
+                ctx,
+            ),
+            Ok(_) => {}
+            // The error names the directory of the destination.
+            Err(e) => {
+                ctx.quiet = false;
+                log_output(&format!("{}: {}", msg.generic_error, e), ctx);
+            }
         }
     }
+
+    // Flush logger if present
+    flush_logger(ctx);
+    results
 }
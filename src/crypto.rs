@@ -0,0 +1,542 @@
+//! Client-side encryption of backed-up files.
+//!
+//! Files are encrypted with ChaCha20-Poly1305 using a chunked construction:
+//! the plaintext is split in 64 KiB chunks, each sealed with a nonce made of
+//! a random per-file prefix, the chunk counter and a "last chunk" flag, so
+//! truncated or reordered files are rejected on decryption.
+//!
+//! The master key is derived either from a passphrase (Argon2id) or from the
+//! contents of a key file. The salt and a key check value are stored in
+//! `.rbackup-crypt.json` at the root of the destination so that later runs
+//! and restores can verify they were given the right key.
+//!
+//! Every encrypted file starts with an encrypted header holding the path of
+//! the file relative to the source directory. This allows file names to be
+//! hidden as well (`--encrypt-names`): in that case the destination name is a
+//! keyed hash of the relative path and the original name is only recoverable
+//! with the key.
+
+use crate::compress::AutoDecompress;
+use crate::destination::temp_path;
+use crate::manifest::manifest_key;
+use crate::utils::Messages;
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::terminal;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write, stdout};
+use std::path::{Path, PathBuf};
+
+/// File holding the key derivation parameters, stored in the destination root.
+pub const KEY_INFO_FILE: &str = ".rbackup-crypt.json";
+
+/// Extension appended to the name of every encrypted file.
+pub const ENCRYPTED_EXTENSION: &str = "enc";
+
+/// Environment variable read for the passphrase before prompting.
+pub const PASSPHRASE_ENV: &str = "RBACKUP_PASSPHRASE";
+
+const MAGIC: &[u8; 8] = b"RBKENC\x00\x01";
+const CHUNK_SIZE: usize = 64 * 1024;
+const NONCE_PREFIX_LEN: usize = 7;
+const TAG_LEN: usize = 16;
+const SALT_LEN: usize = 16;
+
+const KDF_ARGON2ID: &str = "argon2id";
+const KDF_KEY_FILE: &str = "key-file";
+
+/// Where the master key comes from.
+#[derive(Debug, Clone)]
+pub enum KeySource {
+    /// A passphrase, stretched with Argon2id.
+    Passphrase(String),
+    /// A file whose contents are used as key material.
+    KeyFile(PathBuf),
+}
+
+/// Key derivation parameters persisted in `KEY_INFO_FILE`.
+#[derive(Debug, Serialize, Deserialize)]
+struct KeyInfo {
+    version: u32,
+    kdf: String,
+    salt: String,
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+    encrypt_names: bool,
+    check: String,
+}
+
+/// Keys used to encrypt the files of one destination.
+#[derive(Clone)]
+pub struct Encryption {
+    content_key: [u8; 32],
+    name_key: [u8; 32],
+    /// If true, destination file names are replaced by a keyed hash.
+    pub encrypt_names: bool,
+}
+
+impl fmt::Debug for Encryption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Encryption")
+            .field("encrypt_names", &self.encrypt_names)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Encryption {
+    /// Return true if `dest_dir` already holds encryption parameters.
+    pub fn is_initialized(dest_dir: &Path) -> bool {
+        dest_dir.join(KEY_INFO_FILE).is_file()
+    }
+
+    /// Derive the keys for an already initialized destination.
+    ///
+    /// Returns an error of kind `PermissionDenied` when the passphrase or key
+    /// file does not match the one used to initialize the destination, and
+    /// `InvalidInput` when the key source is of the wrong type.
+    pub fn load(dest_dir: &Path, source: &KeySource) -> io::Result<Self> {
+        let data = fs::read(dest_dir.join(KEY_INFO_FILE))?;
+        let info: KeyInfo = serde_json::from_slice(&data)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let salt =
+            hex::decode(&info.salt).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        let expected_kdf = match source {
+            KeySource::Passphrase(_) => KDF_ARGON2ID,
+            KeySource::KeyFile(_) => KDF_KEY_FILE,
+        };
+        if info.kdf != expected_kdf {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("backup was encrypted with a {} key", info.kdf),
+            ));
+        }
+
+        let master = derive_master(source, &salt, info.m_cost, info.t_cost, info.p_cost)?;
+        if check_value(&master) != info.check {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "wrong passphrase or key file",
+            ));
+        }
+        Ok(Encryption::from_master(&master, info.encrypt_names))
+    }
+
+    /// Generate new key derivation parameters for `dest_dir`.
+    ///
+    /// The parameters are written to `KEY_INFO_FILE` only when `persist` is
+    /// true, so dry-runs leave the destination untouched.
+    pub fn init(
+        dest_dir: &Path,
+        source: &KeySource,
+        encrypt_names: bool,
+        persist: bool,
+    ) -> io::Result<Self> {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let params = Params::default();
+        let (m_cost, t_cost, p_cost) = (params.m_cost(), params.t_cost(), params.p_cost());
+        let master = derive_master(source, &salt, m_cost, t_cost, p_cost)?;
+
+        if persist {
+            let info = KeyInfo {
+                version: 1,
+                kdf: match source {
+                    KeySource::Passphrase(_) => KDF_ARGON2ID,
+                    KeySource::KeyFile(_) => KDF_KEY_FILE,
+                }
+                .to_string(),
+                salt: hex::encode(salt),
+                m_cost,
+                t_cost,
+                p_cost,
+                encrypt_names,
+                check: check_value(&master),
+            };
+            fs::create_dir_all(dest_dir)?;
+            let data = serde_json::to_vec_pretty(&info)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            // A truncated parameter file would make the backup unreadable.
+            write_atomically(&dest_dir.join(KEY_INFO_FILE), |w| w.write_all(&data))?;
+        }
+        Ok(Encryption::from_master(&master, encrypt_names))
+    }
+
+    /// Derive the keys for `dest_dir` from `source`, initializing the
+    /// destination when it has no encryption parameters yet.
+    ///
+    /// `encrypt_names` is `None` when the caller accepts the settings stored
+    /// in the destination, as restores do. New parameters are written only
+    /// when `persist` is true. Errors carry a localized message suitable for
+    /// display.
+    pub fn open(
+        dest_dir: &Path,
+        source: &KeySource,
+        encrypt_names: Option<bool>,
+        persist: bool,
+        msg: &Messages,
+    ) -> io::Result<Self> {
+        if !Encryption::is_initialized(dest_dir) {
+            return Encryption::init(dest_dir, source, encrypt_names.unwrap_or(false), persist);
+        }
+
+        let enc = Encryption::load(dest_dir, source).map_err(|e| match e.kind() {
+            io::ErrorKind::PermissionDenied => {
+                io::Error::new(io::ErrorKind::PermissionDenied, msg.wrong_key.clone())
+            }
+            io::ErrorKind::InvalidInput => io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{}: {}", msg.encryption_mismatch, e),
+            ),
+            _ => e,
+        })?;
+        if encrypt_names.is_some_and(|names| names != enc.encrypt_names) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                msg.encryption_mismatch.clone(),
+            ));
+        }
+        Ok(enc)
+    }
+
+    fn from_master(master: &[u8; 32], encrypt_names: bool) -> Self {
+        Self {
+            content_key: blake3::derive_key("rbackup 2026 file contents v1", master),
+            name_key: blake3::derive_key("rbackup 2026 file names v1", master),
+            encrypt_names,
+        }
+    }
+
+    /// Map a path relative to the source to its encrypted counterpart,
+    /// relative to the destination.
+    ///
+    /// Without name encryption the path is kept and `.enc` is appended; with
+    /// name encryption the path becomes `xx/<hash>.enc`, where the hash is
+    /// keyed so it reveals nothing about the original name.
+    pub fn dest_rel_path(&self, rel_path: &Path) -> PathBuf {
+        if self.encrypt_names {
+            let hash = blake3::keyed_hash(&self.name_key, manifest_key(rel_path).as_bytes());
+            let hex = hash.to_hex();
+            PathBuf::from(&hex[..2]).join(format!("{}.{}", &hex[2..32], ENCRYPTED_EXTENSION))
+        } else {
+            let mut name = rel_path.as_os_str().to_owned();
+            name.push(".");
+            name.push(ENCRYPTED_EXTENSION);
+            PathBuf::from(name)
+        }
+    }
+
+    /// Encrypt `src` into `dest`, recording `rel_key` as the original path.
+    ///
+    /// The ciphertext is written to a temporary file next to `dest` and then
+    /// renamed, so a partially written file never replaces a good one.
+    pub fn encrypt_file(&self, src: &Path, dest: &Path, rel_key: &str) -> io::Result<()> {
+        let reader = BufReader::new(File::open(src)?);
+        write_atomically(dest, |w| self.encrypt_stream(reader, w, rel_key))
+    }
+
+    /// Decrypt the file at `src` and write it below `target_dir`, at the path
+    /// recorded in its header. Contents compressed by rBackup before
+    /// encryption are decompressed on the way. Returns the recorded relative
+    /// path.
+    pub fn decrypt_file(&self, src: &Path, target_dir: &Path) -> io::Result<String> {
+        let reader = BufReader::new(File::open(src)?);
+        let mut dest: Option<(PathBuf, PathBuf)> = None;
+        let result = self.decrypt_stream(reader, |rel_key| {
+            let path = target_dir.join(safe_rel_path(rel_key)?);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let tmp = temp_path(&path);
+            let file = File::create(&tmp)?;
+            dest = Some((path, tmp));
+            Ok(AutoDecompress::new(BufWriter::new(file)))
+        });
+        match (result, dest) {
+            (Ok(rel_key), Some((path, tmp))) => {
+                fs::rename(&tmp, &path)?;
+                Ok(rel_key)
+            }
+            (Err(e), Some((_, tmp))) => {
+                let _ = fs::remove_file(&tmp);
+                Err(e)
+            }
+            (result, None) => result,
+        }
+    }
+
+    /// Encrypt everything read from `reader` into `writer`.
+    pub fn encrypt_stream<R: Read, W: Write>(
+        &self,
+        mut reader: R,
+        mut writer: W,
+        rel_key: &str,
+    ) -> io::Result<()> {
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&self.content_key));
+        let mut prefix = [0u8; NONCE_PREFIX_LEN];
+        OsRng.fill_bytes(&mut prefix);
+
+        writer.write_all(MAGIC)?;
+        writer.write_all(&prefix)?;
+
+        let header = seal(&cipher, &prefix, 0, false, rel_key.as_bytes())?;
+        write_frame(&mut writer, &header)?;
+
+        let mut counter: u32 = 1;
+        let mut current = vec![0u8; CHUNK_SIZE];
+        let mut next = vec![0u8; CHUNK_SIZE];
+        let mut current_len = read_full(&mut reader, &mut current)?;
+        loop {
+            let next_len = if current_len == CHUNK_SIZE {
+                read_full(&mut reader, &mut next)?
+            } else {
+                0
+            };
+            let last = next_len == 0;
+            let sealed = seal(&cipher, &prefix, counter, last, &current[..current_len])?;
+            write_frame(&mut writer, &sealed)?;
+            if last {
+                break;
+            }
+            std::mem::swap(&mut current, &mut next);
+            current_len = next_len;
+            counter = counter
+                .checked_add(1)
+                .ok_or_else(|| io::Error::other("file too large to encrypt"))?;
+        }
+        writer.flush()
+    }
+
+    /// Decrypt everything read from `reader`.
+    ///
+    /// `open_writer` receives the relative path recorded in the header and
+    /// returns the writer that receives the plaintext.
+    pub fn decrypt_stream<R: Read, W: Write>(
+        &self,
+        mut reader: R,
+        open_writer: impl FnOnce(&str) -> io::Result<W>,
+    ) -> io::Result<String> {
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&self.content_key));
+
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not an rBackup encrypted file",
+            ));
+        }
+        let mut prefix = [0u8; NONCE_PREFIX_LEN];
+        reader.read_exact(&mut prefix)?;
+
+        let header = read_frame(&mut reader)?.ok_or_else(truncated)?;
+        let rel_key = String::from_utf8(open(&cipher, &prefix, 0, false, &header)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        let mut writer = open_writer(&rel_key)?;
+        let mut counter: u32 = 1;
+        let mut current = read_frame(&mut reader)?.ok_or_else(truncated)?;
+        loop {
+            let next = read_frame(&mut reader)?;
+            let last = next.is_none();
+            let plain = open(&cipher, &prefix, counter, last, &current)?;
+            writer.write_all(&plain)?;
+            match next {
+                Some(n) => current = n,
+                None => break,
+            }
+            counter = counter.checked_add(1).ok_or_else(truncated)?;
+        }
+        writer.flush()?;
+        Ok(rel_key)
+    }
+}
+
+/// Read a passphrase from the terminal without echoing it.
+pub fn prompt_passphrase(prompt: &str) -> io::Result<String> {
+    print!("{} ", prompt);
+    stdout().flush()?;
+
+    terminal::enable_raw_mode()?;
+    let mut passphrase = String::new();
+    let result = loop {
+        match event::read() {
+            Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => match key.code {
+                KeyCode::Enter => break Ok(()),
+                KeyCode::Backspace => {
+                    passphrase.pop();
+                }
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    break Err(io::Error::new(io::ErrorKind::Interrupted, "cancelled"));
+                }
+                KeyCode::Char(c) => passphrase.push(c),
+                _ => {}
+            },
+            Ok(_) => {}
+            Err(e) => break Err(e),
+        }
+    };
+    let _ = terminal::disable_raw_mode();
+    println!();
+
+    result.map(|_| passphrase)
+}
+
+/// Write a file through a temporary sibling that is renamed on success.
+pub(crate) fn write_atomically(
+    dest: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+) -> io::Result<()> {
+    let tmp = temp_path(dest);
+    let result = File::create(&tmp).and_then(|f| {
+        let mut w = BufWriter::new(f);
+        write(&mut w)?;
+        w.flush()
+    });
+    match result {
+        Ok(()) => fs::rename(&tmp, dest),
+        Err(e) => {
+            let _ = fs::remove_file(&tmp);
+            Err(e)
+        }
+    }
+}
+
+/// Turn a relative path read from an encrypted header into a path that stays
+/// below the restore target.
+pub(crate) fn safe_rel_path(rel_key: &str) -> io::Result<PathBuf> {
+    let mut out = PathBuf::new();
+    for part in rel_key.split('/') {
+        if part.is_empty() || part == "." || part == ".." || part.contains(['\\', ':']) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsafe path in backup: {}", rel_key),
+            ));
+        }
+        out.push(part);
+    }
+    Ok(out)
+}
+
+fn derive_master(
+    source: &KeySource,
+    salt: &[u8],
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+) -> io::Result<[u8; 32]> {
+    let mut master = [0u8; 32];
+    match source {
+        KeySource::Passphrase(pass) => {
+            let params = Params::new(m_cost, t_cost, p_cost, Some(32))
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+            Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                .hash_password_into(pass.as_bytes(), salt, &mut master)
+                .map_err(|e| io::Error::other(e.to_string()))?;
+        }
+        KeySource::KeyFile(path) => {
+            let material = fs::read(path)?;
+            if material.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "key file is empty",
+                ));
+            }
+            let mut hasher = blake3::Hasher::new_derive_key("rbackup 2026 key file v1");
+            hasher.update(salt);
+            hasher.update(&material);
+            master = *hasher.finalize().as_bytes();
+        }
+    }
+    Ok(master)
+}
+
+fn check_value(master: &[u8; 32]) -> String {
+    hex::encode(blake3::derive_key("rbackup 2026 key check v1", master))
+}
+
+fn nonce(prefix: &[u8; NONCE_PREFIX_LEN], counter: u32, last: bool) -> Nonce {
+    let mut n = [0u8; 12];
+    n[..NONCE_PREFIX_LEN].copy_from_slice(prefix);
+    n[NONCE_PREFIX_LEN..11].copy_from_slice(&counter.to_be_bytes());
+    n[11] = u8::from(last);
+    *Nonce::from_slice(&n)
+}
+
+fn seal(
+    cipher: &ChaCha20Poly1305,
+    prefix: &[u8; NONCE_PREFIX_LEN],
+    counter: u32,
+    last: bool,
+    plain: &[u8],
+) -> io::Result<Vec<u8>> {
+    cipher
+        .encrypt(&nonce(prefix, counter, last), plain)
+        .map_err(|_| io::Error::other("encryption failed"))
+}
+
+fn open(
+    cipher: &ChaCha20Poly1305,
+    prefix: &[u8; NONCE_PREFIX_LEN],
+    counter: u32,
+    last: bool,
+    sealed: &[u8],
+) -> io::Result<Vec<u8>> {
+    cipher
+        .decrypt(&nonce(prefix, counter, last), sealed)
+        .map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "decryption failed (wrong key or corrupted file)",
+            )
+        })
+}
+
+fn write_frame<W: Write>(writer: &mut W, frame: &[u8]) -> io::Result<()> {
+    writer.write_all(&(frame.len() as u32).to_le_bytes())?;
+    writer.write_all(frame)
+}
+
+/// Read one length-prefixed frame, or `None` at a clean end of stream.
+fn read_frame<R: Read>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut len_buf = [0u8; 4];
+    match read_full(reader, &mut len_buf)? {
+        0 => return Ok(None),
+        4 => {}
+        _ => return Err(truncated()),
+    }
+    let len = u32::from_le_bytes(len_buf) as usize;
+    if len > CHUNK_SIZE + TAG_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "invalid frame length in encrypted file",
+        ));
+    }
+    let mut frame = vec![0u8; len];
+    reader.read_exact(&mut frame).map_err(|_| truncated())?;
+    Ok(Some(frame))
+}
+
+/// Fill `buf` as much as possible, returning fewer bytes only at end of stream.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+fn truncated() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "encrypted file is truncated")
+}
@@ -0,0 +1,322 @@
+//! Built-in scheduler (`rbackup daemon`).
+//!
+//! The daemon runs the configuration profiles that have a `schedule` (a cron
+//! expression, see `schedule::CronExpr`) at the right times. Each run is a
+//! separate `rbackup copy` process, so a failing run cannot take the daemon
+//! down, and a profile is never started while its previous run is still in
+//! progress. Start, end and summary of every run are written to a rotating
+//! log. On Unix, `SIGHUP` reloads the configuration file.
+
+use crate::config::Config;
+use crate::schedule::CronExpr;
+use crate::utils::Messages;
+use chrono::{Local, NaiveDateTime};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Default size above which the daemon log is rotated.
+pub const DEFAULT_LOG_MAX_BYTES: u64 = 1024 * 1024;
+/// Number of rotated daemon logs kept (`daemon.log.1` ... `daemon.log.5`).
+pub const LOG_KEEP: usize = 5;
+/// How often the daemon checks for due profiles and finished runs.
+const TICK: Duration = Duration::from_secs(1);
+
+/// Append-only log file rotated by size.
+///
+/// When a line would make the file exceed `max_bytes`, `file` is renamed to
+/// `file.1` (shifting older ones up to `file.<keep>`) and a new file starts.
+#[derive(Debug, Clone)]
+pub struct RotatingLog {
+    path: PathBuf,
+    max_bytes: u64,
+    keep: usize,
+}
+
+impl RotatingLog {
+    pub fn new(path: PathBuf, max_bytes: u64, keep: usize) -> Self {
+        Self {
+            path,
+            max_bytes,
+            keep,
+        }
+    }
+
+    /// Default location: `daemon.log` next to the configuration file.
+    pub fn default_path() -> PathBuf {
+        Config::config_file().with_file_name("daemon.log")
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut name = self.path.as_os_str().to_owned();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&self) -> io::Result<()> {
+        let _ = fs::remove_file(self.rotated(self.keep));
+        for n in (1..self.keep).rev() {
+            let from = self.rotated(n);
+            if from.exists() {
+                fs::rename(&from, self.rotated(n + 1))?;
+            }
+        }
+        if self.keep > 0 {
+            fs::rename(&self.path, self.rotated(1))
+        } else {
+            fs::remove_file(&self.path)
+        }
+    }
+
+    /// Append `line`, rotating the file first if it would grow too large.
+    pub fn write_line(&self, line: &str) -> io::Result<()> {
+        let size = fs::metadata(&self.path).map(|m| m.len()).unwrap_or(0);
+        if size > 0 && size + line.len() as u64 + 1 > self.max_bytes {
+            self.rotate()?;
+        }
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", line)
+    }
+}
+
+/// A scheduled profile and its next run.
+struct Job {
+    name: String,
+    expr: CronExpr,
+    args: Vec<String>,
+    next: Option<NaiveDateTime>,
+}
+
+/// A run in progress: the child process and the readers of its output,
+/// which return the last non-empty line written to each stream.
+struct Running {
+    child: Child,
+    started: Instant,
+    stdout: JoinHandle<String>,
+    stderr: JoinHandle<String>,
+}
+
+fn last_line<R: Read + Send + 'static>(stream: Option<R>) -> JoinHandle<String> {
+    thread::spawn(move || {
+        let mut last = String::new();
+        if let Some(stream) = stream {
+            for line in BufReader::new(stream).lines().map_while(Result::ok) {
+                if !line.trim().is_empty() {
+                    last = line.trim().to_string();
+                }
+            }
+        }
+        last
+    })
+}
+
+struct Daemon<'a> {
+    msg: &'a Messages,
+    log: RotatingLog,
+    timestamp_format: String,
+    exe: PathBuf,
+    jobs: Vec<Job>,
+    running: HashMap<String, Running>,
+}
+
+impl Daemon<'_> {
+    /// Print `line` with a timestamp and append it to the log.
+    fn note(&self, line: &str) {
+        let line = format!("[{}] {}", Local::now().format(&self.timestamp_format), line);
+        println!("{}", line);
+        if let Err(e) = self.log.write_line(&line) {
+            eprintln!("{}: {}", self.log.path.display(), e);
+        }
+    }
+
+    /// Replace the jobs with the scheduled profiles of `config`.
+    fn load(&mut self, config: &Config) {
+        let now = Local::now().naive_local();
+        self.timestamp_format = config.timestamp_format.clone();
+        self.jobs.clear();
+        for (name, profile) in &config.profiles {
+            let Some(schedule) = &profile.schedule else {
+                continue;
+            };
+            match CronExpr::parse(schedule) {
+                Ok(expr) => {
+                    let next = expr.next_after(now);
+                    self.jobs.push(Job {
+                        name: name.clone(),
+                        expr,
+                        args: profile.run_args(name),
+                        next,
+                    });
+                }
+                Err(e) => self.note(&format!(
+                    "{} {}",
+                    self.msg.daemon_invalid_schedule.replace("{}", name),
+                    e
+                )),
+            }
+        }
+        for job in &self.jobs {
+            let next = job
+                .next
+                .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_else(|| "-".to_string());
+            self.note(&format!(
+                "{} {} ({})",
+                self.msg.daemon_next_run.replace("{}", &job.name),
+                next,
+                job.expr
+            ));
+        }
+    }
+
+    fn start(&mut self, index: usize) {
+        let job = &self.jobs[index];
+        if self.running.contains_key(&job.name) {
+            self.note(&self.msg.daemon_run_overlap.replace("{}", &job.name));
+            return;
+        }
+        let mut args = job.args.clone();
+        // The run has no terminal: only keep the start and end messages.
+        if !args.iter().any(|a| a.starts_with("--progress")) {
+            args.extend(["--progress".to_string(), "none".to_string()]);
+        }
+        let spawned = Command::new(&self.exe)
+            .args(&args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn();
+        let name = job.name.clone();
+        match spawned {
+            Ok(mut child) => {
+                self.note(&self.msg.daemon_run_started.replace("{}", &name));
+                let stdout = last_line(child.stdout.take());
+                let stderr = last_line(child.stderr.take());
+                self.running.insert(
+                    name,
+                    Running {
+                        child,
+                        started: Instant::now(),
+                        stdout,
+                        stderr,
+                    },
+                );
+            }
+            Err(e) => self.note(&format!(
+                "{} {}",
+                self.msg.daemon_run_failed.replace("{}", &name),
+                e
+            )),
+        }
+    }
+
+    /// Log and forget the runs that have ended.
+    fn reap(&mut self) {
+        let finished: Vec<String> = self
+            .running
+            .iter_mut()
+            .filter_map(|(name, run)| match run.child.try_wait() {
+                Ok(None) => None,
+                _ => Some(name.clone()),
+            })
+            .collect();
+        for name in finished {
+            let Some(mut run) = self.running.remove(&name) else {
+                continue;
+            };
+            let status = run.child.wait();
+            let stdout = run.stdout.join().unwrap_or_default();
+            let stderr = run.stderr.join().unwrap_or_default();
+            let elapsed = crate::ui::format_duration(run.started.elapsed());
+            match status {
+                Ok(status) if status.success() => self.note(&format!(
+                    "{} {} [{}]",
+                    self.msg.daemon_run_finished.replace("{}", &name),
+                    stdout,
+                    elapsed
+                )),
+                Ok(status) => self.note(&format!(
+                    "{} {} ({}) [{}]",
+                    self.msg.daemon_run_failed.replace("{}", &name),
+                    if stderr.is_empty() { stdout } else { stderr },
+                    status,
+                    elapsed
+                )),
+                Err(e) => self.note(&format!(
+                    "{} {}",
+                    self.msg.daemon_run_failed.replace("{}", &name),
+                    e
+                )),
+            }
+        }
+    }
+}
+
+/// Run the scheduled profiles of `config` until the process is stopped.
+///
+/// Only returns on errors preventing the daemon from starting.
+pub fn run_daemon(
+    msg: &Messages,
+    config: Config,
+    log_path: &Path,
+    max_bytes: u64,
+) -> io::Result<()> {
+    let reload = Arc::new(AtomicBool::new(false));
+    #[cfg(unix)]
+    signal_hook::flag::register(signal_hook::consts::SIGHUP, reload.clone())?;
+
+    let mut daemon = Daemon {
+        msg,
+        log: RotatingLog::new(log_path.to_path_buf(), max_bytes, LOG_KEEP),
+        timestamp_format: config.timestamp_format.clone(),
+        exe: std::env::current_exe()?,
+        jobs: Vec::new(),
+        running: HashMap::new(),
+    };
+    daemon.load(&config);
+    daemon.note(
+        &msg.daemon_started
+            .replace("{}", &daemon.jobs.len().to_string()),
+    );
+
+    loop {
+        if reload.swap(false, Ordering::Relaxed) {
+            match Config::load() {
+                Ok(config) => {
+                    daemon.load(&config);
+                    daemon.note(
+                        &msg.daemon_reloaded
+                            .replace("{}", &daemon.jobs.len().to_string()),
+                    );
+                }
+                // The error names the configuration file.
+                Err(e) => daemon.note(&e.to_string()),
+            }
+        }
+
+        daemon.reap();
+
+        let now = Local::now().naive_local();
+        for i in 0..daemon.jobs.len() {
+            if daemon.jobs[i].next.is_some_and(|next| next <= now) {
+                daemon.start(i);
+                let job = &mut daemon.jobs[i];
+                job.next = job.expr.next_after(now);
+            }
+        }
+
+        thread::sleep(TICK);
+    }
+}
@@ -0,0 +1,399 @@
+//! Multi-pane terminal dashboard shown during copies (`--progress dashboard`).
+//!
+//! `DashboardObserver` turns the progress events of a copy into updates for
+//! a UI thread that owns the terminal. The dashboard uses the alternate
+//! screen and raw mode, so it can react to keys while the copy runs:
+//!
+//! - `p` / space: pause or resume the workers (they finish their current file);
+//! - `s`: show or hide skipped files in the recent files pane;
+//! - up / down, page up / page down: scroll the error pane;
+//! - ctrl-c: restore the terminal and abort the run.
+
+use crate::progress::{ProgressEvent, ProgressObserver, SkipReason};
+use crate::ui::{
+    ProgressStats, UiThread, format_bytes, progress_bar, sparkline, truncate_to_display_width,
+};
+use crate::utils::Messages;
+use crossterm::{
+    cursor::{Hide, MoveTo, Show},
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    execute, queue,
+    style::Print,
+    terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use std::collections::VecDeque;
+use std::io::{Write, stdout};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+use unicode_width::UnicodeWidthStr;
+
+/// Minimum delay between two redraws.
+const FRAME: Duration = Duration::from_millis(100);
+/// Interval of the throughput samples drawn in the graph.
+const SAMPLE: Duration = Duration::from_millis(500);
+/// Number of recent file lines kept for the recent files pane.
+const RECENT_LINES: usize = 1_000;
+
+/// Update sent by `DashboardObserver` to the UI thread.
+enum Update {
+    Planned {
+        files: usize,
+        bytes: u64,
+    },
+    Started {
+        worker: usize,
+        path: PathBuf,
+    },
+    Done {
+        path: PathBuf,
+        bytes: u64,
+        outcome: Outcome,
+    },
+}
+
+/// Result of processing one file.
+enum Outcome {
+    Copied,
+    Skipped,
+    Failed(String),
+}
+
+/// Observer of the `dashboard` progress mode.
+///
+/// Workers wait in `FileStarted` while the dashboard is paused.
+pub(crate) struct DashboardObserver {
+    thread: UiThread<Update>,
+    paused: Arc<AtomicBool>,
+}
+
+impl DashboardObserver {
+    /// Show the dashboard, for a copy running on `workers` threads.
+    pub(crate) fn start(msg: &Messages, workers: usize, show_skipped: bool) -> Self {
+        let paused = Arc::new(AtomicBool::new(false));
+        let state = Dashboard::new(msg.clone(), workers, show_skipped, paused.clone());
+        Self {
+            thread: UiThread::spawn(move |rx| run(rx, state)),
+            paused,
+        }
+    }
+}
+
+impl ProgressObserver for DashboardObserver {
+    fn on_event(&self, event: &ProgressEvent<'_>) {
+        let done = |path: &Path, bytes, outcome| Update::Done {
+            path: path.to_path_buf(),
+            bytes,
+            outcome,
+        };
+        self.thread.send(match *event {
+            ProgressEvent::Planned { files, bytes, .. } => Update::Planned { files, bytes },
+            ProgressEvent::FileStarted { worker, source, .. } => {
+                while self.paused.load(Ordering::Relaxed) {
+                    thread::sleep(Duration::from_millis(100));
+                }
+                Update::Started {
+                    worker,
+                    path: source.to_path_buf(),
+                }
+            }
+            ProgressEvent::FileCopied { source, bytes, .. } => done(source, bytes, Outcome::Copied),
+            ProgressEvent::FileSkipped {
+                source,
+                reason: SkipReason::Unchanged,
+                ..
+            } => done(source, file_size(source), Outcome::Skipped),
+            ProgressEvent::FileFailed { source, error, .. } => done(
+                source,
+                file_size(source),
+                Outcome::Failed(error.to_string()),
+            ),
+            ProgressEvent::Finished { .. } => return self.thread.finish(),
+            // Per-file events carry everything the dashboard needs.
+            ProgressEvent::FileSkipped { .. } | ProgressEvent::BytesProgress { .. } => return,
+        });
+    }
+}
+
+/// Size of `path`, counted as processed bytes for the files not copied.
+fn file_size(path: &Path) -> u64 {
+    std::fs::metadata(path).map(|m| m.len()).unwrap_or(0)
+}
+
+/// State of the dashboard, updated from `Update`s and rendered to lines.
+struct Dashboard {
+    msg: Messages,
+    stats: ProgressStats,
+    copied: usize,
+    skipped: usize,
+    /// Current file of each worker thread.
+    workers: Vec<Option<PathBuf>>,
+    /// Recent files; the flag marks skipped ones.
+    recent: VecDeque<(bool, String)>,
+    errors: Vec<String>,
+    /// Number of lines the error pane is scrolled up from its end.
+    error_scroll: usize,
+    show_skipped: bool,
+    paused: Arc<AtomicBool>,
+    /// Bytes per second over each `SAMPLE` interval.
+    samples: Vec<u64>,
+    last_sample: (Instant, u64),
+}
+
+impl Dashboard {
+    fn new(msg: Messages, workers: usize, show_skipped: bool, paused: Arc<AtomicBool>) -> Self {
+        let stats = ProgressStats::new(0, 0);
+        let now = Instant::now();
+        Self {
+            msg,
+            stats,
+            copied: 0,
+            skipped: 0,
+            workers: vec![None; workers.max(1)],
+            recent: VecDeque::new(),
+            errors: Vec::new(),
+            error_scroll: 0,
+            show_skipped,
+            paused,
+            samples: Vec::new(),
+            last_sample: (now, 0),
+        }
+    }
+
+    fn apply(&mut self, update: Update) {
+        match update {
+            Update::Planned { files, bytes } => self.stats = ProgressStats::new(files, bytes),
+            Update::Started { worker, path } => {
+                if let Some(slot) = self.workers.get_mut(worker) {
+                    *slot = Some(path);
+                }
+            }
+            Update::Done {
+                path,
+                bytes,
+                outcome,
+            } => {
+                if let Some(slot) = self.workers.iter_mut().find(|w| w.as_ref() == Some(&path)) {
+                    *slot = None;
+                }
+                self.stats
+                    .record(self.stats.done_files + 1, self.stats.done_bytes + bytes);
+                let path = path.display();
+                match outcome {
+                    Outcome::Copied => {
+                        self.copied += 1;
+                        self.push_recent(false, format!("{} - {}", path, self.msg.copied_file));
+                    }
+                    Outcome::Skipped => {
+                        self.skipped += 1;
+                        self.push_recent(true, format!("{} - {}", path, self.msg.skipped_file));
+                    }
+                    Outcome::Failed(e) => {
+                        self.errors.push(e);
+                        // Keep the visible lines in place while scrolled up.
+                        if self.error_scroll > 0 {
+                            self.error_scroll += 1;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn push_recent(&mut self, skipped: bool, line: String) {
+        self.recent.push_back((skipped, line));
+        if self.recent.len() > RECENT_LINES {
+            self.recent.pop_front();
+        }
+    }
+
+    fn sample(&mut self) {
+        let elapsed = self.last_sample.0.elapsed();
+        if elapsed >= SAMPLE {
+            let bytes = self.stats.done_bytes - self.last_sample.1;
+            self.samples
+                .push((bytes as f64 / elapsed.as_secs_f64()) as u64);
+            self.last_sample = (Instant::now(), self.stats.done_bytes);
+        }
+    }
+
+    /// Handle a key press; returns false when the run must be aborted.
+    fn key(&mut self, code: KeyCode, modifiers: KeyModifiers) -> bool {
+        match code {
+            KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => return false,
+            KeyCode::Char('p') | KeyCode::Char(' ') => {
+                self.paused.fetch_xor(true, Ordering::Relaxed);
+            }
+            KeyCode::Char('s') => self.show_skipped = !self.show_skipped,
+            KeyCode::Up => self.error_scroll += 1,
+            KeyCode::Down => self.error_scroll = self.error_scroll.saturating_sub(1),
+            KeyCode::PageUp => self.error_scroll += 10,
+            KeyCode::PageDown => self.error_scroll = self.error_scroll.saturating_sub(10),
+            _ => {}
+        }
+        true
+    }
+
+    /// Render the dashboard as exactly `rows` lines of at most `cols - 1` columns.
+    fn render(&mut self, cols: u16, rows: u16) -> Vec<String> {
+        let width = (cols as usize).saturating_sub(1);
+        let rows = rows as usize;
+        let msg = &self.msg;
+        let stats = &self.stats;
+        let fraction = stats.fraction();
+
+        let mut lines = Vec::with_capacity(rows);
+        let paused = if self.paused.load(Ordering::Relaxed) {
+            format!("  [{}]", msg.dash_paused)
+        } else {
+            String::new()
+        };
+        lines.push(format!("{}{}", msg.dash_title, paused));
+        lines.push(format!(
+            "{} {} {:.0}%  {}/{}",
+            msg.copy_progress,
+            progress_bar(fraction, width.saturating_sub(40).clamp(10, 50)),
+            fraction * 100.0,
+            format_bytes(stats.done_bytes),
+            format_bytes(stats.total_bytes)
+        ));
+        lines.push(format!(
+            "{} {}/{}  {}  {}  {}",
+            msg.files_progress,
+            stats.done_files,
+            stats.total_files,
+            msg.files_copied.replace("{}", &self.copied.to_string()),
+            msg.dash_skipped.replace("{}", &self.skipped.to_string()),
+            msg.dash_errors
+                .replace("{}", &self.errors.len().to_string())
+        ));
+        lines.push(stats.rates_line(msg));
+        lines.push(sparkline(&self.samples, width));
+
+        // Split the remaining rows between workers, recent files and errors,
+        // keeping one header per pane and the key help on the last row.
+        let rest = rows.saturating_sub(lines.len() + 4);
+        let worker_rows = self.workers.len().min(rest / 3);
+        let error_rows = (rest - worker_rows) / 3;
+        let recent_rows = rest - worker_rows - error_rows;
+
+        lines.push(format!("-- {} --", msg.dash_workers));
+        for (i, current) in self.workers.iter().take(worker_rows).enumerate() {
+            match current {
+                Some(path) => lines.push(format!("{:>3} {}", i + 1, path.display())),
+                None => lines.push(format!("{:>3} {}", i + 1, msg.dash_idle)),
+            }
+        }
+
+        lines.push(format!("-- {} --", msg.dash_recent));
+        let show_skipped = self.show_skipped;
+        let mut recent: Vec<&String> = self
+            .recent
+            .iter()
+            .rev()
+            .filter(|(skipped, _)| show_skipped || !skipped)
+            .take(recent_rows)
+            .map(|(_, line)| line)
+            .collect();
+        recent.reverse();
+        lines.extend(recent.into_iter().cloned());
+        while lines.len() < rows.saturating_sub(error_rows + 2) {
+            lines.push(String::new());
+        }
+
+        self.error_scroll = self
+            .error_scroll
+            .min(self.errors.len().saturating_sub(error_rows));
+        lines.push(format!(
+            "-- {} --",
+            msg.dash_errors
+                .replace("{}", &self.errors.len().to_string())
+        ));
+        let end = self.errors.len() - self.error_scroll;
+        let start = end.saturating_sub(error_rows);
+        lines.extend(self.errors[start..end].iter().cloned());
+        while lines.len() < rows.saturating_sub(1) {
+            lines.push(String::new());
+        }
+        lines.push(msg.dash_keys.clone());
+
+        lines.truncate(rows);
+        lines
+            .into_iter()
+            .map(|line| {
+                let mut line = truncate_to_display_width(&line, width);
+                let pad = width.saturating_sub(UnicodeWidthStr::width(line.as_str()));
+                line.push_str(&" ".repeat(pad));
+                line
+            })
+            .collect()
+    }
+
+    fn draw(&mut self) {
+        let (cols, rows) = terminal::size().unwrap_or((80, 24));
+        let mut out = stdout();
+        for (i, line) in self.render(cols, rows).into_iter().enumerate() {
+            let _ = queue!(out, MoveTo(0, i as u16), Print(line));
+        }
+        let _ = out.flush();
+    }
+}
+
+/// Restores the terminal when the dashboard ends, even on panic.
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn enter() -> Self {
+        let _ = terminal::enable_raw_mode();
+        let _ = execute!(stdout(), EnterAlternateScreen, Hide, Clear(ClearType::All));
+        TerminalGuard
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = execute!(stdout(), Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Run the dashboard until the update channel is closed, i.e. until every
+/// file has been processed.
+fn run(rx: Receiver<Update>, mut state: Dashboard) {
+    let guard = TerminalGuard::enter();
+    let mut last_draw = Instant::now() - FRAME;
+
+    loop {
+        match rx.recv_timeout(FRAME) {
+            Ok(update) => {
+                state.apply(update);
+                while let Ok(update) = rx.try_recv() {
+                    state.apply(update);
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        while event::poll(Duration::ZERO).unwrap_or(false) {
+            if let Ok(Event::Key(key)) = event::read()
+                && key.kind == KeyEventKind::Press
+                && !state.key(key.code, key.modifiers)
+            {
+                drop(guard);
+                std::process::exit(130);
+            }
+        }
+
+        state.sample();
+        if last_draw.elapsed() >= FRAME {
+            state.draw();
+            last_draw = Instant::now();
+        }
+    }
+
+    state.draw();
+}
@@ -0,0 +1,226 @@
+//! Filesystems `copy` writes to.
+//!
+//! A destination is a local directory or a remote location given as a URL
+//! (`sftp://[user@]host[:port]/path`, `s3://bucket/prefix`,
+//! `webdav[s]://[user@]host[:port]/path`). Change detection and the copy of
+//! plain and compressed files go through the `Destination` trait: `stat`,
+//! `mkdir` and writing to a temporary file renamed over the final name once
+//! complete, so that an interrupted upload never leaves a truncated file
+//! behind.
+//!
+//! Encryption, manifests and repositories keep files of their own in the
+//! destination and are only available on local destinations; remote
+//! destinations are not locked either.
+
+use crate::config::Config;
+use crate::s3::{S3Destination, S3Url};
+use crate::sftp::{SftpDestination, SftpUrl};
+use crate::webdav::{WebDavDestination, WebDavUrl};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// What `Destination::stat` reports about a path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DestMetadata {
+    pub len: u64,
+    pub is_dir: bool,
+    /// Modification time, if the destination records one.
+    pub modified: Option<SystemTime>,
+}
+
+/// A file being written to a destination.
+///
+/// Data goes to a temporary file that replaces the final path on `commit`;
+/// dropping it without committing discards the data.
+pub trait DestFile: Write {
+    fn commit(self: Box<Self>) -> io::Result<()>;
+}
+
+/// A filesystem files are backed up to.
+///
+/// Paths are absolute paths on the destination side, e.g. the path part of
+/// an `sftp://` URL joined with the path of the file below the source.
+pub trait Destination: fmt::Debug + Send + Sync {
+    /// Metadata of `path`, or `None` if it does not exist.
+    fn stat(&self, path: &Path) -> io::Result<Option<DestMetadata>>;
+
+    /// Create `path` and its missing parents.
+    fn create_dir_all(&self, path: &Path) -> io::Result<()>;
+
+    /// Start writing `path`; its parent directory must exist. `source` is
+    /// the local file the data comes from, for backends that record its
+    /// attributes.
+    fn create(&self, path: &Path, source: &Path) -> io::Result<Box<dyn DestFile + '_>>;
+
+    /// Whether `src` must be copied to `dest`: `dest` is missing or older.
+    ///
+    /// Times are compared in whole seconds, the resolution of most remote
+    /// protocols; the copy itself gets the upload time, which is never older
+    /// than the source.
+    fn is_outdated(&self, src: &Path, dest: &Path) -> io::Result<bool> {
+        let src_time = fs::metadata(src)?.modified()?;
+        let Some(meta) = self.stat(dest)? else {
+            return Ok(true);
+        };
+        let secs = |t: SystemTime| {
+            t.duration_since(SystemTime::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0)
+        };
+        Ok(meta.modified.is_none_or(|t| secs(src_time) > secs(t)))
+    }
+}
+
+/// The local filesystem.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LocalDestination;
+
+impl Destination for LocalDestination {
+    fn stat(&self, path: &Path) -> io::Result<Option<DestMetadata>> {
+        match fs::metadata(path) {
+            Ok(meta) => Ok(Some(DestMetadata {
+                len: meta.len(),
+                is_dir: meta.is_dir(),
+                modified: meta.modified().ok(),
+            })),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir_all(path)
+    }
+
+    fn create(&self, path: &Path, _source: &Path) -> io::Result<Box<dyn DestFile + '_>> {
+        let tmp = temp_path(path);
+        let file = File::create(&tmp)?;
+        Ok(Box::new(LocalFile {
+            file,
+            tmp,
+            path: path.to_path_buf(),
+            committed: false,
+        }))
+    }
+
+    /// Local copies keep the full timestamp precision (`utils::is_newer`).
+    fn is_outdated(&self, src: &Path, dest: &Path) -> io::Result<bool> {
+        crate::utils::is_newer(src, dest)
+    }
+}
+
+struct LocalFile {
+    file: File,
+    tmp: PathBuf,
+    path: PathBuf,
+    committed: bool,
+}
+
+impl Write for LocalFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl DestFile for LocalFile {
+    fn commit(mut self: Box<Self>) -> io::Result<()> {
+        self.file.sync_all()?;
+        fs::rename(&self.tmp, &self.path)?;
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Drop for LocalFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_file(&self.tmp);
+        }
+    }
+}
+
+/// Suffix of the temporary files written before being renamed into place.
+pub const TEMP_SUFFIX: &str = ".rbackup-tmp";
+
+/// Temporary name a file is written under before being renamed to `path`.
+pub fn temp_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}{}", name, TEMP_SUFFIX))
+}
+
+/// A destination given on the command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Location {
+    Local(PathBuf),
+    Sftp(SftpUrl),
+    S3(S3Url),
+    WebDav(WebDavUrl),
+}
+
+impl Location {
+    /// Parse a destination argument: a URL with a supported scheme, or a
+    /// local path.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rbackup::destination::Location;
+    /// use std::path::Path;
+    ///
+    /// let remote = Location::parse(Path::new("sftp://me@nas:2222/srv/backup")).unwrap();
+    /// assert_eq!(remote.root(), Path::new("/srv/backup"));
+    /// assert!(!remote.is_local());
+    /// assert!(Location::parse(Path::new("/mnt/usb")).unwrap().is_local());
+    /// ```
+    pub fn parse(arg: &Path) -> io::Result<Location> {
+        let Some(text) = arg.to_str() else {
+            return Ok(Location::Local(arg.to_path_buf()));
+        };
+        match text.split_once("://") {
+            Some(("sftp", rest)) => Ok(Location::Sftp(SftpUrl::parse(rest)?)),
+            Some(("s3", rest)) => Ok(Location::S3(S3Url::parse(rest)?)),
+            Some(("webdav", rest)) => Ok(Location::WebDav(WebDavUrl::parse(rest, false)?)),
+            Some(("webdavs", rest)) => Ok(Location::WebDav(WebDavUrl::parse(rest, true)?)),
+            Some((scheme, _)) if !scheme.is_empty() && !scheme.contains(['/', '\\']) => {
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{}: unsupported destination scheme '{}'", text, scheme),
+                ))
+            }
+            _ => Ok(Location::Local(arg.to_path_buf())),
+        }
+    }
+
+    pub fn is_local(&self) -> bool {
+        matches!(self, Location::Local(_))
+    }
+
+    /// Directory the backup goes to, on the filesystem of the destination.
+    pub fn root(&self) -> PathBuf {
+        match self {
+            Location::Local(path) => path.clone(),
+            Location::Sftp(url) => url.path.clone(),
+            Location::S3(url) => url.prefix.clone(),
+            Location::WebDav(url) => url.path.clone(),
+        }
+    }
+
+    /// Connect to a remote destination, with the credentials of `config`
+    /// where needed; `None` for local ones.
+    pub fn connect(&self, config: &Config) -> io::Result<Option<Arc<dyn Destination>>> {
+        match self {
+            Location::Local(_) => Ok(None),
+            Location::Sftp(url) => Ok(Some(Arc::new(SftpDestination::connect(url)?))),
+            Location::S3(url) => Ok(Some(Arc::new(S3Destination::connect(url)?))),
+            Location::WebDav(url) => Ok(Some(Arc::new(WebDavDestination::connect(url, config)?))),
+        }
+    }
+}
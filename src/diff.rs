@@ -0,0 +1,193 @@
+//! Preview of the differences between a source and its backup.
+//!
+//! `diff` runs only the planning phase of `copy_incremental` and classifies
+//! every path as new, modified, deleted (present only at the destination) or
+//! excluded, without copying anything and without the full-screen UI.
+
+use crate::check::locate;
+use crate::compress::{COMPRESSED_EXTENSION, is_compressed_file};
+use crate::destination::TEMP_SUFFIX;
+use crate::manifest::{METADATA_PREFIX, manifest_key};
+use crate::output::LogContext;
+use crate::ui::format_bytes;
+use crate::utils::{Messages, is_newer, plan_copy, scan_source};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+use walkdir::WalkDir;
+
+/// A path listed by `diff`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DiffEntry {
+    /// Path relative to the source, `/`-separated.
+    pub path: String,
+    /// Size in bytes (of the source file, or of the backed-up file when deleted).
+    pub size: u64,
+}
+
+/// Paths in one category of a `DiffReport`, with their totals.
+#[derive(Debug, Default, Serialize)]
+pub struct DiffGroup {
+    pub count: usize,
+    pub bytes: u64,
+    pub entries: Vec<DiffEntry>,
+}
+
+impl DiffGroup {
+    fn push(&mut self, path: String, size: u64) {
+        self.count += 1;
+        self.bytes += size;
+        self.entries.push(DiffEntry { path, size });
+    }
+}
+
+/// Differences between a source directory and its backup.
+#[derive(Debug, Default, Serialize)]
+pub struct DiffReport {
+    /// Files missing from the backup.
+    pub new: DiffGroup,
+    /// Files changed since they were backed up.
+    pub modified: DiffGroup,
+    /// Files present only in the backup.
+    pub deleted: DiffGroup,
+    /// Source files matching an exclude pattern.
+    pub excluded: DiffGroup,
+}
+
+/// Compare `src_dir` with the backup in `dest_dir`.
+///
+/// Uses the same planning and change detection as `copy_incremental` with
+/// the same `options` (excludes, compression, encryption, repository).
+pub fn diff_tree(src_dir: &Path, dest_dir: &Path, options: &LogContext) -> io::Result<DiffReport> {
+    if !src_dir.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} is not a directory", src_dir.display()),
+        ));
+    }
+
+    // Unreadable directories are left out, as from a copy.
+    let (entries, _) = scan_source(src_dir, None);
+    let plan = plan_copy(src_dir, dest_dir, &entries, options, false, None, None);
+    let mut report = DiffReport::default();
+
+    for op in &plan.ops {
+        let size = fs::metadata(&op.src_path).map(|m| m.len()).unwrap_or(0);
+        let key = manifest_key(&op.rel_path);
+        let stored = match &options.repository {
+            Some(_) => None,
+            None => locate(dest_dir, &key, options).map(|(path, _)| path),
+        };
+        let recorded = plan.previous.as_ref().and_then(|m| m.get(&op.rel_path));
+        let is_new = match &options.repository {
+            Some(_) => recorded.is_none(),
+            None => stored.is_none(),
+        };
+        if is_new {
+            report.new.push(key, size);
+            continue;
+        }
+        let modified = match (op.changed, &stored) {
+            (Some(changed), _) => changed,
+            (None, Some(dest)) => is_newer(&op.src_path, dest).unwrap_or(true),
+            (None, None) => true,
+        };
+        if modified {
+            report.modified.push(key, size);
+        }
+    }
+
+    for rel_path in &plan.excluded {
+        let size = fs::metadata(src_dir.join(rel_path))
+            .map(|m| m.len())
+            .unwrap_or(0);
+        report.excluded.push(manifest_key(rel_path), size);
+    }
+
+    let source_keys: HashSet<String> = plan
+        .ops
+        .iter()
+        .map(|op| &op.rel_path)
+        .chain(plan.excluded.iter())
+        .map(|p| manifest_key(p))
+        .collect();
+
+    if options.repository.is_some() || options.encryption.is_some() {
+        // Stored names say nothing about the source: use the previous run.
+        if let Some(previous) = &plan.previous {
+            for (key, entry) in &previous.entries {
+                if !source_keys.contains(key) {
+                    report.deleted.push(key.clone(), entry.size);
+                }
+            }
+        }
+    } else {
+        let mut deleted: Vec<(String, u64)> = WalkDir::new(dest_dir)
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|e| e.file_type().is_file())
+            .filter(|e| {
+                let name = e.file_name().to_string_lossy();
+                !name.starts_with(METADATA_PREFIX) && !name.ends_with(TEMP_SUFFIX)
+            })
+            .filter_map(|e| {
+                let rel = e.path().strip_prefix(dest_dir).ok()?;
+                let key = manifest_key(rel);
+                let original = key
+                    .strip_suffix(&format!(".{}", COMPRESSED_EXTENSION))
+                    .filter(|k| source_keys.contains(*k))
+                    .filter(|_| is_compressed_file(e.path()).unwrap_or(false));
+                if source_keys.contains(&key) || original.is_some() {
+                    return None;
+                }
+                Some((key, e.metadata().map(|m| m.len()).unwrap_or(0)))
+            })
+            .collect();
+        deleted.sort();
+        for (key, size) in deleted {
+            report.deleted.push(key, size);
+        }
+    }
+
+    Ok(report)
+}
+
+/// Render `report` as plain text, one path per line followed by a summary.
+pub fn format_plain(report: &DiffReport, msg: &Messages) -> String {
+    let mut out = String::new();
+    let groups = [
+        ("+", &msg.diff_new, &report.new),
+        ("~", &msg.diff_modified, &report.modified),
+        ("-", &msg.diff_deleted, &report.deleted),
+        ("x", &msg.diff_excluded, &report.excluded),
+    ];
+    for (mark, _, group) in &groups {
+        for entry in &group.entries {
+            out.push_str(&format!(
+                "{} {} ({})\n",
+                mark,
+                entry.path,
+                format_bytes(entry.size)
+            ));
+        }
+    }
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    for (_, label, group) in &groups {
+        out.push_str(&format!(
+            "{}: {} ({})\n",
+            label,
+            group.count,
+            format_bytes(group.bytes)
+        ));
+    }
+    out
+}
+
+/// Render `report` as pretty-printed JSON.
+pub fn format_json(report: &DiffReport) -> io::Result<String> {
+    serde_json::to_string_pretty(report).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
@@ -0,0 +1,144 @@
+//! Error type of the library API.
+//!
+//! `Error` tells what failed (the configuration, an exclude pattern, walking
+//! a source, copying a file, ...) and the path it failed on. Errors of single
+//! files do not stop a copy: they are collected into its counts (see
+//! `utils::CopyCounts`) and into the `job::BackupSummary`. `Error` converts
+//! into `io::Error`, keeping its kind and message, for the code built on
+//! plain I/O results.
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Result of the library API.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// What failed, and where.
+#[derive(Debug)]
+pub enum Error {
+    /// The configuration is invalid; `path` is the configuration file, when
+    /// the setting came from one.
+    Config {
+        path: Option<PathBuf>,
+        source: io::Error,
+    },
+    /// An exclude pattern cannot be parsed.
+    Pattern {
+        pattern: String,
+        source: globset::Error,
+    },
+    /// A directory of a source cannot be listed.
+    Walk { path: PathBuf, source: io::Error },
+    /// A file cannot be copied to `destination`.
+    Copy {
+        path: PathBuf,
+        destination: PathBuf,
+        source: io::Error,
+    },
+    /// The metadata or contents of a source file cannot be read while
+    /// planning (e.g. to record it in the manifest).
+    Metadata { path: PathBuf, source: io::Error },
+    /// The lock file at `path` cannot be acquired.
+    Lock { path: PathBuf, source: io::Error },
+    /// A destination cannot be opened or finalized (e.g. its manifest
+    /// cannot be written).
+    Destination { path: PathBuf, source: io::Error },
+    /// The local state index at `path` cannot be written.
+    State { path: PathBuf, source: io::Error },
+    /// The hook `hook` (`pre_hook`, `post_hook`) failed and the run with it.
+    Hook { hook: String, source: io::Error },
+    /// The sources of a `watch` run can no longer be watched.
+    Watch { source: io::Error },
+}
+
+impl Error {
+    /// The path the error is about: the configuration file, the source file
+    /// or directory, the lock file, the destination or the state index.
+    /// Patterns, hooks and watchers have none.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Error::Config { path, .. } => path.as_deref(),
+            Error::Pattern { .. } | Error::Hook { .. } | Error::Watch { .. } => None,
+            Error::Walk { path, .. }
+            | Error::Copy { path, .. }
+            | Error::Metadata { path, .. }
+            | Error::Lock { path, .. }
+            | Error::Destination { path, .. }
+            | Error::State { path, .. } => Some(path),
+        }
+    }
+
+    /// The kind of the underlying I/O error; `InvalidInput` for patterns.
+    pub fn kind(&self) -> io::ErrorKind {
+        match self {
+            Error::Pattern { .. } => io::ErrorKind::InvalidInput,
+            Error::Config { source, .. }
+            | Error::Walk { source, .. }
+            | Error::Copy { source, .. }
+            | Error::Metadata { source, .. }
+            | Error::Lock { source, .. }
+            | Error::Destination { source, .. }
+            | Error::State { source, .. }
+            | Error::Hook { source, .. }
+            | Error::Watch { source } => source.kind(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Config { path: None, source } => write!(f, "{}", source),
+            Error::Config {
+                path: Some(path),
+                source,
+            } => write!(f, "{}: {}", path.display(), source),
+            // The message of globset names the pattern, the one of a hook
+            // names the hook.
+            Error::Pattern { source, .. } => write!(f, "{}", source),
+            Error::Hook { source, .. } | Error::Watch { source } => write!(f, "{}", source),
+            Error::Copy {
+                path,
+                destination,
+                source,
+            } => write!(
+                f,
+                "{} -> {}: {}",
+                path.display(),
+                destination.display(),
+                source
+            ),
+            Error::Walk { path, source }
+            | Error::Metadata { path, source }
+            | Error::Lock { path, source }
+            | Error::Destination { path, source }
+            | Error::State { path, source } => {
+                write!(f, "{}: {}", path.display(), source)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Pattern { source, .. } => Some(source),
+            Error::Config { source, .. }
+            | Error::Walk { source, .. }
+            | Error::Copy { source, .. }
+            | Error::Metadata { source, .. }
+            | Error::Lock { source, .. }
+            | Error::Destination { source, .. }
+            | Error::State { source, .. }
+            | Error::Hook { source, .. }
+            | Error::Watch { source } => Some(source),
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        io::Error::new(e.kind(), e.to_string())
+    }
+}
@@ -0,0 +1,231 @@
+//! Commands run around a `copy` (`pre_hook`, `post_hook`, `on_error`).
+//!
+//! Hooks are shell commands (`sh -c` on Unix, `cmd /C` on Windows) set in the
+//! configuration file, globally and per profile; the settings of the profile
+//! a run belongs to (`copy --profile`) take precedence. They receive the run
+//! through `RBACKUP_*` environment variables, the results only for
+//! `post_hook` and `on_error`. A failing hook (non-zero exit status) stops
+//! the run or is only reported, depending on `hook_failure`.
+
+use crate::output::{LogContext, log_output};
+use crate::utils::Messages;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// What a failing `pre_hook` or `post_hook` does to the run.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HookFailure {
+    /// The run fails (a failing `pre_hook` also prevents the copy).
+    #[default]
+    Fatal,
+    /// A warning is printed and the run goes on.
+    Warn,
+}
+
+/// Hook settings of the configuration file or of a profile.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hooks {
+    /// Run before the copy, e.g. to dump a database.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_hook: Option<String>,
+    /// Run after the copy, whether it succeeded or not.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_hook: Option<String>,
+    /// Run when the run fails, before `post_hook`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_error: Option<String>,
+    /// Whether a failing `pre_hook` or `post_hook` fails the run (default: fatal).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hook_failure: Option<HookFailure>,
+}
+
+impl Hooks {
+    /// These settings overridden by those set in `other` (a profile).
+    pub fn merged(&self, other: &Hooks) -> Hooks {
+        Hooks {
+            pre_hook: other.pre_hook.clone().or_else(|| self.pre_hook.clone()),
+            post_hook: other.post_hook.clone().or_else(|| self.post_hook.clone()),
+            on_error: other.on_error.clone().or_else(|| self.on_error.clone()),
+            hook_failure: other.hook_failure.or(self.hook_failure),
+        }
+    }
+
+    pub fn failure(&self) -> HookFailure {
+        self.hook_failure.unwrap_or_default()
+    }
+}
+
+/// Outcome of a run, passed to `post_hook` and `on_error`.
+#[derive(Debug, Default, Clone)]
+pub struct RunResult {
+    pub copied: usize,
+    pub skipped: usize,
+    pub errors: usize,
+    /// Exit status of `rbackup` for this run (0 on success).
+    pub exit_status: i32,
+    /// Log file of the run (`--log`), if any.
+    pub report: Option<PathBuf>,
+    /// Error that made the run fail, if any.
+    pub error: Option<String>,
+}
+
+/// Environment of a hook: the run and, once known, its result.
+///
+/// `hook` is `pre_hook`, `post_hook` or `on_error`. Several sources (or
+/// destinations) are joined like `PATH` (`:` on Unix, `;` on Windows) in
+/// `RBACKUP_SOURCE` (or `RBACKUP_DESTINATION`).
+pub fn hook_env(
+    hook: &str,
+    sources: &[PathBuf],
+    destinations: &[PathBuf],
+    profile: Option<&str>,
+    result: Option<&RunResult>,
+) -> Vec<(String, String)> {
+    let mut env = vec![
+        ("RBACKUP_HOOK", hook.to_string()),
+        ("RBACKUP_SOURCE", join_paths(sources)),
+        ("RBACKUP_DESTINATION", join_paths(destinations)),
+        ("RBACKUP_PROFILE", profile.unwrap_or_default().to_string()),
+    ];
+    if let Some(r) = result {
+        env.extend([
+            ("RBACKUP_COPIED", r.copied.to_string()),
+            ("RBACKUP_SKIPPED", r.skipped.to_string()),
+            ("RBACKUP_ERRORS", r.errors.to_string()),
+            ("RBACKUP_EXIT_STATUS", r.exit_status.to_string()),
+            (
+                "RBACKUP_REPORT",
+                r.report
+                    .as_ref()
+                    .map(|p| p.display().to_string())
+                    .unwrap_or_default(),
+            ),
+            ("RBACKUP_ERROR", r.error.clone().unwrap_or_default()),
+        ]);
+    }
+    env.into_iter().map(|(k, v)| (k.to_string(), v)).collect()
+}
+
+fn join_paths(paths: &[PathBuf]) -> String {
+    std::env::join_paths(paths)
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Run `command` through the shell with the extra environment `env`.
+///
+/// The hook shares the standard streams of `rbackup`. Returns an error when
+/// it cannot be started or exits with a non-zero status.
+pub fn run_hook(command: &str, env: &[(String, String)]) -> io::Result<()> {
+    let mut cmd = if cfg!(windows) {
+        let mut c = Command::new("cmd");
+        c.arg("/C").arg(command);
+        c
+    } else {
+        let mut c = Command::new("sh");
+        c.arg("-c").arg(command);
+        c
+    };
+    let status = cmd.envs(env.iter().map(|(k, v)| (k, v))).status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(status.to_string()))
+    }
+}
+
+/// The hooks of one `copy` run.
+pub struct HookRunner<'a> {
+    hooks: Hooks,
+    msg: &'a Messages,
+    sources: &'a [PathBuf],
+    destinations: &'a [PathBuf],
+    profile: Option<&'a str>,
+}
+
+impl<'a> HookRunner<'a> {
+    pub fn new(
+        hooks: Hooks,
+        msg: &'a Messages,
+        sources: &'a [PathBuf],
+        destinations: &'a [PathBuf],
+        profile: Option<&'a str>,
+    ) -> Self {
+        Self {
+            hooks,
+            msg,
+            sources,
+            destinations,
+            profile,
+        }
+    }
+
+    /// Run `command` as the hook `name`, reporting a failure. The error is
+    /// returned when the failure is fatal.
+    fn run(
+        &self,
+        name: &str,
+        command: Option<&str>,
+        result: Option<&RunResult>,
+        failure: HookFailure,
+        ctx: &LogContext,
+    ) -> io::Result<()> {
+        let Some(command) = command else {
+            return Ok(());
+        };
+        log_output(
+            &format!("{} {}", self.msg.hook_running.replace("{}", name), command),
+            ctx,
+        );
+        let env = hook_env(name, self.sources, self.destinations, self.profile, result);
+        let Err(e) = run_hook(command, &env) else {
+            return Ok(());
+        };
+        let error = format!("{} {}", self.msg.hook_failed.replace("{}", name), e);
+        match failure {
+            HookFailure::Fatal => {
+                log_output(&format!("\u{274C} {}", error), ctx);
+                Err(io::Error::other(error))
+            }
+            HookFailure::Warn => {
+                log_output(&format!("\u{26A0}\u{FE0F} {}", error), ctx);
+                Ok(())
+            }
+        }
+    }
+
+    /// Run `pre_hook`. An error means the copy must not start.
+    pub fn pre(&self, ctx: &LogContext) -> io::Result<()> {
+        self.run(
+            "pre_hook",
+            self.hooks.pre_hook.as_deref(),
+            None,
+            self.hooks.failure(),
+            ctx,
+        )
+    }
+
+    /// Run `on_error` if the run failed, then `post_hook`. A failing
+    /// `on_error` is only reported; an error means `post_hook` failed fatally.
+    pub fn finish(&self, result: &RunResult, ctx: &LogContext) -> io::Result<()> {
+        if result.exit_status != 0 {
+            self.run(
+                "on_error",
+                self.hooks.on_error.as_deref(),
+                Some(result),
+                HookFailure::Warn,
+                ctx,
+            )?;
+        }
+        self.run(
+            "post_hook",
+            self.hooks.post_hook.as_deref(),
+            Some(result),
+            self.hooks.failure(),
+            ctx,
+        )
+    }
+}
@@ -0,0 +1,791 @@
+//! Library entry point: a reusable backup job.
+//!
+//! A `BackupJob` describes what is copied where (sources, destinations,
+//! excludes, comparison, dry run, worker threads) through builder methods.
+//! `run` copies it and returns a `BackupSummary`; progress is reported to an
+//! optional `ProgressObserver`. A job writes nothing to the terminal unless
+//! given an `Output`. The `copy` command is a job too, with its hooks, locks
+//! waited for, encryption, repositories, watch mode and terminal output set
+//! from the command line.
+//!
+//! ```rust,no_run
+//! use rbackup::job::{BackupJob, Compare};
+//! use rbackup::progress::ProgressEvent;
+//!
+//! let summary = BackupJob::new("/home/me/Documents", "/mnt/backup")
+//!     .exclude("*.tmp")
+//!     .compare(Compare::Delta)
+//!     .observer(|event: &ProgressEvent| {
+//!         if let ProgressEvent::FileFailed { source, error, .. } = event {
+//!             eprintln!("{}: {}", source.display(), error);
+//!         }
+//!     })
+//!     .run()
+//!     .unwrap();
+//! println!("{} copied, {} skipped", summary.copied, summary.skipped);
+//! ```
+
+use crate::compress::Compression;
+use crate::config::Config;
+use crate::copy::{SourceNaming, execute_copy, source_destinations, start_copy_message};
+use crate::crypto::{Encryption, KeySource};
+use crate::destination::Location;
+use crate::error::{Error, Result};
+use crate::hooks::{HookRunner, Hooks, RunResult};
+use crate::lock::{Lock, TryLock};
+use crate::output::{LogContext, ProgressMode, ShowSkipped, log_output};
+use crate::progress::ProgressObserver;
+use crate::repository::{Chunking, Repository};
+use crate::utils::{
+    CopyCounts, CopyTarget, Logger, Messages, build_exclude_matcher, copy_targets,
+    load_translations,
+};
+use crate::watch::{WatchOptions, execute_watch};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Number of worker threads of a job, as in the default configuration.
+pub const DEFAULT_JOBS: usize = 4;
+
+/// How often a job waiting for a lock (`BackupJob::wait`) checks it again.
+const LOCK_POLL: Duration = Duration::from_secs(1);
+
+/// How source files are compared with their copy in the destination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compare {
+    /// Every file is planned, and copied when it is newer than its copy;
+    /// the others are reported as skipped.
+    #[default]
+    Newer,
+    /// Only the files newer than their copy are planned (`--delta`);
+    /// unchanged files are counted as skipped without being reported.
+    Delta,
+}
+
+/// Terminal and file output of a job, as written by the `copy` command.
+#[derive(Debug, Clone, Default)]
+pub struct Output {
+    /// Print nothing but the errors of the destinations, and no progress.
+    pub quiet: bool,
+    /// Prefix the lines with the time, in this `strftime` format.
+    pub timestamp: Option<String>,
+    /// File the lines are written to as well, with a line per processed file.
+    pub logger: Option<Logger>,
+    /// How progress is displayed while copying.
+    pub progress: ProgressMode,
+    /// Which processed files the progress display and the log list.
+    pub show_skipped: ShowSkipped,
+}
+
+/// A copy of one or more sources to one or more destinations.
+#[derive(Debug, Clone)]
+pub struct BackupJob {
+    sources: Vec<PathBuf>,
+    destinations: Vec<PathBuf>,
+    exclude: Vec<String>,
+    ignore_case: bool,
+    absolute_exclude: bool,
+    compare: Compare,
+    dry_run: bool,
+    jobs: usize,
+    source_naming: Option<SourceNaming>,
+    manifest: bool,
+    compression: Option<Compression>,
+    state_dir: Option<PathBuf>,
+    rescan: bool,
+    observer: Option<Arc<dyn ProgressObserver>>,
+    output: Option<Output>,
+    messages: Option<Messages>,
+    config: Config,
+    hooks: Hooks,
+    profile: Option<String>,
+    report: Option<PathBuf>,
+    encryption: Option<(KeySource, bool)>,
+    repository: bool,
+    chunking: bool,
+    wait: bool,
+    watch: Option<WatchOptions>,
+}
+
+impl BackupJob {
+    /// A job copying `source` into `destination`.
+    pub fn new(source: impl Into<PathBuf>, destination: impl Into<PathBuf>) -> Self {
+        Self {
+            sources: vec![source.into()],
+            destinations: vec![destination.into()],
+            exclude: Vec::new(),
+            ignore_case: false,
+            absolute_exclude: false,
+            compare: Compare::default(),
+            dry_run: false,
+            jobs: DEFAULT_JOBS,
+            source_naming: None,
+            manifest: false,
+            compression: None,
+            state_dir: None,
+            rescan: false,
+            observer: None,
+            output: None,
+            messages: None,
+            config: Config::default(),
+            hooks: Hooks::default(),
+            profile: None,
+            report: None,
+            encryption: None,
+            repository: false,
+            chunking: false,
+            wait: false,
+            watch: None,
+        }
+    }
+
+    /// Copy `source` as well. Several sources get a subdirectory each in
+    /// the destinations, see `source_naming`.
+    pub fn source(mut self, source: impl Into<PathBuf>) -> Self {
+        self.sources.push(source.into());
+        self
+    }
+
+    /// Copy to `destination` as well (`--also-to`); the sources are scanned
+    /// once for all the destinations.
+    pub fn also_to(mut self, destination: impl Into<PathBuf>) -> Self {
+        self.destinations.push(destination.into());
+        self
+    }
+
+    /// Skip the files matching the glob `pattern`.
+    pub fn exclude(mut self, pattern: impl Into<String>) -> Self {
+        self.exclude.push(pattern.into());
+        self
+    }
+
+    /// Match the exclude patterns case-insensitively.
+    pub fn ignore_case(mut self, ignore_case: bool) -> Self {
+        self.ignore_case = ignore_case;
+        self
+    }
+
+    /// Match the exclude patterns against absolute source paths instead of
+    /// paths relative to the source.
+    pub fn absolute_exclude(mut self, absolute: bool) -> Self {
+        self.absolute_exclude = absolute;
+        self
+    }
+
+    /// Whether unchanged files are planned and reported (`Compare::Newer`,
+    /// the default) or only the newer ones (`Compare::Delta`).
+    pub fn compare(mut self, compare: Compare) -> Self {
+        self.compare = compare;
+        self
+    }
+
+    /// Report what would be copied without writing anything.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Number of worker threads; must be greater than zero.
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs;
+        self
+    }
+
+    /// How the subdirectories of several sources are named.
+    pub fn source_naming(mut self, naming: SourceNaming) -> Self {
+        self.source_naming = Some(naming);
+        self
+    }
+
+    /// Write a manifest of the source files to each destination.
+    pub fn manifest(mut self, manifest: bool) -> Self {
+        self.manifest = manifest;
+        self
+    }
+
+    /// Compress the files written to the destinations.
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    /// Keep the state of the runs in `state_dir` (see `state::StateIndex`,
+    /// and `state::default_dir` for the directory used by `copy --state`):
+    /// files unchanged since the last successful run are not compared with
+    /// the destinations again.
+    pub fn state(mut self, state_dir: impl Into<PathBuf>) -> Self {
+        self.state_dir = Some(state_dir.into());
+        self
+    }
+
+    /// Compare every file with the destinations even if the state says it
+    /// is unchanged, and rebuild the state (`--rescan`).
+    pub fn rescan(mut self, rescan: bool) -> Self {
+        self.rescan = rescan;
+        self
+    }
+
+    /// Report the progress of `run` to `observer`.
+    // Library API; the binary shows progress through `Output::progress`.
+    #[allow(dead_code)]
+    pub fn observer(mut self, observer: impl ProgressObserver + 'static) -> Self {
+        self.observer = Some(Arc::new(observer));
+        self
+    }
+
+    /// Print the lines of the run (start and summary, hooks, locks waited
+    /// for, failed destinations) and show its progress as set in `output`.
+    pub fn output(mut self, output: Output) -> Self {
+        self.output = Some(output);
+        self
+    }
+
+    /// Localized messages of the output and of the errors; English by
+    /// default.
+    pub fn messages(mut self, messages: Messages) -> Self {
+        self.messages = Some(messages);
+        self
+    }
+
+    /// Configuration remote destinations take their credentials from (see
+    /// `webdav`). A job does not read the configuration file itself.
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Run the `pre_hook`, `post_hook` and `on_error` commands of `hooks`
+    /// around the copy. Dry runs run none.
+    pub fn hooks(mut self, hooks: Hooks) -> Self {
+        self.hooks = hooks;
+        self
+    }
+
+    /// Run as the profile `name`: it is locked for the whole run, hooks
+    /// included, and given to the hooks.
+    pub fn profile(mut self, name: impl Into<String>) -> Self {
+        self.profile = Some(name.into());
+        self
+    }
+
+    /// Log file of the run, given to the hooks as `RBACKUP_REPORT`.
+    pub fn report(mut self, path: impl Into<PathBuf>) -> Self {
+        self.report = Some(path.into());
+        self
+    }
+
+    /// Encrypt the files with the key from `key`, hiding their names too
+    /// when `encrypt_names` is set. Destinations without encryption
+    /// parameters are initialized; encrypted destinations fail without a
+    /// key. The manifest is written, as the files are compared through it.
+    pub fn encryption(mut self, key: KeySource, encrypt_names: bool) -> Self {
+        self.encryption = Some((key, encrypt_names));
+        self
+    }
+
+    /// Store the files in a deduplicating repository (see
+    /// `repository::Repository`), created when the destination has none.
+    /// Destinations holding one are used as one anyway.
+    pub fn repository(mut self, repository: bool) -> Self {
+        self.repository = repository;
+        self
+    }
+
+    /// Split the files of new repositories into content-defined chunks.
+    pub fn chunking(mut self, chunking: bool) -> Self {
+        self.chunking = chunking;
+        self
+    }
+
+    /// Wait for the runs holding the locks of the job (its destinations and
+    /// profile) to end, instead of failing.
+    pub fn wait(mut self, wait: bool) -> Self {
+        self.wait = wait;
+        self
+    }
+
+    /// After the copy, keep watching the sources and copy what changes
+    /// (see `watch`); `run` then only returns when watching fails.
+    pub fn watch(mut self, options: WatchOptions) -> Self {
+        self.watch = Some(options);
+        self
+    }
+
+    /// The thread pool the files are copied in.
+    fn thread_pool(&self) -> Result<ThreadPool> {
+        let config_error = |source| Error::Config { path: None, source };
+        if self.jobs == 0 {
+            return Err(config_error(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "jobs must be > 0",
+            )));
+        }
+        ThreadPoolBuilder::new()
+            .num_threads(self.jobs)
+            .build()
+            .map_err(|e| config_error(io::Error::other(e)))
+    }
+
+    /// The context of a run, with the output of the job if it has one.
+    /// Fails when an exclude pattern is invalid.
+    fn options(&self) -> Result<LogContext> {
+        let (exclude_matcher, exclude_patterns) = match self.exclude.is_empty() {
+            true => (None, None),
+            false => (
+                Some(build_exclude_matcher(&self.exclude, self.ignore_case)?),
+                Some(self.exclude.clone()),
+            ),
+        };
+        let options = LogContext {
+            quiet: true,
+            on_log: false,
+            exclude_match_absolute: self.absolute_exclude,
+            dry_run: self.dry_run,
+            exclude_patterns,
+            exclude_matcher,
+            show_skipped: ShowSkipped::Never,
+            manifest: self.manifest || self.encryption.is_some(),
+            compression: self.compression.clone(),
+            progress: ProgressMode::None,
+            observer: self.observer.clone(),
+            state_dir: self.state_dir.clone(),
+            rescan: self.rescan,
+            ..Default::default()
+        };
+        Ok(match &self.output {
+            Some(output) => LogContext {
+                logger: output.logger.clone(),
+                quiet: output.quiet,
+                with_timestamp: output.timestamp.is_some(),
+                timestamp_format: output.timestamp.clone(),
+                on_log: true,
+                show_skipped: output.show_skipped,
+                progress: output.progress,
+                ..options
+            },
+            None => options,
+        })
+    }
+
+    /// The messages of the job, English unless set.
+    fn load_messages(&self) -> Result<Messages> {
+        if let Some(msg) = &self.messages {
+            return Ok(msg.clone());
+        }
+        load_translations()
+            .and_then(|mut t| {
+                t.remove("en").ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::NotFound, "English messages missing")
+                })
+            })
+            .map_err(|source| Error::Config { path: None, source })
+    }
+
+    /// One target per destination, with the directory each source is copied
+    /// into, and where the destination lives. The targets are not opened.
+    ///
+    /// Destinations that cannot be parsed or are given twice, and sources
+    /// mapped to the same directory, are errors of the job itself, reported
+    /// as `Error::Config`; their message names the path.
+    fn targets(&self, options: &LogContext) -> Result<Vec<(CopyTarget, Location)>> {
+        let config_error = |source| Error::Config { path: None, source };
+        let mut targets = Vec::with_capacity(self.destinations.len());
+        for (i, dest) in self.destinations.iter().enumerate() {
+            let duplicate = self.destinations[..i]
+                .iter()
+                .any(|d| std::path::absolute(d).ok() == std::path::absolute(dest).ok());
+            if duplicate {
+                return Err(config_error(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{}: destination given twice", dest.display()),
+                )));
+            }
+            let location = Location::parse(dest).map_err(config_error)?;
+            let pairs = source_destinations(&self.sources, &location.root(), self.source_naming)
+                .map_err(config_error)?;
+            let target = CopyTarget {
+                destination: dest.clone(),
+                pairs,
+                options: options.clone(),
+            };
+            targets.push((target, location));
+        }
+        Ok(targets)
+    }
+
+    /// Copy the sources to every destination.
+    ///
+    /// Fails when the job itself is invalid (no worker threads, a bad
+    /// exclude pattern, a destination given twice) or its profile is locked
+    /// by another run; nothing is run then, hooks included. Otherwise the
+    /// summary tells the rest: a destination that cannot be locked or opened
+    /// fails on its own, the errors of single files are collected, and a
+    /// failing hook or watcher fails the whole run (`BackupSummary::error`).
+    /// `on_error` and `post_hook` run in every case.
+    pub fn run(&self) -> Result<BackupSummary> {
+        let pool = self.thread_pool()?;
+        let mut ctx = self.options()?;
+        let targets = self.targets(&ctx)?;
+        let msg = self.load_messages()?;
+
+        // Dry runs write nothing: no hooks and no locks.
+        let hooks = match self.dry_run {
+            true => Hooks::default(),
+            false => self.hooks.clone(),
+        };
+        let runner = HookRunner::new(
+            hooks,
+            &msg,
+            &self.sources,
+            &self.destinations,
+            self.profile.as_deref(),
+        );
+
+        // Held until the run ends. The profile lock also covers `pre_hook`;
+        // the destinations are locked afterwards, as the hook may mount them.
+        let mut locks = Vec::new();
+        if !self.dry_run
+            && let Some(name) = &self.profile
+        {
+            let path = Lock::profile_path(name);
+            match self.acquire_lock(&path, &msg, &ctx) {
+                Ok(lock) => locks.push(lock),
+                Err(source) => return Err(Error::Lock { path, source }),
+            }
+        }
+
+        let outcome: Result<Vec<Result<CopyCounts>>> = 'run: {
+            if let Err(source) = runner.pre(&ctx) {
+                break 'run Err(Error::Hook {
+                    hook: "pre_hook".to_string(),
+                    source,
+                });
+            }
+
+            // A destination that cannot be locked or opened fails on its
+            // own; the others are still copied.
+            let mut results = Vec::with_capacity(targets.len());
+            let mut ready = Vec::new();
+            for (mut target, location) in targets {
+                let opened = match location.is_local() {
+                    true => self.open_local(&mut target, &msg, &ctx),
+                    false => self.open_remote(&mut target, &location, &msg),
+                };
+                match opened {
+                    Ok(lock) => {
+                        locks.extend(lock);
+                        ready.push(target);
+                        results.push(None);
+                    }
+                    Err(e) => {
+                        let mut err_ctx = ctx.clone();
+                        err_ctx.with_timestamp = false;
+                        err_ctx.on_log = false;
+                        let line = match self.destinations.len() {
+                            1 => format!("\u{274C} {}", opening_message(&e)),
+                            _ => format!(
+                                "\u{274C} {}: {}",
+                                target.destination.display(),
+                                opening_message(&e)
+                            ),
+                        };
+                        log_output(&line, &err_ctx);
+                        results.push(Some(Err(e)));
+                    }
+                }
+            }
+
+            let delta = self.compare == Compare::Delta;
+            if let Some(options) = self.watch
+                && !ready.is_empty()
+            {
+                // Batches keep coming: a full-screen display cannot be kept up.
+                if matches!(
+                    ctx.progress.resolve(ctx.quiet),
+                    ProgressMode::Fancy | ProgressMode::Dashboard
+                ) {
+                    ctx.progress = ProgressMode::Plain;
+                }
+                start_copy_message(&msg, &ctx, &ready);
+                // Only returns when watching fails.
+                let source =
+                    match pool.install(|| execute_watch(&msg, &ctx, &ready, delta, options)) {
+                        Ok(()) => io::Error::other("filesystem watcher stopped"),
+                        Err(e) => e,
+                    };
+                log_output(&format!("\u{274C} {}: {}", msg.generic_error, source), &ctx);
+                break 'run Err(Error::Watch { source });
+            }
+
+            if !ready.is_empty() {
+                let copied = match self.output.is_some() {
+                    true => {
+                        start_copy_message(&msg, &ctx, &ready);
+                        pool.install(|| execute_copy(&msg, &mut ctx, &ready, delta))
+                    }
+                    // Without output, not even the errors are printed.
+                    false => pool.install(|| copy_targets(&ready, &msg, &ctx, delta, None)),
+                };
+                let mut copied = copied.into_iter();
+                for r in results.iter_mut().filter(|r| r.is_none()) {
+                    *r = copied.next();
+                }
+            }
+            Ok(results.into_iter().flatten().collect())
+        };
+
+        let mut summary = match outcome {
+            Ok(results) => BackupSummary::new(&self.destinations, results),
+            Err(e) => BackupSummary {
+                error: Some(e),
+                ..Default::default()
+            },
+        };
+        let result = summary.run_result(self.report.clone());
+        if let Err(source) = runner.finish(&result, &ctx) {
+            summary.error.get_or_insert(Error::Hook {
+                hook: "post_hook".to_string(),
+                source,
+            });
+        }
+        drop(locks);
+        Ok(summary)
+    }
+
+    /// Lock a local destination and open its encryption keys and the
+    /// repository it holds, if any. Dry runs take no lock.
+    ///
+    /// Errors carry a localized message suitable for display.
+    fn open_local(
+        &self,
+        target: &mut CopyTarget,
+        msg: &Messages,
+        ctx: &LogContext,
+    ) -> Result<Option<Lock>> {
+        let destination = target.destination.as_path();
+        let destination_error = |source| Error::Destination {
+            path: destination.to_path_buf(),
+            source,
+        };
+        // Plaintext files must never be written next to encrypted ones.
+        if self.encryption.is_none() && Encryption::is_initialized(destination) {
+            return Err(destination_error(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                msg.encryption_mismatch.clone(),
+            )));
+        }
+        let lock = match self.dry_run {
+            true => None,
+            false => {
+                let path = Lock::destination_path(destination);
+                match self.acquire_lock(&path, msg, ctx) {
+                    Ok(lock) => Some(lock),
+                    Err(source) => return Err(Error::Lock { path, source }),
+                }
+            }
+        };
+        if let Some((key, names)) = &self.encryption {
+            let enc = Encryption::open(destination, key, Some(*names), !self.dry_run, msg)
+                .map_err(destination_error)?;
+            target.options.encryption = Some(enc);
+        }
+        // Destinations that already hold a repository keep being used as one.
+        if self.repository || Repository::is_repository(destination) {
+            let chunking = self.chunking.then(Chunking::default);
+            let repo = Repository::open_or_init(destination, chunking, !self.dry_run)
+                .map_err(destination_error)?;
+            target.options.repository = Some(repo);
+        }
+        Ok(lock)
+    }
+
+    /// Connect to a remote destination. Remote destinations have no lock,
+    /// encryption, repository or manifest.
+    ///
+    /// Errors carry a localized message suitable for display.
+    fn open_remote(
+        &self,
+        target: &mut CopyTarget,
+        location: &Location,
+        msg: &Messages,
+    ) -> Result<Option<Lock>> {
+        let destination_error = |source| Error::Destination {
+            path: target.destination.clone(),
+            source,
+        };
+        if self.encryption.is_some() || self.repository || target.options.manifest {
+            return Err(destination_error(io::Error::new(
+                io::ErrorKind::Unsupported,
+                msg.remote_unsupported.clone(),
+            )));
+        }
+        let remote = location.connect(&self.config).map_err(destination_error)?;
+        target.options.remote = remote;
+        Ok(None)
+    }
+
+    /// Acquire the lock file at `path`, waiting for its holder to finish
+    /// when the job waits for locks.
+    ///
+    /// Errors carry a localized message suitable for display.
+    fn acquire_lock(&self, path: &Path, msg: &Messages, ctx: &LogContext) -> io::Result<Lock> {
+        let mut waiting = false;
+        loop {
+            match Lock::try_acquire(path)? {
+                TryLock::Acquired(lock) => {
+                    if let Some(holder) = &lock.took_over {
+                        log_output(
+                            &format!(
+                                "{} ({})",
+                                msg.lock_stale.replace("{}", &path.display().to_string()),
+                                holder
+                            ),
+                            ctx,
+                        );
+                    }
+                    return Ok(lock);
+                }
+                TryLock::Held(holder) if self.wait => {
+                    if !waiting {
+                        log_output(
+                            &format!(
+                                "{} ({})",
+                                msg.lock_waiting.replace("{}", &path.display().to_string()),
+                                holder
+                            ),
+                            ctx,
+                        );
+                        waiting = true;
+                    }
+                    std::thread::sleep(LOCK_POLL);
+                }
+                TryLock::Held(holder) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::WouldBlock,
+                        format!(
+                            "{} ({})",
+                            msg.lock_held.replace("{}", &path.display().to_string()),
+                            holder
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+/// Result of one destination of a job.
+#[derive(Debug)]
+pub struct DestinationSummary {
+    /// Destination as given to the job.
+    pub destination: PathBuf,
+    /// Counts of the destination, with the errors of its files, or the
+    /// error that made it fail as a whole (it could not be opened, its
+    /// manifest could not be written).
+    pub result: Result<CopyCounts>,
+}
+
+/// Result of a job.
+#[derive(Debug, Default)]
+pub struct BackupSummary {
+    /// Files copied, over all the destinations.
+    pub copied: usize,
+    /// Files not copied, failed ones included.
+    pub skipped: usize,
+    /// Files whose copy failed.
+    pub failed: usize,
+    /// Result of each destination, in the order they were given; empty when
+    /// the run failed before copying (see `error`).
+    pub destinations: Vec<DestinationSummary>,
+    /// The error that made the whole run fail: a failing `pre_hook` or
+    /// `post_hook`, or a watcher that stopped.
+    pub error: Option<Error>,
+}
+
+impl BackupSummary {
+    /// Gather the result of each of `destinations`; the totals only count
+    /// the destinations that did not fail.
+    pub fn new(destinations: &[PathBuf], results: Vec<Result<CopyCounts>>) -> Self {
+        let mut summary = BackupSummary::default();
+        for (destination, result) in destinations.iter().zip(results) {
+            if let Ok(counts) = &result {
+                summary.copied += counts.copied;
+                summary.skipped += counts.skipped;
+                summary.failed += counts.failed;
+            }
+            summary.destinations.push(DestinationSummary {
+                destination: destination.clone(),
+                result,
+            });
+        }
+        summary
+    }
+
+    /// The destinations that failed as a whole, with their error.
+    pub fn failed_destinations(&self) -> impl Iterator<Item = (&Path, &Error)> {
+        self.destinations.iter().filter_map(|d| {
+            d.result
+                .as_ref()
+                .err()
+                .map(|e| (d.destination.as_path(), e))
+        })
+    }
+
+    /// The errors of single files (or directories) of every destination
+    /// that did not fail as a whole.
+    pub fn file_errors(&self) -> impl Iterator<Item = &Error> {
+        self.destinations
+            .iter()
+            .filter_map(|d| d.result.as_ref().ok())
+            .flat_map(|counts| &counts.errors)
+    }
+
+    /// True when every destination and every file was copied.
+    // Library API; the binary reports through `run_result`.
+    #[allow(dead_code)]
+    pub fn is_success(&self) -> bool {
+        self.error.is_none()
+            && self.failed_destinations().next().is_none()
+            && self.file_errors().next().is_none()
+    }
+
+    /// The result of the run as given to the hooks and notifications, with
+    /// the log file of the run as `report`. The run failed when it failed as
+    /// a whole or a destination did; the errors of single files are only
+    /// counted.
+    pub fn run_result(&self, report: Option<PathBuf>) -> RunResult {
+        let several = self.destinations.len() > 1;
+        let failed = self
+            .failed_destinations()
+            .map(|(destination, e)| match several {
+                true => format!("{}: {}", destination.display(), opening_message(e)),
+                false => opening_message(e),
+            });
+        let errors: Vec<String> = self
+            .error
+            .iter()
+            .map(Error::to_string)
+            .chain(failed)
+            .collect();
+        RunResult {
+            copied: self.copied,
+            skipped: self.skipped,
+            errors: self.failed,
+            exit_status: i32::from(!errors.is_empty()),
+            report,
+            error: (!errors.is_empty()).then(|| errors.join("; ")),
+        }
+    }
+}
+
+/// The message of the error of a destination, without the destination or
+/// lock path its localized message names already.
+fn opening_message(e: &Error) -> String {
+    match e {
+        Error::Destination { source, .. } | Error::Lock { source, .. } => source.to_string(),
+        e => e.to_string(),
+    }
+}
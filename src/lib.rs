@@ -6,21 +6,51 @@
 //!
 //! # Re-exports
 //!
+//! - `BackupJob`, `BackupSummary`, `Compare` — the library entry point: a
+//!   backup job built step by step, run without terminal output.
+//! - `ProgressEvent`, `ProgressObserver`, `SkipReason` — progress reporting
+//!   of a job; the terminal displays and the file logger are observers too.
+//! - `Error` — what failed in a job, with the path it failed on.
 //! - `LogContext` — context for logging and output operations.
 //! - `Logger`, `Messages` — utility types used across the crate.
 //! - `build_exclude_matcher`, `copy_incremental`, `is_newer` — commonly used helper
 //!   functions for building exclude matchers and performing incremental copies.
 
+pub mod check;
 pub mod cli;
 pub mod commands;
+pub mod compress;
 pub mod config;
 pub mod copy;
+pub mod crypto;
+pub mod daemon;
+pub mod dashboard;
+pub mod destination;
+pub mod diff;
+pub mod error;
+pub mod hooks;
+pub mod job;
+pub mod lock;
+pub mod manifest;
+pub mod notifications;
 pub mod output;
+pub mod progress;
+pub mod repository;
+pub mod restore;
+pub mod s3;
+pub mod schedule;
+pub mod sftp;
+pub mod state;
 pub mod ui;
 pub mod utils;
+pub mod watch;
+pub mod webdav;
 
 // Re-exports
+pub use error::Error;
+pub use job::{BackupJob, BackupSummary, Compare};
 pub use output::{LogContext, ShowSkipped};
+pub use progress::{ProgressEvent, ProgressObserver, SkipReason};
 
 /// Thread-safe file logger type: `Arc<Mutex<BufWriter<File>>>`.
 pub use utils::{Logger, Messages, build_exclude_matcher, copy_incremental, is_newer};
@@ -0,0 +1,234 @@
+//! Advisory locks preventing concurrent runs.
+//!
+//! A `copy` run holds `.rbackup-lock` in its destination, and runs of a
+//! configuration profile also hold `<name>.lock` in the `locks` directory next
+//! to the configuration file. A lock file is created exclusively and records
+//! the PID, host and start time of its holder; it is removed when the
+//! `Lock` is dropped. A lock left behind by a process that no longer runs on
+//! this host is stale and is taken over. Locks of other hosts (a destination
+//! on a network share) are never considered stale and must be removed by
+//! hand if their holder crashed.
+//!
+//! Remote destinations (`sftp://`, `s3://`, `webdav://`) take no destination
+//! lock; only the profile lock, when any, guards them against concurrent runs.
+
+use crate::config::Config;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
+
+/// Name of the lock file in a destination directory.
+pub const LOCK_FILE: &str = ".rbackup-lock";
+/// Lock files that cannot be parsed are only considered stale after this
+/// delay, so that a lock being written by another run is not taken over.
+const UNREADABLE_GRACE: Duration = Duration::from_secs(10);
+
+/// Holder of a lock, as recorded in the lock file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockInfo {
+    pub pid: u32,
+    pub host: String,
+    /// Local time at which the lock was taken (RFC 3339).
+    pub started: String,
+}
+
+impl LockInfo {
+    /// Information describing the current process.
+    pub fn current() -> Self {
+        Self {
+            pid: std::process::id(),
+            host: host_name(),
+            started: Local::now().to_rfc3339(),
+        }
+    }
+
+    /// Whether the holder is known to be gone: it ran on this host and its
+    /// process no longer exists.
+    pub fn is_stale(&self) -> bool {
+        self.host == host_name() && !process_alive(self.pid)
+    }
+}
+
+impl fmt::Display for LockInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "pid {}@{}, {}", self.pid, self.host, self.started)
+    }
+}
+
+fn host_name() -> String {
+    gethostname::gethostname().to_string_lossy().into_owned()
+}
+
+#[cfg(unix)]
+fn process_alive(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    if pid == 0 {
+        return false;
+    }
+    // Signal 0 only checks that the process exists; EPERM means it exists
+    // but belongs to another user.
+    // SAFETY: kill with signal 0 sends no signal and has no side effects.
+    unsafe {
+        libc::kill(pid, 0) == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    }
+}
+
+#[cfg(not(unix))]
+fn process_alive(_pid: u32) -> bool {
+    // Without a portable check the holder is assumed to be running.
+    true
+}
+
+/// Result of `Lock::try_acquire`.
+#[derive(Debug)]
+pub enum TryLock {
+    /// The lock is now held by this process.
+    Acquired(Lock),
+    /// Another live run holds the lock.
+    Held(LockInfo),
+}
+
+/// A held lock file, removed on drop.
+#[derive(Debug)]
+pub struct Lock {
+    path: PathBuf,
+    /// Holder of the stale lock taken over to acquire this one, if any.
+    pub took_over: Option<LockInfo>,
+}
+
+impl Lock {
+    /// Path of the lock file in a destination directory.
+    pub fn destination_path(dest_dir: &Path) -> PathBuf {
+        dest_dir.join(LOCK_FILE)
+    }
+
+    /// Path of the lock file of a configuration profile.
+    pub fn profile_path(name: &str) -> PathBuf {
+        Config::config_file()
+            .with_file_name("locks")
+            .join(format!("{}.lock", name))
+    }
+
+    /// Try once to create the lock file at `path`, taking over a stale lock.
+    pub fn try_acquire(path: &Path) -> io::Result<TryLock> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut took_over = None;
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(path) {
+                Ok(mut file) => {
+                    let lock = Lock {
+                        path: path.to_path_buf(),
+                        took_over,
+                    };
+                    let info = serde_json::to_string(&LockInfo::current())?;
+                    file.write_all(info.as_bytes())?;
+                    return Ok(TryLock::Acquired(lock));
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e),
+            }
+
+            let took = match take_over(path)? {
+                Holder::Gone => continue,
+                Holder::Unreadable(true) => None,
+                Holder::Unreadable(false) => {
+                    // Being written: report it as held by an unknown run.
+                    return Ok(TryLock::Held(LockInfo {
+                        pid: 0,
+                        host: "?".to_string(),
+                        started: String::new(),
+                    }));
+                }
+                Holder::Info(info) if info.is_stale() => Some(info),
+                Holder::Info(info) => return Ok(TryLock::Held(info)),
+            };
+            took_over = took.or(took_over);
+        }
+    }
+}
+
+/// Move a stale lock at `path` out of the way and return its holder.
+///
+/// Several runs may find the same stale lock. Each one renames the lock file
+/// to a name of its own and only reads the holder from the renamed file, so
+/// that a lock another run created after taking the stale one over is never
+/// deleted: it is moved back and reported as held instead.
+fn take_over(path: &Path) -> io::Result<Holder> {
+    match read_holder(path)? {
+        Holder::Unreadable(true) => {}
+        Holder::Info(info) if info.is_stale() => {}
+        holder => return Ok(holder),
+    }
+    static TAKEOVERS: AtomicU64 = AtomicU64::new(0);
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(
+        ".stale-{}-{}",
+        std::process::id(),
+        TAKEOVERS.fetch_add(1, Ordering::Relaxed)
+    ));
+    let moved = path.with_file_name(name);
+    match fs::rename(path, &moved) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Holder::Gone),
+        Err(e) => return Err(e),
+    }
+    let holder = read_holder(&moved);
+    match &holder {
+        Ok(Holder::Unreadable(true)) => {}
+        Ok(Holder::Info(info)) if info.is_stale() => {}
+        _ => {
+            // Not the lock found stale: put it back unless a new one exists.
+            // Without hard links (FAT file systems) it is renamed back.
+            match fs::hard_link(&moved, path) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(_) => {
+                    fs::rename(&moved, path)?;
+                    return holder;
+                }
+            }
+        }
+    }
+    fs::remove_file(&moved)?;
+    holder
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+enum Holder {
+    /// The lock disappeared in the meantime.
+    Gone,
+    /// The lock cannot be parsed; `true` when it is old enough to be stale.
+    Unreadable(bool),
+    Info(LockInfo),
+}
+
+fn read_holder(path: &Path) -> io::Result<Holder> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Holder::Gone),
+        Err(e) => return Err(e),
+    };
+    if let Ok(info) = serde_json::from_slice::<LockInfo>(&data) {
+        return Ok(Holder::Info(info));
+    }
+    let age = fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| SystemTime::now().duration_since(t).ok())
+        .unwrap_or_default();
+    Ok(Holder::Unreadable(age >= UNREADABLE_GRACE))
+}
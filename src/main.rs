@@ -4,13 +4,35 @@
 //! loads translations and configuration, selects the effective language and
 //! dispatches the requested command handler.
 
+mod check;
 mod cli;
 mod commands;
+mod compress;
 mod config;
 mod copy;
+mod crypto;
+mod daemon;
+mod dashboard;
+mod destination;
+mod diff;
+mod error;
+mod hooks;
+mod job;
+mod lock;
+mod manifest;
+mod notifications;
 mod output;
+mod progress;
+mod repository;
+mod restore;
+mod s3;
+mod schedule;
+mod sftp;
+mod state;
 mod ui;
 mod utils;
+mod watch;
+mod webdav;
 
 use crate::cli::{Cli, Commands};
 use crate::config::Config;
@@ -66,6 +88,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     match &cli.command {
         Some(cmd @ Commands::Config { .. }) => commands::handle_conf(cmd, msg, &config),
         Some(cmd @ Commands::Copy { .. }) => commands::handle_copy(cmd, msg, &config),
+        Some(cmd @ Commands::Restore { .. }) => commands::handle_restore(cmd, msg, &config),
+        Some(cmd @ Commands::Check { .. }) => commands::handle_check(cmd, msg, &config),
+        Some(cmd @ Commands::Diff { .. }) => commands::handle_diff(cmd, msg, &config),
+        Some(cmd @ Commands::Schedule { .. }) => commands::handle_schedule(cmd, msg, &config),
+        Some(cmd @ Commands::Daemon { .. }) => commands::handle_daemon(cmd, msg, &config),
         None => unreachable!(),
     }
 }
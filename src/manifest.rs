@@ -0,0 +1,193 @@
+//! Backup manifest persisted in the destination directory.
+//!
+//! A manifest records, for every source file handled by a run, its path
+//! relative to the source directory, its size, its modification time and a
+//! BLAKE3 hash of its contents. The manifest is built during the planning
+//! phase of `copy_incremental` and written to the destination once the copy
+//! has completed, so that later runs can tell which files changed since the
+//! previous backup without re-reading unchanged contents.
+
+use crate::crypto::{ENCRYPTED_EXTENSION, Encryption, write_atomically};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File, Metadata};
+use std::io::{self, BufReader, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Prefix shared by the metadata files rBackup keeps in a destination (the
+/// manifest, `crypto::KEY_INFO_FILE`, the lock, the repository settings).
+pub const METADATA_PREFIX: &str = ".rbackup-";
+
+/// File name of the manifest stored at the root of the destination.
+pub const MANIFEST_FILE: &str = ".rbackup-manifest.json";
+
+/// Current on-disk format version of the manifest.
+pub const MANIFEST_VERSION: u32 = 1;
+
+/// Metadata recorded for a single file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// File size in bytes.
+    pub size: u64,
+    /// Modification time, seconds since the Unix epoch.
+    pub mtime: i64,
+    /// Sub-second part of the modification time.
+    pub mtime_nanos: u32,
+    /// Hex-encoded BLAKE3 hash of the file contents.
+    pub hash: String,
+}
+
+impl ManifestEntry {
+    /// Build an entry from filesystem metadata and an already computed hash.
+    pub fn from_metadata(meta: &Metadata, hash: String) -> Self {
+        let (mtime, mtime_nanos) = mtime_parts(meta);
+        Self {
+            size: meta.len(),
+            mtime,
+            mtime_nanos,
+            hash,
+        }
+    }
+
+    /// Return true if `meta` has the same size and modification time as this
+    /// entry, meaning the recorded hash can be reused without reading the file.
+    pub fn matches_metadata(&self, meta: &Metadata) -> bool {
+        let (mtime, mtime_nanos) = mtime_parts(meta);
+        self.size == meta.len() && self.mtime == mtime && self.mtime_nanos == mtime_nanos
+    }
+}
+
+/// Manifest of a backup run.
+///
+/// Entries are keyed by the relative path of the file using `/` as the
+/// separator on every platform, so manifests stay portable between systems.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Format version (see `MANIFEST_VERSION`).
+    pub version: u32,
+    /// Local time at which the manifest was created (RFC 3339).
+    pub created: String,
+    /// Recorded files keyed by their relative path.
+    pub entries: BTreeMap<String, ManifestEntry>,
+}
+
+impl Default for Manifest {
+    fn default() -> Self {
+        Self {
+            version: MANIFEST_VERSION,
+            created: chrono::Local::now().to_rfc3339(),
+            entries: BTreeMap::new(),
+        }
+    }
+}
+
+impl Manifest {
+    /// Location of the manifest inside `dest_dir`.
+    ///
+    /// Encrypted destinations keep the manifest encrypted as well, under the
+    /// same name with the `.enc` extension appended.
+    pub fn path_in(dest_dir: &Path, encryption: Option<&Encryption>) -> PathBuf {
+        match encryption {
+            Some(_) => dest_dir.join(format!("{}.{}", MANIFEST_FILE, ENCRYPTED_EXTENSION)),
+            None => dest_dir.join(MANIFEST_FILE),
+        }
+    }
+
+    /// Load the manifest stored in `dest_dir`.
+    ///
+    /// Returns `Ok(None)` when no manifest exists, or an `InvalidData` error
+    /// when the file cannot be decrypted or parsed.
+    pub fn load(dest_dir: &Path, encryption: Option<&Encryption>) -> io::Result<Option<Self>> {
+        let path = Manifest::path_in(dest_dir, encryption);
+        let data = match fs::read(&path) {
+            Ok(d) => d,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let data = match encryption {
+            Some(enc) => {
+                let mut plain = Vec::new();
+                enc.decrypt_stream(data.as_slice(), |_| Ok(&mut plain))?;
+                plain
+            }
+            None => data,
+        };
+        let manifest: Manifest = serde_json::from_slice(&data)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(Some(manifest))
+    }
+
+    /// Write the manifest into `dest_dir`, encrypting it when `encryption`
+    /// is given.
+    ///
+    /// The file is first written under a temporary name and then renamed, so
+    /// an interrupted run never leaves a truncated manifest behind.
+    pub fn save(&self, dest_dir: &Path, encryption: Option<&Encryption>) -> io::Result<()> {
+        fs::create_dir_all(dest_dir)?;
+        let data = serde_json::to_vec_pretty(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        write_atomically(
+            &Manifest::path_in(dest_dir, encryption),
+            |w| match encryption {
+                Some(enc) => enc.encrypt_stream(data.as_slice(), w, MANIFEST_FILE),
+                None => w.write_all(&data),
+            },
+        )
+    }
+
+    /// Look up the entry recorded for `rel_path`.
+    pub fn get(&self, rel_path: &Path) -> Option<&ManifestEntry> {
+        self.entries.get(&manifest_key(rel_path))
+    }
+}
+
+/// Convert a relative path into the key used by the manifest.
+///
+/// # Example
+///
+/// ```rust
+/// use rbackup::manifest::manifest_key;
+/// use std::path::Path;
+/// assert_eq!(manifest_key(Path::new("docs/report.txt")), "docs/report.txt");
+/// ```
+pub fn manifest_key(rel_path: &Path) -> String {
+    rel_path
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(s) => Some(s.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Compute the hex-encoded BLAKE3 hash of the file at `path`.
+pub fn hash_file(path: &Path) -> io::Result<String> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut hasher = blake3::Hasher::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Modification time of `meta` as whole seconds since the epoch and the
+/// nanoseconds past them. Times before the epoch have negative seconds and
+/// still count their nanoseconds forward (-0.5 s is `(-1, 500_000_000)`).
+pub(crate) fn mtime_parts(meta: &Metadata) -> (i64, u32) {
+    let nanos = match meta.modified().map(|t| t.duration_since(UNIX_EPOCH)) {
+        Ok(Ok(d)) => d.as_nanos() as i128,
+        Ok(Err(e)) => -(e.duration().as_nanos() as i128),
+        Err(_) => 0,
+    };
+    (
+        nanos.div_euclid(1_000_000_000) as i64,
+        nanos.rem_euclid(1_000_000_000) as u32,
+    )
+}
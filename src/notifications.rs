@@ -0,0 +1,320 @@
+//! Notifications sent when a `copy` run ends.
+//!
+//! The `notifications` section of the configuration file lists channels:
+//! email through an SMTP server, a JSON payload posted to a webhook, or the
+//! local syslog (which journald also collects). Each channel can be limited
+//! to successful or failed runs with `on`. Delivery problems are reported
+//! but never change the outcome of the run.
+
+use crate::hooks::RunResult;
+use crate::output::{LogContext, log_output};
+use crate::utils::Messages;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Time allowed to an SMTP server or a webhook to answer.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Runs a channel is notified of.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifyOn {
+    #[default]
+    Always,
+    Success,
+    /// Failed runs, and runs where some files could not be copied.
+    Failure,
+}
+
+/// Security of the SMTP connection.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Plain connection upgraded with STARTTLS (port 587 by default).
+    #[default]
+    Starttls,
+    /// TLS from the start (port 465 by default).
+    Tls,
+    /// Unencrypted (port 25 by default), e.g. for a local relay.
+    None,
+}
+
+/// Email sent through an SMTP server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmailChannel {
+    pub smtp_host: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub smtp_port: Option<u16>,
+    #[serde(default)]
+    pub tls: SmtpTls,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    /// Password; prefer `password_env` to keep it out of the file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// Environment variable holding the password.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_env: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+}
+
+/// JSON payload posted to a URL.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookChannel {
+    pub url: String,
+    /// Extra HTTP headers, e.g. an authorization token.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+}
+
+/// Where a notification is delivered.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Channel {
+    Email(EmailChannel),
+    Webhook(WebhookChannel),
+    /// The local syslog daemon (or journald), facility `user`.
+    Syslog,
+}
+
+impl Channel {
+    fn name(&self) -> &'static str {
+        match self {
+            Channel::Email(_) => "email",
+            Channel::Webhook(_) => "webhook",
+            Channel::Syslog => "syslog",
+        }
+    }
+}
+
+/// A notification channel of the configuration file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Notification {
+    #[serde(default)]
+    pub on: NotifyOn,
+    #[serde(flatten)]
+    pub channel: Channel,
+}
+
+/// What is reported about a run; also the JSON payload of webhooks.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Notice {
+    /// `success` or `failure`.
+    pub status: String,
+    pub sources: Vec<String>,
+    pub destinations: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    pub host: String,
+    /// Local time at which the run ended (RFC 3339).
+    pub finished: String,
+    pub copied: usize,
+    pub skipped: usize,
+    pub errors: usize,
+    pub exit_status: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub report: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Notice {
+    pub fn new(
+        result: &RunResult,
+        sources: &[PathBuf],
+        destinations: &[PathBuf],
+        profile: Option<&str>,
+    ) -> Self {
+        let success = result.exit_status == 0 && result.errors == 0;
+        Self {
+            status: if success { "success" } else { "failure" }.to_string(),
+            sources: sources.iter().map(|s| s.display().to_string()).collect(),
+            destinations: destinations
+                .iter()
+                .map(|d| d.display().to_string())
+                .collect(),
+            profile: profile.map(str::to_string),
+            host: gethostname::gethostname().to_string_lossy().into_owned(),
+            finished: Local::now().to_rfc3339(),
+            copied: result.copied,
+            skipped: result.skipped,
+            errors: result.errors,
+            exit_status: result.exit_status,
+            report: result.report.as_ref().map(|p| p.display().to_string()),
+            error: result.error.clone(),
+        }
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.status == "success"
+    }
+
+    /// One-line summary, used as email subject and syslog message.
+    pub fn subject(&self, msg: &Messages) -> String {
+        let sources = self.sources.join(", ");
+        let name = self.profile.as_deref().unwrap_or(&sources);
+        let template = if self.is_success() {
+            &msg.notify_success
+        } else {
+            &msg.notify_failure
+        };
+        format!("rbackup: {}", template.replace("{}", name))
+    }
+
+    /// Plain-text description of the run, used as email body.
+    pub fn body(&self, msg: &Messages) -> String {
+        let mut lines = vec![
+            self.subject(msg),
+            String::new(),
+            format!(
+                "{} {} {} {}",
+                msg.starting_backup,
+                self.sources.join(", "),
+                msg.to,
+                self.destinations.join(", ")
+            ),
+            format!(
+                "{}, {}, {}",
+                msg.files_copied.replace("{}", &self.copied.to_string()),
+                msg.files_skipped.replace("{}", &self.skipped.to_string()),
+                msg.notify_errors.replace("{}", &self.errors.to_string())
+            ),
+        ];
+        if let Some(error) = &self.error {
+            lines.push(format!("{}: {}", msg.generic_error, error));
+        }
+        if let Some(report) = &self.report {
+            lines.push(format!("{} {}", msg.notify_report, report));
+        }
+        lines.push(format!("{} ({})", self.finished, self.host));
+        lines.join("\n") + "\n"
+    }
+}
+
+impl Notification {
+    /// Whether the channel wants to hear about `notice`.
+    pub fn wants(&self, notice: &Notice) -> bool {
+        match self.on {
+            NotifyOn::Always => true,
+            NotifyOn::Success => notice.is_success(),
+            NotifyOn::Failure => !notice.is_success(),
+        }
+    }
+
+    /// Deliver `notice` through this channel.
+    pub fn send(&self, notice: &Notice, msg: &Messages) -> io::Result<()> {
+        match &self.channel {
+            Channel::Email(email) => send_email(email, notice, msg),
+            Channel::Webhook(hook) => send_webhook(hook, notice),
+            Channel::Syslog => send_syslog(notice, msg),
+        }
+    }
+}
+
+fn send_email(channel: &EmailChannel, notice: &Notice, msg: &Messages) -> io::Result<()> {
+    use lettre::message::header::ContentType;
+    use lettre::transport::smtp::authentication::Credentials;
+    use lettre::{Message, SmtpTransport, Transport};
+
+    let invalid =
+        |e: &dyn std::fmt::Display| io::Error::new(io::ErrorKind::InvalidInput, e.to_string());
+    let mut builder = Message::builder()
+        .from(channel.from.parse().map_err(|e| invalid(&e))?)
+        .subject(notice.subject(msg))
+        .header(ContentType::TEXT_PLAIN);
+    for to in &channel.to {
+        builder = builder.to(to.parse().map_err(|e| invalid(&e))?);
+    }
+    let email = builder.body(notice.body(msg)).map_err(|e| invalid(&e))?;
+
+    let mut transport = match channel.tls {
+        SmtpTls::Starttls => SmtpTransport::starttls_relay(&channel.smtp_host),
+        SmtpTls::Tls => SmtpTransport::relay(&channel.smtp_host),
+        SmtpTls::None => Ok(SmtpTransport::builder_dangerous(&channel.smtp_host)),
+    }
+    .map_err(io::Error::other)?
+    .timeout(Some(TIMEOUT));
+    if let Some(port) = channel.smtp_port {
+        transport = transport.port(port);
+    }
+    if let Some(user) = &channel.username {
+        let password = match &channel.password_env {
+            Some(var) => std::env::var(var).map_err(|e| invalid(&format!("{}: {}", var, e)))?,
+            None => channel.password.clone().unwrap_or_default(),
+        };
+        transport = transport.credentials(Credentials::new(user.clone(), password));
+    }
+    transport.build().send(&email).map_err(io::Error::other)?;
+    Ok(())
+}
+
+fn send_webhook(channel: &WebhookChannel, notice: &Notice) -> io::Result<()> {
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(TIMEOUT))
+        .build()
+        .into();
+    let mut request = agent.post(&channel.url);
+    for (name, value) in &channel.headers {
+        request = request.header(name, value);
+    }
+    request.send_json(notice).map_err(io::Error::other)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+fn send_syslog(notice: &Notice, msg: &Messages) -> io::Result<()> {
+    let formatter = syslog::Formatter3164 {
+        facility: syslog::Facility::LOG_USER,
+        hostname: None,
+        process: "rbackup".to_string(),
+        pid: std::process::id(),
+    };
+    let mut logger = syslog::unix(formatter).map_err(|e| io::Error::other(e.to_string()))?;
+    let line = format!(
+        "{} ({}, {})",
+        notice.subject(msg),
+        msg.files_copied.replace("{}", &notice.copied.to_string()),
+        msg.notify_errors.replace("{}", &notice.errors.to_string())
+    );
+    if notice.is_success() {
+        logger.info(line)
+    } else {
+        logger.err(line)
+    }
+    .map_err(|e| io::Error::other(e.to_string()))
+}
+
+#[cfg(not(unix))]
+fn send_syslog(_notice: &Notice, _msg: &Messages) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "syslog is only available on Unix",
+    ))
+}
+
+/// Send `notice` to every channel of `notifications` that wants it,
+/// reporting delivery failures.
+pub fn notify_all(
+    notifications: &[Notification],
+    notice: &Notice,
+    msg: &Messages,
+    ctx: &LogContext,
+) {
+    for notification in notifications.iter().filter(|n| n.wants(notice)) {
+        if let Err(e) = notification.send(notice, msg) {
+            log_output(
+                &format!(
+                    "\u{26A0}\u{FE0F} {} {}",
+                    msg.notify_failed.replace("{}", notification.channel.name()),
+                    e
+                ),
+                ctx,
+            );
+        }
+    }
+}
@@ -3,9 +3,12 @@
 //! This module centralizes terminal and file logging logic. The `LogContext`
 //! struct carries per-run options such as timestamp formatting, quiet mode and
 //! an optional file logger. The `log_output` function performs the combined
-//! terminal and file write.
+//! terminal and file write. `LogObserver` writes the processed files of a
+//! copy to the file logger.
 
-use crate::utils::Logger;
+use crate::destination::{Destination, LocalDestination};
+use crate::progress::{ProgressEvent, ProgressObserver, SkipReason};
+use crate::utils::{Logger, Messages};
 use crossterm::style::ResetColor;
 use crossterm::{
     cursor::MoveTo,
@@ -13,7 +16,9 @@ use crossterm::{
     style::Print,
     terminal::{Clear, ClearType},
 };
-use std::io::{Write, stdout};
+use std::io::{IsTerminal, Write, stdout};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 /// Context used for logging and terminal output.
 ///
@@ -49,6 +54,44 @@ pub struct LogContext {
     /// - Summary: do not print skipped items during the run (only show the final summary).
     /// - All: print both copied and skipped items.
     pub show_skipped: ShowSkipped,
+
+    /// If true, a manifest (path, size, mtime, hash) describing the source
+    /// files is written to the destination at the end of the run.
+    /// See `manifest::Manifest` for details.
+    pub manifest: bool,
+
+    /// Optional encryption applied to the files written to the destination.
+    /// Encrypted runs rely on the manifest for change detection, so
+    /// `manifest` should be enabled as well.
+    pub encryption: Option<crate::crypto::Encryption>,
+
+    /// Optional per-file compression applied to the files written to the
+    /// destination. Compressed files get the `.zst` extension appended.
+    pub compression: Option<crate::compress::Compression>,
+
+    /// Optional deduplicating repository the files are stored into instead
+    /// of a plain tree. See `repository::Repository` for details.
+    pub repository: Option<crate::repository::Repository>,
+
+    /// Optional remote filesystem the files are written to instead of the
+    /// local one. See `destination::Destination` for details.
+    pub remote: Option<Arc<dyn Destination>>,
+
+    /// How progress is displayed while copying. See `ProgressMode`.
+    pub progress: ProgressMode,
+
+    /// Optional receiver of the progress events of a copy run, independent
+    /// of `progress`. See `progress::ProgressObserver`.
+    pub observer: Option<Arc<dyn ProgressObserver>>,
+
+    /// Optional directory of the local state indexes (see
+    /// `state::StateIndex`). When set, files unchanged since the last
+    /// successful run to a destination are not compared with it again.
+    pub state_dir: Option<std::path::PathBuf>,
+
+    /// If true, the state indexes are not trusted: every file is compared
+    /// with the destination and the indexes are rebuilt.
+    pub rescan: bool,
 }
 
 /// Policy for displaying skipped items.
@@ -59,6 +102,35 @@ pub enum ShowSkipped {
     #[default]
     All,
 }
+/// How progress is displayed during a copy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProgressMode {
+    /// Fancy on a terminal, plain otherwise.
+    #[default]
+    Auto,
+    /// Full-screen view with a scrolling buffer and a progress bar.
+    Fancy,
+    /// One line per event and periodic percentages, without cursor movement.
+    Plain,
+    /// Multi-pane dashboard with per-worker activity, throughput graph and
+    /// errors, with keyboard controls. See `dashboard`.
+    Dashboard,
+    /// No progress output at all.
+    None,
+}
+
+impl ProgressMode {
+    /// Resolve `Auto` against the current stdout; quiet runs show no progress.
+    pub fn resolve(self, quiet: bool) -> ProgressMode {
+        match self {
+            _ if quiet => ProgressMode::None,
+            ProgressMode::Auto if stdout().is_terminal() => ProgressMode::Fancy,
+            ProgressMode::Auto => ProgressMode::Plain,
+            mode => mode,
+        }
+    }
+}
+
 impl Default for LogContext {
     fn default() -> Self {
         Self {
@@ -73,6 +145,25 @@ impl Default for LogContext {
             exclude_patterns: None,
             exclude_matcher: None,
             show_skipped: ShowSkipped::default(),
+            manifest: false,
+            encryption: None,
+            compression: None,
+            repository: None,
+            remote: None,
+            progress: ProgressMode::default(),
+            observer: None,
+            state_dir: None,
+            rescan: false,
+        }
+    }
+}
+
+impl LogContext {
+    /// Filesystem the files are written to: `remote`, or the local one.
+    pub fn destination(&self) -> &dyn Destination {
+        match &self.remote {
+            Some(remote) => remote.as_ref(),
+            None => &LocalDestination,
         }
     }
 }
@@ -151,3 +242,117 @@ pub fn log_output(msg: &str, ctx: &LogContext) {
         }
     }
 }
+
+/// Numbered lines describing the processed files of a copy, as written by
+/// the file logger and the plain and fancy progress displays.
+///
+/// Copied and failed files always get a line, failed ones with their
+/// error; unchanged ones only with `ShowSkipped::All`. Excluded files get
+/// none.
+pub(crate) struct FileLines {
+    msg: Messages,
+    show_skipped: bool,
+    /// Whether the run has several destinations, to tell which one a line
+    /// is about.
+    several: AtomicBool,
+    /// Number of files processed so far.
+    count: AtomicUsize,
+}
+
+impl FileLines {
+    pub(crate) fn new(msg: &Messages, show_skipped: ShowSkipped) -> Self {
+        Self {
+            msg: msg.clone(),
+            show_skipped: show_skipped == ShowSkipped::All,
+            several: AtomicBool::new(false),
+            count: AtomicUsize::new(0),
+        }
+    }
+
+    /// The line of `event`, when it is about a processed file to show.
+    pub(crate) fn line(&self, event: &ProgressEvent<'_>) -> Option<String> {
+        let (source, destination, status) = match *event {
+            ProgressEvent::Planned { destinations, .. } => {
+                self.several.store(destinations > 1, Ordering::Relaxed);
+                return None;
+            }
+            ProgressEvent::FileCopied {
+                source,
+                destination,
+                ..
+            } => (source, destination, Some(self.msg.copied_file.clone())),
+            ProgressEvent::FileSkipped {
+                source,
+                destination,
+                reason: SkipReason::Unchanged,
+            } => (
+                source,
+                destination,
+                self.show_skipped.then(|| self.msg.skipped_file.clone()),
+            ),
+            ProgressEvent::FileFailed {
+                source,
+                destination,
+                error,
+            } => (
+                source,
+                destination,
+                Some(format!("{} {}", self.msg.failed_file, error)),
+            ),
+            _ => return None,
+        };
+        let n = self.count.fetch_add(1, Ordering::Relaxed) + 1;
+        let status = status?;
+        Some(if self.several.load(Ordering::Relaxed) {
+            format!(
+                "#{} {} {} {} {} - {}.",
+                n,
+                self.msg.copying_file,
+                source.display(),
+                self.msg.to,
+                destination.display(),
+                status
+            )
+        } else {
+            format!(
+                "#{} {} {} - {}.",
+                n,
+                self.msg.copying_file,
+                source.display(),
+                status
+            )
+        })
+    }
+}
+
+/// Observer writing a line per processed file to the file logger of a run.
+pub(crate) struct LogObserver {
+    lines: FileLines,
+    ctx: LogContext,
+}
+
+impl LogObserver {
+    /// The observer of the file logger of `options`, if it has one.
+    pub(crate) fn new(msg: &Messages, options: &LogContext) -> Option<Self> {
+        let logger = options.logger.clone()?;
+        Some(Self {
+            lines: FileLines::new(msg, options.show_skipped),
+            ctx: LogContext {
+                logger: Some(logger),
+                quiet: true,
+                with_timestamp: options.with_timestamp,
+                timestamp_format: options.timestamp_format.clone(),
+                on_log: true,
+                ..Default::default()
+            },
+        })
+    }
+}
+
+impl ProgressObserver for LogObserver {
+    fn on_event(&self, event: &ProgressEvent<'_>) {
+        if let Some(line) = self.lines.line(event) {
+            log_output(&line, &self.ctx);
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! Progress reporting for programs embedding rBackup.
+//!
+//! A `ProgressObserver` is told about each step of a copy run through a
+//! `ProgressEvent`: the plan, the start and outcome of every file, the bytes
+//! processed so far and the end of the run. Events are delivered from the
+//! worker threads, so observers must be thread-safe and should return
+//! quickly. Any `Fn(&ProgressEvent)` closure is an observer.
+//!
+//! The terminal displays of the binary (`--progress`) and the file logger
+//! are observers too: a run without them writes nothing to the terminal.
+
+use crate::error::Error;
+use std::fmt;
+use std::path::Path;
+
+/// One step of a copy run.
+// Library API; the built-in observers do not read every field.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub enum ProgressEvent<'a> {
+    /// Planning is done: `files` files totalling `bytes` bytes are to be
+    /// processed, over all `destinations`. In delta mode, unchanged files are
+    /// left out of the plan and get no event.
+    Planned {
+        files: usize,
+        bytes: u64,
+        destinations: usize,
+    },
+    /// Worker thread `worker` starts processing `source` for `destination`.
+    FileStarted {
+        worker: usize,
+        source: &'a Path,
+        destination: &'a Path,
+    },
+    /// `source` was copied to `destination`.
+    FileCopied {
+        source: &'a Path,
+        destination: &'a Path,
+        bytes: u64,
+    },
+    /// `source` was not copied to `destination`, for `reason`. Excluded
+    /// files are reported right after `Planned`.
+    FileSkipped {
+        source: &'a Path,
+        destination: &'a Path,
+        reason: SkipReason,
+    },
+    /// Copying `source` to `destination` failed.
+    FileFailed {
+        source: &'a Path,
+        destination: &'a Path,
+        error: &'a Error,
+    },
+    /// `done_files` of the planned files, totalling `done_bytes` of
+    /// `total_bytes` bytes, were processed. Sent every few files and after
+    /// the last one.
+    BytesProgress {
+        done_files: usize,
+        done_bytes: u64,
+        total_bytes: u64,
+    },
+    /// Every file was processed; the counts cover all the destinations.
+    Finished {
+        copied: usize,
+        skipped: usize,
+        failed: usize,
+    },
+}
+
+/// Why a file was not copied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    /// The destination is already up to date.
+    Unchanged,
+    /// The file matches an exclude pattern.
+    Excluded,
+}
+
+/// Receiver of the `ProgressEvent`s of a copy run.
+pub trait ProgressObserver: Send + Sync {
+    fn on_event(&self, event: &ProgressEvent<'_>);
+}
+
+impl<F> ProgressObserver for F
+where
+    F: Fn(&ProgressEvent<'_>) + Send + Sync,
+{
+    fn on_event(&self, event: &ProgressEvent<'_>) {
+        self(event)
+    }
+}
+
+impl fmt::Debug for dyn ProgressObserver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProgressObserver")
+    }
+}
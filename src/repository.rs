@@ -0,0 +1,551 @@
+//! Content-addressed, deduplicating backup repository.
+//!
+//! Instead of mirroring the source tree, a repository stores every distinct
+//! piece of content once, under its BLAKE3 hash, and records each run as a
+//! snapshot listing the objects that make up every file. Identical files,
+//! whether they come from the same machine or from different ones, share
+//! their objects. With content-defined chunking enabled, files are split at
+//! content-dependent boundaries so that files which only partially changed
+//! share most of their chunks as well.
+//!
+//! Layout of a repository directory:
+//!
+//! ```text
+//! .rbackup-repo.json        repository parameters (format version, chunking)
+//! objects/ab/abcdef...      contents, named after their BLAKE3 hash
+//! snapshots/<id>.json       one index per backup run
+//! ```
+
+use crate::check::ReadData;
+use crate::compress::{Compression, is_compressed_file};
+use crate::destination::TEMP_SUFFIX;
+use crate::manifest::{Manifest, ManifestEntry};
+use chacha20poly1305::aead::OsRng;
+use chacha20poly1305::aead::rand_core::RngCore;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// File holding the repository parameters, at the root of the repository.
+pub const REPO_CONFIG_FILE: &str = ".rbackup-repo.json";
+
+/// Current on-disk format version of repositories and snapshots.
+pub const REPO_VERSION: u32 = 1;
+
+/// Directory holding the content objects.
+pub const OBJECTS_DIR: &str = "objects";
+
+/// Directory holding the snapshot indexes.
+pub const SNAPSHOTS_DIR: &str = "snapshots";
+
+/// Parameters of content-defined chunking (FastCDC), in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Chunking {
+    pub min_size: u32,
+    pub avg_size: u32,
+    pub max_size: u32,
+}
+
+impl Default for Chunking {
+    fn default() -> Self {
+        Self {
+            min_size: 256 * 1024,
+            avg_size: 1024 * 1024,
+            max_size: 4 * 1024 * 1024,
+        }
+    }
+}
+
+/// Parameters stored in `REPO_CONFIG_FILE`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepoConfig {
+    /// Format version (see `REPO_VERSION`).
+    pub version: u32,
+    /// Local time at which the repository was created (RFC 3339).
+    pub created: String,
+    /// Chunking parameters, or `None` to store every file as one object.
+    pub chunking: Option<Chunking>,
+}
+
+/// A file recorded in a snapshot.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotEntry {
+    /// Size, modification time and hash of the whole file.
+    #[serde(flatten)]
+    pub file: ManifestEntry,
+    /// Objects whose concatenation gives the file contents.
+    pub chunks: Vec<String>,
+}
+
+/// Index of a backup run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Format version (see `REPO_VERSION`).
+    pub version: u32,
+    /// Identifier, also used as the file name of the snapshot.
+    pub id: String,
+    /// Local time at which the snapshot was created (RFC 3339).
+    pub created: String,
+    /// Host that produced the snapshot.
+    pub host: String,
+    /// Source directory that was backed up.
+    pub source: String,
+    /// Recorded files keyed by their relative path (see `manifest::manifest_key`).
+    pub files: BTreeMap<String, SnapshotEntry>,
+}
+
+impl Snapshot {
+    /// Create an empty snapshot of `source` taken now on this host.
+    ///
+    /// Identifiers sort chronologically; a random suffix keeps them unique
+    /// when several machines write to the same repository.
+    pub fn new(source: &Path) -> Self {
+        let now = chrono::Local::now();
+        let mut suffix = [0u8; 4];
+        OsRng.fill_bytes(&mut suffix);
+        Self {
+            version: REPO_VERSION,
+            id: format!("{}-{}", now.format("%Y%m%d-%H%M%S%6f"), hex::encode(suffix)),
+            created: now.to_rfc3339(),
+            host: host_name(),
+            source: source_key(source),
+            files: BTreeMap::new(),
+        }
+    }
+
+    /// View of the snapshot as a manifest, used for change detection.
+    pub fn to_manifest(&self) -> Manifest {
+        let mut manifest = Manifest {
+            created: self.created.clone(),
+            ..Default::default()
+        };
+        manifest.entries.extend(
+            self.files
+                .iter()
+                .map(|(key, entry)| (key.clone(), entry.file.clone())),
+        );
+        manifest
+    }
+}
+
+/// Outcome of `Repository::check`.
+#[derive(Debug, Default)]
+pub struct CheckReport {
+    /// Number of snapshots read.
+    pub snapshots: usize,
+    /// Number of distinct objects referenced by the snapshots.
+    pub objects: usize,
+    /// Snapshots that could not be read or parsed.
+    pub unreadable_snapshots: Vec<String>,
+    /// Referenced objects that do not exist.
+    pub missing_objects: Vec<String>,
+    /// Read objects whose contents do not match their name.
+    pub corrupt_objects: Vec<String>,
+}
+
+impl CheckReport {
+    /// Return true if no problem was found.
+    pub fn is_ok(&self) -> bool {
+        self.unreadable_snapshots.is_empty()
+            && self.missing_objects.is_empty()
+            && self.corrupt_objects.is_empty()
+    }
+}
+
+/// An opened repository.
+#[derive(Debug, Clone)]
+pub struct Repository {
+    root: PathBuf,
+    config: RepoConfig,
+}
+
+impl Repository {
+    /// Return true if `dir` contains a repository.
+    pub fn is_repository(dir: &Path) -> bool {
+        dir.join(REPO_CONFIG_FILE).is_file()
+    }
+
+    /// Create a new repository in `dir`.
+    ///
+    /// Nothing is written when `persist` is false, as in dry-run mode.
+    pub fn init(dir: &Path, chunking: Option<Chunking>, persist: bool) -> io::Result<Self> {
+        if Repository::is_repository(dir) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already contains a repository", dir.display()),
+            ));
+        }
+        let config = RepoConfig {
+            version: REPO_VERSION,
+            created: chrono::Local::now().to_rfc3339(),
+            chunking,
+        };
+        if persist {
+            fs::create_dir_all(dir.join(OBJECTS_DIR))?;
+            fs::create_dir_all(dir.join(SNAPSHOTS_DIR))?;
+            let data = serde_json::to_vec_pretty(&config)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            write_unique(&dir.join(REPO_CONFIG_FILE), |w| w.write_all(&data))?;
+        }
+        Ok(Self {
+            root: dir.to_owned(),
+            config,
+        })
+    }
+
+    /// Open the repository stored in `dir`.
+    pub fn open(dir: &Path) -> io::Result<Self> {
+        let data = fs::read(dir.join(REPO_CONFIG_FILE))?;
+        let config: RepoConfig = serde_json::from_slice(&data)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if config.version != REPO_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported repository version {}", config.version),
+            ));
+        }
+        Ok(Self {
+            root: dir.to_owned(),
+            config,
+        })
+    }
+
+    /// Open the repository in `dir`, creating it when it does not exist.
+    ///
+    /// `chunking` only applies to new repositories; existing ones keep the
+    /// parameters they were created with so that their objects stay shared.
+    pub fn open_or_init(dir: &Path, chunking: Option<Chunking>, persist: bool) -> io::Result<Self> {
+        if Repository::is_repository(dir) {
+            Repository::open(dir)
+        } else {
+            Repository::init(dir, chunking, persist)
+        }
+    }
+
+    /// Path of the object named `id`.
+    pub fn object_path(&self, id: &str) -> PathBuf {
+        let fanout = id.get(..2).unwrap_or("00");
+        self.root.join(OBJECTS_DIR).join(fanout).join(id)
+    }
+
+    /// Store the contents of the file at `path` and return the objects that
+    /// make it up.
+    ///
+    /// `hash` is the BLAKE3 hash of the whole file, as recorded in the
+    /// manifest; without chunking it is the name of the single object. Only
+    /// objects missing from the repository are written, compressed when
+    /// `compression` is given.
+    pub fn store_file(
+        &self,
+        path: &Path,
+        hash: &str,
+        compression: Option<&Compression>,
+    ) -> io::Result<Vec<String>> {
+        match self.config.chunking {
+            None => {
+                if !self.object_path(hash).exists() {
+                    let mut reader = HashingReader::new(BufReader::new(File::open(path)?));
+                    self.write_object(hash, &mut reader, compression, |r| {
+                        // The file changed since it was hashed.
+                        if r.finalize() != hash {
+                            return Err(io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                format!("{} changed while being stored", path.display()),
+                            ));
+                        }
+                        Ok(())
+                    })?;
+                }
+                Ok(vec![hash.to_string()])
+            }
+            Some(c) => {
+                let mut chunks = Vec::new();
+                let cdc = fastcdc::v2020::StreamCDC::new(
+                    BufReader::new(File::open(path)?),
+                    c.min_size,
+                    c.avg_size,
+                    c.max_size,
+                );
+                for chunk in cdc {
+                    let chunk = chunk.map_err(io::Error::from)?;
+                    let id = blake3::hash(&chunk.data).to_hex().to_string();
+                    if !self.object_path(&id).exists() {
+                        self.write_object(
+                            &id,
+                            &mut chunk.data.as_slice(),
+                            compression,
+                            |_| Ok(()),
+                        )?;
+                    }
+                    chunks.push(id);
+                }
+                Ok(chunks)
+            }
+        }
+    }
+
+    /// Open the object named `id`, decompressing it when needed.
+    pub fn read_object(&self, id: &str) -> io::Result<Box<dyn Read>> {
+        let path = self.object_path(id);
+        let file = BufReader::new(File::open(&path)?);
+        if is_compressed_file(&path)? {
+            Ok(Box::new(zstd::stream::read::Decoder::with_buffer(file)?))
+        } else {
+            Ok(Box::new(file))
+        }
+    }
+
+    /// Write the contents of `entry` into `writer`, verifying them against
+    /// the recorded hash.
+    pub fn restore_entry(&self, entry: &SnapshotEntry, writer: &mut impl Write) -> io::Result<()> {
+        let mut hasher = blake3::Hasher::new();
+        let mut buf = vec![0u8; 64 * 1024];
+        for id in &entry.chunks {
+            let mut object = self.read_object(id)?;
+            loop {
+                let n = object.read(&mut buf)?;
+                if n == 0 {
+                    break;
+                }
+                hasher.update(&buf[..n]);
+                writer.write_all(&buf[..n])?;
+            }
+        }
+        if hasher.finalize().to_hex().as_str() != entry.file.hash {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "restored contents do not match the recorded hash",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Identifiers of the stored snapshots, oldest first.
+    pub fn snapshot_ids(&self) -> io::Result<Vec<String>> {
+        let mut ids: Vec<String> = fs::read_dir(self.root.join(SNAPSHOTS_DIR))?
+            .filter_map(Result::ok)
+            .filter_map(|e| {
+                e.file_name()
+                    .to_str()
+                    .and_then(|n| n.strip_suffix(".json"))
+                    .map(str::to_string)
+            })
+            .collect();
+        ids.sort();
+        Ok(ids)
+    }
+
+    /// Load the snapshot named `id`.
+    pub fn load_snapshot(&self, id: &str) -> io::Result<Snapshot> {
+        let path = self.root.join(SNAPSHOTS_DIR).join(format!("{}.json", id));
+        let data = match fs::read(&path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("snapshot {} not found", id),
+                ));
+            }
+            other => other?,
+        };
+        serde_json::from_slice(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Load the most recent snapshot, or the most recent one taken of
+    /// `source` on this host when `source` is given.
+    pub fn latest_snapshot(&self, source: Option<&Path>) -> io::Result<Option<Snapshot>> {
+        let wanted = source.map(|s| (host_name(), source_key(s)));
+        for id in self.snapshot_ids()?.iter().rev() {
+            let snapshot = self.load_snapshot(id)?;
+            match &wanted {
+                Some((host, source)) if snapshot.host != *host || snapshot.source != *source => {}
+                _ => return Ok(Some(snapshot)),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Record a backup run of `source` and return the saved snapshot.
+    ///
+    /// `manifest` describes the source files that made it into the run and
+    /// `stored` the objects of the files written during it. Files that were
+    /// not rewritten reuse the objects recorded for the same contents in the
+    /// previous snapshot of `source`; files for which none can be found are
+    /// left out.
+    pub fn commit_snapshot(
+        &self,
+        source: &Path,
+        manifest: &Manifest,
+        stored: &HashMap<String, Vec<String>>,
+    ) -> io::Result<Snapshot> {
+        let previous = self.latest_snapshot(Some(source))?;
+        let mut snapshot = Snapshot::new(source);
+        for (key, file) in &manifest.entries {
+            let chunks = stored.get(key).cloned().or_else(|| {
+                previous
+                    .as_ref()
+                    .and_then(|p| p.files.get(key))
+                    .filter(|e| e.file.hash == file.hash)
+                    .map(|e| e.chunks.clone())
+            });
+            if let Some(chunks) = chunks {
+                snapshot.files.insert(
+                    key.clone(),
+                    SnapshotEntry {
+                        file: file.clone(),
+                        chunks,
+                    },
+                );
+            }
+        }
+        let data = serde_json::to_vec_pretty(&snapshot)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let dir = self.root.join(SNAPSHOTS_DIR);
+        fs::create_dir_all(&dir)?;
+        write_unique(&dir.join(format!("{}.json", snapshot.id)), |w| {
+            w.write_all(&data)
+        })?;
+        Ok(snapshot)
+    }
+
+    /// Verify that every object referenced by a snapshot exists and that the
+    /// contents of the objects selected by `read` still match their hash.
+    pub fn check(&self, read: ReadData) -> io::Result<CheckReport> {
+        let mut report = CheckReport::default();
+        let mut referenced = BTreeSet::new();
+        for id in self.snapshot_ids()? {
+            match self.load_snapshot(&id) {
+                Ok(snapshot) => {
+                    report.snapshots += 1;
+                    for entry in snapshot.files.values() {
+                        referenced.extend(entry.chunks.iter().cloned());
+                    }
+                }
+                Err(_) => report.unreadable_snapshots.push(id),
+            }
+        }
+        report.objects = referenced.len();
+        let to_read = read.select(referenced.iter().map(String::as_str));
+
+        let problems: Vec<(String, bool)> = referenced
+            .par_iter()
+            .filter_map(|id| {
+                if !self.object_path(id).is_file() {
+                    return Some((id.clone(), true));
+                }
+                if to_read.contains(id.as_str())
+                    && self.object_hash(id).ok().as_deref() != Some(id.as_str())
+                {
+                    return Some((id.clone(), false));
+                }
+                None
+            })
+            .collect();
+        for (id, missing) in problems {
+            if missing {
+                report.missing_objects.push(id);
+            } else {
+                report.corrupt_objects.push(id);
+            }
+        }
+        Ok(report)
+    }
+
+    fn object_hash(&self, id: &str) -> io::Result<String> {
+        let mut reader = HashingReader::new(self.read_object(id)?);
+        io::copy(&mut reader, &mut io::sink())?;
+        Ok(reader.finalize())
+    }
+
+    fn write_object<R: Read>(
+        &self,
+        id: &str,
+        reader: &mut R,
+        compression: Option<&Compression>,
+        verify: impl FnOnce(&R) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let path = self.object_path(id);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        write_unique(&path, |w| {
+            match compression {
+                Some(c) => {
+                    io::copy(&mut c.reader(&mut *reader)?, w)?;
+                }
+                None => {
+                    io::copy(reader, w)?;
+                }
+            }
+            verify(reader)
+        })
+    }
+}
+
+/// Reader that hashes everything read through it.
+struct HashingReader<R> {
+    inner: R,
+    hasher: blake3::Hasher,
+}
+
+impl<R> HashingReader<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: blake3::Hasher::new(),
+        }
+    }
+
+    fn finalize(&self) -> String {
+        self.hasher.finalize().to_hex().to_string()
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// Write `dest` through a uniquely named temporary sibling, so that several
+/// writers storing the same object at once never share a temporary file.
+fn write_unique(
+    dest: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut suffix = [0u8; 8];
+    OsRng.fill_bytes(&mut suffix);
+    let mut name = dest.as_os_str().to_owned();
+    name.push(format!(".{}{}", hex::encode(suffix), TEMP_SUFFIX));
+    let tmp = PathBuf::from(name);
+
+    let result = File::create(&tmp).and_then(|f| {
+        let mut w = BufWriter::new(f);
+        write(&mut w)?;
+        w.flush()
+    });
+    match result {
+        Ok(()) => fs::rename(&tmp, dest),
+        Err(e) => {
+            let _ = fs::remove_file(&tmp);
+            Err(e)
+        }
+    }
+}
+
+/// Name of this host, recorded in snapshots.
+fn host_name() -> String {
+    gethostname::gethostname().to_string_lossy().into_owned()
+}
+
+/// Source directory as recorded in snapshots.
+fn source_key(source: &Path) -> String {
+    fs::canonicalize(source)
+        .unwrap_or_else(|_| source.to_owned())
+        .to_string_lossy()
+        .into_owned()
+}
@@ -0,0 +1,264 @@
+//! Restore of backed-up files.
+//!
+//! A backup destination is either a plain tree of files, optionally
+//! compressed and/or encrypted, or a deduplicating repository. Trees are
+//! walked and every file is written back below a target directory,
+//! decrypting encrypted files with the keys carried by the `LogContext`;
+//! repositories are restored from one of their snapshots.
+
+use crate::compress::{COMPRESSED_EXTENSION, decompress_file, is_compressed_file};
+use crate::copy::flush_logger;
+use crate::crypto::{ENCRYPTED_EXTENSION, safe_rel_path, write_atomically};
+use crate::destination::TEMP_SUFFIX;
+use crate::manifest::{METADATA_PREFIX, manifest_key};
+use crate::output::{LogContext, log_output};
+use crate::repository::Repository;
+use crate::utils::Messages;
+use rayon::prelude::*;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use walkdir::WalkDir;
+
+/// Restore the backup stored in `backup_dir` into `target_dir`.
+///
+/// Encrypted files (`.enc`) are decrypted to the path recorded in their
+/// header when `options.encryption` is set, and files compressed by rBackup
+/// are decompressed; every other file is copied as is. rBackup metadata
+/// files (manifest, key parameters) are not restored.
+///
+/// Returns a tuple `(restored_count, failed_count)`.
+pub fn restore_tree(
+    backup_dir: &Path,
+    target_dir: &Path,
+    msg: &Messages,
+    options: &LogContext,
+) -> io::Result<(usize, usize)> {
+    if !backup_dir.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} is not a directory", backup_dir.display()),
+        ));
+    }
+
+    let mut entries: Vec<_> = WalkDir::new(backup_dir)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|e| e.file_type().is_file())
+        .filter(|e| {
+            let name = e.file_name().to_string_lossy();
+            !name.starts_with(METADATA_PREFIX) && !name.ends_with(TEMP_SUFFIX)
+        })
+        .collect();
+    entries.sort_by_key(|e| e.path().to_owned());
+
+    Ok(restore_all(
+        &entries,
+        |entry| entry.path().display().to_string(),
+        |entry| restore_file(entry.path(), backup_dir, target_dir, options),
+        msg,
+        options,
+    ))
+}
+
+/// Restore a snapshot of the repository stored in `repo_dir` into
+/// `target_dir`.
+///
+/// `snapshot` selects the snapshot by id; the most recent one is restored
+/// when it is `None`. Every file is verified against the hash recorded in
+/// the snapshot; in dry-run mode files are only read and verified.
+///
+/// Returns a tuple `(restored_count, failed_count)`.
+pub fn restore_snapshot(
+    repo_dir: &Path,
+    snapshot: Option<&str>,
+    target_dir: &Path,
+    msg: &Messages,
+    options: &LogContext,
+) -> io::Result<(usize, usize)> {
+    let repo = Repository::open(repo_dir)?;
+    let snapshot = match snapshot {
+        Some(id) => repo.load_snapshot(id)?,
+        None => repo.latest_snapshot(None)?.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "the repository has no snapshots")
+        })?,
+    };
+    let files: Vec<_> = snapshot.files.iter().collect();
+
+    Ok(restore_all(
+        &files,
+        |(key, _)| key.to_string(),
+        |(key, entry)| {
+            if options.dry_run {
+                repo.restore_entry(entry, &mut io::sink())?;
+            } else {
+                let dest_path = target_dir.join(safe_rel_path(key)?);
+                if let Some(parent) = dest_path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                write_atomically(&dest_path, |w| repo.restore_entry(entry, w))?;
+            }
+            Ok(key.to_string())
+        },
+        msg,
+        options,
+    ))
+}
+
+/// Restore `items` in parallel, printing one line per item, and return a
+/// tuple `(restored_count, failed_count)`.
+///
+/// `restore` returns the relative path of the restored file; `label` names
+/// an item whose restore failed.
+fn restore_all<T: Sync>(
+    items: &[T],
+    label: impl Fn(&T) -> String + Sync,
+    restore: impl Fn(&T) -> io::Result<String> + Sync,
+    msg: &Messages,
+    options: &LogContext,
+) -> (usize, usize) {
+    let restored = AtomicUsize::new(0);
+    let failed = AtomicUsize::new(0);
+    let done = AtomicUsize::new(0);
+
+    items.par_iter().for_each(|item| {
+        let result = restore(item);
+
+        let n = done.fetch_add(1, Ordering::Relaxed) + 1;
+        let line = match &result {
+            Ok(rel_key) => {
+                restored.fetch_add(1, Ordering::Relaxed);
+                format!(
+                    "#{} {} {} - {}.",
+                    n, msg.restoring_file, rel_key, msg.copied_file
+                )
+            }
+            Err(e) => {
+                failed.fetch_add(1, Ordering::Relaxed);
+                format!(
+                    "#{} {} {} - {}: {}",
+                    n,
+                    msg.restoring_file,
+                    label(item),
+                    msg.generic_error,
+                    e
+                )
+            }
+        };
+        log_output(&line, options);
+    });
+
+    (
+        restored.load(Ordering::Relaxed),
+        failed.load(Ordering::Relaxed),
+    )
+}
+
+/// Restore a single file and return its path relative to the backup source.
+fn restore_file(
+    src_path: &Path,
+    backup_dir: &Path,
+    target_dir: &Path,
+    options: &LogContext,
+) -> io::Result<String> {
+    let is_encrypted = src_path
+        .extension()
+        .is_some_and(|ext| ext == ENCRYPTED_EXTENSION);
+
+    match &options.encryption {
+        Some(enc) if is_encrypted => {
+            if options.dry_run {
+                // Decrypting into a sink still verifies the file and reveals its name.
+                enc.decrypt_stream(io::BufReader::new(fs::File::open(src_path)?), |_| {
+                    Ok(io::sink())
+                })
+            } else {
+                enc.decrypt_file(src_path, target_dir)
+            }
+        }
+        _ => {
+            let rel_path = src_path
+                .strip_prefix(backup_dir)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            // Files compressed by rBackup get their original name back.
+            let is_compressed = rel_path
+                .extension()
+                .is_some_and(|ext| ext == COMPRESSED_EXTENSION)
+                && is_compressed_file(src_path)?;
+            let rel_path = if is_compressed {
+                rel_path.with_extension("")
+            } else {
+                rel_path.to_owned()
+            };
+            if !options.dry_run {
+                let dest_path = target_dir.join(&rel_path);
+                if let Some(parent) = dest_path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                if is_compressed {
+                    decompress_file(src_path, &dest_path)?;
+                } else {
+                    fs::copy(src_path, &dest_path)?;
+                }
+            }
+            Ok(manifest_key(&rel_path))
+        }
+    }
+}
+
+/// Run a restore and print the start and summary messages.
+///
+/// Repositories are restored from `snapshot` (or their latest snapshot),
+/// other destinations with `restore_tree`. Mirrors `copy::execute_copy`:
+/// unrecoverable errors are reported and the process exits with a non-zero
+/// status after flushing the logger.
+pub fn execute_restore(
+    msg: &Messages,
+    ctx: &LogContext,
+    backup_dir: &Path,
+    target_dir: &Path,
+    snapshot: Option<&str>,
+) {
+    log_output(&msg.restore_init, ctx);
+    log_output(
+        &format!(
+            "{} {} {} {}\n",
+            msg.starting_restore,
+            backup_dir.display(),
+            msg.to,
+            target_dir.display()
+        ),
+        ctx,
+    );
+
+    let result = if Repository::is_repository(backup_dir) {
+        restore_snapshot(backup_dir, snapshot, target_dir, msg, ctx)
+    } else {
+        restore_tree(backup_dir, target_dir, msg, ctx)
+    };
+    match result {
+        Ok((restored, failed)) => {
+            log_output(
+                &format!(
+                    "\n{} ({}, {})",
+                    msg.restore_ended,
+                    msg.files_restored.replace("{}", &restored.to_string()),
+                    msg.files_failed.replace("{}", &failed.to_string())
+                ),
+                ctx,
+            );
+            flush_logger(ctx);
+            if failed > 0 {
+                std::process::exit(1);
+            }
+        }
+        Err(e) => {
+            let mut ctx = ctx.clone();
+            ctx.quiet = false;
+            log_output(&format!("{}: {}", msg.generic_error, e), &ctx);
+            flush_logger(&ctx);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,587 @@
+//! S3-compatible object storage destinations (`s3://bucket/prefix`).
+//!
+//! Paths below the destination map to object keys below the prefix, e.g.
+//! `s3://backup/laptop` puts `docs/a.txt` at the key `laptop/docs/a.txt`.
+//! Requests are signed with AWS Signature Version 4. The endpoint, region and
+//! credentials come from the usual AWS environment variables, so MinIO and
+//! other S3-compatible servers work by setting `AWS_ENDPOINT_URL`:
+//!
+//! - `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and optionally
+//!   `AWS_SESSION_TOKEN`;
+//! - `AWS_REGION` (or `AWS_DEFAULT_REGION`, default `us-east-1`);
+//! - `AWS_ENDPOINT_URL`, e.g. `http://localhost:9000`. Buckets are then
+//!   addressed by path (`<endpoint>/<bucket>/<key>`); without it, the AWS
+//!   endpoint of the region is used with the bucket as host name.
+//!
+//! Objects record the size, mtime and BLAKE3 hash of the source file as
+//! metadata (`x-amz-meta-rbackup-*`), so that unchanged files are recognized
+//! from a `HEAD` request without downloading anything. Files larger than the
+//! part size are sent as multipart uploads, aborted if the copy fails.
+
+use crate::destination::{DestFile, DestMetadata, Destination};
+use crate::manifest::hash_file;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use ureq::http;
+
+/// Size of the parts of a multipart upload, and the largest file sent with
+/// a single `PUT`.
+pub const DEFAULT_PART_SIZE: usize = 8 * 1024 * 1024;
+
+const TIMEOUT: Duration = Duration::from_secs(300);
+
+const META_SIZE: &str = "x-amz-meta-rbackup-size";
+const META_MTIME: &str = "x-amz-meta-rbackup-mtime";
+const META_HASH: &str = "x-amz-meta-rbackup-blake3";
+
+/// The parts of an `s3://` URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct S3Url {
+    pub bucket: String,
+    /// Key prefix, as an absolute path (`/` for the whole bucket).
+    pub prefix: PathBuf,
+}
+
+impl S3Url {
+    /// Parse what follows `s3://`: `bucket[/prefix]`.
+    pub fn parse(rest: &str) -> io::Result<S3Url> {
+        let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+        if bucket.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("s3://{}: missing bucket", rest),
+            ));
+        }
+        Ok(S3Url {
+            bucket: bucket.to_string(),
+            prefix: Path::new("/").join(prefix.trim_matches('/')),
+        })
+    }
+}
+
+impl fmt::Display for S3Url {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "s3://{}{}", self.bucket, self.prefix.display())
+    }
+}
+
+/// Access keys of an S3 account.
+#[derive(Clone, PartialEq, Eq)]
+pub struct S3Credentials {
+    pub access_key: String,
+    pub secret_key: String,
+    /// Token of temporary credentials, if any.
+    pub session_token: Option<String>,
+}
+
+impl fmt::Debug for S3Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("S3Credentials")
+            .field("access_key", &self.access_key)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Where and as whom requests are sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct S3Config {
+    /// `scheme://host[:port]` of an S3-compatible server, or `None` for AWS.
+    pub endpoint: Option<String>,
+    pub region: String,
+    pub credentials: S3Credentials,
+    /// Size of the parts of multipart uploads (at least 5 MiB on AWS),
+    /// `DEFAULT_PART_SIZE` by default.
+    pub part_size: usize,
+}
+
+impl S3Config {
+    /// Read the configuration from the AWS environment variables.
+    pub fn from_env() -> io::Result<S3Config> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let required = |name: &str| {
+            var(name).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, format!("{} is not set", name))
+            })
+        };
+        Ok(S3Config {
+            endpoint: var("AWS_ENDPOINT_URL"),
+            region: var("AWS_REGION")
+                .or_else(|| var("AWS_DEFAULT_REGION"))
+                .unwrap_or_else(|| "us-east-1".to_string()),
+            credentials: S3Credentials {
+                access_key: required("AWS_ACCESS_KEY_ID")?,
+                secret_key: required("AWS_SECRET_ACCESS_KEY")?,
+                session_token: var("AWS_SESSION_TOKEN"),
+            },
+            part_size: DEFAULT_PART_SIZE,
+        })
+    }
+}
+
+/// Percent-encode `text` as AWS expects: everything but unreserved
+/// characters, and `/` too unless `keep_slash`.
+pub(crate) fn uri_encode(text: &str, keep_slash: bool) -> String {
+    let mut out = String::with_capacity(text.len());
+    for b in text.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(b as char)
+            }
+            b'/' if keep_slash => out.push('/'),
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any size");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// A request to sign with AWS Signature Version 4.
+#[derive(Debug, Clone)]
+pub struct SigningRequest<'a> {
+    pub method: &'a str,
+    /// URI-encoded path, e.g. `/bucket/some%20key`.
+    pub path: &'a str,
+    /// Query parameters, not encoded.
+    pub query: &'a [(&'a str, &'a str)],
+    /// Headers to sign, `host` and `x-amz-*` included; names in lowercase.
+    pub headers: &'a [(String, String)],
+    /// Hex SHA-256 of the body (`x-amz-content-sha256`).
+    pub payload_hash: &'a str,
+}
+
+/// Query string of `query`, encoded and sorted as signatures expect.
+fn canonical_query(query: &[(&str, &str)]) -> String {
+    let mut pairs: Vec<String> = query
+        .iter()
+        .map(|(k, v)| format!("{}={}", uri_encode(k, false), uri_encode(v, false)))
+        .collect();
+    pairs.sort();
+    pairs.join("&")
+}
+
+impl SigningRequest<'_> {
+    /// The `Authorization` header of this request sent at `time`.
+    pub fn authorization(
+        &self,
+        credentials: &S3Credentials,
+        region: &str,
+        time: DateTime<Utc>,
+    ) -> String {
+        let mut headers: Vec<(&str, &str)> = self
+            .headers
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.trim()))
+            .collect();
+        headers.sort();
+        let signed_headers = headers
+            .iter()
+            .map(|(k, _)| *k)
+            .collect::<Vec<_>>()
+            .join(";");
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(k, v)| format!("{}:{}\n", k, v))
+            .collect();
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            self.method,
+            self.path,
+            canonical_query(self.query),
+            canonical_headers,
+            signed_headers,
+            self.payload_hash
+        );
+        let date = time.format("%Y%m%d").to_string();
+        let scope = format!("{}/{}/s3/aws4_request", date, region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            time.format("%Y%m%dT%H%M%SZ"),
+            scope,
+            sha256_hex(canonical_request.as_bytes())
+        );
+        let key = [region, "s3", "aws4_request"].iter().fold(
+            hmac(format!("AWS4{}", credentials.secret_key).as_bytes(), &date),
+            |key, part| hmac(&key, part),
+        );
+        let signature = hex::encode(hmac(&key, &string_to_sign));
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            credentials.access_key, scope, signed_headers, signature
+        )
+    }
+}
+
+/// Text of the first `<tag>` element of an XML reply.
+fn xml_text<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let start = xml.find(&format!("<{}>", tag))? + tag.len() + 2;
+    let len = xml[start..].find(&format!("</{}>", tag))?;
+    Some(&xml[start..start + len])
+}
+
+/// `mtime` as recorded in object metadata: seconds and nanoseconds.
+fn mtime_text(mtime: SystemTime) -> String {
+    let d = mtime.duration_since(UNIX_EPOCH).unwrap_or_default();
+    format!("{}.{:09}", d.as_secs(), d.subsec_nanos())
+}
+
+fn parse_mtime(text: &str) -> Option<SystemTime> {
+    let (secs, nanos) = text.split_once('.').unwrap_or((text, "0"));
+    Some(UNIX_EPOCH + Duration::new(secs.parse().ok()?, nanos.parse().ok()?))
+}
+
+/// A bucket of an S3-compatible server.
+pub struct S3Destination {
+    agent: ureq::Agent,
+    config: S3Config,
+    /// `scheme://host[:port]` requests go to.
+    base: String,
+    /// `host[:port]`, signed with every request.
+    host: String,
+    /// Path before the keys: `/bucket` when buckets are addressed by path.
+    bucket_path: String,
+    part_size: usize,
+}
+
+impl fmt::Debug for S3Destination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("S3Destination")
+            .field("base", &self.base)
+            .field("bucket_path", &self.bucket_path)
+            .field("part_size", &self.part_size)
+            .finish_non_exhaustive()
+    }
+}
+
+impl S3Destination {
+    /// The bucket of `url`, configured from the environment.
+    pub fn connect(url: &S3Url) -> io::Result<S3Destination> {
+        S3Destination::new(S3Config::from_env()?, &url.bucket)
+    }
+
+    pub fn new(config: S3Config, bucket: &str) -> io::Result<S3Destination> {
+        let (base, bucket_path) = match &config.endpoint {
+            Some(endpoint) => (
+                endpoint.trim_end_matches('/').to_string(),
+                format!("/{}", uri_encode(bucket, false)),
+            ),
+            None => (
+                format!("https://{}.s3.{}.amazonaws.com", bucket, config.region),
+                String::new(),
+            ),
+        };
+        let host = base
+            .split_once("://")
+            .map(|(_, host)| host)
+            .filter(|host| !host.is_empty() && !host.contains('/'))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{}: invalid S3 endpoint", base),
+                )
+            })?
+            .to_string();
+        let agent = ureq::Agent::config_builder()
+            .timeout_global(Some(TIMEOUT))
+            .http_status_as_error(false)
+            .build()
+            .into();
+        Ok(S3Destination {
+            agent,
+            part_size: config.part_size.max(1),
+            config,
+            base,
+            host,
+            bucket_path,
+        })
+    }
+
+    /// Object key of a destination path.
+    fn key(path: &Path) -> String {
+        path.to_string_lossy()
+            .replace('\\', "/")
+            .trim_start_matches('/')
+            .to_string()
+    }
+
+    /// Send a signed request about `key`. Replies other than 2xx are errors.
+    fn request(
+        &self,
+        method: &str,
+        key: &str,
+        query: &[(&str, &str)],
+        extra_headers: &[(String, String)],
+        body: &[u8],
+    ) -> io::Result<http::Response<ureq::Body>> {
+        let path = format!("{}/{}", self.bucket_path, uri_encode(key, true));
+        let payload_hash = sha256_hex(body);
+        let time = Utc::now();
+        let mut headers = vec![
+            ("host".to_string(), self.host.clone()),
+            ("x-amz-content-sha256".to_string(), payload_hash.clone()),
+            (
+                "x-amz-date".to_string(),
+                time.format("%Y%m%dT%H%M%SZ").to_string(),
+            ),
+        ];
+        if let Some(token) = &self.config.credentials.session_token {
+            headers.push(("x-amz-security-token".to_string(), token.clone()));
+        }
+        headers.extend_from_slice(extra_headers);
+        let authorization = SigningRequest {
+            method,
+            path: &path,
+            query,
+            headers: &headers,
+            payload_hash: &payload_hash,
+        }
+        .authorization(&self.config.credentials, &self.config.region, time);
+
+        let mut uri = format!("{}{}", self.base, path);
+        if !query.is_empty() {
+            uri = format!("{}?{}", uri, canonical_query(query));
+        }
+        let mut request = http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("authorization", authorization);
+        for (name, value) in &headers {
+            request = request.header(name, value);
+        }
+        if matches!(method, "PUT" | "POST") {
+            // S3 wants a length even for empty bodies.
+            request = request.header("content-length", body.len());
+        }
+        let request = request.body(body).map_err(io::Error::other)?;
+        let mut response = self.agent.run(request).map_err(io::Error::other)?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let reply = response.body_mut().read_to_string().unwrap_or_default();
+        let kind = match status.as_u16() {
+            404 => io::ErrorKind::NotFound,
+            401 | 403 => io::ErrorKind::PermissionDenied,
+            _ => io::ErrorKind::Other,
+        };
+        let reason = xml_text(&reply, "Message")
+            .or_else(|| xml_text(&reply, "Code"))
+            .unwrap_or(status.as_str());
+        Err(io::Error::new(
+            kind,
+            format!("s3: {} {}: {}", method, key, reason),
+        ))
+    }
+
+    /// Headers of the object `key`, or `None` if there is none.
+    fn head(&self, key: &str) -> io::Result<Option<http::HeaderMap>> {
+        match self.request("HEAD", key, &[], &[], &[]) {
+            Ok(response) => Ok(Some(response.headers().clone())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+fn header<'a>(headers: &'a http::HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
+
+impl Destination for S3Destination {
+    fn stat(&self, path: &Path) -> io::Result<Option<DestMetadata>> {
+        let Some(headers) = self.head(&Self::key(path))? else {
+            return Ok(None);
+        };
+        let modified = header(&headers, META_MTIME)
+            .and_then(parse_mtime)
+            .or_else(|| {
+                header(&headers, "last-modified")
+                    .and_then(|t| DateTime::parse_from_rfc2822(t).ok())
+                    .map(SystemTime::from)
+            });
+        Ok(Some(DestMetadata {
+            len: header(&headers, "content-length")
+                .and_then(|l| l.parse().ok())
+                .unwrap_or(0),
+            is_dir: false,
+            modified,
+        }))
+    }
+
+    /// Object stores have no directories: keys are created with the objects.
+    fn create_dir_all(&self, _path: &Path) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn create(&self, path: &Path, source: &Path) -> io::Result<Box<dyn DestFile + '_>> {
+        let meta = fs::metadata(source)?;
+        let metadata = vec![
+            (META_HASH.to_string(), hash_file(source)?),
+            (META_MTIME.to_string(), mtime_text(meta.modified()?)),
+            (META_SIZE.to_string(), meta.len().to_string()),
+        ];
+        Ok(Box::new(S3File {
+            dest: self,
+            key: Self::key(path),
+            metadata,
+            buf: Vec::new(),
+            upload: None,
+            done: false,
+        }))
+    }
+
+    /// Compares the size and mtime of `src` with those recorded in the
+    /// metadata of the object; objects without them are copied again.
+    fn is_outdated(&self, src: &Path, dest: &Path) -> io::Result<bool> {
+        let meta = fs::metadata(src)?;
+        let Some(headers) = self.head(&Self::key(dest))? else {
+            return Ok(true);
+        };
+        let size = header(&headers, META_SIZE).and_then(|s| s.parse::<u64>().ok());
+        let mtime = header(&headers, META_MTIME).and_then(parse_mtime);
+        Ok(size != Some(meta.len()) || mtime != Some(meta.modified()?))
+    }
+}
+
+/// A multipart upload in progress.
+struct Upload {
+    id: String,
+    etags: Vec<String>,
+}
+
+/// An object being uploaded: a single `PUT` when it fits in one part.
+struct S3File<'a> {
+    dest: &'a S3Destination,
+    key: String,
+    /// `x-amz-meta-*` headers of the object.
+    metadata: Vec<(String, String)>,
+    buf: Vec<u8>,
+    upload: Option<Upload>,
+    done: bool,
+}
+
+impl S3File<'_> {
+    /// Send the buffer as the next part, starting the upload if needed.
+    fn send_part(&mut self) -> io::Result<()> {
+        let dest = self.dest;
+        if self.upload.is_none() {
+            let mut response =
+                dest.request("POST", &self.key, &[("uploads", "")], &self.metadata, &[])?;
+            let reply = response
+                .body_mut()
+                .read_to_string()
+                .map_err(io::Error::other)?;
+            let id =
+                xml_text(&reply, "UploadId").ok_or_else(|| io::Error::other("s3: no upload id"))?;
+            self.upload = Some(Upload {
+                id: id.to_string(),
+                etags: Vec::new(),
+            });
+        }
+        let Some(upload) = self.upload.as_mut() else {
+            return Ok(());
+        };
+        let number = (upload.etags.len() + 1).to_string();
+        let response = dest.request(
+            "PUT",
+            &self.key,
+            &[("partNumber", &number), ("uploadId", &upload.id)],
+            &[],
+            &self.buf,
+        )?;
+        let etag = header(response.headers(), "etag")
+            .ok_or_else(|| io::Error::other("s3: no part ETag"))?;
+        upload.etags.push(etag.to_string());
+        self.buf.clear();
+        Ok(())
+    }
+}
+
+impl Write for S3File<'_> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let n = data.len().min(self.dest.part_size - self.buf.len());
+        self.buf.extend_from_slice(&data[..n]);
+        if self.buf.len() == self.dest.part_size {
+            self.send_part()?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl DestFile for S3File<'_> {
+    fn commit(mut self: Box<Self>) -> io::Result<()> {
+        if self.upload.is_none() {
+            self.dest
+                .request("PUT", &self.key, &[], &self.metadata, &self.buf)?;
+            self.done = true;
+            return Ok(());
+        }
+        if !self.buf.is_empty() {
+            self.send_part()?;
+        }
+        let Some(upload) = &self.upload else {
+            return Ok(());
+        };
+        let parts: String = upload
+            .etags
+            .iter()
+            .enumerate()
+            .map(|(i, etag)| {
+                format!(
+                    "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+                    i + 1,
+                    etag
+                )
+            })
+            .collect();
+        let body = format!(
+            "<CompleteMultipartUpload>{}</CompleteMultipartUpload>",
+            parts
+        );
+        let mut response = self.dest.request(
+            "POST",
+            &self.key,
+            &[("uploadId", &upload.id)],
+            &[],
+            body.as_bytes(),
+        )?;
+        // Completion can fail after the reply has started: 200 with an error.
+        let reply = response
+            .body_mut()
+            .read_to_string()
+            .map_err(io::Error::other)?;
+        if let Some(code) = xml_text(&reply, "Code") {
+            return Err(io::Error::other(format!("s3: {}: {}", self.key, code)));
+        }
+        self.done = true;
+        Ok(())
+    }
+}
+
+impl Drop for S3File<'_> {
+    fn drop(&mut self) {
+        if let (false, Some(upload)) = (self.done, &self.upload) {
+            let _ = self
+                .dest
+                .request("DELETE", &self.key, &[("uploadId", &upload.id)], &[], &[]);
+        }
+    }
+}
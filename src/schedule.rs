@@ -0,0 +1,591 @@
+//! Scheduled backups through the system scheduler.
+//!
+//! `rbackup schedule` turns a cron expression and a `copy` command line (or a
+//! configuration profile) into either a user systemd service and timer or a
+//! crontab entry, and lists or removes the schedules it installed. Installed
+//! items are recognized by their name: `rbackup-<NAME>.service` /
+//! `rbackup-<NAME>.timer` units, or crontab lines tagged `# rbackup:<NAME>`.
+
+use chrono::{Datelike, NaiveDate, NaiveDateTime, TimeDelta, Timelike};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Prefix of the systemd units installed by rBackup.
+pub const UNIT_PREFIX: &str = "rbackup-";
+/// Comment tagging the crontab lines installed by rBackup.
+pub const CRON_TAG: &str = "# rbackup:";
+/// Comment carrying the original cron expression in a generated timer.
+const TIMER_EXPR_COMMENT: &str = "# rbackup schedule: ";
+
+/// System scheduler used to run a schedule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// User systemd service and timer under `~/.config/systemd/user`.
+    Systemd,
+    /// Entry in the user crontab.
+    Cron,
+}
+
+impl Backend {
+    /// Systemd when running on Linux with `systemctl` available, cron otherwise.
+    pub fn detect() -> Backend {
+        let systemctl = Command::new("systemctl")
+            .arg("--version")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|s| s.success());
+        if cfg!(target_os = "linux") && systemctl {
+            Backend::Systemd
+        } else {
+            Backend::Cron
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Backend::Systemd => "systemd",
+            Backend::Cron => "cron",
+        })
+    }
+}
+
+/// One element of a cron field: `first-last/step`, where `*` covers the
+/// whole range of the field and a single value has `first == last`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Part {
+    first: u32,
+    last: u32,
+    step: u32,
+    any: bool,
+}
+
+impl Part {
+    fn values(&self) -> impl Iterator<Item = u32> {
+        (self.first..=self.last).step_by(self.step as usize)
+    }
+
+    fn contains(&self, v: u32) -> bool {
+        (self.first..=self.last).contains(&v) && (v - self.first).is_multiple_of(self.step)
+    }
+}
+
+/// Names and ranges of the five cron fields.
+const FIELDS: [(&str, u32, u32); 5] = [
+    ("minute", 0, 59),
+    ("hour", 0, 23),
+    ("day of month", 1, 31),
+    ("month", 1, 12),
+    ("day of week", 0, 7),
+];
+
+/// A standard five-field cron expression (`minute hour day month weekday`).
+///
+/// Fields accept `*`, numbers, ranges (`1-5`), steps (`*/15`, `0-30/10`) and
+/// comma-separated lists. The `@hourly`, `@daily` (`@midnight`), `@weekly`,
+/// `@monthly` and `@yearly` (`@annually`) shortcuts are also accepted.
+///
+/// # Example
+///
+/// ```rust
+/// use rbackup::schedule::CronExpr;
+///
+/// let expr = CronExpr::parse("30 2 * * 1-5").unwrap();
+/// assert_eq!(expr.to_on_calendar().unwrap(), "Mon,Tue,Wed,Thu,Fri *-*-* 02:30:00");
+/// assert!(CronExpr::parse("61 * * * *").is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronExpr {
+    source: String,
+    fields: [Vec<Part>; 5],
+}
+
+impl CronExpr {
+    /// Parse and validate a cron expression.
+    pub fn parse(expr: &str) -> io::Result<Self> {
+        let expr = expr.trim();
+        let expanded = match expr {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+        let tokens: Vec<&str> = expanded.split_whitespace().collect();
+        if tokens.len() != 5 {
+            return Err(invalid(format!(
+                "invalid cron expression '{}': expected 5 fields",
+                expr
+            )));
+        }
+        let mut fields: [Vec<Part>; 5] = Default::default();
+        for (i, token) in tokens.iter().enumerate() {
+            fields[i] = parse_field(token, FIELDS[i])
+                .map_err(|e| invalid(format!("invalid cron expression '{}': {}", expr, e)))?;
+        }
+        Ok(Self {
+            source: expr.to_string(),
+            fields,
+        })
+    }
+
+    /// Next time strictly after `after` (at minute precision) matching the
+    /// expression, or `None` if there is none within the next five years.
+    ///
+    /// As in cron, when both the day of month and the day of week are
+    /// restricted a day matches if either does.
+    pub fn next_after(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        let matches = |i: usize, v: u32| self.fields[i].iter().any(|p| p.contains(v));
+        let any = |i: usize| self.fields[i].iter().any(|p| p.any && p.step == 1);
+        let day_matches = |t: &NaiveDateTime| {
+            let dom = matches(2, t.day());
+            let weekday = t.weekday().num_days_from_sunday();
+            let dow = matches(4, weekday) || (weekday == 0 && matches(4, 7));
+            match (any(2), any(4)) {
+                (false, false) => dom || dow,
+                _ => dom && dow,
+            }
+        };
+
+        let mut t = after.with_second(0)?.with_nanosecond(0)? + TimeDelta::minutes(1);
+        let limit = after + TimeDelta::days(5 * 366);
+        while t <= limit {
+            let midnight = t.date().and_hms_opt(0, 0, 0)?;
+            if !matches(3, t.month()) {
+                let (y, m) = if t.month() == 12 {
+                    (t.year() + 1, 1)
+                } else {
+                    (t.year(), t.month() + 1)
+                };
+                t = NaiveDate::from_ymd_opt(y, m, 1)?.and_hms_opt(0, 0, 0)?;
+            } else if !day_matches(&t) {
+                t = midnight + TimeDelta::days(1);
+            } else if !matches(1, t.hour()) {
+                t = t.with_minute(0)? + TimeDelta::hours(1);
+            } else if !matches(0, t.minute()) {
+                t += TimeDelta::minutes(1);
+            } else {
+                return Some(t);
+            }
+        }
+        None
+    }
+
+    /// Translate the expression into a systemd `OnCalendar=` value.
+    ///
+    /// Fails when both the day of month and the day of week are restricted:
+    /// cron runs when either matches, systemd only when both do.
+    pub fn to_on_calendar(&self) -> io::Result<String> {
+        let restricted = |i: usize| !self.fields[i].iter().any(|p| p.any && p.step == 1);
+        if restricted(2) && restricted(4) {
+            return Err(invalid(format!(
+                "cron expression '{}' restricts both the day of month and the day of week, \
+                 which systemd cannot express",
+                self.source
+            )));
+        }
+
+        let field = |i: usize, width: usize| -> String {
+            self.fields[i]
+                .iter()
+                .map(|p| match (p.any, p.step) {
+                    (true, 1) => "*".to_string(),
+                    (true, step) => format!("{:0w$}/{}", p.first, step, w = width),
+                    (false, 1) if p.first == p.last => format!("{:0w$}", p.first, w = width),
+                    (false, 1) => format!("{:0w$}..{:0w$}", p.first, p.last, w = width),
+                    (false, _) => p
+                        .values()
+                        .map(|v| format!("{:0w$}", v, w = width))
+                        .collect::<Vec<_>>()
+                        .join(","),
+                })
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+
+        let mut calendar = String::new();
+        if restricted(4) {
+            const DAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+            let mut days = [false; 7];
+            for v in self.fields[4].iter().flat_map(Part::values) {
+                // Cron counts from Sunday (0 or 7), systemd from Monday.
+                days[(v as usize + 6) % 7] = true;
+            }
+            let names: Vec<&str> = DAYS
+                .iter()
+                .zip(days)
+                .filter_map(|(name, on)| on.then_some(*name))
+                .collect();
+            calendar.push_str(&names.join(","));
+            calendar.push(' ');
+        }
+        calendar.push_str(&format!(
+            "*-{}-{} {}:{}:00",
+            field(3, 2),
+            field(2, 2),
+            field(1, 2),
+            field(0, 2)
+        ));
+        Ok(calendar)
+    }
+}
+
+impl fmt::Display for CronExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+fn parse_field(token: &str, (name, min, max): (&str, u32, u32)) -> Result<Vec<Part>, String> {
+    let number = |s: &str| -> Result<u32, String> {
+        let v: u32 = s
+            .parse()
+            .map_err(|_| format!("'{}' is not a number in the {} field", s, name))?;
+        if v < min || v > max {
+            return Err(format!(
+                "{} is out of range {}-{} in the {} field",
+                v, min, max, name
+            ));
+        }
+        Ok(v)
+    };
+
+    token
+        .split(',')
+        .map(|item| {
+            let (range, step) = match item.split_once('/') {
+                Some((range, step)) => {
+                    let step: u32 =
+                        step.parse().ok().filter(|s| *s > 0).ok_or_else(|| {
+                            format!("invalid step '{}' in the {} field", step, name)
+                        })?;
+                    (range, step)
+                }
+                None => (item, 1),
+            };
+            let (first, last, any) = match range {
+                "*" => (min, max, true),
+                _ => match range.split_once('-') {
+                    Some((a, b)) => (number(a)?, number(b)?, false),
+                    // `n/step` runs from n to the end of the field.
+                    None if step > 1 => (number(range)?, max, false),
+                    None => {
+                        let v = number(range)?;
+                        (v, v, false)
+                    }
+                },
+            };
+            if first > last {
+                return Err(format!("invalid range '{}' in the {} field", range, name));
+            }
+            Ok(Part {
+                first,
+                last,
+                step,
+                any,
+            })
+        })
+        .collect()
+}
+
+/// A schedule installed by rBackup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduleEntry {
+    pub name: String,
+    pub backend: Backend,
+    /// Cron expression (or `OnCalendar=` value for timers edited by hand).
+    pub expression: String,
+    /// Command line run by the scheduler.
+    pub command: String,
+}
+
+/// Check that `name` can be used in unit file names and crontab tags.
+pub fn validate_name(name: &str) -> io::Result<()> {
+    if !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        Ok(())
+    } else {
+        Err(invalid(format!(
+            "invalid schedule name '{}': use letters, digits, '-' and '_'",
+            name
+        )))
+    }
+}
+
+/// Quote a command-line word for a systemd `ExecStart=` line.
+fn systemd_quote(word: &str) -> String {
+    let escaped = word.replace('%', "%%").replace('$', "$$");
+    if !escaped.is_empty()
+        && !escaped
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, '"' | '\'' | '\\' | ';'))
+    {
+        return escaped;
+    }
+    format!("\"{}\"", escaped.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Quote a command-line word for `sh`, as used by cron.
+fn shell_quote(word: &str) -> String {
+    if !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_./:=@,+-".contains(c))
+    {
+        return word.to_string();
+    }
+    format!("'{}'", word.replace('\'', "'\\''"))
+}
+
+/// Render the user service and timer running `exe args...` on `expr`.
+///
+/// Returns `(service, timer)` file contents.
+pub fn systemd_units(
+    name: &str,
+    expr: &CronExpr,
+    exe: &Path,
+    args: &[String],
+) -> io::Result<(String, String)> {
+    let exec: Vec<String> = std::iter::once(exe.to_string_lossy().into_owned())
+        .chain(args.iter().cloned())
+        .map(|w| systemd_quote(&w))
+        .collect();
+    let service = format!(
+        "[Unit]\n\
+         Description=rBackup scheduled backup '{name}'\n\
+         \n\
+         [Service]\n\
+         Type=oneshot\n\
+         ExecStart={}\n",
+        exec.join(" ")
+    );
+    let timer = format!(
+        "{TIMER_EXPR_COMMENT}{expr}\n\
+         [Unit]\n\
+         Description=rBackup schedule '{name}'\n\
+         \n\
+         [Timer]\n\
+         OnCalendar={}\n\
+         Persistent=true\n\
+         \n\
+         [Install]\n\
+         WantedBy=timers.target\n",
+        expr.to_on_calendar()?
+    );
+    Ok((service, timer))
+}
+
+/// Render the crontab line running `exe args...` on `expr`.
+pub fn crontab_line(name: &str, expr: &CronExpr, exe: &Path, args: &[String]) -> String {
+    let command: Vec<String> = std::iter::once(exe.to_string_lossy().into_owned())
+        .chain(args.iter().cloned())
+        .map(|w| shell_quote(&w))
+        .collect();
+    // An unescaped `%` ends the command in a crontab.
+    format!(
+        "{} {} {}{}",
+        expr,
+        command.join(" ").replace('%', "\\%"),
+        CRON_TAG,
+        name
+    )
+}
+
+/// Return `crontab` without the line tagged with `name`, followed by `line`
+/// when given.
+pub fn crontab_with(crontab: &str, name: &str, line: Option<&str>) -> String {
+    let tag = format!("{}{}", CRON_TAG, name);
+    let mut out: String = crontab
+        .lines()
+        .filter(|l| !l.trim_end().ends_with(&tag))
+        .map(|l| format!("{}\n", l))
+        .collect();
+    if let Some(line) = line {
+        out.push_str(line);
+        out.push('\n');
+    }
+    out
+}
+
+/// List the rBackup entries of a crontab.
+pub fn parse_crontab(crontab: &str) -> Vec<ScheduleEntry> {
+    crontab
+        .lines()
+        .filter(|l| !l.trim_start().starts_with('#'))
+        .filter_map(|l| {
+            let (body, name) = l.trim_end().rsplit_once(CRON_TAG)?;
+            validate_name(name).ok()?;
+            let body = body.trim();
+            let fields = if body.starts_with('@') { 1 } else { 5 };
+            let mut split = body.splitn(fields + 1, char::is_whitespace);
+            let expression: Vec<&str> = split.by_ref().take(fields).collect();
+            Some(ScheduleEntry {
+                name: name.to_string(),
+                backend: Backend::Cron,
+                expression: expression.join(" "),
+                command: split.next().unwrap_or_default().trim().to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Directory of the user systemd units.
+pub fn systemd_dir() -> PathBuf {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(env::var_os("HOME").unwrap_or_default()).join(".config"));
+    base.join("systemd").join("user")
+}
+
+fn run(cmd: &mut Command) -> io::Result<()> {
+    let status = cmd.status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("{:?} failed: {}", cmd, status)))
+    }
+}
+
+fn systemctl(args: &[&str]) -> io::Result<()> {
+    run(Command::new("systemctl").arg("--user").args(args))
+}
+
+fn read_crontab() -> io::Result<String> {
+    let output = Command::new("crontab").arg("-l").output()?;
+    // A user without a crontab gets a non-zero status.
+    Ok(if output.status.success() {
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    } else {
+        String::new()
+    })
+}
+
+fn write_crontab(content: &str) -> io::Result<()> {
+    let mut child = Command::new("crontab")
+        .arg("-")
+        .stdin(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(content.as_bytes())?;
+    }
+    let status = child.wait()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("crontab failed: {}", status)))
+    }
+}
+
+/// Install (or replace) the schedule `name` running `exe args...` on `expr`.
+pub fn install(
+    backend: Backend,
+    name: &str,
+    expr: &CronExpr,
+    exe: &Path,
+    args: &[String],
+) -> io::Result<()> {
+    validate_name(name)?;
+    match backend {
+        Backend::Systemd => {
+            let (service, timer) = systemd_units(name, expr, exe, args)?;
+            let dir = systemd_dir();
+            fs::create_dir_all(&dir)?;
+            fs::write(dir.join(format!("{UNIT_PREFIX}{name}.service")), service)?;
+            fs::write(dir.join(format!("{UNIT_PREFIX}{name}.timer")), timer)?;
+            systemctl(&["daemon-reload"])?;
+            systemctl(&["enable", "--now", &format!("{UNIT_PREFIX}{name}.timer")])
+        }
+        Backend::Cron => {
+            let line = crontab_line(name, expr, exe, args);
+            write_crontab(&crontab_with(&read_crontab()?, name, Some(&line)))
+        }
+    }
+}
+
+/// List the schedules installed with `backend`.
+pub fn list(backend: Backend) -> io::Result<Vec<ScheduleEntry>> {
+    match backend {
+        Backend::Systemd => {
+            let dir = systemd_dir();
+            let Ok(entries) = fs::read_dir(&dir) else {
+                return Ok(Vec::new());
+            };
+            let mut found = Vec::new();
+            for entry in entries.filter_map(Result::ok) {
+                let file_name = entry.file_name().to_string_lossy().into_owned();
+                let Some(name) = file_name
+                    .strip_prefix(UNIT_PREFIX)
+                    .and_then(|n| n.strip_suffix(".timer"))
+                else {
+                    continue;
+                };
+                let timer = fs::read_to_string(entry.path())?;
+                let service = fs::read_to_string(dir.join(format!("{UNIT_PREFIX}{name}.service")))
+                    .unwrap_or_default();
+                let value = |content: &str, prefix: &str| {
+                    content
+                        .lines()
+                        .find_map(|l| l.strip_prefix(prefix))
+                        .unwrap_or_default()
+                        .to_string()
+                };
+                let expression = match value(&timer, TIMER_EXPR_COMMENT) {
+                    e if e.is_empty() => value(&timer, "OnCalendar="),
+                    e => e,
+                };
+                found.push(ScheduleEntry {
+                    name: name.to_string(),
+                    backend,
+                    expression,
+                    command: value(&service, "ExecStart="),
+                });
+            }
+            found.sort_by(|a, b| a.name.cmp(&b.name));
+            Ok(found)
+        }
+        Backend::Cron => Ok(parse_crontab(&read_crontab()?)),
+    }
+}
+
+/// Remove the schedule `name`; returns false when it was not installed.
+pub fn remove(backend: Backend, name: &str) -> io::Result<bool> {
+    validate_name(name)?;
+    match backend {
+        Backend::Systemd => {
+            let dir = systemd_dir();
+            let timer = dir.join(format!("{UNIT_PREFIX}{name}.timer"));
+            if !timer.exists() {
+                return Ok(false);
+            }
+            // The timer may already be stopped or disabled.
+            let _ = systemctl(&["disable", "--now", &format!("{UNIT_PREFIX}{name}.timer")]);
+            fs::remove_file(timer)?;
+            let _ = fs::remove_file(dir.join(format!("{UNIT_PREFIX}{name}.service")));
+            systemctl(&["daemon-reload"])?;
+            Ok(true)
+        }
+        Backend::Cron => {
+            let crontab = read_crontab()?;
+            let updated = crontab_with(&crontab, name, None);
+            if updated.lines().count() == crontab.lines().count() {
+                return Ok(false);
+            }
+            write_crontab(&updated)?;
+            Ok(true)
+        }
+    }
+}
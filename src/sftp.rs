@@ -0,0 +1,598 @@
+//! SFTP destinations (`sftp://[user@]host[:port]/path`).
+//!
+//! The connection is made by the system `ssh` client running the `sftp`
+//! subsystem, like `sftp(1)` does, so host keys, agents, keys and
+//! `~/.ssh/config` work as usual. `RBACKUP_SSH` replaces the `ssh` command,
+//! e.g. `RBACKUP_SSH="ssh -i ~/.ssh/backup_key"`.
+//!
+//! Only the part of SFTP version 3 needed to back up files is implemented:
+//! stat, mkdir, writing a temporary file and renaming it over the final name
+//! (with the `posix-rename@openssh.com` extension when the server has it).
+//! Requests are sent one at a time; the workers of a run share the session.
+
+use crate::destination::{DestFile, DestMetadata, Destination, temp_path};
+use std::collections::HashSet;
+use std::fmt;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+/// Environment variable replacing the `ssh` command.
+pub const SSH_ENV: &str = "RBACKUP_SSH";
+
+const SSH_FXP_INIT: u8 = 1;
+const SSH_FXP_VERSION: u8 = 2;
+const SSH_FXP_OPEN: u8 = 3;
+const SSH_FXP_CLOSE: u8 = 4;
+const SSH_FXP_WRITE: u8 = 6;
+const SSH_FXP_REMOVE: u8 = 13;
+const SSH_FXP_MKDIR: u8 = 14;
+const SSH_FXP_STAT: u8 = 17;
+const SSH_FXP_RENAME: u8 = 18;
+const SSH_FXP_STATUS: u8 = 101;
+const SSH_FXP_HANDLE: u8 = 102;
+const SSH_FXP_ATTRS: u8 = 105;
+const SSH_FXP_EXTENDED: u8 = 200;
+
+const SSH_FXF_WRITE: u32 = 0x02;
+const SSH_FXF_CREAT: u32 = 0x08;
+const SSH_FXF_TRUNC: u32 = 0x10;
+
+const SSH_FILEXFER_ATTR_SIZE: u32 = 0x01;
+const SSH_FILEXFER_ATTR_UIDGID: u32 = 0x02;
+const SSH_FILEXFER_ATTR_PERMISSIONS: u32 = 0x04;
+const SSH_FILEXFER_ATTR_ACMODTIME: u32 = 0x08;
+const SSH_FILEXFER_ATTR_EXTENDED: u32 = 0x8000_0000;
+
+const SSH_FX_OK: u32 = 0;
+const SSH_FX_NO_SUCH_FILE: u32 = 2;
+const SSH_FX_PERMISSION_DENIED: u32 = 3;
+
+const POSIX_RENAME: &str = "posix-rename@openssh.com";
+/// Largest payload of a write request; every server accepts 32 KiB.
+const WRITE_CHUNK: usize = 32 * 1024;
+/// Upper bound on the size of a reply, against a corrupted stream.
+const MAX_PACKET: usize = 256 * 1024;
+
+/// The parts of an `sftp://` URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SftpUrl {
+    pub user: Option<String>,
+    pub host: String,
+    pub port: Option<u16>,
+    /// Directory on the server; relative paths start at the login directory.
+    pub path: PathBuf,
+}
+
+impl SftpUrl {
+    /// Parse what follows `sftp://`: `[user@]host[:port][/path]`.
+    pub fn parse(rest: &str) -> io::Result<SftpUrl> {
+        let invalid = |why: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("sftp://{}: {}", rest, why),
+            )
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "."),
+        };
+        let (user, host_port) = match authority.rsplit_once('@') {
+            Some((u, h)) => (Some(u.to_string()), h),
+            None => (None, authority),
+        };
+        // `[::1]:22` keeps the colons of an IPv6 address apart from the port.
+        let (host, port) = match host_port.strip_prefix('[') {
+            Some(v6) => match v6.split_once(']') {
+                Some((h, p)) => (h, p.strip_prefix(':')),
+                None => return Err(invalid("unterminated IPv6 address")),
+            },
+            None => match host_port.split_once(':') {
+                Some((h, p)) => (h, Some(p)),
+                None => (host_port, None),
+            },
+        };
+        if host.is_empty() {
+            return Err(invalid("missing host"));
+        }
+        let port = port
+            .map(|p| p.parse::<u16>().map_err(|_| invalid("invalid port")))
+            .transpose()?;
+        Ok(SftpUrl {
+            user,
+            host: host.to_string(),
+            port,
+            path: PathBuf::from(path),
+        })
+    }
+}
+
+impl fmt::Display for SftpUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "sftp://")?;
+        if let Some(user) = &self.user {
+            write!(f, "{}@", user)?;
+        }
+        match self.host.contains(':') {
+            true => write!(f, "[{}]", self.host)?,
+            false => write!(f, "{}", self.host)?,
+        }
+        if let Some(port) = self.port {
+            write!(f, ":{}", port)?;
+        }
+        write!(f, "{}", self.path.display())
+    }
+}
+
+type Reader = Box<dyn Read + Send>;
+type Writer = Box<dyn Write + Send>;
+
+struct Session {
+    reader: BufReader<Reader>,
+    writer: BufWriter<Writer>,
+    next_id: u32,
+}
+
+/// Attributes of a file as sent by the server.
+#[derive(Debug, Default)]
+struct Attrs {
+    size: Option<u64>,
+    permissions: Option<u32>,
+    mtime: Option<u32>,
+}
+
+impl Attrs {
+    fn is_dir(&self) -> bool {
+        self.permissions.is_some_and(|p| p & 0o170000 == 0o040000)
+    }
+}
+
+fn put_u32(buf: &mut Vec<u8>, v: u32) {
+    buf.extend_from_slice(&v.to_be_bytes());
+}
+
+fn put_u64(buf: &mut Vec<u8>, v: u64) {
+    buf.extend_from_slice(&v.to_be_bytes());
+}
+
+fn put_str(buf: &mut Vec<u8>, s: &[u8]) {
+    put_u32(buf, s.len() as u32);
+    buf.extend_from_slice(s);
+}
+
+fn put_path(buf: &mut Vec<u8>, path: &Path) {
+    // SFTP paths always use `/`.
+    let text = path.to_string_lossy().replace('\\', "/");
+    put_str(buf, text.as_bytes());
+}
+
+fn protocol_error(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("sftp: {}", what))
+}
+
+/// Cursor over the payload of a reply.
+struct Payload<'a>(&'a [u8]);
+
+impl Payload<'_> {
+    fn take(&mut self, n: usize) -> io::Result<&[u8]> {
+        if self.0.len() < n {
+            return Err(protocol_error("truncated packet"));
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let b = self.take(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok((u64::from(self.u32()?) << 32) | u64::from(self.u32()?))
+    }
+
+    fn string(&mut self) -> io::Result<Vec<u8>> {
+        let n = self.u32()? as usize;
+        Ok(self.take(n)?.to_vec())
+    }
+
+    fn attrs(&mut self) -> io::Result<Attrs> {
+        let flags = self.u32()?;
+        let mut attrs = Attrs::default();
+        if flags & SSH_FILEXFER_ATTR_SIZE != 0 {
+            attrs.size = Some(self.u64()?);
+        }
+        if flags & SSH_FILEXFER_ATTR_UIDGID != 0 {
+            self.take(8)?;
+        }
+        if flags & SSH_FILEXFER_ATTR_PERMISSIONS != 0 {
+            attrs.permissions = Some(self.u32()?);
+        }
+        if flags & SSH_FILEXFER_ATTR_ACMODTIME != 0 {
+            self.take(4)?;
+            attrs.mtime = Some(self.u32()?);
+        }
+        if flags & SSH_FILEXFER_ATTR_EXTENDED != 0 {
+            for _ in 0..self.u32()? {
+                self.string()?;
+                self.string()?;
+            }
+        }
+        Ok(attrs)
+    }
+}
+
+impl Session {
+    fn send(&mut self, kind: u8, body: &[u8]) -> io::Result<()> {
+        self.writer
+            .write_all(&(body.len() as u32 + 1).to_be_bytes())?;
+        self.writer.write_all(&[kind])?;
+        self.writer.write_all(body)?;
+        self.writer.flush()
+    }
+
+    fn receive(&mut self) -> io::Result<(u8, Vec<u8>)> {
+        let mut len = [0u8; 4];
+        self.reader.read_exact(&mut len)?;
+        let len = u32::from_be_bytes(len) as usize;
+        if len == 0 || len > MAX_PACKET {
+            return Err(protocol_error("invalid packet length"));
+        }
+        let mut packet = vec![0u8; len];
+        self.reader.read_exact(&mut packet)?;
+        let kind = packet.remove(0);
+        Ok((kind, packet))
+    }
+
+    /// Send a request with a fresh id and return the reply to it.
+    fn request(&mut self, kind: u8, body: impl FnOnce(&mut Vec<u8>)) -> io::Result<(u8, Vec<u8>)> {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        let mut buf = Vec::new();
+        put_u32(&mut buf, id);
+        body(&mut buf);
+        self.send(kind, &buf)?;
+        let (reply, data) = self.receive()?;
+        let mut payload = Payload(&data);
+        if payload.u32()? != id {
+            return Err(protocol_error("reply to an unknown request"));
+        }
+        Ok((reply, payload.0.to_vec()))
+    }
+}
+
+/// Turn a `STATUS` reply into a result.
+fn status(data: &[u8]) -> io::Result<()> {
+    let mut payload = Payload(data);
+    let code = payload.u32()?;
+    let message = payload
+        .string()
+        .map(|m| String::from_utf8_lossy(&m).into_owned())
+        .unwrap_or_default();
+    let kind = match code {
+        SSH_FX_OK => return Ok(()),
+        SSH_FX_NO_SUCH_FILE => io::ErrorKind::NotFound,
+        SSH_FX_PERMISSION_DENIED => io::ErrorKind::PermissionDenied,
+        _ => io::ErrorKind::Other,
+    };
+    Err(io::Error::new(kind, format!("sftp: {}", message)))
+}
+
+/// Expect a `STATUS` reply.
+fn expect_status((kind, data): (u8, Vec<u8>)) -> io::Result<()> {
+    match kind {
+        SSH_FXP_STATUS => status(&data),
+        _ => Err(protocol_error("unexpected reply")),
+    }
+}
+
+/// A directory on an SFTP server.
+pub struct SftpDestination {
+    session: Mutex<Session>,
+    /// Whether the server renames over existing files (`posix-rename`).
+    posix_rename: bool,
+    /// Directories known to exist, to spare a round trip per file.
+    dirs: Mutex<HashSet<PathBuf>>,
+    /// The `ssh` process carrying the session, if any.
+    child: Option<Mutex<Child>>,
+}
+
+impl fmt::Debug for SftpDestination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SftpDestination")
+            .field("posix_rename", &self.posix_rename)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SftpDestination {
+    /// Start `ssh` for `url` and open an SFTP session over it.
+    pub fn connect(url: &SftpUrl) -> io::Result<SftpDestination> {
+        let ssh = std::env::var(SSH_ENV).unwrap_or_else(|_| "ssh".to_string());
+        let mut words = ssh.split_whitespace();
+        let program = words.next().unwrap_or("ssh");
+        let mut cmd = Command::new(program);
+        cmd.args(words);
+        if let Some(port) = url.port {
+            cmd.arg("-p").arg(port.to_string());
+        }
+        let target = match &url.user {
+            Some(user) => format!("{}@{}", user, url.host),
+            None => url.host.clone(),
+        };
+        cmd.arg(target).args(["-s", "sftp"]);
+        let mut child = cmd
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", program, e)))?;
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            return Err(io::Error::other("ssh: no standard streams"));
+        };
+        match SftpDestination::from_streams(stdout, stdin) {
+            Ok(mut dest) => {
+                dest.child = Some(Mutex::new(child));
+                Ok(dest)
+            }
+            Err(e) => {
+                // A broken pipe means `ssh` gave up; its status says more.
+                let _ = child.kill();
+                let reason = match child.wait() {
+                    Ok(status) if !status.success() => format!("{} {}", program, status),
+                    _ => e.to_string(),
+                };
+                Err(io::Error::new(e.kind(), format!("{}: {}", url, reason)))
+            }
+        }
+    }
+
+    /// Open an SFTP session over an established connection: `reader`
+    /// carries the replies of the server and `writer` the requests.
+    pub fn from_streams(
+        reader: impl Read + Send + 'static,
+        writer: impl Write + Send + 'static,
+    ) -> io::Result<SftpDestination> {
+        let mut session = Session {
+            reader: BufReader::new(Box::new(reader) as Reader),
+            writer: BufWriter::new(Box::new(writer) as Writer),
+            next_id: 1,
+        };
+        let mut init = Vec::new();
+        put_u32(&mut init, 3);
+        session.send(SSH_FXP_INIT, &init)?;
+        let (kind, data) = session.receive()?;
+        if kind != SSH_FXP_VERSION {
+            return Err(protocol_error("no version reply"));
+        }
+        let mut payload = Payload(&data);
+        if payload.u32()? < 3 {
+            return Err(protocol_error("server too old"));
+        }
+        let mut posix_rename = false;
+        while !payload.0.is_empty() {
+            let name = payload.string()?;
+            payload.string()?;
+            posix_rename |= name == POSIX_RENAME.as_bytes();
+        }
+        Ok(SftpDestination {
+            session: Mutex::new(session),
+            posix_rename,
+            dirs: Mutex::default(),
+            child: None,
+        })
+    }
+
+    fn request(&self, kind: u8, body: impl FnOnce(&mut Vec<u8>)) -> io::Result<(u8, Vec<u8>)> {
+        let mut session = self
+            .session
+            .lock()
+            .map_err(|_| io::Error::other("sftp: session poisoned"))?;
+        session.request(kind, body)
+    }
+
+    fn attrs(&self, path: &Path) -> io::Result<Option<Attrs>> {
+        let (kind, data) = self.request(SSH_FXP_STAT, |b| put_path(b, path))?;
+        match kind {
+            SSH_FXP_ATTRS => Payload(&data).attrs().map(Some),
+            SSH_FXP_STATUS => match status(&data) {
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e),
+                Ok(()) => Err(protocol_error("unexpected reply")),
+            },
+            _ => Err(protocol_error("unexpected reply")),
+        }
+    }
+
+    fn mkdir(&self, path: &Path) -> io::Result<()> {
+        expect_status(self.request(SSH_FXP_MKDIR, |b| {
+            put_path(b, path);
+            put_u32(b, 0);
+        })?)
+    }
+
+    fn close(&self, handle: &[u8]) -> io::Result<()> {
+        expect_status(self.request(SSH_FXP_CLOSE, |b| put_str(b, handle))?)
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        expect_status(self.request(SSH_FXP_REMOVE, |b| put_path(b, path))?)
+    }
+
+    /// Rename `from` over `to`, replacing it.
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        if self.posix_rename {
+            return expect_status(self.request(SSH_FXP_EXTENDED, |b| {
+                put_str(b, POSIX_RENAME.as_bytes());
+                put_path(b, from);
+                put_path(b, to);
+            })?);
+        }
+        // Plain SFTP renames refuse to replace an existing file.
+        match self.remove(to) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        expect_status(self.request(SSH_FXP_RENAME, |b| {
+            put_path(b, from);
+            put_path(b, to);
+        })?)
+    }
+}
+
+impl Drop for SftpDestination {
+    fn drop(&mut self) {
+        if let Some(child) = &self.child
+            && let Ok(mut child) = child.lock()
+        {
+            // Closing our end of the pipes ends the session; `ssh` exits.
+            if let Ok(mut session) = self.session.lock() {
+                session.writer = BufWriter::new(Box::new(io::sink()));
+            }
+            let _ = child.wait();
+        }
+    }
+}
+
+impl Destination for SftpDestination {
+    fn stat(&self, path: &Path) -> io::Result<Option<DestMetadata>> {
+        Ok(self.attrs(path)?.map(|a| DestMetadata {
+            len: a.size.unwrap_or(0),
+            is_dir: a.is_dir(),
+            modified: a
+                .mtime
+                .map(|t| SystemTime::UNIX_EPOCH + Duration::from_secs(u64::from(t))),
+        }))
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        let known = |p: &Path| self.dirs.lock().is_ok_and(|d| d.contains(p));
+        // Walk up to the first existing ancestor, then create downwards.
+        let mut missing = Vec::new();
+        for dir in path.ancestors() {
+            if dir.as_os_str().is_empty() || known(dir) {
+                break;
+            }
+            match self.attrs(dir)? {
+                Some(a) if a.is_dir() => break,
+                Some(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        format!("{}: not a directory", dir.display()),
+                    ));
+                }
+                None => missing.push(dir),
+            }
+        }
+        for dir in missing.into_iter().rev() {
+            // Another worker may have created it in the meantime.
+            if let Err(e) = self.mkdir(dir)
+                && !self.attrs(dir)?.is_some_and(|a| a.is_dir())
+            {
+                return Err(e);
+            }
+        }
+        if let Ok(mut dirs) = self.dirs.lock() {
+            dirs.insert(path.to_path_buf());
+        }
+        Ok(())
+    }
+
+    fn create(&self, path: &Path, _source: &Path) -> io::Result<Box<dyn DestFile + '_>> {
+        let tmp = temp_path(path);
+        let (kind, data) = self.request(SSH_FXP_OPEN, |b| {
+            put_path(b, &tmp);
+            put_u32(b, SSH_FXF_WRITE | SSH_FXF_CREAT | SSH_FXF_TRUNC);
+            put_u32(b, 0);
+        })?;
+        let handle = match kind {
+            SSH_FXP_HANDLE => Payload(&data).string()?,
+            SSH_FXP_STATUS => {
+                status(&data)?;
+                return Err(protocol_error("unexpected reply"));
+            }
+            _ => return Err(protocol_error("unexpected reply")),
+        };
+        Ok(Box::new(SftpFile {
+            dest: self,
+            handle: Some(handle),
+            tmp,
+            path: path.to_path_buf(),
+            offset: 0,
+            buf: Vec::with_capacity(WRITE_CHUNK),
+        }))
+    }
+}
+
+/// A file being uploaded to a temporary name.
+struct SftpFile<'a> {
+    dest: &'a SftpDestination,
+    /// Open handle; `None` once closed.
+    handle: Option<Vec<u8>>,
+    tmp: PathBuf,
+    path: PathBuf,
+    offset: u64,
+    buf: Vec<u8>,
+}
+
+impl SftpFile<'_> {
+    fn send_buf(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let handle = self
+            .handle
+            .as_deref()
+            .ok_or_else(|| io::Error::other("sftp: file closed"))?;
+        let (offset, data) = (self.offset, &self.buf);
+        expect_status(self.dest.request(SSH_FXP_WRITE, |b| {
+            put_str(b, handle);
+            put_u64(b, offset);
+            put_str(b, data);
+        })?)?;
+        self.offset += self.buf.len() as u64;
+        self.buf.clear();
+        Ok(())
+    }
+}
+
+impl Write for SftpFile<'_> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let n = data.len().min(WRITE_CHUNK - self.buf.len());
+        self.buf.extend_from_slice(&data[..n]);
+        if self.buf.len() == WRITE_CHUNK {
+            self.send_buf()?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send_buf()
+    }
+}
+
+impl DestFile for SftpFile<'_> {
+    fn commit(mut self: Box<Self>) -> io::Result<()> {
+        self.send_buf()?;
+        if let Some(handle) = self.handle.take() {
+            self.dest.close(&handle)?;
+        }
+        self.dest.rename(&self.tmp, &self.path)?;
+        // Nothing left to clean up.
+        self.tmp = PathBuf::new();
+        Ok(())
+    }
+}
+
+impl Drop for SftpFile<'_> {
+    fn drop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            let _ = self.dest.close(&handle);
+        }
+        if !self.tmp.as_os_str().is_empty() {
+            let _ = self.dest.remove(&self.tmp);
+        }
+    }
+}
@@ -0,0 +1,224 @@
+//! Local state of previous runs, kept outside the destination.
+//!
+//! A `StateIndex` records the size, modification time and (when known) hash
+//! of every file of the last successful run from a source to a destination.
+//! Planning trusts it: a file whose size and modification time did not change
+//! since then is up to date in the destination as long as its copy exists
+//! there; the copy is not compared with the source. A copy deleted from the
+//! destination (or a destination that is not mounted) is copied again, but
+//! copies modified directly in the destination go unnoticed, hence
+//! `copy --rescan`, which compares every file again and rebuilds the index.
+//!
+//! Indexes live in `~/.rbackup/state/`, one file per source and destination
+//! pair, in a compact binary format:
+//!
+//! ```text
+//! magic "RBSTATE\0" | version: u32 | count: u64 | count entries
+//! entry: key length: u32 | key (UTF-8) | size: u64 | mtime: i64
+//!        | mtime nanos: u32 | hash length: u8 | hash (raw bytes)
+//! ```
+//!
+//! Integers are little-endian; keys are relative paths as in the manifest.
+
+use crate::config::Config;
+use crate::crypto::write_atomically;
+use crate::manifest::{manifest_key, mtime_parts};
+use std::collections::BTreeMap;
+use std::fs::{self, Metadata};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Current on-disk format version of the state indexes.
+pub const STATE_VERSION: u32 = 1;
+
+/// First bytes of every state index.
+const MAGIC: &[u8; 8] = b"RBSTATE\0";
+
+/// Directory of the state indexes: `state` next to the configuration file.
+pub fn default_dir() -> PathBuf {
+    Config::config_file().with_file_name("state")
+}
+
+/// State recorded for a single file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateEntry {
+    /// File size in bytes.
+    pub size: u64,
+    /// Modification time, seconds since the Unix epoch.
+    pub mtime: i64,
+    /// Sub-second part of the modification time.
+    pub mtime_nanos: u32,
+    /// Hex-encoded BLAKE3 hash of the file contents, when the run computed
+    /// it (e.g. for the manifest).
+    pub hash: Option<String>,
+}
+
+impl StateEntry {
+    /// Build an entry from filesystem metadata.
+    pub fn from_metadata(meta: &Metadata) -> Self {
+        let (mtime, mtime_nanos) = mtime_parts(meta);
+        Self {
+            size: meta.len(),
+            mtime,
+            mtime_nanos,
+            hash: None,
+        }
+    }
+
+    /// Return true if `meta` has the same size and modification time as this
+    /// entry, i.e. the file did not change since it was recorded.
+    pub fn matches_metadata(&self, meta: &Metadata) -> bool {
+        let (mtime, mtime_nanos) = mtime_parts(meta);
+        self.size == meta.len() && self.mtime == mtime && self.mtime_nanos == mtime_nanos
+    }
+}
+
+/// Files of the last successful run from a source to a destination.
+///
+/// # Example
+///
+/// ```rust
+/// use rbackup::state::{StateEntry, StateIndex};
+/// use std::path::Path;
+///
+/// let dir = std::env::temp_dir().join("rbackup-state-doc");
+/// let path = StateIndex::path_in(&dir, Path::new("/src"), Path::new("sftp://nas/backup"), Path::new("/backup"));
+/// let mut index = StateIndex::default();
+/// index.entries.insert(
+///     "a.txt".into(),
+///     StateEntry { size: 1, mtime: 2, mtime_nanos: 3, hash: None },
+/// );
+/// index.save(&path).unwrap();
+/// assert_eq!(StateIndex::load(&path).unwrap(), Some(index));
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StateIndex {
+    /// Recorded files keyed by their relative path (see `manifest::manifest_key`).
+    pub entries: BTreeMap<String, StateEntry>,
+}
+
+impl StateIndex {
+    /// Location in `state_dir` of the index of the runs from `source` to
+    /// `dest_dir`, below the destination given by the user as `destination`
+    /// (a path or a URL). `source` and `dest_dir` are made absolute first,
+    /// so that the same relative arguments used from different directories
+    /// do not share an index.
+    pub fn path_in(
+        state_dir: &Path,
+        source: &Path,
+        destination: &Path,
+        dest_dir: &Path,
+    ) -> PathBuf {
+        let absolute = |p: &Path| std::path::absolute(p).unwrap_or_else(|_| p.to_path_buf());
+        let mut hasher = blake3::Hasher::new();
+        for part in [
+            absolute(source),
+            destination.to_path_buf(),
+            absolute(dest_dir),
+        ] {
+            hasher.update(part.as_os_str().as_encoded_bytes());
+            hasher.update(&[0]);
+        }
+        state_dir.join(format!("{}.idx", &hasher.finalize().to_hex()[..32]))
+    }
+
+    /// Load the index stored at `path`.
+    ///
+    /// Returns `Ok(None)` when it does not exist, or an error when it is not
+    /// a complete index of the current version.
+    pub fn load(path: &Path) -> io::Result<Option<Self>> {
+        let data = match fs::read(path) {
+            Ok(d) => d,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let invalid = |what: &str| io::Error::new(io::ErrorKind::InvalidData, what.to_string());
+        let mut r = data.as_slice();
+        let mut magic = [0; 8];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a state index"));
+        }
+        if read_u32(&mut r)? != STATE_VERSION {
+            return Err(invalid("unsupported state index version"));
+        }
+        let mut index = StateIndex::default();
+        for _ in 0..read_u64(&mut r)? {
+            let len = read_u32(&mut r)? as usize;
+            let key = read_bytes(&mut r, len)?;
+            let key = String::from_utf8(key).map_err(|_| invalid("invalid path"))?;
+            let size = read_u64(&mut r)?;
+            let mtime = read_u64(&mut r)? as i64;
+            let mtime_nanos = read_u32(&mut r)?;
+            let mut hash_len = [0; 1];
+            r.read_exact(&mut hash_len)?;
+            let hash = read_bytes(&mut r, hash_len[0] as usize)?;
+            let entry = StateEntry {
+                size,
+                mtime,
+                mtime_nanos,
+                hash: (!hash.is_empty()).then(|| hex::encode(hash)),
+            };
+            index.entries.insert(key, entry);
+        }
+        Ok(Some(index))
+    }
+
+    /// Write the index to `path`, creating its directory.
+    ///
+    /// The file is first written under a temporary name and then renamed, so
+    /// an interrupted run never leaves a truncated index behind.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        write_atomically(path, |w| {
+            w.write_all(MAGIC)?;
+            w.write_all(&STATE_VERSION.to_le_bytes())?;
+            w.write_all(&(self.entries.len() as u64).to_le_bytes())?;
+            for (key, entry) in &self.entries {
+                let hash = entry
+                    .hash
+                    .as_deref()
+                    .and_then(|h| hex::decode(h).ok())
+                    .filter(|h| h.len() <= u8::MAX as usize)
+                    .unwrap_or_default();
+                w.write_all(&(key.len() as u32).to_le_bytes())?;
+                w.write_all(key.as_bytes())?;
+                w.write_all(&entry.size.to_le_bytes())?;
+                w.write_all(&entry.mtime.to_le_bytes())?;
+                w.write_all(&entry.mtime_nanos.to_le_bytes())?;
+                w.write_all(&[hash.len() as u8])?;
+                w.write_all(&hash)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Look up the entry recorded for `rel_path`.
+    pub fn get(&self, rel_path: &Path) -> Option<&StateEntry> {
+        self.entries.get(&manifest_key(rel_path))
+    }
+}
+
+fn read_u32(r: &mut &[u8]) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64(r: &mut &[u8]) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_bytes(r: &mut &[u8], len: usize) -> io::Result<Vec<u8>> {
+    if r.len() < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    let (bytes, rest) = r.split_at(len);
+    *r = rest;
+    Ok(bytes.to_vec())
+}
@@ -1,17 +1,28 @@
 //! Terminal UI helpers used for displaying progress bars and simple status lines.
 //!
-//! This module contains a small helper to draw a file-level progress bar.
+//! This module contains a small helper to draw a file-level progress bar,
+//! the formatting helpers shared with the dashboard (`dashboard` module) and
+//! the observers of the `plain` and `fancy` progress modes.
 
+use crate::output::{FileLines, LogContext, log_output};
+use crate::progress::{ProgressEvent, ProgressObserver};
 use crate::utils::Messages;
 use crossterm::{
     cursor::MoveTo,
     execute,
     style::{Print, ResetColor},
-    terminal::{Clear, ClearType},
+    terminal::{self, Clear, ClearType},
 };
+use std::collections::VecDeque;
 use std::io::{Write, stdout};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
-fn format_bytes(bytes: u64) -> String {
+/// Format a byte count with a binary unit (B, KiB, MiB, ...).
+pub(crate) fn format_bytes(bytes: u64) -> String {
     const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
     let mut size = bytes as f64;
     let mut unit = 0usize;
@@ -28,30 +39,200 @@ fn format_bytes(bytes: u64) -> String {
     }
 }
 
+/// Format a duration as `HH:MM:SS`.
+pub(crate) fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// Render `fraction` (0.0..=1.0) as a bracketed bar `width` cells wide.
+pub(crate) fn progress_bar(fraction: f64, width: usize) -> String {
+    let filled = ((fraction.clamp(0.0, 1.0) * width as f64).round() as usize).min(width);
+    format!(
+        "[{}{}]",
+        "\u{2588}".repeat(filled),
+        "\u{2591}".repeat(width - filled)
+    )
+}
+
+/// Render `samples` as a one-line bar graph scaled to the largest sample.
+///
+/// Only the last `width` samples are drawn.
+///
+/// # Example
+///
+/// ```rust
+/// use rbackup::ui::sparkline;
+/// assert_eq!(sparkline(&[0, 4, 8], 10), " \u{2584}\u{2588}");
+/// assert_eq!(sparkline(&[1, 2, 3], 2), "\u{2585}\u{2588}");
+/// ```
+pub fn sparkline(samples: &[u64], width: usize) -> String {
+    const LEVELS: [char; 9] = [
+        ' ', '\u{2581}', '\u{2582}', '\u{2583}', '\u{2584}', '\u{2585}', '\u{2586}', '\u{2587}',
+        '\u{2588}',
+    ];
+    let samples = &samples[samples.len().saturating_sub(width)..];
+    let max = samples.iter().copied().max().unwrap_or(0).max(1);
+    samples
+        .iter()
+        .map(|&v| LEVELS[((v as f64 / max as f64) * 8.0).round() as usize])
+        .collect()
+}
+
 use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
-/// Draw a simple progress bar and file counter on the given terminal row.
+/// Time span used for the moving-window throughput.
+const RATE_WINDOW: Duration = Duration::from_secs(5);
+
+/// Running statistics of a copy, fed from `ProgressEvent::BytesProgress` events.
+///
+/// Keeps the start time and the recent `(time, bytes)` samples needed for the
+/// elapsed time, the average and moving-window throughput and the ETA.
+///
+/// # Example
+///
+/// ```rust
+/// use rbackup::ui::ProgressStats;
+/// use std::time::{Duration, Instant};
+///
+/// let start = Instant::now();
+/// let mut stats = ProgressStats::new_at(start, 4, 400);
+/// stats.record_at(start + Duration::from_secs(1), 1, 100);
+/// stats.record_at(start + Duration::from_secs(2), 2, 200);
+/// assert_eq!(stats.average_rate(), 100.0);
+/// assert_eq!(stats.eta(), Some(Duration::from_secs(2)));
+/// ```
+#[derive(Debug, Clone)]
+pub struct ProgressStats {
+    pub total_files: usize,
+    pub total_bytes: u64,
+    pub done_files: usize,
+    pub done_bytes: u64,
+    started: Instant,
+    window: VecDeque<(Instant, u64)>,
+}
+
+impl ProgressStats {
+    /// Start tracking a copy of `total_files` files and `total_bytes` bytes now.
+    pub fn new(total_files: usize, total_bytes: u64) -> Self {
+        Self::new_at(Instant::now(), total_files, total_bytes)
+    }
+
+    /// Start tracking a copy that started at `started`.
+    pub fn new_at(started: Instant, total_files: usize, total_bytes: u64) -> Self {
+        Self {
+            total_files,
+            total_bytes,
+            done_files: 0,
+            done_bytes: 0,
+            started,
+            window: VecDeque::from([(started, 0)]),
+        }
+    }
+
+    /// Record the files and bytes processed so far.
+    pub fn record(&mut self, done_files: usize, done_bytes: u64) {
+        self.record_at(Instant::now(), done_files, done_bytes);
+    }
+
+    /// Record the files and bytes processed so far, as of `at`.
+    pub fn record_at(&mut self, at: Instant, done_files: usize, done_bytes: u64) {
+        self.done_files = done_files;
+        self.done_bytes = done_bytes;
+        self.window.push_back((at, done_bytes));
+        // Keep one sample older than the window so its full span is covered.
+        while self.window.len() > 2 && at.duration_since(self.window[1].0) >= RATE_WINDOW {
+            self.window.pop_front();
+        }
+    }
+
+    /// Time since the copy started.
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Fraction of the bytes processed, between 0.0 and 1.0.
+    pub fn fraction(&self) -> f64 {
+        if self.total_bytes == 0 {
+            1.0
+        } else {
+            (self.done_bytes as f64 / self.total_bytes as f64).min(1.0)
+        }
+    }
+
+    /// Bytes per second since the start, up to the last recorded sample.
+    pub fn average_rate(&self) -> f64 {
+        let last = self.window.back().map_or(self.started, |(t, _)| *t);
+        let secs = last.duration_since(self.started).as_secs_f64();
+        if secs > 0.0 {
+            self.done_bytes as f64 / secs
+        } else {
+            0.0
+        }
+    }
+
+    /// Bytes per second over the last few seconds.
+    pub fn window_rate(&self) -> f64 {
+        match (self.window.front(), self.window.back()) {
+            (Some((t0, b0)), Some((t1, b1))) if t1 > t0 => {
+                b1.saturating_sub(*b0) as f64 / t1.duration_since(*t0).as_secs_f64()
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// Estimated time remaining, from the moving-window throughput (or the
+    /// average one when the window saw no progress). `None` when unknown.
+    pub fn eta(&self) -> Option<Duration> {
+        let remaining = self.total_bytes.saturating_sub(self.done_bytes);
+        if remaining == 0 {
+            return Some(Duration::ZERO);
+        }
+        let rate = match self.window_rate() {
+            r if r > 0.0 => r,
+            _ => self.average_rate(),
+        };
+        (rate > 0.0).then(|| Duration::from_secs_f64(remaining as f64 / rate))
+    }
+
+    /// Localized `elapsed / throughput (avg) / ETA` summary.
+    pub(crate) fn rates_line(&self, msg: &Messages) -> String {
+        format!(
+            "{} {}  {} {}/s ({} {}/s)  {} {}",
+            msg.elapsed,
+            format_duration(self.elapsed()),
+            msg.throughput,
+            format_bytes(self.window_rate() as u64),
+            msg.throughput_avg,
+            format_bytes(self.average_rate() as u64),
+            msg.eta,
+            self.eta()
+                .map_or_else(|| "--:--:--".to_string(), format_duration)
+        )
+    }
+}
+
+/// Draw the progress line on the given terminal row: bytes and percentage,
+/// file counter, elapsed time, throughput, ETA and a bar filling the
+/// remaining width.
 ///
 /// # Parameters
-/// - `copied`: number of items processed so far (as float to support partial updates)
+/// - `stats`: progress statistics of the running copy
 /// - `progress_row`: terminal row where the progress bar will be drawn
-/// - `total`: total number of items to process
-/// - `msg`: localized message bundle used for the label
+/// - `msg`: localized message bundle used for the labels
 ///
 /// # Example
 ///
 /// ```rust,ignore
 /// // Example usage (ignored by doctest because it requires full `Messages` setup)
-/// use rbackup::ui::draw_ui;
+/// use rbackup::ui::{ProgressStats, draw_ui};
 /// // prepare `msg` by loading translations in real code
-/// // draw_ui(10.0, 20, 100.0, &msg);
+/// // let mut stats = ProgressStats::new(10, 1000);
+/// // stats.record(5, 500);
+/// // draw_ui(&stats, 20, &msg);
 /// ```
-pub fn draw_ui(done_bytes: u64, progress_row: u16, total_bytes: u64, msg: &Messages) {
-    let progress = if total_bytes == 0 {
-        0.0
-    } else {
-        done_bytes as f64 / total_bytes as f64
-    };
+pub fn draw_ui(stats: &ProgressStats, progress_row: u16, msg: &Messages) {
+    let progress = stats.fraction();
     let percent = (progress * 100.0).round();
 
     // Determine terminal width to avoid producing lines longer than the
@@ -59,30 +240,22 @@ pub fn draw_ui(done_bytes: u64, progress_row: u16, total_bytes: u64, msg: &Messa
     let (cols, _rows) = crossterm::terminal::size().unwrap_or((80, 24));
     let cols = cols as usize;
 
-    // Reserve some space for textual prefix and brackets; compute a
-    // bar width that fits the terminal. Keep a sensible minimum.
-    let reserved = 30; // approximate space for prefix (file counts and percent)
-    let bar_width = if cols > reserved + 10 {
-        std::cmp::min(50, cols - reserved)
-    } else {
-        10_usize
-    };
-
-    let filled = (progress * bar_width as f64).round() as usize;
-    let empty = bar_width.saturating_sub(filled);
-
-    let bar = format!(
-        "[{}{}]",
-        "\u{2588}".repeat(filled),
-        "\u{2591}".repeat(empty)
+    let text = format!(
+        "{} {}/{} ({:.0}%)  {} {}/{}  {}",
+        msg.copy_progress,
+        format_bytes(stats.done_bytes),
+        format_bytes(stats.total_bytes),
+        percent,
+        msg.files_progress,
+        stats.done_files,
+        stats.total_files,
+        stats.rates_line(msg)
     );
-    let done_h = format_bytes(done_bytes);
-    let total_h = format_bytes(total_bytes);
 
-    let mut progress_line = format!(
-        "{} bytes {}/{} ({:.0}%) {}",
-        msg.copy_progress, done_h, total_h, percent, bar
-    );
+    // The bar takes the space left on the line, within sensible bounds.
+    let free = cols.saturating_sub(UnicodeWidthStr::width(text.as_str()) + 2);
+    let bar = progress_bar(progress, free.clamp(10, 50));
+    let mut progress_line = format!("{} {}", text, bar);
 
     // Truncate progress line by display width to avoid terminal wrapping
     // which would push content below the bottom row.
@@ -106,7 +279,7 @@ pub fn draw_ui(done_bytes: u64, progress_row: u16, total_bytes: u64, msg: &Messa
 
 /// Truncate a string preserving Unicode character boundaries so its
 /// displayed width does not exceed `max` columns.
-fn truncate_to_display_width(s: &str, max: usize) -> String {
+pub(crate) fn truncate_to_display_width(s: &str, max: usize) -> String {
     if max == 0 {
         return String::new();
     }
@@ -122,3 +295,211 @@ fn truncate_to_display_width(s: &str, max: usize) -> String {
     }
     out
 }
+
+/// Thread owning the terminal while a progress display runs, fed through a
+/// channel so that the workers never write to the terminal themselves.
+pub(crate) struct UiThread<T> {
+    tx: Mutex<Option<Sender<T>>>,
+    handle: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl<T: Send + 'static> UiThread<T> {
+    pub(crate) fn spawn(run: impl FnOnce(Receiver<T>) + Send + 'static) -> Self {
+        let (tx, rx) = mpsc::channel();
+        Self {
+            tx: Mutex::new(Some(tx)),
+            handle: Mutex::new(Some(thread::spawn(move || run(rx)))),
+        }
+    }
+
+    pub(crate) fn send(&self, update: T) {
+        if let Some(tx) = &*self.tx.lock().unwrap_or_else(PoisonError::into_inner) {
+            let _ = tx.send(update);
+        }
+    }
+
+    /// Close the channel and wait for the thread to draw its last frame.
+    pub(crate) fn finish(&self) {
+        drop(
+            self.tx
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .take(),
+        );
+        let handle = self
+            .handle
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        if let Some(handle) = handle {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Observer of the `plain` progress mode: a line per processed file and a
+/// percentage every 10 %, printed as they come.
+pub(crate) struct PlainProgress {
+    lines: FileLines,
+    msg: Messages,
+    ctx: LogContext,
+    /// Last tenth of the bytes reported.
+    last_step: AtomicUsize,
+}
+
+impl PlainProgress {
+    pub(crate) fn new(msg: &Messages, options: &LogContext) -> Self {
+        Self {
+            lines: FileLines::new(msg, options.show_skipped),
+            msg: msg.clone(),
+            ctx: LogContext {
+                with_timestamp: options.with_timestamp,
+                timestamp_format: options.timestamp_format.clone(),
+                on_log: false,
+                ..Default::default()
+            },
+            last_step: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl ProgressObserver for PlainProgress {
+    fn on_event(&self, event: &ProgressEvent<'_>) {
+        if let Some(line) = self.lines.line(event) {
+            log_output(&line, &self.ctx);
+        }
+        if let ProgressEvent::BytesProgress {
+            done_bytes,
+            total_bytes,
+            ..
+        } = *event
+        {
+            let percent = (done_bytes.saturating_mul(100) / total_bytes.max(1)).min(100);
+            let step = (percent / 10) as usize;
+            if step > self.last_step.fetch_max(step, Ordering::Relaxed) {
+                log_output(
+                    &format!(
+                        "{} {}% ({}/{})",
+                        self.msg.copy_progress,
+                        percent,
+                        format_bytes(done_bytes),
+                        format_bytes(total_bytes)
+                    ),
+                    &self.ctx,
+                );
+            }
+        }
+    }
+}
+
+/// Update of the `fancy` progress display.
+enum FancyUpdate {
+    Planned { files: usize, bytes: u64 },
+    Line(String),
+    Progress { done_files: usize, done_bytes: u64 },
+}
+
+/// Observer of the `fancy` progress mode: the file lines scroll above a
+/// progress line kept on the last terminal row.
+pub(crate) struct FancyProgress {
+    lines: FileLines,
+    thread: UiThread<FancyUpdate>,
+}
+
+impl FancyProgress {
+    pub(crate) fn start(msg: &Messages, options: &LogContext) -> Self {
+        let ui_msg = msg.clone();
+        Self {
+            lines: FileLines::new(msg, options.show_skipped),
+            thread: UiThread::spawn(move |rx| fancy_ui(rx, &ui_msg)),
+        }
+    }
+}
+
+impl ProgressObserver for FancyProgress {
+    fn on_event(&self, event: &ProgressEvent<'_>) {
+        if let Some(line) = self.lines.line(event) {
+            self.thread.send(FancyUpdate::Line(line));
+        }
+        match *event {
+            ProgressEvent::Planned { files, bytes, .. } => {
+                self.thread.send(FancyUpdate::Planned { files, bytes })
+            }
+            ProgressEvent::BytesProgress {
+                done_files,
+                done_bytes,
+                ..
+            } => self.thread.send(FancyUpdate::Progress {
+                done_files,
+                done_bytes,
+            }),
+            ProgressEvent::Finished { .. } => self.thread.finish(),
+            _ => {}
+        }
+    }
+}
+
+/// Draw the `fancy` display until the channel is closed.
+fn fancy_ui(rx: Receiver<FancyUpdate>, msg: &Messages) {
+    let mut stats = ProgressStats::new(0, 0);
+    let mut buffer: VecDeque<String> = VecDeque::new();
+
+    let redraw = |buf: &VecDeque<String>, cols: u16, scroll_rows: usize| {
+        if cols == 0 {
+            return;
+        }
+        let max_content = cols.saturating_sub(1) as usize;
+
+        for i in 0..scroll_rows {
+            if i < buf.len() {
+                let mut s = truncate_to_display_width(&buf[i], max_content);
+                let disp = UnicodeWidthStr::width(s.as_str());
+                if disp < max_content {
+                    s.push_str(&" ".repeat(max_content - disp));
+                }
+                let _ = execute!(
+                    stdout(),
+                    MoveTo(0, i as u16),
+                    Clear(ClearType::CurrentLine),
+                    Print(s)
+                );
+            } else {
+                let _ = execute!(stdout(), MoveTo(0, i as u16), Clear(ClearType::CurrentLine));
+            }
+        }
+    };
+
+    // initial draw: clear whole screen
+    let _ = execute!(stdout(), Clear(ClearType::All));
+
+    for update in rx {
+        let (cols, rows) = terminal::size().unwrap_or((80, 24));
+        let progress_row = rows.saturating_sub(1);
+        let scroll_rows = progress_row as usize;
+
+        while buffer.len() > scroll_rows {
+            buffer.pop_front();
+        }
+
+        match update {
+            FancyUpdate::Planned { files, bytes } => stats = ProgressStats::new(files, bytes),
+            FancyUpdate::Line(s) => {
+                if scroll_rows == 0 {
+                    continue;
+                }
+                buffer.push_back(s);
+                if buffer.len() > scroll_rows {
+                    buffer.pop_front();
+                }
+                redraw(&buffer, cols, scroll_rows);
+            }
+            FancyUpdate::Progress {
+                done_files,
+                done_bytes,
+            } => {
+                stats.record(done_files, done_bytes);
+                draw_ui(&stats, progress_row, msg);
+            }
+        }
+    }
+}
@@ -5,68 +5,51 @@
 //! copying implementation. Public items are documented with examples where
 //! relevant.
 
-use crate::output::{LogContext, ShowSkipped};
+use crate::compress::compressed_name;
+use crate::crypto::write_atomically;
+use crate::dashboard::DashboardObserver;
+use crate::destination::temp_path;
+use crate::error::Error;
+use crate::manifest::{Manifest, ManifestEntry, hash_file, manifest_key};
+use crate::output::LogObserver;
+use crate::output::{LogContext, ProgressMode, ShowSkipped};
+use crate::progress::{ProgressEvent, ProgressObserver, SkipReason};
+use crate::state::{StateEntry, StateIndex};
+use crate::ui::{FancyProgress, PlainProgress};
 use crossterm::execute;
 use crossterm::terminal::{Clear, ClearType};
 use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
 use rayon::prelude::*; // parallel iterator utilities
 use serde::Deserialize;
 use std::io::{BufWriter, Write, stdout};
-use std::sync::mpsc;
-use std::thread;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fs::{self, File},
     io::{self},
-    path::Path,
+    path::{Path, PathBuf},
     sync::{
         Arc, Mutex,
         atomic::{AtomicU64, AtomicUsize, Ordering},
     },
 };
-use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
-use walkdir::WalkDir; // added for UI scroll buffer
-
-/// Truncate a string so that its displayed width (in monospace columns)
-/// does not exceed `max`. This preserves Unicode character boundaries.
-fn truncate_to_width(s: &str, max: usize) -> String {
-    if max == 0 {
-        return String::new();
-    }
-    let mut out = String::new();
-    let mut width = 0usize;
-    for c in s.chars() {
-        let w = UnicodeWidthChar::width(c).unwrap_or(0);
-        if width + w > max {
-            break;
-        }
-        out.push(c);
-        width += w;
-    }
-    out
-}
-
-/// Events sent to the single UI thread to serialize terminal access.
-///
-/// Message: a textual line to append to the scrollable area.
-/// Progress: update the fixed progress bar displayed on the bottom row.
-#[derive(Debug)]
-enum UiEvent {
-    Message(String),
-    Progress { done_bytes: u64, total_bytes: u64 },
-}
 
 #[derive(Debug, Clone)]
-struct CopyOp {
-    src_path: std::path::PathBuf,
-    dest_path: std::path::PathBuf,
+pub(crate) struct CopyOp {
+    pub(crate) src_path: std::path::PathBuf,
+    pub(crate) rel_path: std::path::PathBuf,
+    pub(crate) dest_path: std::path::PathBuf,
+    /// Change decision taken during planning, when it cannot be deferred to
+    /// an mtime comparison at execution time (e.g. encrypted destinations).
+    pub(crate) changed: Option<bool>,
+    /// Whether the file is stored compressed.
+    pub(crate) compress: bool,
 }
 
 /// Localizable messages loaded from `assets/translations.json`.
 ///
 /// The fields map to the string keys used in the translations bundle. This
 /// struct is deserialized automatically by `serde`.
-#[derive(Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct Messages {
     pub cur_conf: String,
     pub conf_file_not_found: String,
@@ -84,8 +67,82 @@ pub struct Messages {
     pub copy_progress: String,
     pub copied_file: String,
     pub skipped_file: String,
+    pub failed_file: String,
     pub generic_error: String,
     pub error_exclude_parsing: String,
+    pub restore_init: String,
+    pub restore_ended: String,
+    pub starting_restore: String,
+    pub restoring_file: String,
+    pub files_restored: String,
+    pub files_failed: String,
+    pub passphrase_prompt: String,
+    pub passphrase_confirm: String,
+    pub passphrase_mismatch: String,
+    pub wrong_key: String,
+    pub encryption_mismatch: String,
+    pub check_init: String,
+    pub check_ended: String,
+    pub checking_repository: String,
+    pub snapshots_checked: String,
+    pub objects_checked: String,
+    pub check_ok: String,
+    pub check_failed: String,
+    pub unreadable_snapshot: String,
+    pub missing_object: String,
+    pub corrupt_object: String,
+    pub missing_file: String,
+    pub extra_file: String,
+    pub size_mismatch: String,
+    pub hash_mismatch: String,
+    pub files_checked: String,
+    pub files_read: String,
+    pub diff_new: String,
+    pub diff_modified: String,
+    pub diff_deleted: String,
+    pub diff_excluded: String,
+    pub dash_title: String,
+    pub dash_paused: String,
+    pub files_progress: String,
+    pub dash_skipped: String,
+    pub dash_errors: String,
+    pub dash_workers: String,
+    pub dash_idle: String,
+    pub dash_recent: String,
+    pub dash_keys: String,
+    pub elapsed: String,
+    pub throughput: String,
+    pub eta: String,
+    pub throughput_avg: String,
+    pub schedule_installed: String,
+    pub schedule_removed: String,
+    pub schedule_not_found: String,
+    pub schedule_none: String,
+    pub profile_not_found: String,
+    pub daemon_started: String,
+    pub daemon_reloaded: String,
+    pub daemon_next_run: String,
+    pub daemon_invalid_schedule: String,
+    pub daemon_run_started: String,
+    pub daemon_run_finished: String,
+    pub daemon_run_failed: String,
+    pub daemon_run_overlap: String,
+    pub watch_started: String,
+    pub watch_batch: String,
+    pub watch_rescan: String,
+    pub watch_deleted: String,
+    pub watch_error: String,
+    pub lock_held: String,
+    pub lock_waiting: String,
+    pub lock_stale: String,
+    pub hook_running: String,
+    pub hook_failed: String,
+    pub notify_success: String,
+    pub notify_failure: String,
+    pub notify_errors: String,
+    pub notify_report: String,
+    pub notify_failed: String,
+    pub remote_unsupported: String,
 }
 
 /// Thread-safe file logger type: `Arc<Mutex<BufWriter<File>>>`.
@@ -151,14 +208,377 @@ pub fn is_newer(src: &Path, dest: &Path) -> io::Result<bool> {
     }
 }
 
+/// Outcome of the planning phase of `copy_incremental`.
+pub(crate) struct CopyPlan {
+    /// Number of regular files found in the source tree.
+    pub(crate) considered: usize,
+    /// Number of files skipped because they matched an exclude pattern.
+    pub(crate) skipped_excluded: usize,
+    /// Relative paths of the files that matched an exclude pattern.
+    pub(crate) excluded: Vec<PathBuf>,
+    /// Files to process during the execution phase.
+    pub(crate) ops: Vec<CopyOp>,
+    /// Sum of the sizes of the files in `ops`.
+    pub(crate) total_bytes: u64,
+    /// Manifest describing the source files, when requested.
+    pub(crate) manifest: Option<Manifest>,
+    /// Manifest (or snapshot) of the previous run the plan was compared with.
+    pub(crate) previous: Option<Manifest>,
+    /// State of the files whose status is known, to record for the next run
+    /// when `LogContext::state_dir` is set.
+    pub(crate) state: Option<StateIndex>,
+    /// Files that could not be recorded in the manifest.
+    pub(crate) errors: Vec<Error>,
+}
+
+/// List the files of `src_dir`, sorted, for `plan_copy`.
+///
+/// When `paths` is given, only those files (and the files below those
+/// directories) are listed instead of the whole tree; paths that no longer
+/// exist are dropped. A source shared by several destinations is scanned
+/// once and planned against each of them. Directories that cannot be listed
+/// are returned as errors next to the files.
+///
+/// Directories are listed in parallel on the current rayon pool (see
+/// `walk_parallel`); the sort keeps the result independent of the order in
+/// which they are visited.
+pub(crate) fn scan_source(src_dir: &Path, paths: Option<&[PathBuf]>) -> (Vec<PathBuf>, Vec<Error>) {
+    let (mut entries, mut errors): (Vec<PathBuf>, Vec<Error>) = match paths {
+        None => walk_parallel(src_dir),
+        Some(paths) => {
+            let walked: Vec<(Vec<PathBuf>, Vec<Error>)> = paths
+                .par_iter()
+                .filter(|p| p.starts_with(src_dir))
+                .map(|p| match p.is_dir() {
+                    true => walk_parallel(p),
+                    false if p.is_file() => (vec![p.clone()], Vec::new()),
+                    false => (Vec::new(), Vec::new()),
+                })
+                .collect();
+            let (entries, errors): (Vec<_>, Vec<_>) = walked.into_iter().unzip();
+            (entries.concat(), errors.into_iter().flatten().collect())
+        }
+    };
+
+    // Sort entries deterministically to improve cache behaviour and make output stable.
+    entries.par_sort_unstable();
+    entries.dedup();
+    errors.sort_by(|a, b| a.path().cmp(&b.path()));
+    (entries, errors)
+}
+
+/// List the files below `dir`, in no particular order.
+///
+/// Each directory is read by one task and its subdirectories are walked as
+/// separate tasks, so that idle workers of the rayon pool steal them. As
+/// with `WalkDir`, symbolic links are not followed, but links to files are
+/// listed.
+fn walk_parallel(dir: &Path) -> (Vec<PathBuf>, Vec<Error>) {
+    let walk_error = |source| Error::Walk {
+        path: dir.to_path_buf(),
+        source,
+    };
+    let listing = match fs::read_dir(dir) {
+        Ok(listing) => listing,
+        Err(e) => return (Vec::new(), vec![walk_error(e)]),
+    };
+    let mut files = Vec::new();
+    let mut dirs = Vec::new();
+    let mut errors = Vec::new();
+    for entry in listing {
+        match entry.and_then(|e| Ok((e.file_type()?, e.path()))) {
+            Ok((kind, path)) if kind.is_dir() => dirs.push(path),
+            Ok((kind, path)) if kind.is_file() || (kind.is_symlink() && path.is_file()) => {
+                files.push(path)
+            }
+            Ok(_) => {}
+            Err(e) => errors.push(walk_error(e)),
+        }
+    }
+    let below: Vec<(Vec<PathBuf>, Vec<Error>)> =
+        dirs.par_iter().map(|d| walk_parallel(d)).collect();
+    for (f, e) in below {
+        files.extend(f);
+        errors.extend(e);
+    }
+    (files, errors)
+}
+
+/// Decision taken by `plan_copy` for one source file.
+enum Planned<'a> {
+    /// The file (relative path) matches an exclude pattern.
+    Excluded(&'a Path),
+    /// The file (relative path) is backed up; the operation and the size of
+    /// the file are given unless it is unchanged in delta mode. The state of
+    /// the file is given when it is recorded and its status is known.
+    Included(&'a Path, Option<(CopyOp, u64)>, Option<StateEntry>),
+}
+
+/// Build the list of copy operations of the files `entries` of `src_dir`
+/// (as listed by `scan_source`).
+///
+/// When `paths` is given, `entries` only cover those paths and the manifest
+/// is the previous one updated for the given paths only.
+///
+/// In delta mode only files that are newer (or missing) at the destination
+/// are planned; in normal mode every non-excluded file is planned and the
+/// decision is taken at execution time. When `options.manifest` is set, a
+/// manifest of every non-excluded source file is built as well; hashes from
+/// the manifest already stored in `dest_dir` are reused for files whose size
+/// and modification time did not change. Repository runs always build the
+/// manifest and compare against the latest snapshot of the same source.
+///
+/// Files unchanged since they were recorded in `state` (the index of the
+/// last successful run to `dest_dir`) are taken as up to date as long as
+/// their copy exists, without comparing it with the source. When
+/// `options.state_dir` is set, the plan records the state of the files for
+/// the next run.
+pub(crate) fn plan_copy(
+    src_dir: &Path,
+    dest_dir: &Path,
+    entries: &[PathBuf],
+    options: &LogContext,
+    delta: bool,
+    paths: Option<&[PathBuf]>,
+    state: Option<&StateIndex>,
+) -> CopyPlan {
+    // Source paths, used to detect collisions with compressed names.
+    let source_files: HashSet<&Path> = if options.compression.is_some() {
+        entries.iter().map(PathBuf::as_path).collect()
+    } else {
+        HashSet::new()
+    };
+    let is_source_file = |path: &Path| match paths {
+        None => source_files.contains(path),
+        Some(_) => path.is_file(),
+    };
+
+    // A file is not compressed when its compressed name would collide with
+    // another source file (e.g. `data` next to `data.zst`).
+    let destination_of = |src_path: &Path, rel_path: &Path| {
+        let compress = options
+            .compression
+            .as_ref()
+            .is_some_and(|c| c.applies_to(rel_path) && !is_source_file(&compressed_name(src_path)));
+        (
+            compress,
+            dest_dir.join(dest_rel_path(rel_path, compress, options)),
+        )
+    };
+
+    let mut excluded: Vec<PathBuf> = Vec::new();
+
+    // In delta mode, `ops` contains only the files that will actually be copied.
+    // In normal mode, `ops` contains all candidate files (so we can log skipped).
+    let mut ops: Vec<CopyOp> = Vec::new();
+    let mut total_bytes: u64 = 0;
+
+    // Relative paths of the non-excluded files, used to build the manifest.
+    let mut included: Vec<&Path> = Vec::new();
+    let build_manifest = options.manifest || options.repository.is_some();
+    let previous = if let Some(repo) = &options.repository {
+        repo.latest_snapshot(Some(src_dir))
+            .ok()
+            .flatten()
+            .map(|s| s.to_manifest())
+    } else if options.manifest {
+        Manifest::load(dest_dir, options.encryption.as_ref())
+            .ok()
+            .flatten()
+    } else {
+        None
+    };
+
+    // Files are matched and compared in parallel (a stat of the source and
+    // one of the destination each); the decisions keep the order of
+    // `entries`.
+    let decisions: Vec<Planned> = entries
+        .par_iter()
+        .filter_map(|src_path| {
+            let src_path = src_path.as_path();
+            let rel_path = src_path.strip_prefix(src_dir).ok()?;
+
+            if is_excluded(options, src_path, rel_path) {
+                return Some(Planned::Excluded(rel_path));
+            }
+
+            let (compress, dest_path) = destination_of(src_path, rel_path);
+
+            // Encrypted files cannot be compared by mtime with their source, and
+            // repositories have no per-file destination, so the decision is taken
+            // here against the previous manifest instead. Files unchanged since
+            // the last run recorded in `state` are up to date.
+            let meta = fs::metadata(src_path).ok();
+            let changed = if options.repository.is_some() || options.encryption.is_some() {
+                let recorded = previous.as_ref().and_then(|m| m.get(rel_path));
+                Some(match (recorded, &meta) {
+                    (Some(e), Some(meta)) => {
+                        !e.matches_metadata(meta)
+                            || (options.repository.is_none() && !dest_path.exists())
+                    }
+                    _ => true,
+                })
+            } else {
+                // The copy must still be there: a file deleted from the
+                // destination since is compared (and copied) again.
+                let recorded = state.and_then(|s| s.get(rel_path));
+                recorded
+                    .zip(meta.as_ref())
+                    .filter(|(e, meta)| e.matches_metadata(meta))
+                    .filter(|_| {
+                        options
+                            .destination()
+                            .stat(&dest_path)
+                            .is_ok_and(|m| m.is_some())
+                    })
+                    .map(|_| false)
+            };
+
+            // In delta mode, keep only the delta (newer or missing at
+            // destination); unchanged (or unknown) files are counted later as
+            // skipped. In normal mode, consider everything: the decision is
+            // taken during execution.
+            let outdated = match changed {
+                Some(c) => Ok(c),
+                None if delta => options.destination().is_outdated(src_path, &dest_path),
+                None => Ok(true),
+            };
+            let op = (!delta || *outdated.as_ref().unwrap_or(&false)).then(|| {
+                let sz = meta.as_ref().map(|m| m.len()).unwrap_or(0);
+                let op = CopyOp {
+                    src_path: src_path.to_owned(),
+                    rel_path: rel_path.to_owned(),
+                    dest_path,
+                    changed,
+                    compress,
+                };
+                (op, sz)
+            });
+            let recorded = meta
+                .as_ref()
+                .filter(|_| options.state_dir.is_some() && outdated.is_ok())
+                .map(StateEntry::from_metadata);
+            Some(Planned::Included(rel_path, op, recorded))
+        })
+        .collect();
+
+    let mut recorded_state: Vec<(&Path, StateEntry)> = Vec::new();
+    for decision in decisions {
+        match decision {
+            Planned::Excluded(rel_path) => excluded.push(rel_path.to_owned()),
+            Planned::Included(rel_path, op, recorded) => {
+                if build_manifest {
+                    included.push(rel_path);
+                }
+                if let Some(entry) = recorded {
+                    recorded_state.push((rel_path, entry));
+                }
+                if let Some((op, sz)) = op {
+                    total_bytes = total_bytes.saturating_add(sz);
+                    ops.push(op);
+                }
+            }
+        }
+    }
+
+    let mut errors = Vec::new();
+    let manifest = if build_manifest {
+        let recorded: Vec<Result<(String, ManifestEntry), Error>> = included
+            .par_iter()
+            .map(|rel_path| {
+                let src_path = src_dir.join(rel_path);
+                let metadata_error = |source| Error::Metadata {
+                    path: src_path.clone(),
+                    source,
+                };
+                let meta = fs::metadata(&src_path).map_err(metadata_error)?;
+                let reused = previous
+                    .as_ref()
+                    .and_then(|m| m.get(rel_path))
+                    .filter(|e| e.matches_metadata(&meta))
+                    .map(|e| e.hash.clone());
+                let hash = match reused {
+                    Some(h) => h,
+                    None => hash_file(&src_path).map_err(metadata_error)?,
+                };
+                Ok((
+                    manifest_key(rel_path),
+                    ManifestEntry::from_metadata(&meta, hash),
+                ))
+            })
+            .collect();
+        let recorded: Vec<(String, ManifestEntry)> = recorded
+            .into_iter()
+            .filter_map(|r| r.map_err(|e| errors.push(e)).ok())
+            .collect();
+        let mut manifest = Manifest::default();
+        if let (Some(paths), Some(previous)) = (paths, &previous) {
+            // Keep the entries of the paths that were not touched.
+            let touched: Vec<String> = paths
+                .iter()
+                .filter_map(|p| p.strip_prefix(src_dir).ok())
+                .map(manifest_key)
+                .collect();
+            manifest.entries.extend(
+                previous
+                    .entries
+                    .iter()
+                    .filter(|(key, _)| {
+                        !touched.iter().any(|t| {
+                            t.is_empty()
+                                || *key == t
+                                || key
+                                    .strip_prefix(t.as_str())
+                                    .is_some_and(|r| r.starts_with('/'))
+                        })
+                    })
+                    .map(|(k, e)| (k.clone(), e.clone())),
+            );
+        }
+        manifest.entries.extend(recorded);
+        Some(manifest)
+    } else {
+        None
+    };
+
+    // Hashes computed for the manifest are recorded in the state as well.
+    let state = options.state_dir.as_ref().map(|_| {
+        let entries = recorded_state.into_iter().map(|(rel_path, mut entry)| {
+            let key = manifest_key(rel_path);
+            entry.hash = manifest
+                .as_ref()
+                .and_then(|m| m.entries.get(&key))
+                .map(|e| e.hash.clone());
+            (key, entry)
+        });
+        StateIndex {
+            entries: entries.collect(),
+        }
+    });
+
+    CopyPlan {
+        considered: entries.len(),
+        skipped_excluded: excluded.len(),
+        excluded,
+        ops,
+        total_bytes,
+        manifest,
+        previous,
+        state,
+        errors,
+    }
+}
+
 /// Perform an incremental copy from `src_dir` to `dest_dir`.
 ///
 /// The function walks the source directory recursively, applies the optional
 /// exclude matcher (if present in `options`), copies files that are newer
-/// (or missing) and updates simple progress output via `LogContext` and the
-/// `ui::draw_ui` helper.
+/// (or missing) and reports each file to the progress display, the file
+/// logger and the observer selected by `options`.
 ///
-/// Returns a tuple `(copied_count, skipped_count)` on success.
+/// Returns a tuple `(copied_count, skipped_count)` on success; files that
+/// failed to copy are counted as skipped. Use `copy_sources` to get their
+/// errors in `CopyCounts::errors`.
 ///
 /// # Parameters
 /// - `src_dir`: source directory path
@@ -167,356 +587,619 @@ pub fn is_newer(src: &Path, dest: &Path) -> io::Result<bool> {
 /// - `options`: `LogContext` carrying runtime options like logger, dry-run, etc.
 ///
 /// # Errors
-/// Returns an `io::Error` if listing directories or reading/writing files fails
-/// in an unexpected way.
+/// Returns an `rbackup::Error` if the source cannot be walked or the manifest
+/// or snapshot cannot be written. Errors of single files do not fail the
+/// copy.
+///
+/// Programs embedding rBackup should rather use `job::BackupJob`, which
+/// builds the context and reports progress to an observer.
 ///
 /// # Example (high level)
 ///
 /// ```rust,no_run
 /// use std::path::Path;
-/// use rbackup::{copy_incremental, Messages, LogContext};
+/// use rbackup::{copy_incremental, LogContext};
+/// use rbackup::utils::load_translations;
 ///
-/// // Prepare placeholders (in real code load translations and build a LogContext)
-/// let msg: Messages = serde_json::from_str("{}") /* load proper messages */ .unwrap_or_else(|_| panic!());
-/// let ctx = LogContext { logger: None, quiet: false, with_timestamp: false, timestamp_format: None, row: None, on_log: true, exclude_matcher: None, exclude_match_absolute: false, dry_run: true, exclude_patterns: None, show_skipped: rbackup::output::ShowSkipped::Summary };
+/// let msg = load_translations().unwrap().remove("en").unwrap();
+/// let ctx = LogContext { dry_run: true, ..Default::default() };
 ///
 /// // Run a dry-run copy (will not actually copy files because dry_run = true)
 /// let (copied, skipped) = copy_incremental(Path::new("/tmp/src"), Path::new("/tmp/dest"), &msg, &ctx, false).unwrap();
 /// println!("copied={}, skipped={}", copied, skipped);
 /// ```
+// Library API; the binary copies through `copy_sources`.
+#[allow(dead_code)]
 pub fn copy_incremental(
     src_dir: &Path,
     dest_dir: &Path,
     msg: &Messages,
     options: &LogContext,
     delta: bool,
-) -> io::Result<(usize, usize)> {
-    // Collect all file entries in a single pass to avoid walking the tree twice.
-    let mut entries: Vec<_> = WalkDir::new(src_dir)
-        .into_iter()
-        .filter_map(Result::ok)
-        .filter(|e| e.path().is_file())
-        .collect();
+) -> Result<(usize, usize), Error> {
+    let pairs = [(src_dir.to_path_buf(), dest_dir.to_path_buf())];
+    copy_sources(&pairs, msg, options, delta, None).map(|c| (c.copied, c.skipped))
+}
 
-    // Sort entries deterministically to improve cache behaviour and make output stable.
-    entries.sort_by_key(|e| e.path().to_owned());
+/// Counts of a copy run.
+#[derive(Debug, Default)]
+pub struct CopyCounts {
+    pub copied: usize,
+    /// Files not copied, failed ones included.
+    pub skipped: usize,
+    /// Files whose copy failed.
+    pub failed: usize,
+    /// Errors of single files: the `failed` copies, and the directories
+    /// that could not be listed or files that could not be recorded in the
+    /// manifest. They do not fail the run.
+    pub errors: Vec<Error>,
+}
 
-    // --- Phase 1: build operations list (and optionally a delta-only plan) -----
-    let mut skipped_excluded: usize = 0;
+/// One destination of a copy run.
+///
+/// `options` carries the settings of this destination (its encryption key,
+/// repository, dry-run and manifest flags, excludes, ...); the display
+/// settings of the run are taken from the context given to `copy_targets`.
+#[derive(Debug, Clone, Default)]
+pub struct CopyTarget {
+    /// Destination as given by the user, used in messages.
+    pub destination: PathBuf,
+    /// `(source, destination directory)` pairs, as for `copy_sources`.
+    pub pairs: Vec<(PathBuf, PathBuf)>,
+    pub options: LogContext,
+}
 
-    // In delta mode, `ops` contains only the files that will actually be copied.
-    // In normal mode, `ops` contains all candidate files (so we can log skipped).
-    let mut ops: Vec<CopyOp> = Vec::new();
+/// Copy several sources in one run, each `(source, destination)` pair like
+/// `copy_incremental`.
+///
+/// All sources are planned first, then copied together: the files share the
+/// worker pool, the progress display and the returned counts. Manifests and
+/// repository snapshots are still written per source.
+///
+/// When `paths` is given, only those paths (files or directories below the
+/// sources) are planned instead of walking the whole sources: this is the
+/// incremental step of watch mode. Paths that no longer exist are removed
+/// from the manifest (or snapshot) but kept in the backup.
+pub fn copy_sources(
+    pairs: &[(PathBuf, PathBuf)],
+    msg: &Messages,
+    options: &LogContext,
+    delta: bool,
+    paths: Option<&[PathBuf]>,
+) -> Result<CopyCounts, Error> {
+    let target = CopyTarget {
+        pairs: pairs.to_vec(),
+        options: options.clone(),
+        ..Default::default()
+    };
+    copy_targets(std::slice::from_ref(&target), msg, options, delta, paths)
+        .pop()
+        .unwrap_or(Ok(CopyCounts::default()))
+}
+
+/// Copy the same sources to several destinations in one run.
+///
+/// Each source is scanned once and planned against every destination; the
+/// directories of a source that cannot be listed are reported with its
+/// first destination. The files of all destinations share the worker pool
+/// and the progress display of `options`; a file copied unchanged (neither
+/// compressed, encrypted nor stored in a repository) to several destinations
+/// is read once for all of them. Returns the result of each target, in
+/// order: an error of one destination (e.g. its manifest cannot be written)
+/// does not affect the others.
+pub fn copy_targets(
+    targets: &[CopyTarget],
+    msg: &Messages,
+    options: &LogContext,
+    delta: bool,
+    paths: Option<&[PathBuf]>,
+) -> Vec<Result<CopyCounts, Error>> {
+    // Plan every (target, source) unit, then execute the operations of all
+    // of them together; each operation keeps the index of its unit.
+    let units: Vec<(usize, &Path, &Path)> = targets
+        .iter()
+        .enumerate()
+        .flat_map(|(t, target)| {
+            target
+                .pairs
+                .iter()
+                .map(move |(src, dest)| (t, src.as_path(), dest.as_path()))
+        })
+        .collect();
+    let mut scans: HashMap<&Path, Vec<PathBuf>> = HashMap::new();
+    let mut errors: Vec<Vec<Error>> = targets.iter().map(|_| Vec::new()).collect();
+    let mut considered = vec![0; targets.len()];
+    let mut skipped_excluded = vec![0; targets.len()];
+    let mut planned = vec![0; targets.len()];
     let mut total_bytes: u64 = 0;
+    let mut ops: Vec<(usize, CopyOp)> = Vec::new();
+    let mut manifests: Vec<Option<Manifest>> = Vec::new();
+    let mut excluded: Vec<Vec<PathBuf>> = Vec::new();
+    let mut states: Vec<Option<(PathBuf, StateIndex)>> = Vec::new();
+    for (u, &(t, src_dir, dest_dir)) in units.iter().enumerate() {
+        let entries = scans.entry(src_dir).or_insert_with(|| {
+            let (entries, walk_errors) = scan_source(src_dir, paths);
+            errors[t].extend(walk_errors);
+            entries
+        });
+        let options = &targets[t].options;
+        let state_path = options
+            .state_dir
+            .as_ref()
+            .map(|dir| StateIndex::path_in(dir, src_dir, &targets[t].destination, dest_dir));
+        // An unreadable index is rebuilt.
+        let previous_state = state_path
+            .as_ref()
+            .and_then(|path| StateIndex::load(path).ok().flatten());
+        let trusted = previous_state.as_ref().filter(|_| !options.rescan);
+        let plan = plan_copy(src_dir, dest_dir, entries, options, delta, paths, trusted);
+        // Watch mode plans a few paths: the rest of the last state still holds.
+        states.push(state_path.zip(plan.state).map(|(path, mut state)| {
+            if paths.is_some()
+                && let Some(mut previous) = previous_state
+            {
+                previous.entries.append(&mut state.entries);
+                state = previous;
+            }
+            (path, state)
+        }));
+        considered[t] += plan.considered;
+        skipped_excluded[t] += plan.skipped_excluded;
+        planned[t] += plan.ops.len();
+        total_bytes = total_bytes.saturating_add(plan.total_bytes);
+        ops.extend(plan.ops.into_iter().map(|op| (u, op)));
+        manifests.push(plan.manifest);
+        excluded.push(plan.excluded);
+        errors[t].extend(plan.errors);
+    }
+    drop(scans);
 
-    for entry in &entries {
-        let src_path = entry.path();
-        let rel_path = match src_path.strip_prefix(src_dir) {
-            Ok(p) => p,
-            Err(_) => continue,
-        };
+    // Operations reading the same source file are processed together, so
+    // that the file is read once for all the destinations copying it as is.
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut group_of: HashMap<&Path, usize> = HashMap::new();
+    for (k, (_, op)) in ops.iter().enumerate() {
+        let g = *group_of.entry(op.src_path.as_path()).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+        groups[g].push(k);
+    }
+    drop(group_of);
 
-        // Exclude matcher applies to rel path (default) or absolute.
-        if let Some(ex) = &options.exclude_matcher {
-            let target_path = if options.exclude_match_absolute {
-                src_path
-            } else {
-                rel_path
-            };
-            let matched_pattern = ex.is_match(target_path).or_else(|| {
-                rel_path
-                    .file_name()
-                    .and_then(|name| ex.is_match(Path::new(name)))
-            });
-            if matched_pattern.is_some() {
-                skipped_excluded += 1;
-                continue;
-            }
+    // The progress display and the file logger of the run observe it like
+    // the observer of the caller does.
+    let mut observers: Vec<Arc<dyn ProgressObserver>> = Vec::new();
+    match options.progress.resolve(options.quiet) {
+        ProgressMode::None => {}
+        ProgressMode::Dashboard => observers.push(Arc::new(DashboardObserver::start(
+            msg,
+            rayon::current_num_threads(),
+            options.show_skipped == ShowSkipped::All,
+        ))),
+        ProgressMode::Plain => observers.push(Arc::new(PlainProgress::new(msg, options))),
+        _ => observers.push(Arc::new(FancyProgress::start(msg, options))),
+    }
+    if let Some(observer) = LogObserver::new(msg, options) {
+        observers.push(Arc::new(observer));
+    }
+    if let Some(observer) = &options.observer {
+        observers.push(observer.clone());
+    }
+    let notify = |event: ProgressEvent<'_>| {
+        for observer in &observers {
+            observer.on_event(&event);
         }
+    };
 
-        let dest_path = dest_dir.join(rel_path);
-
-        if delta {
-            // Keep only the delta (newer or missing at destination).
-            match is_newer(src_path, &dest_path) {
-                Ok(true) => {
-                    let sz = entry.metadata().map(|m| m.len()).unwrap_or(0);
-                    total_bytes = total_bytes.saturating_add(sz);
-                    ops.push(CopyOp {
-                        src_path: src_path.to_owned(),
-                        dest_path,
-                    });
-                }
-                Ok(false) | Err(_) => {
-                    // unchanged (or unknown) in delta mode -> ignore here; counted later as skipped
-                }
-            }
-        } else {
-            // Normal mode: consider everything (we will decide copy/skip during execution).
-            let sz = entry.metadata().map(|m| m.len()).unwrap_or(0);
-            total_bytes = total_bytes.saturating_add(sz);
-            ops.push(CopyOp {
-                src_path: src_path.to_owned(),
-                dest_path,
+    notify(ProgressEvent::Planned {
+        files: ops.len(),
+        bytes: total_bytes,
+        destinations: targets.len(),
+    });
+    for (&(_, src_dir, dest_dir), excluded) in units.iter().zip(&excluded) {
+        for rel_path in excluded {
+            notify(ProgressEvent::FileSkipped {
+                source: &src_dir.join(rel_path),
+                destination: &dest_dir.join(rel_path),
+                reason: SkipReason::Excluded,
             });
         }
     }
+    drop(excluded);
 
-    // Prepare UI channel and spawn a dedicated UI thread that owns all terminal writes.
-    let (ui_tx, ui_handle) = {
-        let (tx, rx) = mpsc::channel::<UiEvent>();
-
-        let ui_msg = Messages {
-            cur_conf: msg.cur_conf.clone(),
-            conf_file_not_found: msg.conf_file_not_found.clone(),
-            conf_initialized: msg.conf_initialized.clone(),
-            backup_init: msg.backup_init.clone(),
-            backup_ended: msg.backup_ended.clone(),
-            starting_backup: msg.starting_backup.clone(),
-            to: msg.to.clone(),
-            copying_file: msg.copying_file.clone(),
-            language_not_supported: msg.language_not_supported.clone(),
-            files_total: msg.files_total.clone(),
-            files_copied: msg.files_copied.clone(),
-            files_skipped: msg.files_skipped.clone(),
-            copy_progress: msg.copy_progress.clone(),
-            copied_file: msg.copied_file.clone(),
-            skipped_file: msg.skipped_file.clone(),
-            generic_error: msg.generic_error.clone(),
-            error_exclude_parsing: msg.error_exclude_parsing.clone(),
-        };
-
-        let handle = thread::spawn(move || {
-            use crossterm::cursor::MoveTo;
-            use crossterm::execute;
-            use crossterm::style::Print;
-            use crossterm::terminal;
-            use crossterm::terminal::ClearType;
-            use std::collections::VecDeque;
-            use std::io::stdout;
+    let copied: Vec<AtomicUsize> = targets.iter().map(|_| AtomicUsize::new(0)).collect();
+    let skipped_unchanged: Vec<AtomicUsize> = targets.iter().map(|_| AtomicUsize::new(0)).collect();
+    let skipped_errors: Vec<AtomicUsize> = targets.iter().map(|_| AtomicUsize::new(0)).collect();
 
-            let mut buffer: VecDeque<String> = VecDeque::new();
-
-            let redraw = |buf: &VecDeque<String>, cols: u16, scroll_rows: usize| {
-                if cols == 0 {
-                    return;
-                }
-                let max_content = cols.saturating_sub(1) as usize;
-
-                for i in 0..scroll_rows {
-                    if i < buf.len() {
-                        let mut s = truncate_to_width(&buf[i], max_content);
-                        let disp = UnicodeWidthStr::width(s.as_str());
-                        if disp < max_content {
-                            s.push_str(&" ".repeat(max_content - disp));
-                        }
-                        let _ = execute!(
-                            stdout(),
-                            MoveTo(0, i as u16),
-                            Clear(ClearType::CurrentLine),
-                            Print(s)
-                        );
-                    } else {
-                        let _ =
-                            execute!(stdout(), MoveTo(0, i as u16), Clear(ClearType::CurrentLine));
-                    }
-                }
-            };
-
-            // initial draw: clear whole screen
-            let _ = execute!(stdout(), Clear(ClearType::All));
-
-            for ev in rx {
-                let (cols, rows) = terminal::size().unwrap_or((80, 24));
-                let progress_row = rows.saturating_sub(1);
-                let scroll_rows = progress_row as usize;
-
-                while buffer.len() > scroll_rows {
-                    buffer.pop_front();
-                }
+    let done_ops = AtomicUsize::new(0);
+    let done_bytes = AtomicU64::new(0);
 
-                match ev {
-                    UiEvent::Message(s) => {
-                        if scroll_rows == 0 {
-                            continue;
-                        }
-                        buffer.push_back(s);
-                        if buffer.len() > scroll_rows {
-                            buffer.pop_front();
-                        }
-                        redraw(&buffer, cols, scroll_rows);
-                    }
-                    UiEvent::Progress {
-                        done_bytes,
-                        total_bytes,
-                    } => {
-                        crate::ui::draw_ui(done_bytes, progress_row, total_bytes, &ui_msg);
+    // Relative paths whose copy failed, with the error, per unit; they are
+    // dropped from the manifest so it only describes what actually reached
+    // the destination.
+    let failed: Vec<Mutex<Vec<(PathBuf, Error)>>> =
+        units.iter().map(|_| Mutex::default()).collect();
+    // Objects of the files stored into a repository, per unit, keyed by
+    // manifest key.
+    let stored: Vec<Mutex<HashMap<String, Vec<String>>>> =
+        units.iter().map(|_| Mutex::default()).collect();
+    let copy_attempt = |u: usize, op: &CopyOp| -> io::Result<()> {
+        let options = &targets[units[u].0].options;
+        match &options.repository {
+            Some(repo) => {
+                let key = manifest_key(&op.rel_path);
+                let hash = manifests[u]
+                    .as_ref()
+                    .and_then(|m| m.entries.get(&key))
+                    .map(|e| e.hash.as_str());
+                match hash {
+                    _ if options.dry_run => Ok(()),
+                    Some(hash) => {
+                        let compression = options.compression.as_ref().filter(|_| op.compress);
+                        repo.store_file(&op.src_path, hash, compression)
+                            .map(|chunks| {
+                                if let Ok(mut s) = stored[u].lock() {
+                                    s.insert(key, chunks);
+                                }
+                            })
                     }
+                    // The file could not be hashed during planning.
+                    None => Err(io::Error::other("file could not be read")),
                 }
             }
-        });
-
-        (Some(tx), handle)
+            None => copy_op(op, options),
+        }
+    };
+    let error_of = |op: &CopyOp, result: io::Result<()>| -> Option<Error> {
+        result.err().map(|e| Error::Copy {
+            path: op.src_path.clone(),
+            destination: op.dest_path.clone(),
+            source: e,
+        })
+    };
+    // Copies that can share a single read of the source file.
+    let is_plain = |u: usize, op: &CopyOp| {
+        let options = &targets[units[u].0].options;
+        !options.dry_run
+            && options.remote.is_none()
+            && options.repository.is_none()
+            && options.encryption.is_none()
+            && !op.compress
     };
 
-    // Prepare channel and logger thread if a file logger is configured.
-    let (log_tx, log_handle) = if let Some(logger) = &options.logger {
-        let (tx, rx) = mpsc::channel::<String>();
-        let logger = logger.clone();
-        let handle = thread::spawn(move || {
-            for line in rx {
-                match logger.lock() {
-                    Ok(mut guard) => {
-                        let _ = writeln!(guard, "{}", line);
-                    }
-                    Err(poisoned) => {
-                        let mut guard = poisoned.into_inner();
-                        let _ = writeln!(guard, "{}", line);
-                    }
-                }
-            }
+    groups.par_iter().for_each(|group| {
+        let worker = rayon::current_thread_index().unwrap_or(0);
+        let src_path = ops[group[0]].1.src_path.as_path();
+        notify(ProgressEvent::FileStarted {
+            worker,
+            source: src_path,
+            destination: &ops[group[0]].1.dest_path,
         });
-        (Some(tx), Some(handle))
-    } else {
-        (None, None)
-    };
+        let file_size = fs::metadata(src_path).map(|m| m.len()).unwrap_or(0);
 
-    let copied = AtomicUsize::new(0);
-    let skipped_unchanged = AtomicUsize::new(0);
-    let skipped_errors = AtomicUsize::new(0);
+        // Delta mode: every op is supposed to be copied. Normal mode: decide
+        // at runtime whether the file is newer, unless the decision was
+        // already taken during planning; when the destination cannot be
+        // compared (e.g. missing), the copy is attempted.
+        let to_copy: Vec<bool> = group
+            .iter()
+            .map(|&k| {
+                let op = &ops[k].1;
+                delta
+                    || match op.changed {
+                        Some(c) => c,
+                        None => targets[units[ops[k].0].0]
+                            .options
+                            .destination()
+                            .is_outdated(src_path, op.dest_path.as_path())
+                            .unwrap_or(true),
+                    }
+            })
+            .collect();
+        let plain: Vec<usize> = group
+            .iter()
+            .zip(&to_copy)
+            .filter(|&(&k, &copy)| copy && is_plain(ops[k].0, &ops[k].1))
+            .map(|(&k, _)| k)
+            .collect();
+        let mut shared: HashMap<usize, io::Result<()>> = if plain.len() > 1 {
+            let dests: Vec<&Path> = plain
+                .iter()
+                .map(|&k| ops[k].1.dest_path.as_path())
+                .collect();
+            plain
+                .iter()
+                .copied()
+                .zip(copy_to_many(src_path, &dests))
+                .collect()
+        } else {
+            HashMap::new()
+        };
 
-    let done_ops = AtomicUsize::new(0);
-    let done_bytes = AtomicU64::new(0);
+        for (j, &k) in group.iter().enumerate() {
+            let (u, op) = &ops[k];
+            let (u, t) = (*u, units[*u].0);
+            let (source, destination) = (op.src_path.as_path(), op.dest_path.as_path());
+            if j > 0 {
+                notify(ProgressEvent::FileStarted {
+                    worker,
+                    source,
+                    destination,
+                });
+            }
 
-    let total_bytes_for_ui = if total_bytes == 0 { 1 } else { total_bytes };
+            let error = if to_copy[j] {
+                let result = match shared.remove(&k) {
+                    Some(result) => result,
+                    None => copy_attempt(u, op),
+                };
+                error_of(op, result)
+            } else {
+                None
+            };
 
-    ops.par_iter().for_each(|op| {
-        let src_path = op.src_path.as_path();
-        let file_size = fs::metadata(src_path).map(|m| m.len()).unwrap_or(0);
+            let cur_ops = done_ops.fetch_add(1, Ordering::Relaxed) + 1;
+            let cur_bytes = done_bytes.fetch_add(file_size, Ordering::Relaxed) + file_size;
 
-        let status_is_copied = if delta {
-            // Delta mode: every op is supposed to be copied.
-            if !options.dry_run {
-                if let Some(parent) = op.dest_path.parent() {
-                    let _ = fs::create_dir_all(parent);
-                }
-                fs::copy(src_path, op.dest_path.as_path()).is_ok()
-            } else {
-                true
-            }
-        } else {
-            // Normal mode: decide at runtime whether this file is newer.
-            match is_newer(src_path, op.dest_path.as_path()) {
-                Ok(true) => {
-                    if !options.dry_run {
-                        if let Some(parent) = op.dest_path.parent() {
-                            let _ = fs::create_dir_all(parent);
-                        }
-                        fs::copy(src_path, op.dest_path.as_path()).is_ok()
-                    } else {
-                        true
-                    }
+            match &error {
+                Some(error) => {
+                    skipped_errors[t].fetch_add(1, Ordering::Relaxed);
+                    notify(ProgressEvent::FileFailed {
+                        source,
+                        destination,
+                        error,
+                    });
                 }
-                Ok(false) => {
-                    skipped_unchanged.fetch_add(1, Ordering::Relaxed);
-                    false
+                None if to_copy[j] => {
+                    copied[t].fetch_add(1, Ordering::Relaxed);
+                    notify(ProgressEvent::FileCopied {
+                        source,
+                        destination,
+                        bytes: file_size,
+                    });
                 }
-                Err(_) => {
-                    // If we can't compare (e.g. dest missing/permission), treat as copy attempt.
-                    if !options.dry_run {
-                        if let Some(parent) = op.dest_path.parent() {
-                            let _ = fs::create_dir_all(parent);
-                        }
-                        fs::copy(src_path, op.dest_path.as_path()).is_ok()
-                    } else {
-                        true
-                    }
+                None => {
+                    skipped_unchanged[t].fetch_add(1, Ordering::Relaxed);
+                    notify(ProgressEvent::FileSkipped {
+                        source,
+                        destination,
+                        reason: SkipReason::Unchanged,
+                    });
                 }
             }
-        };
+            if let Some(error) = error
+                && let Ok(mut f) = failed[u].lock()
+            {
+                f.push((op.rel_path.clone(), error));
+            }
 
-        if status_is_copied {
-            copied.fetch_add(1, Ordering::Relaxed);
-        } else if delta {
-            // In delta mode, the only way to "not copied" is an error.
-            skipped_errors.fetch_add(1, Ordering::Relaxed);
+            // Throttle progress updates.
+            if cur_ops.is_multiple_of(16) || cur_ops == ops.len() {
+                notify(ProgressEvent::BytesProgress {
+                    done_files: cur_ops,
+                    done_bytes: cur_bytes,
+                    total_bytes,
+                });
+            }
         }
+    });
 
-        let cur_ops = done_ops.fetch_add(1, Ordering::Relaxed) + 1;
-        let cur_bytes = done_bytes.fetch_add(file_size, Ordering::Relaxed) + file_size;
+    let mut results: Vec<Result<CopyCounts, Error>> = (0..targets.len())
+        .zip(errors)
+        .map(|(t, errors)| {
+            // Compute skipped totals.
+            let copied_n = copied[t].load(Ordering::Relaxed);
+            let skipped_unchanged_n = skipped_unchanged[t].load(Ordering::Relaxed);
+            let skipped_errors_n = skipped_errors[t].load(Ordering::Relaxed);
 
-        // Message line: always show copied; show skipped only when requested.
-        let should_print = status_is_copied || options.show_skipped == ShowSkipped::All;
-        if should_print {
-            let status = if status_is_copied {
-                &msg.copied_file
+            // In delta mode, unchanged files are those we ignored during planning.
+            let skipped_total = if delta {
+                // total files considered (entries) minus those excluded minus copied candidates (ops)
+                // This is only an approximation; we treat it as skipped for summary.
+                let considered = considered[t].saturating_sub(skipped_excluded[t]);
+                let unchanged = considered.saturating_sub(planned[t]);
+                unchanged + skipped_excluded[t] + skipped_errors_n
             } else {
-                &msg.skipped_file
+                skipped_excluded[t] + skipped_unchanged_n + skipped_errors_n
             };
+            Ok(CopyCounts {
+                copied: copied_n,
+                skipped: skipped_total,
+                failed: 0,
+                errors,
+            })
+        })
+        .collect();
 
-            let log_line = format!(
-                "#{} {} {} - {}.",
-                cur_ops,
-                msg.copying_file,
-                op.src_path.display(),
-                status
-            );
-
-            if let Some(tx) = &log_tx {
-                let full = if options.with_timestamp {
-                    let fmt = options
-                        .timestamp_format
-                        .as_deref()
-                        .unwrap_or("%Y-%m-%d %H:%M:%S");
-                    format!("[{}] {}", crate::output::now(fmt), log_line)
-                } else {
-                    log_line.clone()
-                };
-                let _ = tx.send(full);
+    let unit_results = manifests.into_iter().zip(failed).zip(stored).zip(states);
+    for ((((mut manifest, failed), stored), state), &(t, src_dir, dest_dir)) in
+        unit_results.zip(&units)
+    {
+        let (failed, failed_errors): (Vec<PathBuf>, Vec<Error>) =
+            failed.into_inner().unwrap_or_default().into_iter().unzip();
+        if let Ok(counts) = results[t].as_mut() {
+            counts.failed += failed.len();
+            counts.errors.extend(failed_errors);
+        }
+        let options = &targets[t].options;
+        if let Some(manifest) = manifest.as_mut()
+            && !options.dry_run
+            && results[t].is_ok()
+        {
+            for rel_path in &failed {
+                manifest.entries.remove(&manifest_key(rel_path));
             }
-
-            if let Some(tx) = &ui_tx {
-                let _ = tx.send(UiEvent::Message(log_line));
+            let saved = match options.manifest {
+                true => manifest.save(dest_dir, options.encryption.as_ref()),
+                false => Ok(()),
+            };
+            let committed = saved.and_then(|()| match &options.repository {
+                Some(repo) => {
+                    let stored = stored.into_inner().unwrap_or_default();
+                    repo.commit_snapshot(src_dir, manifest, &stored).map(|_| ())
+                }
+                None => Ok(()),
+            });
+            if let Err(e) = committed {
+                results[t] = Err(Error::Destination {
+                    path: dest_dir.to_path_buf(),
+                    source: e,
+                });
             }
         }
-
-        // Throttle UI progress updates.
-        if (cur_ops.is_multiple_of(16) || cur_ops == ops.len())
-            && let Some(tx) = &ui_tx
+        // Failed files are compared again next time.
+        if let Some((path, mut state)) = state
+            && !options.dry_run
+            && let Ok(counts) = results[t].as_mut()
         {
-            let _ = tx.send(UiEvent::Progress {
-                done_bytes: cur_bytes,
-                total_bytes: total_bytes_for_ui,
-            });
+            for rel_path in &failed {
+                state.entries.remove(&manifest_key(rel_path));
+            }
+            if let Err(e) = state.save(&path) {
+                counts.errors.push(Error::State { path, source: e });
+            }
         }
+    }
+
+    let done = results.iter().filter_map(|r| r.as_ref().ok());
+    let (copied, skipped, failed) = done.fold((0, 0, 0), |(c, s, f), counts| {
+        (c + counts.copied, s + counts.skipped, f + counts.failed)
+    });
+    notify(ProgressEvent::Finished {
+        copied,
+        skipped,
+        failed,
     });
 
-    // Close channels and join helper threads.
-    drop(ui_tx);
-    let _ = ui_handle.join();
+    results
+}
 
-    drop(log_tx);
-    if let Some(h) = log_handle {
-        let _ = h.join();
-    }
+/// Copy `src_path` to each of `dests`, reading it once.
+///
+/// Returns the result of each destination, in order: a destination that
+/// cannot be written does not prevent the copy to the others. Permissions
+/// are copied like `fs::copy` does. Each copy is written to a temporary file
+/// renamed over the destination once complete, so that a failed read never
+/// leaves a truncated file that looks up to date.
+fn copy_to_many(src_path: &Path, dests: &[&Path]) -> Vec<io::Result<()>> {
+    use std::io::Read;
 
-    // Compute skipped totals.
-    let copied_n = copied.load(Ordering::Relaxed);
-    let skipped_unchanged_n = skipped_unchanged.load(Ordering::Relaxed);
-    let skipped_errors_n = skipped_errors.load(Ordering::Relaxed);
-
-    // In delta mode, unchanged files are those we ignored during planning.
-    let skipped_total = if delta {
-        // total files considered (entries) minus those excluded minus copied candidates (ops)
-        // This is only an approximation; we treat it as skipped for summary.
-        let considered = entries.len().saturating_sub(skipped_excluded);
-        let unchanged = considered.saturating_sub(ops.len());
-        unchanged + skipped_excluded + skipped_errors_n
-    } else {
-        skipped_excluded + skipped_unchanged_n + skipped_errors_n
+    let mut src = match File::open(src_path) {
+        Ok(f) => f,
+        Err(e) => return dests.iter().map(|_| Err(copy_error(&e))).collect(),
     };
+    let mut writers: Vec<io::Result<File>> = dests
+        .iter()
+        .map(|dest| {
+            if let Some(parent) = dest.parent() {
+                let _ = fs::create_dir_all(parent);
+            }
+            File::create(temp_path(dest))
+        })
+        .collect();
+
+    let mut buf = vec![0u8; 256 * 1024];
+    loop {
+        let n = match src.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                for w in writers.iter_mut().filter(|w| w.is_ok()) {
+                    *w = Err(copy_error(&e));
+                }
+                break;
+            }
+        };
+        for w in writers.iter_mut() {
+            if let Ok(file) = w
+                && let Err(e) = file.write_all(&buf[..n])
+            {
+                *w = Err(e);
+            }
+        }
+    }
 
-    Ok((copied_n, skipped_total))
+    let permissions = src.metadata().map(|m| m.permissions());
+    writers
+        .into_iter()
+        .zip(dests)
+        .map(|(w, dest)| {
+            let tmp = temp_path(dest);
+            let result = w.and_then(|file| {
+                if let Ok(permissions) = &permissions {
+                    file.set_permissions(permissions.clone())?;
+                }
+                file.sync_all()?;
+                fs::rename(&tmp, dest)
+            });
+            if result.is_err() {
+                let _ = fs::remove_file(&tmp);
+            }
+            result
+        })
+        .collect()
+}
+
+/// The same error for each destination of `copy_to_many`.
+fn copy_error(e: &io::Error) -> io::Error {
+    io::Error::new(e.kind(), e.to_string())
+}
+
+/// Map a path relative to the source to the path of its copy, relative to
+/// the destination, accounting for compression and encryption.
+fn dest_rel_path(rel_path: &Path, compress: bool, options: &LogContext) -> PathBuf {
+    match &options.encryption {
+        // Encrypted names are derived from the source path alone; whether
+        // the payload is compressed is recorded inside the file.
+        Some(enc) if enc.encrypt_names => enc.dest_rel_path(rel_path),
+        Some(enc) if compress => enc.dest_rel_path(&compressed_name(rel_path)),
+        Some(enc) => enc.dest_rel_path(rel_path),
+        None if compress => compressed_name(rel_path),
+        None => rel_path.to_owned(),
+    }
+}
+
+/// Copy a single planned file, creating the destination parent directories.
+///
+/// The file is compressed and/or encrypted on the way according to the plan
+/// and `options`. Nothing is written in dry-run mode.
+fn copy_op(op: &CopyOp, options: &LogContext) -> io::Result<()> {
+    if options.dry_run {
+        return Ok(());
+    }
+    let compression = options.compression.as_ref().filter(|_| op.compress);
+    if let Some(remote) = &options.remote {
+        if let Some(parent) = op.dest_path.parent() {
+            remote.create_dir_all(parent)?;
+        }
+        let src = File::open(&op.src_path)?;
+        let mut reader: Box<dyn io::Read> = match compression {
+            Some(comp) => Box::new(comp.reader(io::BufReader::new(src))?),
+            None => Box::new(src),
+        };
+        let mut file = remote.create(&op.dest_path, &op.src_path)?;
+        io::copy(&mut reader, &mut file)?;
+        return file.commit();
+    }
+    if let Some(parent) = op.dest_path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    match (&options.encryption, compression) {
+        (Some(enc), Some(comp)) => File::open(&op.src_path)
+            .and_then(|f| comp.reader(io::BufReader::new(f)))
+            .and_then(|reader| {
+                write_atomically(&op.dest_path, |w| {
+                    enc.encrypt_stream(reader, w, &manifest_key(&op.rel_path))
+                })
+            }),
+        (Some(enc), None) => {
+            enc.encrypt_file(&op.src_path, &op.dest_path, &manifest_key(&op.rel_path))
+        }
+        (None, Some(comp)) => comp.compress_file(&op.src_path, &op.dest_path),
+        (None, None) => fs::copy(&op.src_path, &op.dest_path).map(|_| ()),
+    }
 }
 
 /// Pattern matcher for exclude lists.
@@ -551,11 +1234,30 @@ impl ExcludeMatcher {
     }
 }
 
+/// Return true if the source file `src_path`, at `rel_path` below its source
+/// directory, is excluded by the matcher of `options`: its relative path (or
+/// its absolute one, see `LogContext::exclude_match_absolute`) or its name
+/// matches a pattern.
+pub(crate) fn is_excluded(options: &LogContext, src_path: &Path, rel_path: &Path) -> bool {
+    let Some(ex) = &options.exclude_matcher else {
+        return false;
+    };
+    let target_path = if options.exclude_match_absolute {
+        src_path
+    } else {
+        rel_path
+    };
+    ex.is_match(target_path).is_some()
+        || rel_path
+            .file_name()
+            .is_some_and(|name| ex.is_match(Path::new(name)).is_some())
+}
+
 /// Build an `ExcludeMatcher` from a list of glob patterns.
 ///
 /// The `case_insensitive` flag controls whether the globs are built in a
-/// case-insensitive manner. Returns an I/O-like error when a pattern cannot
-/// be parsed to keep the API consistent with other file operations.
+/// case-insensitive manner. Returns an `Error::Pattern` naming the pattern
+/// that cannot be parsed.
 ///
 /// # Example
 ///
@@ -569,19 +1271,23 @@ impl ExcludeMatcher {
 pub fn build_exclude_matcher(
     patterns: &[String],
     case_insensitive: bool,
-) -> io::Result<ExcludeMatcher> {
+) -> Result<ExcludeMatcher, Error> {
     let mut combined_builder = GlobSetBuilder::new();
     let mut singles: Vec<(String, GlobSet)> = Vec::new();
 
     for pattern in patterns {
         // build Glob with optional case insensitivity
+        let pattern_error = |source| Error::Pattern {
+            pattern: pattern.clone(),
+            source,
+        };
         let glob = if case_insensitive {
             GlobBuilder::new(pattern)
                 .case_insensitive(true)
                 .build()
-                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?
+                .map_err(pattern_error)?
         } else {
-            Glob::new(pattern).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?
+            Glob::new(pattern).map_err(pattern_error)?
         };
 
         // add to combined
@@ -590,16 +1296,15 @@ pub fn build_exclude_matcher(
         // build single-set for pattern to identify which pattern matched
         let mut single_builder = GlobSetBuilder::new();
         single_builder.add(glob);
-        let single_set = single_builder
-            .build()
-            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let single_set = single_builder.build().map_err(pattern_error)?;
 
         singles.push((pattern.clone(), single_set));
     }
 
-    let combined = combined_builder
-        .build()
-        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let combined = combined_builder.build().map_err(|source| Error::Pattern {
+        pattern: patterns.join(" "),
+        source,
+    })?;
 
     Ok(ExcludeMatcher { combined, singles })
 }
@@ -0,0 +1,247 @@
+//! Continuous backup (`copy --watch`).
+//!
+//! After an initial full run, the sources are watched for filesystem events
+//! (inotify on Linux). Touched paths are collected until no event arrives for
+//! the debounce delay, then only those paths are copied with
+//! `utils::copy_targets`, honoring the excludes and destination settings of the
+//! run. Deleted (or renamed away) files are dropped from the manifest but
+//! kept in the backup, like a regular `copy` does. A periodic full rescan,
+//! also triggered when the watcher reports lost events, catches anything the
+//! events missed.
+
+use crate::copy::flush_logger;
+use crate::output::{LogContext, log_output};
+use crate::utils::{CopyCounts, CopyTarget, Messages, copy_targets};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::io;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+/// Default delay without events before a batch of changes is copied.
+pub const DEFAULT_DEBOUNCE_SECS: u64 = 2;
+/// Default interval between two full rescans of the source.
+pub const DEFAULT_RESCAN_SECS: u64 = 3600;
+
+/// Timing of watch mode.
+#[derive(Debug, Clone, Copy)]
+pub struct WatchOptions {
+    /// Delay without events before the touched paths are copied.
+    pub debounce: Duration,
+    /// Interval between two full rescans.
+    pub rescan: Duration,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        Self {
+            debounce: Duration::from_secs(DEFAULT_DEBOUNCE_SECS),
+            rescan: Duration::from_secs(DEFAULT_RESCAN_SECS),
+        }
+    }
+}
+
+/// Whether an event may change what has to be backed up. Access events are
+/// ignored, in particular those caused by reading the files to copy them.
+fn is_change(event: &Event) -> bool {
+    matches!(
+        event.kind,
+        EventKind::Any | EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+    )
+}
+
+/// Paths of `event` to copy again: those below a source of `pairs`, for
+/// events that may change them. Changes below a destination inside a source
+/// are our own and ignored. Both paths of a rename are kept; the old one no
+/// longer exists and is dropped from the manifest.
+pub fn touched_paths(event: &Event, pairs: &[(PathBuf, PathBuf)]) -> Vec<PathBuf> {
+    if !is_change(event) {
+        return Vec::new();
+    }
+    event
+        .paths
+        .iter()
+        .filter(|p| {
+            pairs.iter().any(|(src, _)| p.starts_with(src))
+                && !pairs.iter().any(|(_, dest)| p.starts_with(dest))
+        })
+        .cloned()
+        .collect()
+}
+
+/// What watch mode does next, as decided by `Batch::step`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Step {
+    /// Keep waiting for events.
+    Wait,
+    /// Copy the whole sources again.
+    Rescan,
+    /// Copy the touched `paths`; `deleted` of them no longer exist.
+    Copy { paths: Vec<PathBuf>, deleted: usize },
+}
+
+/// Paths touched since the last copy of watch mode, with the timing of the
+/// next copy and of the next full rescan.
+#[derive(Debug)]
+pub struct Batch {
+    options: WatchOptions,
+    pending: BTreeSet<PathBuf>,
+    last_event: Instant,
+    next_rescan: Instant,
+}
+
+impl Batch {
+    /// An empty batch, the next rescan being due one interval after `now`.
+    pub fn new(options: WatchOptions, now: Instant) -> Self {
+        Self {
+            options,
+            pending: BTreeSet::new(),
+            last_event: now,
+            next_rescan: now + options.rescan,
+        }
+    }
+
+    /// How long to wait for the next event: until the debounce delay is
+    /// over when paths are pending, at most until the next rescan.
+    pub fn timeout(&self, now: Instant) -> Duration {
+        let until = match self.pending.is_empty() {
+            true => self.next_rescan,
+            false => (self.last_event + self.options.debounce).min(self.next_rescan),
+        };
+        until.saturating_duration_since(now)
+    }
+
+    /// Add the paths touched by an event received at `now`. Each event
+    /// postpones the copy by the debounce delay.
+    pub fn add(&mut self, paths: Vec<PathBuf>, now: Instant) {
+        if !paths.is_empty() {
+            self.pending.extend(paths);
+            self.last_event = now;
+        }
+    }
+
+    /// Decide what to do at `now`. `rescan` forces a full rescan, e.g. when
+    /// the watcher lost events; it replaces the pending paths.
+    pub fn step(&mut self, rescan: bool, now: Instant) -> Step {
+        if rescan || now >= self.next_rescan {
+            self.pending.clear();
+            self.next_rescan = now + self.options.rescan;
+            return Step::Rescan;
+        }
+        if self.pending.is_empty() || now < self.last_event + self.options.debounce {
+            return Step::Wait;
+        }
+        let paths: Vec<PathBuf> = std::mem::take(&mut self.pending).into_iter().collect();
+        let deleted = paths.iter().filter(|p| !p.exists()).count();
+        Step::Copy { paths, deleted }
+    }
+}
+
+fn summary(msg: &Messages, label: &str, copied: usize, skipped: usize) -> String {
+    format!(
+        "{} {}, {}",
+        label,
+        msg.files_copied.replace("{}", &copied.to_string()),
+        msg.files_skipped.replace("{}", &skipped.to_string())
+    )
+}
+
+/// Back up the sources to each target, then keep watching the sources and
+/// copying what changes to every target until the process is stopped.
+///
+/// Only returns on errors that stop the watcher itself; errors of a single
+/// run are reported and the next run is attempted.
+pub fn execute_watch(
+    msg: &Messages,
+    ctx: &LogContext,
+    targets: &[CopyTarget],
+    delta: bool,
+    options: WatchOptions,
+) -> io::Result<()> {
+    // Events carry absolute paths below the watched directories.
+    let mut targets = targets.to_vec();
+    for target in &mut targets {
+        for (src, dest) in &mut target.pairs {
+            *src = src.canonicalize()?;
+            *dest = std::path::absolute(&*dest)?;
+        }
+    }
+    let pairs: Vec<(PathBuf, PathBuf)> = targets.iter().flat_map(|t| t.pairs.clone()).collect();
+
+    let run = |label: &str, delta: bool, paths: Option<&[PathBuf]>| {
+        let results = copy_targets(&targets, msg, ctx, delta, paths);
+        let mut total = CopyCounts::default();
+        for (target, result) in targets.iter().zip(results) {
+            match result {
+                Ok(c) => {
+                    total.copied += c.copied;
+                    total.skipped += c.skipped;
+                }
+                Err(e) if targets.len() == 1 => {
+                    log_output(&format!("{}: {}", msg.generic_error, e), ctx)
+                }
+                Err(e) => log_output(
+                    &format!(
+                        "{}: {}: {}",
+                        msg.generic_error,
+                        target.destination.display(),
+                        e
+                    ),
+                    ctx,
+                ),
+            }
+        }
+        log_output(&summary(msg, label, total.copied, total.skipped), ctx);
+    };
+    let full_run = |label: &str| {
+        run(label, delta, None);
+        flush_logger(ctx);
+    };
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).map_err(io::Error::other)?;
+    // Every target has the same sources.
+    let sources: BTreeSet<&PathBuf> = pairs.iter().map(|(src, _)| src).collect();
+    for source in &sources {
+        watcher
+            .watch(source, RecursiveMode::Recursive)
+            .map_err(io::Error::other)?;
+    }
+    let names: Vec<String> = sources.iter().map(|s| s.display().to_string()).collect();
+
+    full_run(&msg.backup_ended);
+    log_output(&msg.watch_started.replace("{}", &names.join(", ")), ctx);
+
+    let mut batch = Batch::new(options, Instant::now());
+    loop {
+        let mut rescan = false;
+        match rx.recv_timeout(batch.timeout(Instant::now())) {
+            Ok(Ok(event)) => {
+                rescan = event.need_rescan();
+                batch.add(touched_paths(&event, &pairs), Instant::now());
+            }
+            Ok(Err(e)) => {
+                log_output(&format!("{} {}", msg.watch_error, e), ctx);
+                rescan = true;
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(io::Error::other("filesystem watcher stopped"));
+            }
+        }
+
+        match batch.step(rescan, Instant::now()) {
+            Step::Wait => {}
+            Step::Rescan => full_run(&msg.watch_rescan),
+            Step::Copy { paths, deleted } => {
+                let label = msg.watch_batch.replace("{}", &paths.len().to_string());
+                run(&label, true, Some(&paths));
+                if deleted > 0 {
+                    log_output(&msg.watch_deleted.replace("{}", &deleted.to_string()), ctx);
+                }
+                flush_logger(ctx);
+            }
+        }
+    }
+}
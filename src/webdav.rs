@@ -0,0 +1,587 @@
+//! WebDAV destinations (`webdav://host[:port]/path`, `webdavs://` for HTTPS).
+//!
+//! Directories are listed with `PROPFIND` (depth 1, cached for the run) to
+//! find the size and modification time of the files already there, created
+//! with `MKCOL`, and files are written with a `PUT` to a temporary name moved
+//! over the final one (`MOVE`) once complete.
+//!
+//! Credentials (HTTP basic authentication) come from the `webdav` entries of
+//! the configuration file, the one with the longest `url` the destination
+//! starts with (and of the user named in the URL, if any), or else from
+//! `RBACKUP_WEBDAV_USER` and `RBACKUP_WEBDAV_PASSWORD`. A user name in the URL
+//! takes precedence over `RBACKUP_WEBDAV_USER`.
+
+use crate::config::Config;
+use crate::destination::{DestFile, DestMetadata, Destination, temp_path};
+use crate::s3::uri_encode;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{self, PipeWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime};
+use ureq::http;
+
+/// Environment variable holding the user name, without a `webdav` entry.
+pub const USER_ENV: &str = "RBACKUP_WEBDAV_USER";
+/// Environment variable holding the password, without a `webdav` entry.
+pub const PASSWORD_ENV: &str = "RBACKUP_WEBDAV_PASSWORD";
+
+const TIMEOUT: Duration = Duration::from_secs(300);
+
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?><propfind xmlns="DAV:"><prop><resourcetype/><getcontentlength/><getlastmodified/></prop></propfind>"#;
+
+/// Credentials of a WebDAV server, set in the configuration file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebDavLogin {
+    /// Destinations these credentials apply to: those starting with it,
+    /// e.g. `webdavs://dms.example.com/backup`.
+    pub url: String,
+    pub username: String,
+    /// Password; prefer `password_env` to keep it out of the file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// Environment variable holding the password.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_env: Option<String>,
+}
+
+/// The parts of a `webdav://` or `webdavs://` URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebDavUrl {
+    /// Whether the server is reached over HTTPS (`webdavs://`).
+    pub secure: bool,
+    pub user: Option<String>,
+    /// Host name and optional port.
+    pub host: String,
+    /// Directory on the server.
+    pub path: PathBuf,
+}
+
+impl WebDavUrl {
+    /// Parse what follows `webdav://` (or `webdavs://` when `secure`):
+    /// `[user@]host[:port][/path]`.
+    pub fn parse(rest: &str, secure: bool) -> io::Result<WebDavUrl> {
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        let (user, host) = match authority.rsplit_once('@') {
+            Some((u, h)) => (Some(u.to_string()), h),
+            None => (None, authority),
+        };
+        if host.is_empty() || host.starts_with(':') {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{}{}: missing host", Self::scheme(secure), rest),
+            ));
+        }
+        Ok(WebDavUrl {
+            secure,
+            user,
+            host: host.to_string(),
+            path: PathBuf::from(path),
+        })
+    }
+
+    fn scheme(secure: bool) -> &'static str {
+        match secure {
+            true => "webdavs://",
+            false => "webdav://",
+        }
+    }
+
+    /// `http[s]://host[:port]` of the server.
+    pub fn base(&self) -> String {
+        match self.secure {
+            true => format!("https://{}", self.host),
+            false => format!("http://{}", self.host),
+        }
+    }
+
+    /// User name and password to log in with, if any (see the module
+    /// documentation).
+    pub fn credentials(&self, logins: &[WebDavLogin]) -> io::Result<Option<(String, String)>> {
+        let url = WebDavUrl {
+            user: None,
+            ..self.clone()
+        }
+        .to_string();
+        let login = logins
+            .iter()
+            .filter(|l| {
+                let prefix = l.url.trim_end_matches('/');
+                url == prefix || url.starts_with(&format!("{}/", prefix))
+            })
+            .filter(|l| self.user.as_ref().is_none_or(|u| *u == l.username))
+            .max_by_key(|l| l.url.len());
+        let (user, password) = match login {
+            Some(login) => {
+                let password = match &login.password_env {
+                    Some(var) => Some(std::env::var(var).map_err(|e| {
+                        io::Error::new(io::ErrorKind::InvalidInput, format!("{}: {}", var, e))
+                    })?),
+                    None => login.password.clone(),
+                };
+                (Some(login.username.clone()), password)
+            }
+            None => (
+                std::env::var(USER_ENV).ok(),
+                std::env::var(PASSWORD_ENV).ok(),
+            ),
+        };
+        Ok(self
+            .user
+            .clone()
+            .or(user)
+            .map(|user| (user, password.unwrap_or_default())))
+    }
+}
+
+impl fmt::Display for WebDavUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Self::scheme(self.secure))?;
+        if let Some(user) = &self.user {
+            write!(f, "{}@", user)?;
+        }
+        write!(f, "{}{}", self.host, self.path.display())
+    }
+}
+
+/// Contents of the elements named `name` in `xml`, whatever their namespace
+/// prefix; `name` elements must not nest.
+fn elements<'a>(xml: &'a str, name: &str) -> Vec<&'a str> {
+    let mut found = Vec::new();
+    let mut open = None;
+    let mut pos = 0;
+    while let Some(i) = xml[pos..].find('<') {
+        let start = pos + i + 1;
+        let Some(len) = xml[start..].find('>') else {
+            break;
+        };
+        let tag = &xml[start..start + len];
+        pos = start + len + 1;
+        let (closing, tag) = match tag.strip_prefix('/') {
+            Some(tag) => (true, tag),
+            None => (false, tag),
+        };
+        let tag_name = tag.split([' ', '\t', '\r', '\n', '/']).next().unwrap_or("");
+        if tag_name.rsplit(':').next() != Some(name) {
+            continue;
+        }
+        match (closing, tag.ends_with('/')) {
+            (true, _) => {
+                if let Some(open) = open.take() {
+                    found.push(&xml[open..start - 1]);
+                }
+            }
+            (false, true) => found.push(""),
+            (false, false) => open = Some(pos),
+        }
+    }
+    found
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = text
+            .get(i + 1..i + 3)
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(b)) => {
+                out.push(b);
+                i += 3;
+            }
+            (b, _) => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Entries of a directory: name and metadata.
+type Listing = HashMap<String, DestMetadata>;
+
+/// A directory of a WebDAV server.
+pub struct WebDavDestination {
+    agent: ureq::Agent,
+    /// `http[s]://host[:port]` requests go to.
+    base: String,
+    /// `Authorization` header, if logged in.
+    authorization: Option<String>,
+    /// Listings of the directories seen so far; `None` for missing ones.
+    listings: Mutex<HashMap<PathBuf, Option<Listing>>>,
+    /// Directories known to exist.
+    dirs: Mutex<HashSet<PathBuf>>,
+}
+
+impl fmt::Debug for WebDavDestination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebDavDestination")
+            .field("base", &self.base)
+            .finish_non_exhaustive()
+    }
+}
+
+impl WebDavDestination {
+    /// The server of `url`, logging in with the credentials found in
+    /// `config` or the environment. The destination directory is looked up
+    /// right away, so that an unreachable server or rejected credentials
+    /// fail the destination rather than every file.
+    pub fn connect(url: &WebDavUrl, config: &Config) -> io::Result<WebDavDestination> {
+        let dest = WebDavDestination::new(url.base(), url.credentials(&config.webdav)?);
+        dest.stat(&url.path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", url, e)))?;
+        Ok(dest)
+    }
+
+    /// A server at `base` (`http[s]://host[:port]`), with an optional user
+    /// name and password.
+    pub fn new(base: String, credentials: Option<(String, String)>) -> WebDavDestination {
+        let agent = ureq::Agent::config_builder()
+            .timeout_connect(Some(TIMEOUT))
+            .timeout_recv_response(Some(TIMEOUT))
+            .http_status_as_error(false)
+            .allow_non_standard_methods(true)
+            .build()
+            .into();
+        WebDavDestination {
+            agent,
+            base: base.trim_end_matches('/').to_string(),
+            authorization: credentials.map(|(user, password)| {
+                format!("Basic {}", BASE64.encode(format!("{}:{}", user, password)))
+            }),
+            listings: Mutex::default(),
+            dirs: Mutex::default(),
+        }
+    }
+
+    fn url(&self, path: &Path, dir: bool) -> String {
+        let text = path.to_string_lossy().replace('\\', "/");
+        let mut url = format!("{}{}", self.base, uri_encode(&text, true));
+        if dir && !url.ends_with('/') {
+            url.push('/');
+        }
+        url
+    }
+
+    fn builder(&self, method: &str, url: String) -> http::request::Builder {
+        let request = http::Request::builder().method(method).uri(url);
+        match &self.authorization {
+            Some(auth) => request.header("authorization", auth),
+            None => request,
+        }
+    }
+
+    /// Send `method` for `path`, whatever the status of the reply.
+    fn send(
+        &self,
+        method: &str,
+        path: &Path,
+        dir: bool,
+        headers: &[(&str, &str)],
+        body: &str,
+    ) -> io::Result<http::Response<ureq::Body>> {
+        let mut request = self.builder(method, self.url(path, dir));
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        let request = request.body(body).map_err(io::Error::other)?;
+        self.agent.run(request).map_err(io::Error::other)
+    }
+
+    /// Send `method` for `path` and check the status of the reply.
+    fn request(
+        &self,
+        method: &str,
+        path: &Path,
+        dir: bool,
+        headers: &[(&str, &str)],
+        body: &str,
+    ) -> io::Result<http::Response<ureq::Body>> {
+        check_status(self.send(method, path, dir, headers, body)?, method, path)
+    }
+
+    /// Metadata of the entries of `dir`, from the cache or the server.
+    fn list(&self, dir: &Path) -> io::Result<Option<Listing>> {
+        if let Some(listing) = self.lock_listings()?.get(dir) {
+            return Ok(listing.clone());
+        }
+        let listing = match self.request(
+            "PROPFIND",
+            dir,
+            true,
+            &[("depth", "1"), ("content-type", "application/xml")],
+            PROPFIND_BODY,
+        ) {
+            Ok(mut response) => {
+                let xml = response
+                    .body_mut()
+                    .read_to_string()
+                    .map_err(io::Error::other)?;
+                Some(parse_listing(&xml, dir))
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        self.lock_listings()?
+            .insert(dir.to_path_buf(), listing.clone());
+        Ok(listing)
+    }
+
+    fn lock_listings(
+        &self,
+    ) -> io::Result<std::sync::MutexGuard<'_, HashMap<PathBuf, Option<Listing>>>> {
+        self.listings
+            .lock()
+            .map_err(|_| io::Error::other("webdav: listing cache poisoned"))
+    }
+
+    /// Record `path` with `meta` in the cached listing of its directory.
+    fn remember(&self, path: &Path, meta: DestMetadata) {
+        let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+            return;
+        };
+        if let Ok(mut listings) = self.listings.lock() {
+            if let Some(Some(listing)) = listings.get_mut(parent) {
+                listing.insert(name.to_string_lossy().into_owned(), meta);
+            }
+            if meta.is_dir {
+                listings.insert(path.to_path_buf(), Some(Listing::new()));
+            }
+        }
+    }
+}
+
+/// Turn a reply other than 2xx into an error.
+fn check_status(
+    mut response: http::Response<ureq::Body>,
+    method: &str,
+    path: &Path,
+) -> io::Result<http::Response<ureq::Body>> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let _ = response.body_mut().read_to_string();
+    let kind = match status.as_u16() {
+        404 | 409 => io::ErrorKind::NotFound,
+        401 | 403 => io::ErrorKind::PermissionDenied,
+        _ => io::ErrorKind::Other,
+    };
+    Err(io::Error::new(
+        kind,
+        format!("webdav: {} {}: {}", method, path.display(), status),
+    ))
+}
+
+/// Entries of the `PROPFIND` reply `xml` about `dir`, the directory itself
+/// excepted.
+fn parse_listing(xml: &str, dir: &Path) -> Listing {
+    let own = dir.to_string_lossy().trim_matches('/').to_string();
+    let mut listing = Listing::new();
+    for response in elements(xml, "response") {
+        let Some(href) = elements(response, "href")
+            .first()
+            .map(|h| percent_decode(h.trim()))
+        else {
+            continue;
+        };
+        // Servers may answer with full URLs.
+        let path = match href.split_once("://") {
+            Some((_, rest)) => rest.find('/').map_or("", |i| &rest[i..]).to_string(),
+            None => href,
+        };
+        let path = path.trim_matches('/');
+        if path == own {
+            continue;
+        }
+        let name = path.rsplit('/').next().unwrap_or(path);
+        let value = |prop: &str| elements(response, prop).first().map(|v| v.trim());
+        let is_dir = elements(response, "resourcetype")
+            .first()
+            .is_some_and(|t| !elements(t, "collection").is_empty());
+        listing.insert(
+            name.to_string(),
+            DestMetadata {
+                len: value("getcontentlength")
+                    .and_then(|l| l.parse().ok())
+                    .unwrap_or(0),
+                is_dir,
+                modified: value("getlastmodified")
+                    .and_then(|t| DateTime::parse_from_rfc2822(t).ok())
+                    .map(SystemTime::from),
+            },
+        );
+    }
+    listing
+}
+
+impl Destination for WebDavDestination {
+    fn stat(&self, path: &Path) -> io::Result<Option<DestMetadata>> {
+        let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+            // The root of the server.
+            return Ok(Some(DestMetadata {
+                len: 0,
+                is_dir: true,
+                modified: None,
+            }));
+        };
+        Ok(self
+            .list(parent)?
+            .and_then(|listing| listing.get(&*name.to_string_lossy()).copied()))
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        let known = |p: &Path| self.dirs.lock().is_ok_and(|d| d.contains(p));
+        let mut missing = Vec::new();
+        for dir in path.ancestors() {
+            if known(dir) {
+                break;
+            }
+            match self.stat(dir)? {
+                Some(meta) if meta.is_dir => break,
+                Some(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        format!("{}: not a directory", dir.display()),
+                    ));
+                }
+                None => missing.push(dir),
+            }
+        }
+        for dir in missing.into_iter().rev() {
+            let response = self.send("MKCOL", dir, true, &[], "")?;
+            // 405: another worker created it in the meantime.
+            if response.status() != http::StatusCode::METHOD_NOT_ALLOWED {
+                check_status(response, "MKCOL", dir)?;
+            }
+            self.remember(
+                dir,
+                DestMetadata {
+                    len: 0,
+                    is_dir: true,
+                    modified: Some(SystemTime::now()),
+                },
+            );
+        }
+        if let Ok(mut dirs) = self.dirs.lock() {
+            dirs.insert(path.to_path_buf());
+        }
+        Ok(())
+    }
+
+    fn create(&self, path: &Path, _source: &Path) -> io::Result<Box<dyn DestFile + '_>> {
+        let tmp = temp_path(path);
+        let (reader, writer) = io::pipe()?;
+        let request = self
+            .builder("PUT", self.url(&tmp, false))
+            .body(())
+            .map_err(io::Error::other)?;
+        let (agent, tmp_path) = (self.agent.clone(), tmp.clone());
+        // The body is streamed from the pipe as the file is written.
+        let upload = std::thread::spawn(move || {
+            let (parts, ()) = request.into_parts();
+            let request =
+                http::Request::from_parts(parts, ureq::SendBody::from_owned_reader(reader));
+            let response = agent.run(request).map_err(io::Error::other)?;
+            check_status(response, "PUT", &tmp_path).map(|_| ())
+        });
+        Ok(Box::new(WebDavFile {
+            dest: self,
+            writer: Some(writer),
+            upload: Some(upload),
+            tmp,
+            path: path.to_path_buf(),
+            len: 0,
+            committed: false,
+        }))
+    }
+}
+
+/// A file being uploaded to a temporary name.
+struct WebDavFile<'a> {
+    dest: &'a WebDavDestination,
+    /// Body of the `PUT` in progress; `None` once finished.
+    writer: Option<PipeWriter>,
+    upload: Option<JoinHandle<io::Result<()>>>,
+    tmp: PathBuf,
+    path: PathBuf,
+    len: u64,
+    committed: bool,
+}
+
+impl WebDavFile<'_> {
+    /// End the body and wait for the reply to the `PUT`.
+    fn finish(&mut self) -> io::Result<()> {
+        self.writer = None;
+        match self.upload.take().map(|upload| upload.join()) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(io::Error::other("webdav: upload thread panicked")),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Write for WebDavFile<'_> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let Some(writer) = self.writer.as_mut() else {
+            return Err(io::Error::other("webdav: upload finished"));
+        };
+        match writer.write(data) {
+            Ok(n) => {
+                self.len += n as u64;
+                Ok(n)
+            }
+            // The server stopped reading: its reply says why.
+            Err(e) => Err(self.finish().err().unwrap_or(e)),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl DestFile for WebDavFile<'_> {
+    fn commit(mut self: Box<Self>) -> io::Result<()> {
+        self.finish()?;
+        let destination = self.dest.url(&self.path, false);
+        self.dest.request(
+            "MOVE",
+            &self.tmp,
+            false,
+            &[("destination", &destination), ("overwrite", "T")],
+            "",
+        )?;
+        self.committed = true;
+        self.dest.remember(
+            &self.path,
+            DestMetadata {
+                len: self.len,
+                is_dir: false,
+                modified: Some(SystemTime::now()),
+            },
+        );
+        Ok(())
+    }
+}
+
+impl Drop for WebDavFile<'_> {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = self.finish();
+            let _ = self.dest.request("DELETE", &self.tmp, false, &[], "");
+        }
+    }
+}
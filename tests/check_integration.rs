@@ -0,0 +1,112 @@
+use tempfile::tempdir;
+
+use rbackup::check::{ReadData, check_tree};
+use rbackup::compress::Compression;
+use rbackup::utils::load_translations;
+use rbackup::{LogContext, copy_incremental};
+
+fn quiet_ctx() -> LogContext {
+    LogContext {
+        quiet: true,
+        on_log: false,
+        manifest: true,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_check_tree_against_manifest() {
+    let src_dir = tempdir().unwrap();
+    let dst_dir = tempdir().unwrap();
+
+    std::fs::create_dir(src_dir.path().join("sub")).unwrap();
+    std::fs::write(src_dir.path().join("a.txt"), b"alpha").unwrap();
+    std::fs::write(src_dir.path().join("b.txt"), b"bravo").unwrap();
+    std::fs::write(src_dir.path().join("sub").join("c.txt"), b"charlie").unwrap();
+    std::fs::write(src_dir.path().join("d.txt"), b"delta").unwrap();
+
+    let msg = load_translations().unwrap().remove("en").unwrap();
+    let ctx = quiet_ctx();
+    copy_incremental(src_dir.path(), dst_dir.path(), &msg, &ctx, false).unwrap();
+
+    let report = check_tree(dst_dir.path(), None, ReadData::All, &ctx).unwrap();
+    assert!(report.is_ok());
+    assert_eq!((report.files, report.read), (4, 4));
+
+    std::fs::remove_file(dst_dir.path().join("a.txt")).unwrap();
+    std::fs::write(dst_dir.path().join("b.txt"), b"bravo!").unwrap();
+    // Same size, different contents: only visible when reading data.
+    std::fs::write(dst_dir.path().join("sub").join("c.txt"), b"CHARLIE").unwrap();
+    std::fs::write(dst_dir.path().join("stray.txt"), b"?").unwrap();
+
+    let report = check_tree(dst_dir.path(), None, ReadData::Off, &ctx).unwrap();
+    assert_eq!(report.missing, vec!["a.txt"]);
+    assert_eq!(report.size_mismatch, vec!["b.txt"]);
+    assert_eq!(report.extra, vec!["stray.txt"]);
+    assert!(report.hash_mismatch.is_empty());
+    assert_eq!(report.read, 0);
+
+    let report = check_tree(dst_dir.path(), None, ReadData::All, &ctx).unwrap();
+    assert_eq!(report.hash_mismatch, vec!["sub/c.txt"]);
+
+    // A subset reads a bounded number of files.
+    let report = check_tree(dst_dir.path(), None, ReadData::Subset(0.5), &ctx).unwrap();
+    assert_eq!(report.read, 2);
+}
+
+#[test]
+fn test_check_compressed_tree_against_source() {
+    let src_dir = tempdir().unwrap();
+    let dst_dir = tempdir().unwrap();
+
+    let log = "line of text\n".repeat(1_000);
+    std::fs::write(src_dir.path().join("app.log"), &log).unwrap();
+
+    let msg = load_translations().unwrap().remove("en").unwrap();
+    let ctx = LogContext {
+        manifest: false,
+        compression: Some(Compression::parse("zstd").unwrap()),
+        ..quiet_ctx()
+    };
+    copy_incremental(src_dir.path(), dst_dir.path(), &msg, &ctx, false).unwrap();
+
+    // Without a manifest there is nothing to compare against.
+    assert!(check_tree(dst_dir.path(), None, ReadData::Off, &ctx).is_err());
+
+    let report = check_tree(dst_dir.path(), Some(src_dir.path()), ReadData::All, &ctx).unwrap();
+    assert!(report.is_ok());
+
+    std::fs::write(src_dir.path().join("app.log"), "changed\n").unwrap();
+    let report = check_tree(dst_dir.path(), Some(src_dir.path()), ReadData::All, &ctx).unwrap();
+    assert_eq!(report.size_mismatch, vec!["app.log"]);
+}
+
+#[test]
+fn test_check_against_source_skips_excluded_files() {
+    let src_dir = tempdir().unwrap();
+    let dst_dir = tempdir().unwrap();
+    std::fs::create_dir(src_dir.path().join("logs")).unwrap();
+    std::fs::write(src_dir.path().join("a.txt"), b"alpha").unwrap();
+    std::fs::write(src_dir.path().join("b.log"), b"bravo").unwrap();
+    std::fs::write(src_dir.path().join("logs").join("C.LOG"), b"charlie").unwrap();
+
+    let msg = load_translations().unwrap().remove("en").unwrap();
+    let ctx = LogContext {
+        exclude_matcher: Some(rbackup::build_exclude_matcher(&["*.log".into()], true).unwrap()),
+        ..quiet_ctx()
+    };
+    copy_incremental(src_dir.path(), dst_dir.path(), &msg, &ctx, false).unwrap();
+
+    // The files the copy excluded are not missing.
+    let report = check_tree(dst_dir.path(), Some(src_dir.path()), ReadData::All, &ctx).unwrap();
+    assert!(report.is_ok(), "{:?}", report.missing);
+    assert_eq!(report.files, 1);
+
+    let report = check_tree(
+        dst_dir.path(),
+        Some(src_dir.path()),
+        ReadData::Off,
+        &quiet_ctx(),
+    );
+    assert_eq!(report.unwrap().missing, ["b.log", "logs/C.LOG"]);
+}
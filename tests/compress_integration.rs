@@ -0,0 +1,93 @@
+use tempfile::tempdir;
+
+use rbackup::compress::Compression;
+use rbackup::crypto::{Encryption, KeySource};
+use rbackup::restore::restore_tree;
+use rbackup::utils::load_translations;
+use rbackup::{LogContext, copy_incremental};
+
+fn compressed_ctx() -> LogContext {
+    LogContext {
+        quiet: true,
+        on_log: false,
+        compression: Some(Compression::parse("zstd:5").unwrap()),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_compressed_tree_backup_and_restore() {
+    let src_dir = tempdir().unwrap();
+    let dst_dir = tempdir().unwrap();
+    let out_dir = tempdir().unwrap();
+
+    let log = "2026-10-18 INFO request served\n".repeat(2_000);
+    std::fs::write(src_dir.path().join("app.log"), &log).unwrap();
+    std::fs::write(src_dir.path().join("photo.jpg"), b"not really a jpeg").unwrap();
+    // A file that is already zstd data must come back untouched.
+    let archive = zstd::encode_all(&b"user archive"[..], 1).unwrap();
+    std::fs::write(src_dir.path().join("old.zst"), &archive).unwrap();
+
+    let msg = load_translations().unwrap().remove("en").unwrap();
+    let ctx = compressed_ctx();
+
+    let (copied, _) = copy_incremental(src_dir.path(), dst_dir.path(), &msg, &ctx, false).unwrap();
+    assert_eq!(copied, 3);
+
+    let stored_log = dst_dir.path().join("app.log.zst");
+    assert!(stored_log.exists());
+    assert!(std::fs::metadata(&stored_log).unwrap().len() < log.len() as u64);
+    assert!(dst_dir.path().join("photo.jpg").exists());
+    assert!(!dst_dir.path().join("photo.jpg.zst").exists());
+
+    // Incremental detection maps the source names to the compressed ones.
+    let (copied, _) = copy_incremental(src_dir.path(), dst_dir.path(), &msg, &ctx, true).unwrap();
+    assert_eq!(copied, 0);
+
+    let (restored, failed) = restore_tree(dst_dir.path(), out_dir.path(), &msg, &ctx).unwrap();
+    assert_eq!((restored, failed), (3, 0));
+    assert_eq!(
+        std::fs::read_to_string(out_dir.path().join("app.log")).unwrap(),
+        log
+    );
+    assert_eq!(
+        std::fs::read(out_dir.path().join("old.zst")).unwrap(),
+        archive
+    );
+}
+
+#[test]
+fn test_compressed_and_encrypted_roundtrip() {
+    let src_dir = tempdir().unwrap();
+    let dst_dir = tempdir().unwrap();
+    let out_dir = tempdir().unwrap();
+
+    let csv = "id,name,amount\n1,alice,10\n".repeat(5_000);
+    std::fs::write(src_dir.path().join("export.csv"), &csv).unwrap();
+
+    let msg = load_translations().unwrap().remove("en").unwrap();
+    let enc = Encryption::init(
+        dst_dir.path(),
+        &KeySource::Passphrase("pass".into()),
+        false,
+        true,
+    )
+    .unwrap();
+    let ctx = LogContext {
+        manifest: true,
+        encryption: Some(enc),
+        ..compressed_ctx()
+    };
+
+    copy_incremental(src_dir.path(), dst_dir.path(), &msg, &ctx, false).unwrap();
+    let stored = dst_dir.path().join("export.csv.zst.enc");
+    assert!(stored.exists());
+    assert!(std::fs::metadata(&stored).unwrap().len() < csv.len() as u64);
+
+    let (restored, failed) = restore_tree(dst_dir.path(), out_dir.path(), &msg, &ctx).unwrap();
+    assert_eq!((restored, failed), (1, 0));
+    assert_eq!(
+        std::fs::read_to_string(out_dir.path().join("export.csv")).unwrap(),
+        csv
+    );
+}
@@ -0,0 +1,165 @@
+use std::path::PathBuf;
+use tempfile::tempdir;
+
+use rbackup::LogContext;
+use rbackup::copy::{SourceNaming, source_destinations};
+use rbackup::utils::{CopyTarget, copy_sources, copy_targets, load_translations};
+
+#[test]
+fn test_source_destinations_naming() {
+    let root = tempdir().unwrap();
+    let docs = root.path().join("Documents");
+    let etc = root.path().join("etc");
+    std::fs::create_dir(&docs).unwrap();
+    std::fs::create_dir(&etc).unwrap();
+    let dest = PathBuf::from("/mnt/backup");
+
+    // A single source is copied into the destination itself.
+    let pairs = source_destinations(std::slice::from_ref(&docs), &dest, None).unwrap();
+    assert_eq!(pairs, vec![(docs.clone(), dest.clone())]);
+
+    let sources = [docs.clone(), etc.clone()];
+    let pairs = source_destinations(&sources, &dest, None).unwrap();
+    assert_eq!(pairs[0].1, dest.join("Documents"));
+    assert_eq!(pairs[1].1, dest.join("etc"));
+
+    let pairs = source_destinations(&sources, &dest, Some(SourceNaming::FullPath)).unwrap();
+    let full = etc.canonicalize().unwrap();
+    assert_eq!(pairs[1].1, dest.join(full.strip_prefix("/").unwrap()));
+
+    // Two sources with the same basename cannot share a directory.
+    let other = root.path().join("other").join("etc");
+    std::fs::create_dir_all(&other).unwrap();
+    let err = source_destinations(&[etc, other], &dest, None).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_copy_sources_combined_counts() {
+    let root = tempdir().unwrap();
+    let dst_dir = tempdir().unwrap();
+    let a = root.path().join("a");
+    let b = root.path().join("b");
+    std::fs::create_dir(&a).unwrap();
+    std::fs::create_dir_all(b.join("sub")).unwrap();
+    std::fs::write(a.join("one.txt"), b"one").unwrap();
+    std::fs::write(b.join("two.txt"), b"two").unwrap();
+    std::fs::write(b.join("sub").join("three.txt"), b"three").unwrap();
+
+    let msg = load_translations().unwrap().remove("en").unwrap();
+    let ctx = LogContext {
+        quiet: true,
+        on_log: false,
+        manifest: true,
+        ..Default::default()
+    };
+    let pairs = source_destinations(&[a, b], dst_dir.path(), None).unwrap();
+    let counts = copy_sources(&pairs, &msg, &ctx, true, None).unwrap();
+    assert_eq!((counts.copied, counts.skipped, counts.failed), (3, 0, 0));
+
+    let dst = dst_dir.path();
+    assert_eq!(
+        std::fs::read(dst.join("a").join("one.txt")).unwrap(),
+        b"one"
+    );
+    assert_eq!(
+        std::fs::read(dst.join("b").join("sub").join("three.txt")).unwrap(),
+        b"three"
+    );
+    // Each source keeps its own manifest.
+    assert!(dst.join("a").join(".rbackup-manifest.json").exists());
+    assert!(dst.join("b").join(".rbackup-manifest.json").exists());
+
+    // A second run finds nothing to copy in any source.
+    let counts = copy_sources(&pairs, &msg, &ctx, true, None).unwrap();
+    assert_eq!(counts.copied, 0);
+}
+
+#[test]
+fn test_copy_targets_reports_each_destination() {
+    let src_dir = tempdir().unwrap();
+    let usb = tempdir().unwrap();
+    let nas = tempdir().unwrap();
+    std::fs::create_dir(src_dir.path().join("sub")).unwrap();
+    std::fs::write(src_dir.path().join("one.txt"), b"one").unwrap();
+    std::fs::write(src_dir.path().join("sub").join("two.txt"), b"two").unwrap();
+    // A destination that cannot be created: its parent is a file.
+    std::fs::write(nas.path().join("blocker"), b"").unwrap();
+
+    let msg = load_translations().unwrap().remove("en").unwrap();
+    let ctx = LogContext {
+        quiet: true,
+        on_log: false,
+        manifest: true,
+        ..Default::default()
+    };
+    let target = |dest: PathBuf| CopyTarget {
+        destination: dest.clone(),
+        pairs: vec![(src_dir.path().to_path_buf(), dest)],
+        options: ctx.clone(),
+    };
+    let targets = [
+        target(usb.path().to_path_buf()),
+        target(nas.path().join("blocker").join("backup")),
+        target(nas.path().join("copy")),
+    ];
+    let results = copy_targets(&targets, &msg, &ctx, true, None);
+
+    // The failing destination does not hide the others.
+    assert_eq!(results[0].as_ref().unwrap().copied, 2);
+    assert!(results[1].is_err());
+    assert_eq!(results[2].as_ref().unwrap().copied, 2);
+    for dest in [usb.path().to_path_buf(), nas.path().join("copy")] {
+        assert_eq!(
+            std::fs::read(dest.join("sub").join("two.txt")).unwrap(),
+            b"two"
+        );
+        assert!(dest.join(".rbackup-manifest.json").exists());
+    }
+
+    // Unchanged files are skipped in every destination.
+    let results = copy_targets(&targets[..1], &msg, &ctx, true, None);
+    assert_eq!(results[0].as_ref().unwrap().copied, 0);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_failed_read_leaves_no_file_in_destinations() {
+    let src_dir = tempdir().unwrap();
+    let usb = tempdir().unwrap();
+    let nas = tempdir().unwrap();
+    std::fs::write(src_dir.path().join("one.txt"), b"one").unwrap();
+    // A file that can be opened but not read: offset 0 of the memory of a
+    // process is never mapped.
+    std::os::unix::fs::symlink("/proc/self/mem", src_dir.path().join("mem")).unwrap();
+
+    let msg = load_translations().unwrap().remove("en").unwrap();
+    let ctx = LogContext {
+        quiet: true,
+        on_log: false,
+        ..Default::default()
+    };
+    let target = |dest: PathBuf| CopyTarget {
+        destination: dest.clone(),
+        pairs: vec![(src_dir.path().to_path_buf(), dest)],
+        options: ctx.clone(),
+    };
+    let targets = [
+        target(usb.path().to_path_buf()),
+        target(nas.path().to_path_buf()),
+    ];
+    let results = copy_targets(&targets, &msg, &ctx, true, None);
+
+    for (result, dest) in results.iter().zip([usb.path(), nas.path()]) {
+        let counts = result.as_ref().unwrap();
+        assert_eq!(counts.copied, 1);
+        assert_eq!(counts.errors.len(), 1);
+        // Neither the file nor its temporary copy is left behind.
+        let mut names: Vec<_> = std::fs::read_dir(dest)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["one.txt"]);
+    }
+}
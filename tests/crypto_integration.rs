@@ -0,0 +1,154 @@
+use std::collections::BTreeMap;
+
+use clap::Parser;
+use tempfile::tempdir;
+
+use rbackup::cli::Cli;
+use rbackup::commands::handle_copy;
+use rbackup::config::Config;
+use rbackup::crypto::{Encryption, KeySource};
+use rbackup::hooks::Hooks;
+use rbackup::restore::restore_tree;
+use rbackup::utils::load_translations;
+use rbackup::{LogContext, copy_incremental};
+
+fn encrypted_ctx(enc: Encryption) -> LogContext {
+    LogContext {
+        quiet: true,
+        on_log: false,
+        manifest: true,
+        encryption: Some(enc),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_encrypted_backup_and_restore_roundtrip() {
+    let src_dir = tempdir().unwrap();
+    let dst_dir = tempdir().unwrap();
+    let out_dir = tempdir().unwrap();
+    let key_dir = tempdir().unwrap();
+
+    let key_file = key_dir.path().join("backup.key");
+    std::fs::write(&key_file, b"correct horse battery staple").unwrap();
+    std::fs::create_dir(src_dir.path().join("docs")).unwrap();
+    std::fs::write(
+        src_dir.path().join("docs").join("secret.txt"),
+        b"top secret",
+    )
+    .unwrap();
+    // Larger than one encryption chunk.
+    let big: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+    std::fs::write(src_dir.path().join("big.bin"), &big).unwrap();
+
+    let msg = load_translations().unwrap().remove("en").unwrap();
+    let source = KeySource::KeyFile(key_file);
+    let enc = Encryption::init(dst_dir.path(), &source, true, true).unwrap();
+    let ctx = encrypted_ctx(enc);
+
+    let (copied, _) = copy_incremental(src_dir.path(), dst_dir.path(), &msg, &ctx, false).unwrap();
+    assert_eq!(copied, 2);
+
+    // Names are hidden and contents are not stored in plaintext.
+    assert!(!dst_dir.path().join("docs").exists());
+    for entry in walkdir::WalkDir::new(dst_dir.path()) {
+        let entry = entry.unwrap();
+        if entry.file_type().is_file() {
+            let data = std::fs::read(entry.path()).unwrap();
+            assert!(!data.windows(10).any(|w| w == b"top secret"));
+        }
+    }
+
+    // Unchanged files are detected through the encrypted manifest.
+    let enc = Encryption::load(dst_dir.path(), &source).unwrap();
+    let ctx = encrypted_ctx(enc);
+    let (copied, skipped) =
+        copy_incremental(src_dir.path(), dst_dir.path(), &msg, &ctx, false).unwrap();
+    assert_eq!((copied, skipped), (0, 2));
+
+    let (restored, failed) = restore_tree(dst_dir.path(), out_dir.path(), &msg, &ctx).unwrap();
+    assert_eq!((restored, failed), (2, 0));
+    assert_eq!(
+        std::fs::read(out_dir.path().join("docs").join("secret.txt")).unwrap(),
+        b"top secret"
+    );
+    assert_eq!(std::fs::read(out_dir.path().join("big.bin")).unwrap(), big);
+}
+
+#[test]
+fn test_wrong_passphrase_is_rejected() {
+    let dst_dir = tempdir().unwrap();
+    let right = KeySource::Passphrase("right".into());
+    let wrong = KeySource::Passphrase("wrong".into());
+
+    Encryption::init(dst_dir.path(), &right, false, true).unwrap();
+    assert!(Encryption::load(dst_dir.path(), &right).is_ok());
+    let err = Encryption::load(dst_dir.path(), &wrong).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+}
+
+#[test]
+fn test_tampered_file_fails_to_decrypt() {
+    let dst_dir = tempdir().unwrap();
+    let work = tempdir().unwrap();
+    let enc = Encryption::init(
+        dst_dir.path(),
+        &KeySource::Passphrase("pass".into()),
+        false,
+        false,
+    )
+    .unwrap();
+
+    let plain = work.path().join("plain.txt");
+    let sealed = work.path().join("plain.txt.enc");
+    std::fs::write(&plain, b"some data to protect").unwrap();
+    enc.encrypt_file(&plain, &sealed, "plain.txt").unwrap();
+
+    let mut data = std::fs::read(&sealed).unwrap();
+    let last = data.len() - 1;
+    data[last] ^= 0x01;
+    std::fs::write(&sealed, data).unwrap();
+
+    assert!(enc.decrypt_file(&sealed, &work.path().join("out")).is_err());
+    assert!(!work.path().join("out").join("plain.txt").exists());
+}
+
+#[test]
+fn test_plain_copy_into_encrypted_destination_fails() {
+    let src_dir = tempdir().unwrap();
+    let dst_dir = tempdir().unwrap();
+    let key_dir = tempdir().unwrap();
+    let key_file = key_dir.path().join("backup.key");
+    std::fs::write(&key_file, b"correct horse battery staple").unwrap();
+    std::fs::write(src_dir.path().join("a.txt"), b"secret a").unwrap();
+    std::fs::write(src_dir.path().join("b.txt"), b"secret b").unwrap();
+
+    let msg = load_translations().unwrap().remove("en").unwrap();
+    let config = Config {
+        language: "en".into(),
+        timestamp_format: "%Y-%m-%d %H:%M:%S".into(),
+        jobs: 2,
+        profiles: BTreeMap::new(),
+        hooks: Hooks::default(),
+        notifications: Vec::new(),
+        webdav: Vec::new(),
+    };
+    let copy = |extra: &[&str]| {
+        let src = src_dir.path().to_str().unwrap();
+        let dst = dst_dir.path().to_str().unwrap();
+        let args = ["rbackup", "copy", src, dst, "--quiet", "--progress", "none"];
+        let cli = Cli::try_parse_from(args.iter().chain(extra)).unwrap();
+        handle_copy(cli.command.as_ref().unwrap(), &msg, &config)
+    };
+
+    let key_arg = key_file.to_str().unwrap();
+    copy(&["--encrypt", "--key-file", key_arg]).unwrap();
+    assert!(!dst_dir.path().join("a.txt").exists());
+
+    // Without the key, nothing is written in plaintext next to the
+    // encrypted files.
+    assert!(copy(&[]).is_err());
+    assert!(copy(&["--dry-run"]).is_err());
+    assert!(!dst_dir.path().join("a.txt").exists());
+    assert!(!dst_dir.path().join("b.txt").exists());
+}
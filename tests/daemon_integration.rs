@@ -0,0 +1,21 @@
+use tempfile::tempdir;
+
+use rbackup::daemon::RotatingLog;
+
+#[test]
+fn test_rotating_log() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("daemon.log");
+    let log = RotatingLog::new(path.clone(), 20, 2);
+
+    for line in ["first line", "second line", "third line", "fourth line"] {
+        log.write_line(line).unwrap();
+    }
+
+    let read = |name: &str| std::fs::read_to_string(dir.path().join(name)).unwrap();
+    assert_eq!(read("daemon.log"), "fourth line\n");
+    assert_eq!(read("daemon.log.1"), "third line\n");
+    assert_eq!(read("daemon.log.2"), "second line\n");
+    // Only `keep` rotated files are kept.
+    assert!(!dir.path().join("daemon.log.3").exists());
+}
@@ -0,0 +1,108 @@
+use filetime::{FileTime, set_file_mtime};
+use tempfile::tempdir;
+
+use rbackup::diff::{diff_tree, format_json};
+use rbackup::utils::load_translations;
+use rbackup::{LogContext, build_exclude_matcher, copy_incremental};
+
+#[test]
+fn test_diff_classifies_paths() {
+    let src_dir = tempdir().unwrap();
+    let dst_dir = tempdir().unwrap();
+
+    std::fs::write(src_dir.path().join("same.txt"), b"same").unwrap();
+    std::fs::write(src_dir.path().join("changed.txt"), b"old").unwrap();
+    std::fs::write(src_dir.path().join("gone.txt"), b"bye").unwrap();
+
+    let msg = load_translations().unwrap().remove("en").unwrap();
+    let ctx = LogContext {
+        quiet: true,
+        on_log: false,
+        ..Default::default()
+    };
+    copy_incremental(src_dir.path(), dst_dir.path(), &msg, &ctx, false).unwrap();
+
+    std::fs::remove_file(src_dir.path().join("gone.txt")).unwrap();
+    std::fs::write(src_dir.path().join("changed.txt"), b"new contents").unwrap();
+    set_file_mtime(
+        src_dir.path().join("changed.txt"),
+        FileTime::from_unix_time(4_000_000_000, 0),
+    )
+    .unwrap();
+    std::fs::write(src_dir.path().join("added.txt"), b"hello").unwrap();
+    std::fs::write(src_dir.path().join("cache.tmp"), b"tmp").unwrap();
+
+    let diff_ctx = LogContext {
+        exclude_matcher: Some(build_exclude_matcher(&["*.tmp".to_string()], false).unwrap()),
+        dry_run: true,
+        ..ctx
+    };
+    let report = diff_tree(src_dir.path(), dst_dir.path(), &diff_ctx).unwrap();
+
+    let paths =
+        |g: &rbackup::diff::DiffGroup| g.entries.iter().map(|e| e.path.clone()).collect::<Vec<_>>();
+    assert_eq!(paths(&report.new), vec!["added.txt"]);
+    assert_eq!(paths(&report.modified), vec!["changed.txt"]);
+    assert_eq!(paths(&report.deleted), vec!["gone.txt"]);
+    assert_eq!(paths(&report.excluded), vec!["cache.tmp"]);
+    assert_eq!((report.new.count, report.new.bytes), (1, 5));
+    assert_eq!(report.modified.bytes, 12);
+
+    // Nothing was copied.
+    assert!(!dst_dir.path().join("added.txt").exists());
+
+    let json: serde_json::Value = serde_json::from_str(&format_json(&report).unwrap()).unwrap();
+    assert_eq!(json["deleted"]["count"], 1);
+    assert_eq!(json["new"]["entries"][0]["path"], "added.txt");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_diff_lists_wide_tree_in_order() {
+    let src_dir = tempdir().unwrap();
+    let dst_dir = tempdir().unwrap();
+    let outside = tempdir().unwrap();
+    let mut expected = Vec::new();
+    for d in 0..12 {
+        for e in 0..5 {
+            let dir = src_dir
+                .path()
+                .join(format!("d{:02}", d))
+                .join(format!("e{}", e));
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join("f.txt"), b"f").unwrap();
+            expected.push(format!("d{:02}/e{}/f.txt", d, e));
+        }
+    }
+    std::fs::write(src_dir.path().join("top.txt"), b"top").unwrap();
+    std::fs::write(outside.path().join("target.txt"), b"t").unwrap();
+    // Links to files are listed, links to directories are not followed.
+    std::os::unix::fs::symlink(
+        outside.path().join("target.txt"),
+        src_dir.path().join("link.txt"),
+    )
+    .unwrap();
+    std::os::unix::fs::symlink(outside.path(), src_dir.path().join("linked-dir")).unwrap();
+    expected.push("link.txt".into());
+    expected.push("top.txt".into());
+    expected.sort();
+
+    let ctx = LogContext {
+        quiet: true,
+        dry_run: true,
+        ..Default::default()
+    };
+    // Directories are listed by several workers; the order must not depend
+    // on which finishes first.
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(8)
+        .build()
+        .unwrap();
+    for _ in 0..3 {
+        let report = pool
+            .install(|| diff_tree(src_dir.path(), dst_dir.path(), &ctx))
+            .unwrap();
+        let paths: Vec<&str> = report.new.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, expected);
+    }
+}
@@ -0,0 +1,60 @@
+use std::path::PathBuf;
+
+use rbackup::config::Config;
+use rbackup::hooks::{HookFailure, RunResult, hook_env, run_hook};
+
+#[test]
+fn test_hooks_from_config_and_profile() {
+    let conf: Config = serde_yaml::from_str(
+        "language: en\ntimestamp_format: '%H'\njobs: 2\npre_hook: mount /mnt/b\npost_hook: umount /mnt/b\nprofiles:\n  db:\n    source: /srv/db\n    destination: /mnt/b/db\n    pre_hook: pg_dump db > /srv/db/db.sql\n    hook_failure: warn\n",
+    )
+    .unwrap();
+    assert_eq!(conf.hooks.failure(), HookFailure::Fatal);
+
+    // The profile overrides what it sets and inherits the rest.
+    let hooks = conf.hooks.merged(&conf.profiles["db"].hooks);
+    assert_eq!(
+        hooks.pre_hook.as_deref(),
+        Some("pg_dump db > /srv/db/db.sql")
+    );
+    assert_eq!(hooks.post_hook.as_deref(), Some("umount /mnt/b"));
+    assert_eq!(hooks.on_error, None);
+    assert_eq!(hooks.failure(), HookFailure::Warn);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_post_hook_environment() {
+    let dir = tempfile::tempdir().unwrap();
+    let out = dir.path().join("env.txt");
+    let result = RunResult {
+        copied: 3,
+        skipped: 2,
+        errors: 1,
+        report: Some(dir.path().join("run.log")),
+        ..Default::default()
+    };
+    let env = hook_env(
+        "post_hook",
+        &[PathBuf::from("/src"), PathBuf::from("/data")],
+        &[PathBuf::from("/dst")],
+        Some("docs"),
+        Some(&result),
+    );
+
+    run_hook(
+        &format!(
+            "echo \"$RBACKUP_HOOK $RBACKUP_SOURCE $RBACKUP_PROFILE $RBACKUP_COPIED $RBACKUP_SKIPPED $RBACKUP_ERRORS $RBACKUP_EXIT_STATUS\" > '{}'",
+            out.display()
+        ),
+        &env,
+    )
+    .unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&out).unwrap(),
+        "post_hook /src:/data docs 3 2 1 0\n"
+    );
+
+    // A non-zero exit status is a failure.
+    assert!(run_hook("exit 3", &env).is_err());
+}
@@ -0,0 +1,242 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use filetime::{FileTime, set_file_mtime};
+use tempfile::tempdir;
+
+use rbackup::crypto::KeySource;
+use rbackup::hooks::Hooks;
+use rbackup::{BackupJob, Compare, Error, ProgressEvent};
+
+/// Names of the events an observer received, in order.
+type Events = Mutex<Vec<String>>;
+
+fn record(events: &'static Events) -> impl Fn(&ProgressEvent) + Send + Sync {
+    move |event| {
+        let name = match event {
+            ProgressEvent::Planned { files, .. } => format!("planned {}", files),
+            ProgressEvent::FileCopied { source, .. } => {
+                format!("copied {}", source.file_name().unwrap().to_string_lossy())
+            }
+            ProgressEvent::FileSkipped { source, reason, .. } => format!(
+                "skipped {} {:?}",
+                source.file_name().unwrap().to_string_lossy(),
+                reason
+            ),
+            ProgressEvent::FileFailed { source, .. } => {
+                format!("failed {}", source.file_name().unwrap().to_string_lossy())
+            }
+            ProgressEvent::Finished {
+                copied,
+                skipped,
+                failed,
+            } => format!("finished {} {} {}", copied, skipped, failed),
+            ProgressEvent::FileStarted { .. } | ProgressEvent::BytesProgress { .. } => return,
+        };
+        events.lock().unwrap().push(name);
+    }
+}
+
+#[test]
+fn test_backup_job_copies_and_reports() {
+    static EVENTS: Events = Mutex::new(Vec::new());
+    let src = tempdir().unwrap();
+    let dest = tempdir().unwrap();
+    fs::create_dir(src.path().join("sub")).unwrap();
+    fs::write(src.path().join("a.txt"), b"a").unwrap();
+    fs::write(src.path().join("sub").join("b.txt"), b"b").unwrap();
+    fs::write(src.path().join("c.tmp"), b"c").unwrap();
+
+    let job = BackupJob::new(src.path(), dest.path())
+        .exclude("*.tmp")
+        .jobs(2)
+        .observer(record(&EVENTS));
+    let summary = job.run().unwrap();
+    assert!(summary.is_success());
+    assert_eq!((summary.copied, summary.skipped, summary.failed), (2, 1, 0));
+    assert_eq!(
+        fs::read(dest.path().join("sub").join("b.txt")).unwrap(),
+        b"b"
+    );
+    assert!(!dest.path().join("c.tmp").exists());
+
+    let mut events = EVENTS.lock().unwrap().clone();
+    assert_eq!(events[..2], ["planned 2", "skipped c.tmp Excluded"]);
+    assert_eq!(events.last().unwrap(), "finished 2 1 0");
+    events.sort();
+    assert_eq!(&events[..2], ["copied a.txt", "copied b.txt"]);
+
+    // The destination is now up to date.
+    EVENTS.lock().unwrap().clear();
+    let summary = job.run().unwrap();
+    assert_eq!((summary.copied, summary.skipped), (0, 3));
+    let skipped = EVENTS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|e| e.ends_with("Unchanged"))
+        .count();
+    assert_eq!(skipped, 2);
+}
+
+#[test]
+fn test_backup_job_delta_and_dry_run() {
+    let src = tempdir().unwrap();
+    let dest = tempdir().unwrap();
+    fs::write(src.path().join("old.txt"), b"old").unwrap();
+    fs::write(src.path().join("new.txt"), b"new").unwrap();
+    fs::write(dest.path().join("old.txt"), b"old").unwrap();
+    let past = FileTime::from_system_time(SystemTime::now() - Duration::from_secs(60));
+    set_file_mtime(src.path().join("old.txt"), past).unwrap();
+
+    let job = BackupJob::new(src.path(), dest.path()).compare(Compare::Delta);
+    let summary = job.clone().dry_run(true).run().unwrap();
+    assert_eq!((summary.copied, summary.skipped), (1, 1));
+    assert!(!dest.path().join("new.txt").exists());
+
+    let summary = job.run().unwrap();
+    assert_eq!((summary.copied, summary.skipped), (1, 1));
+    assert_eq!(fs::read(dest.path().join("new.txt")).unwrap(), b"new");
+}
+
+#[test]
+fn test_backup_job_several_destinations() {
+    let src = tempdir().unwrap();
+    let dest = tempdir().unwrap();
+    let other = tempdir().unwrap();
+    fs::write(src.path().join("a.txt"), b"a").unwrap();
+    // A file where the destination directory should be.
+    let blocked: PathBuf = other.path().join("blocked");
+    fs::write(&blocked, b"").unwrap();
+
+    let summary = BackupJob::new(src.path(), dest.path())
+        .also_to(&blocked)
+        .run()
+        .unwrap();
+    assert!(!summary.is_success());
+    assert_eq!(summary.destinations.len(), 2);
+    assert_eq!(summary.destinations[0].result.as_ref().unwrap().copied, 1);
+    let errors: Vec<_> = summary.failed_destinations().collect();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].0, blocked.as_path());
+    // The lock file cannot be created below a file.
+    assert!(matches!(errors[0].1, Error::Lock { .. }));
+    assert!(dest.path().join("a.txt").exists());
+}
+
+#[test]
+fn test_backup_job_invalid() {
+    let src = tempdir().unwrap();
+    let dest = tempdir().unwrap();
+    let job = BackupJob::new(src.path(), dest.path());
+
+    let err = job.clone().jobs(0).run().unwrap_err();
+    assert!(matches!(err, Error::Config { path: None, .. }));
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    match job.clone().exclude("*.tmp").exclude("[").run().unwrap_err() {
+        Error::Pattern { pattern, .. } => assert_eq!(pattern, "["),
+        other => panic!("unexpected error {:?}", other),
+    }
+    let err = job.clone().also_to(dest.path()).run().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    // Library errors still convert into I/O errors.
+    let err: io::Error = job.jobs(0).run().unwrap_err().into();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_backup_job_collects_file_errors() {
+    let src = tempdir().unwrap();
+    let dest = tempdir().unwrap();
+    fs::write(src.path().join("good.txt"), b"good").unwrap();
+    fs::write(src.path().join("bad.txt"), b"bad").unwrap();
+    // A directory where the copy of `bad.txt` should go, older than it.
+    let blocked = dest.path().join("bad.txt");
+    fs::create_dir(&blocked).unwrap();
+    let past = FileTime::from_system_time(SystemTime::now() - Duration::from_secs(60));
+    set_file_mtime(&blocked, past).unwrap();
+
+    let summary = BackupJob::new(src.path(), dest.path()).run().unwrap();
+    assert!(!summary.is_success());
+    assert_eq!((summary.copied, summary.failed), (1, 1));
+    assert!(summary.failed_destinations().next().is_none());
+    let errors: Vec<&Error> = summary.file_errors().collect();
+    assert_eq!(errors.len(), 1);
+    match errors[0] {
+        Error::Copy {
+            path, destination, ..
+        } => {
+            assert_eq!(path, &src.path().join("bad.txt"));
+            assert_eq!(destination, &blocked);
+        }
+        other => panic!("unexpected error {:?}", other),
+    }
+    assert_eq!(errors[0].path(), Some(src.path().join("bad.txt").as_path()));
+    assert!(errors[0].to_string().contains("bad.txt"));
+}
+
+#[test]
+fn test_backup_job_encryption() {
+    let src = tempdir().unwrap();
+    let dest = tempdir().unwrap();
+    fs::write(src.path().join("a.txt"), b"secret").unwrap();
+    let key = KeySource::Passphrase("pass".into());
+
+    let job = BackupJob::new(src.path(), dest.path());
+    let summary = job.clone().encryption(key.clone(), false).run().unwrap();
+    assert_eq!(summary.copied, 1);
+    assert!(dest.path().join("a.txt.enc").exists());
+    // The manifest detects the unchanged file.
+    let summary = job.clone().encryption(key, false).run().unwrap();
+    assert_eq!((summary.copied, summary.skipped), (0, 1));
+
+    // Without the key, the destination fails instead of getting plaintext.
+    let summary = job.run().unwrap();
+    let errors: Vec<_> = summary.failed_destinations().collect();
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(errors[0].1, Error::Destination { .. }));
+    assert!(!dest.path().join("a.txt").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_backup_job_hooks() {
+    let src = tempdir().unwrap();
+    let dest = tempdir().unwrap();
+    let work = tempdir().unwrap();
+    fs::write(src.path().join("a.txt"), b"a").unwrap();
+    let post = work.path().join("post.txt");
+    let hooks = Hooks {
+        post_hook: Some(format!(
+            "echo \"$RBACKUP_EXIT_STATUS $RBACKUP_COPIED $RBACKUP_REPORT\" > {}",
+            post.display()
+        )),
+        ..Default::default()
+    };
+
+    let job = BackupJob::new(src.path(), dest.path())
+        .hooks(hooks.clone())
+        .report("run.log");
+    let summary = job.run().unwrap();
+    assert!(summary.is_success());
+    assert_eq!(fs::read_to_string(&post).unwrap(), "0 1 run.log\n");
+
+    // A failing `pre_hook` fails the run before anything is copied;
+    // `post_hook` still runs.
+    fs::write(src.path().join("b.txt"), b"b").unwrap();
+    let summary = job
+        .hooks(Hooks {
+            pre_hook: Some("exit 3".into()),
+            ..hooks
+        })
+        .run()
+        .unwrap();
+    assert!(matches!(&summary.error, Some(Error::Hook { hook, .. }) if hook == "pre_hook"));
+    assert!(summary.destinations.is_empty());
+    assert!(!dest.path().join("b.txt").exists());
+    assert_eq!(fs::read_to_string(&post).unwrap(), "1 0 run.log\n");
+    assert_eq!(summary.run_result(None).exit_status, 1);
+}
@@ -0,0 +1,99 @@
+use tempfile::tempdir;
+
+use rbackup::lock::{LOCK_FILE, Lock, LockInfo, TryLock};
+
+fn write_holder(path: &std::path::Path, info: &LockInfo) {
+    std::fs::write(path, serde_json::to_string(info).unwrap()).unwrap();
+}
+
+#[test]
+fn test_lock_held_and_released() {
+    let dir = tempdir().unwrap();
+    let path = Lock::destination_path(dir.path());
+    assert_eq!(path, dir.path().join(LOCK_FILE));
+
+    let TryLock::Acquired(lock) = Lock::try_acquire(&path).unwrap() else {
+        panic!("lock not acquired");
+    };
+    assert!(lock.took_over.is_none());
+
+    // This process is alive: a second attempt sees it as the holder.
+    match Lock::try_acquire(&path).unwrap() {
+        TryLock::Held(holder) => assert_eq!(holder.pid, std::process::id()),
+        TryLock::Acquired(_) => panic!("lock acquired twice"),
+    }
+
+    drop(lock);
+    assert!(!path.exists());
+    assert!(matches!(
+        Lock::try_acquire(&path).unwrap(),
+        TryLock::Acquired(_)
+    ));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_stale_lock_taken_over() {
+    let dir = tempdir().unwrap();
+    let path = Lock::destination_path(dir.path());
+
+    // A process of this host that does not exist anymore.
+    let gone = LockInfo {
+        pid: 0x3fff_fff0,
+        ..LockInfo::current()
+    };
+    write_holder(&path, &gone);
+    match Lock::try_acquire(&path).unwrap() {
+        TryLock::Acquired(lock) => assert_eq!(lock.took_over, Some(gone)),
+        TryLock::Held(_) => panic!("stale lock not taken over"),
+    }
+
+    // The liveness of processes of other hosts cannot be checked.
+    let remote = LockInfo {
+        pid: 0x3fff_fff0,
+        host: "some-other-host.invalid".to_string(),
+        ..LockInfo::current()
+    };
+    write_holder(&path, &remote);
+    match Lock::try_acquire(&path).unwrap() {
+        TryLock::Held(holder) => assert_eq!(holder, remote),
+        TryLock::Acquired(_) => panic!("remote lock taken over"),
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_stale_lock_taken_over_once() {
+    let dir = tempdir().unwrap();
+    let path = Lock::destination_path(dir.path());
+    let gone = LockInfo {
+        pid: 0x3fff_fff0,
+        ..LockInfo::current()
+    };
+
+    // Runs finding the same stale lock at once must not all acquire it.
+    for _ in 0..500 {
+        write_holder(&path, &gone);
+        let barrier = std::sync::Barrier::new(16);
+        let results: Vec<TryLock> = std::thread::scope(|s| {
+            let handles: Vec<_> = (0..16)
+                .map(|_| {
+                    s.spawn(|| {
+                        barrier.wait();
+                        Lock::try_acquire(&path).unwrap()
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        let acquired = results
+            .iter()
+            .filter(|r| matches!(r, TryLock::Acquired(_)))
+            .count();
+        assert_eq!(acquired, 1);
+        drop(results);
+        assert!(!path.exists());
+    }
+    // No renamed lock file is left behind.
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+}
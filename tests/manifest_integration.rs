@@ -0,0 +1,143 @@
+use tempfile::tempdir;
+
+use rbackup::manifest::{Manifest, hash_file};
+use rbackup::utils::copy_sources;
+use rbackup::utils::load_translations;
+use rbackup::{LogContext, copy_incremental};
+
+#[test]
+fn test_manifest_written_and_reused() {
+    let src_dir = tempdir().unwrap();
+    let dst_dir = tempdir().unwrap();
+
+    std::fs::create_dir(src_dir.path().join("sub")).unwrap();
+    std::fs::write(src_dir.path().join("a.txt"), b"alpha").unwrap();
+    std::fs::write(src_dir.path().join("sub").join("b.txt"), b"beta").unwrap();
+
+    let msg = load_translations().unwrap().remove("en").unwrap();
+    let ctx = LogContext {
+        quiet: true,
+        on_log: false,
+        manifest: true,
+        ..Default::default()
+    };
+
+    copy_incremental(src_dir.path(), dst_dir.path(), &msg, &ctx, false).unwrap();
+
+    let manifest = Manifest::load(dst_dir.path(), None)
+        .unwrap()
+        .expect("manifest");
+    assert_eq!(manifest.entries.len(), 2);
+    let b = &manifest.entries["sub/b.txt"];
+    assert_eq!(b.size, 4);
+    assert_eq!(
+        b.hash,
+        hash_file(&src_dir.path().join("sub").join("b.txt")).unwrap()
+    );
+
+    // A second run keeps the manifest consistent with the source.
+    std::fs::write(src_dir.path().join("c.txt"), b"gamma").unwrap();
+    copy_incremental(src_dir.path(), dst_dir.path(), &msg, &ctx, true).unwrap();
+    let manifest = Manifest::load(dst_dir.path(), None)
+        .unwrap()
+        .expect("manifest");
+    assert_eq!(manifest.entries.len(), 3);
+    assert!(manifest.entries.contains_key("c.txt"));
+}
+
+#[test]
+fn test_manifest_not_written_on_dry_run() {
+    let src_dir = tempdir().unwrap();
+    let dst_dir = tempdir().unwrap();
+    std::fs::write(src_dir.path().join("a.txt"), b"alpha").unwrap();
+
+    let msg = load_translations().unwrap().remove("en").unwrap();
+    let ctx = LogContext {
+        quiet: true,
+        on_log: false,
+        manifest: true,
+        dry_run: true,
+        ..Default::default()
+    };
+
+    copy_incremental(src_dir.path(), dst_dir.path(), &msg, &ctx, false).unwrap();
+    assert!(Manifest::load(dst_dir.path(), None).unwrap().is_none());
+}
+
+#[test]
+fn test_copy_sources_paths_updates_only_touched_entries() {
+    let src_dir = tempdir().unwrap();
+    let dst_dir = tempdir().unwrap();
+    let src = src_dir.path();
+
+    std::fs::create_dir(src.join("sub")).unwrap();
+    std::fs::write(src.join("a.txt"), b"alpha").unwrap();
+    std::fs::write(src.join("keep.txt"), b"keep").unwrap();
+    std::fs::write(src.join("sub").join("b.txt"), b"beta").unwrap();
+
+    let msg = load_translations().unwrap().remove("en").unwrap();
+    let ctx = LogContext {
+        quiet: true,
+        on_log: false,
+        manifest: true,
+        ..Default::default()
+    };
+    copy_incremental(src, dst_dir.path(), &msg, &ctx, false).unwrap();
+
+    // What a watcher would report: a new directory, a change and a deletion.
+    std::fs::create_dir(src.join("new")).unwrap();
+    std::fs::write(src.join("new").join("c.txt"), b"gamma").unwrap();
+    std::fs::write(src.join("sub").join("b.txt"), b"beta, longer").unwrap();
+    // Make the change visible despite coarse filesystem timestamps.
+    std::fs::File::options()
+        .write(true)
+        .open(src.join("sub").join("b.txt"))
+        .unwrap()
+        .set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(5))
+        .unwrap();
+    std::fs::remove_file(src.join("a.txt")).unwrap();
+    let touched = [
+        src.join("new"),
+        src.join("sub").join("b.txt"),
+        src.join("a.txt"),
+    ];
+
+    let pairs = [(src.to_path_buf(), dst_dir.path().to_path_buf())];
+    let counts = copy_sources(&pairs, &msg, &ctx, true, Some(&touched)).unwrap();
+    assert_eq!(counts.copied, 2);
+    assert_eq!(
+        std::fs::read(dst_dir.path().join("sub").join("b.txt")).unwrap(),
+        b"beta, longer"
+    );
+    // Deleted files stay in the backup.
+    assert!(dst_dir.path().join("a.txt").exists());
+
+    let manifest = Manifest::load(dst_dir.path(), None)
+        .unwrap()
+        .expect("manifest");
+    let mut keys: Vec<&str> = manifest.entries.keys().map(String::as_str).collect();
+    keys.sort();
+    assert_eq!(keys, ["keep.txt", "new/c.txt", "sub/b.txt"]);
+    assert_eq!(manifest.entries["sub/b.txt"].size, 12);
+}
+
+#[test]
+fn test_manifest_entry_pre_epoch_mtime() {
+    use filetime::{FileTime, set_file_mtime};
+    use rbackup::manifest::ManifestEntry;
+
+    let dir = tempdir().unwrap();
+    let before = dir.path().join("before.txt");
+    let after = dir.path().join("after.txt");
+    std::fs::write(&before, b"x").unwrap();
+    std::fs::write(&after, b"x").unwrap();
+    // Half a second before and after the epoch.
+    set_file_mtime(&before, FileTime::from_unix_time(-1, 500_000_000)).unwrap();
+    set_file_mtime(&after, FileTime::from_unix_time(0, 500_000_000)).unwrap();
+
+    let meta = std::fs::metadata(&before).unwrap();
+    let entry = ManifestEntry::from_metadata(&meta, String::new());
+    assert_eq!((entry.mtime, entry.mtime_nanos), (-1, 500_000_000));
+    assert!(entry.matches_metadata(&meta));
+    assert!(!entry.matches_metadata(&std::fs::metadata(&after).unwrap()));
+}
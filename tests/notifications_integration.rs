@@ -0,0 +1,158 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::thread;
+
+use rbackup::config::Config;
+use rbackup::hooks::RunResult;
+use rbackup::notifications::{Channel, Notice};
+use rbackup::utils::load_translations;
+
+fn notice(exit_status: i32, errors: usize) -> Notice {
+    let result = RunResult {
+        copied: 4,
+        skipped: 1,
+        errors,
+        exit_status,
+        ..Default::default()
+    };
+    Notice::new(
+        &result,
+        &[PathBuf::from("/src")],
+        &[PathBuf::from("/dst")],
+        Some("docs"),
+    )
+}
+
+#[test]
+fn test_notifications_config_and_filter() {
+    let conf: Config = serde_yaml::from_str(
+        "language: en\ntimestamp_format: '%H'\njobs: 2\nnotifications:\n  - type: syslog\n  - type: webhook\n    on: failure\n    url: http://localhost/hook\n  - type: email\n    on: success\n    smtp_host: localhost\n    tls: none\n    from: a@example.com\n    to: [b@example.com]\n",
+    )
+    .unwrap();
+    let [syslog, webhook, email] = conf.notifications.as_slice() else {
+        panic!("three channels expected");
+    };
+    assert!(matches!(syslog.channel, Channel::Syslog));
+
+    let ok = notice(0, 0);
+    // Files that could not be copied make the run a failure to report.
+    let partial = notice(0, 2);
+    assert!(syslog.wants(&ok) && syslog.wants(&partial));
+    assert!(!webhook.wants(&ok) && webhook.wants(&partial));
+    assert!(email.wants(&ok) && !email.wants(&notice(1, 0)));
+}
+
+#[test]
+fn test_webhook_posts_json() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut length = 0;
+        let mut auth = String::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            let lower = line.to_ascii_lowercase();
+            if let Some(v) = lower.strip_prefix("content-length:") {
+                length = v.trim().parse().unwrap();
+            }
+            if lower.starts_with("x-token:") {
+                auth = line[8..].trim().to_string();
+            }
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        reader
+            .get_mut()
+            .write_all(b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        (auth, body)
+    });
+
+    let conf: Config = serde_yaml::from_str(&format!(
+        "language: en\ntimestamp_format: '%H'\njobs: 2\nnotifications:\n  - type: webhook\n    url: http://127.0.0.1:{}/hook\n    headers: {{ X-Token: secret }}\n",
+        port
+    ))
+    .unwrap();
+    let msg = load_translations().unwrap().remove("en").unwrap();
+    let sent = notice(1, 0);
+    conf.notifications[0].send(&sent, &msg).unwrap();
+
+    let (auth, body) = server.join().unwrap();
+    assert_eq!(auth, "secret");
+    let received: Notice = serde_json::from_slice(&body).unwrap();
+    assert_eq!(received, sent);
+    assert_eq!(received.status, "failure");
+}
+
+#[test]
+fn test_email_through_smtp() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    // Minimal SMTP server accepting a single message.
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut writer = stream.try_clone().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut reply = |text: &str| {
+            writer
+                .write_all(format!("{}\r\n", text).as_bytes())
+                .unwrap()
+        };
+        reply("220 localhost ESMTP");
+        let mut recipients = Vec::new();
+        let mut data = String::new();
+        let mut in_data = false;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).unwrap() == 0 {
+                break;
+            }
+            if in_data {
+                if line == ".\r\n" {
+                    in_data = false;
+                    reply("250 queued");
+                } else {
+                    data.push_str(&line);
+                }
+                continue;
+            }
+            let command = line.trim_end().to_ascii_uppercase();
+            if command.starts_with("EHLO") || command.starts_with("HELO") {
+                reply("250 localhost");
+            } else if command.starts_with("RCPT TO:") {
+                recipients.push(line.trim_end()[8..].to_string());
+                reply("250 ok");
+            } else if command == "DATA" {
+                in_data = true;
+                reply("354 go ahead");
+            } else if command == "QUIT" {
+                reply("221 bye");
+                break;
+            } else {
+                reply("250 ok");
+            }
+        }
+        (recipients, data)
+    });
+
+    let conf: Config = serde_yaml::from_str(&format!(
+        "language: en\ntimestamp_format: '%H'\njobs: 2\nnotifications:\n  - type: email\n    smtp_host: 127.0.0.1\n    smtp_port: {}\n    tls: none\n    from: rbackup@example.com\n    to: [me@example.com]\n",
+        port
+    ))
+    .unwrap();
+    let msg = load_translations().unwrap().remove("en").unwrap();
+    conf.notifications[0].send(&notice(0, 0), &msg).unwrap();
+
+    let (recipients, data) = server.join().unwrap();
+    assert_eq!(recipients, ["<me@example.com>"]);
+    assert!(data.contains("Subject: rbackup: backup of docs completed"));
+    assert!(data.contains("Copied: 4"));
+}
@@ -0,0 +1,163 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use tempfile::tempdir;
+
+use rbackup::utils::{copy_sources, create_logger, load_translations};
+use rbackup::{LogContext, ProgressEvent, ShowSkipped, SkipReason, copy_incremental};
+
+/// Events of a run, with their paths as owned values.
+#[derive(Debug, PartialEq)]
+enum Seen {
+    Planned(usize, u64),
+    Started(PathBuf),
+    Copied(PathBuf),
+    Skipped(PathBuf, SkipReason),
+    Bytes(usize, u64),
+    Finished(usize, usize),
+}
+
+fn observe(seen: Arc<Mutex<Vec<Seen>>>) -> impl Fn(&ProgressEvent) + Send + Sync {
+    move |event| {
+        let name = |p: &std::path::Path| PathBuf::from(p.file_name().unwrap());
+        let seen_event = match *event {
+            ProgressEvent::Planned { files, bytes, .. } => Seen::Planned(files, bytes),
+            ProgressEvent::FileStarted { source, .. } => Seen::Started(name(source)),
+            ProgressEvent::FileCopied { source, .. } => Seen::Copied(name(source)),
+            ProgressEvent::FileSkipped { source, reason, .. } => {
+                Seen::Skipped(name(source), reason)
+            }
+            ProgressEvent::FileFailed { error, .. } => panic!("unexpected failure {}", error),
+            ProgressEvent::BytesProgress {
+                done_files,
+                done_bytes,
+                ..
+            } => Seen::Bytes(done_files, done_bytes),
+            ProgressEvent::Finished {
+                copied, skipped, ..
+            } => Seen::Finished(copied, skipped),
+        };
+        seen.lock().unwrap().push(seen_event);
+    }
+}
+
+#[test]
+fn test_observer_events_and_file_logger() {
+    let src = tempdir().unwrap();
+    let dest = tempdir().unwrap();
+    let logs = tempdir().unwrap();
+    fs::write(src.path().join("a.txt"), b"aaa").unwrap();
+    fs::write(src.path().join("b.txt"), b"bb").unwrap();
+    fs::write(src.path().join("c.tmp"), b"c").unwrap();
+    let log_path = logs.path().join("run.log");
+    let msg = load_translations().unwrap().remove("en").unwrap();
+
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    {
+        let ctx = LogContext {
+            logger: create_logger(Some(&log_path)).unwrap(),
+            on_log: true,
+            exclude_matcher: Some(
+                rbackup::build_exclude_matcher(&["*.tmp".into()], false).unwrap(),
+            ),
+            observer: Some(Arc::new(observe(seen.clone()))),
+            ..Default::default()
+        };
+        assert_eq!(
+            copy_incremental(src.path(), dest.path(), &msg, &ctx, false).unwrap(),
+            (2, 1)
+        );
+    }
+
+    let seen = seen.lock().unwrap();
+    assert_eq!(
+        seen[..2],
+        [
+            Seen::Planned(2, 5),
+            Seen::Skipped("c.tmp".into(), SkipReason::Excluded)
+        ]
+    );
+    // Every file is started before its outcome is reported.
+    for file in ["a.txt", "b.txt"] {
+        let started = seen.iter().position(|e| *e == Seen::Started(file.into()));
+        let copied = seen.iter().position(|e| *e == Seen::Copied(file.into()));
+        assert!(started.unwrap() < copied.unwrap());
+    }
+    assert_eq!(
+        seen[seen.len() - 2..],
+        [Seen::Bytes(2, 5), Seen::Finished(2, 1)]
+    );
+
+    // The file logger is an observer too: one numbered line per copied file,
+    // none for the excluded one.
+    let log = fs::read_to_string(&log_path).unwrap();
+    let lines: Vec<&str> = log.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines.iter().any(|l| l.starts_with("#1 ")));
+    assert!(lines.iter().any(|l| l.contains("a.txt")));
+    assert!(!log.contains("c.tmp"));
+}
+
+#[test]
+fn test_file_logger_shows_skipped_files() {
+    let src = tempdir().unwrap();
+    let dest = tempdir().unwrap();
+    let logs = tempdir().unwrap();
+    fs::write(src.path().join("a.txt"), b"a").unwrap();
+    let log_path = logs.path().join("run.log");
+    let msg = load_translations().unwrap().remove("en").unwrap();
+
+    let run = |show_skipped| {
+        let ctx = LogContext {
+            logger: create_logger(Some(&log_path)).unwrap(),
+            on_log: true,
+            show_skipped,
+            ..Default::default()
+        };
+        copy_incremental(src.path(), dest.path(), &msg, &ctx, false).unwrap()
+    };
+    assert_eq!(run(ShowSkipped::Never), (1, 0));
+    assert!(
+        fs::read_to_string(&log_path)
+            .unwrap()
+            .contains(&msg.copied_file)
+    );
+    // Unchanged files are only logged when asked for.
+    assert_eq!(run(ShowSkipped::Never), (0, 1));
+    assert_eq!(fs::read_to_string(&log_path).unwrap(), "");
+    assert_eq!(run(ShowSkipped::All), (0, 1));
+    let log = fs::read_to_string(&log_path).unwrap();
+    assert_eq!(log.lines().count(), 1);
+    assert!(log.contains(&msg.skipped_file));
+}
+
+#[test]
+fn test_file_logger_always_shows_failed_files() {
+    let src = tempdir().unwrap();
+    let dest = tempdir().unwrap();
+    let logs = tempdir().unwrap();
+    fs::write(src.path().join("a.txt"), b"a").unwrap();
+    // A directory in the way of the copy makes it fail.
+    fs::create_dir_all(dest.path().join("a.txt").join("sub")).unwrap();
+    filetime::set_file_mtime(dest.path().join("a.txt"), filetime::FileTime::zero()).unwrap();
+    let log_path = logs.path().join("run.log");
+    let msg = load_translations().unwrap().remove("en").unwrap();
+
+    let ctx = LogContext {
+        logger: create_logger(Some(&log_path)).unwrap(),
+        on_log: true,
+        show_skipped: ShowSkipped::Never,
+        ..Default::default()
+    };
+    let pairs = [(src.path().to_path_buf(), dest.path().to_path_buf())];
+    let counts = copy_sources(&pairs, &msg, &ctx, false, None).unwrap();
+    assert_eq!(counts.errors.len(), 1, "{:?}", counts.errors);
+    // Dropping the logger flushes it.
+    drop(ctx);
+    let log = fs::read_to_string(&log_path).unwrap();
+    assert_eq!(log.lines().count(), 1, "{log}");
+    assert!(log.contains(&msg.failed_file), "{log}");
+    assert!(!log.contains(&msg.skipped_file), "{log}");
+    assert!(log.contains(&counts.errors[0].to_string()), "{log}");
+}
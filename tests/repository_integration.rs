@@ -0,0 +1,124 @@
+use tempfile::tempdir;
+
+use rbackup::check::ReadData;
+use rbackup::repository::{Chunking, OBJECTS_DIR, Repository};
+use rbackup::restore::restore_snapshot;
+use rbackup::utils::load_translations;
+use rbackup::{LogContext, copy_incremental};
+
+fn repo_ctx(repo: Repository) -> LogContext {
+    LogContext {
+        quiet: true,
+        on_log: false,
+        repository: Some(repo),
+        ..Default::default()
+    }
+}
+
+fn object_count(repo_dir: &std::path::Path) -> usize {
+    walkdir::WalkDir::new(repo_dir.join(OBJECTS_DIR))
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|e| e.file_type().is_file())
+        .count()
+}
+
+#[test]
+fn test_repository_deduplicates_and_restores() {
+    let src_a = tempdir().unwrap();
+    let src_b = tempdir().unwrap();
+    let repo_dir = tempdir().unwrap();
+    let out_dir = tempdir().unwrap();
+
+    let shared: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+    std::fs::write(src_a.path().join("shared.bin"), &shared).unwrap();
+    std::fs::write(src_a.path().join("copy.bin"), &shared).unwrap();
+    std::fs::write(src_a.path().join("a.txt"), b"only in a").unwrap();
+    std::fs::create_dir(src_b.path().join("sub")).unwrap();
+    std::fs::write(src_b.path().join("sub").join("shared.bin"), &shared).unwrap();
+
+    let msg = load_translations().unwrap().remove("en").unwrap();
+    let repo = Repository::open_or_init(repo_dir.path(), None, true).unwrap();
+    let ctx = repo_ctx(repo);
+
+    let (copied, _) = copy_incremental(src_a.path(), repo_dir.path(), &msg, &ctx, false).unwrap();
+    assert_eq!(copied, 3);
+    copy_incremental(src_b.path(), repo_dir.path(), &msg, &ctx, false).unwrap();
+    // Three copies of the same contents are stored once.
+    assert_eq!(object_count(repo_dir.path()), 2);
+
+    // Unchanged files are detected through the previous snapshot of the source.
+    let (copied, skipped) =
+        copy_incremental(src_a.path(), repo_dir.path(), &msg, &ctx, false).unwrap();
+    assert_eq!((copied, skipped), (0, 3));
+
+    let repo = Repository::open(repo_dir.path()).unwrap();
+    assert_eq!(repo.snapshot_ids().unwrap().len(), 3);
+    assert!(repo.check(ReadData::All).unwrap().is_ok());
+
+    // The latest snapshot (of source a) still lists every file.
+    let (restored, failed) =
+        restore_snapshot(repo_dir.path(), None, out_dir.path(), &msg, &ctx).unwrap();
+    assert_eq!((restored, failed), (3, 0));
+    assert_eq!(
+        std::fs::read(out_dir.path().join("copy.bin")).unwrap(),
+        shared
+    );
+    assert_eq!(
+        std::fs::read(out_dir.path().join("a.txt")).unwrap(),
+        b"only in a"
+    );
+}
+
+#[test]
+fn test_chunked_repository_roundtrip_and_check() {
+    let src_dir = tempdir().unwrap();
+    let repo_dir = tempdir().unwrap();
+    let out_dir = tempdir().unwrap();
+
+    let big: Vec<u8> = (0..3_000_000u32)
+        .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+        .collect();
+    std::fs::write(src_dir.path().join("big.bin"), &big).unwrap();
+
+    let msg = load_translations().unwrap().remove("en").unwrap();
+    let chunking = Chunking {
+        min_size: 16 * 1024,
+        avg_size: 64 * 1024,
+        max_size: 256 * 1024,
+    };
+    let repo = Repository::open_or_init(repo_dir.path(), Some(chunking), true).unwrap();
+    let ctx = repo_ctx(repo.clone());
+
+    copy_incremental(src_dir.path(), repo_dir.path(), &msg, &ctx, false).unwrap();
+    let first_objects = object_count(repo_dir.path());
+    assert!(first_objects > 1);
+
+    // Changing a few bytes in the middle only adds a few chunks.
+    let mut changed = big.clone();
+    changed[1_500_000..1_500_010].copy_from_slice(b"0123456789");
+    std::fs::write(src_dir.path().join("big.bin"), &changed).unwrap();
+    copy_incremental(src_dir.path(), repo_dir.path(), &msg, &ctx, false).unwrap();
+    assert!(object_count(repo_dir.path()) - first_objects <= 3);
+
+    let ids = repo.snapshot_ids().unwrap();
+    let (restored, failed) =
+        restore_snapshot(repo_dir.path(), Some(&ids[0]), out_dir.path(), &msg, &ctx).unwrap();
+    assert_eq!((restored, failed), (1, 0));
+    assert_eq!(std::fs::read(out_dir.path().join("big.bin")).unwrap(), big);
+
+    // Damaging an object is reported by a full check only; removing one always is.
+    let snapshot = repo.load_snapshot(&ids[1]).unwrap();
+    let chunks = &snapshot.files["big.bin"].chunks;
+    std::fs::write(repo.object_path(&chunks[0]), b"garbage").unwrap();
+    assert!(repo.check(ReadData::Off).unwrap().is_ok());
+    assert_eq!(
+        repo.check(ReadData::All).unwrap().corrupt_objects,
+        vec![chunks[0].clone()]
+    );
+    std::fs::remove_file(repo.object_path(&chunks[1])).unwrap();
+    assert_eq!(
+        repo.check(ReadData::Off).unwrap().missing_objects,
+        vec![chunks[1].clone()]
+    );
+}
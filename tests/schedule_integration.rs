@@ -0,0 +1,162 @@
+use std::path::Path;
+
+use rbackup::config::Config;
+use rbackup::schedule::{
+    Backend, CronExpr, crontab_line, crontab_with, parse_crontab, systemd_units,
+};
+
+#[test]
+fn test_cron_to_on_calendar() {
+    let cases = [
+        ("0 2 * * *", "*-*-* 02:00:00"),
+        ("@daily", "*-*-* 00:00:00"),
+        ("*/15 * * * *", "*-*-* *:00/15:00"),
+        ("0 8-18 * * 0,6", "Sat,Sun *-*-* 08..18:00:00"),
+        ("0 0 1,15 */3 *", "*-01/3-01,15 00:00:00"),
+        ("0-30/10 4 * * 7", "Sun *-*-* 04:00,10,20,30:00"),
+    ];
+    for (cron, calendar) in cases {
+        assert_eq!(
+            CronExpr::parse(cron).unwrap().to_on_calendar().unwrap(),
+            calendar,
+            "{}",
+            cron
+        );
+    }
+
+    for bad in [
+        "* * * *",
+        "60 * * * *",
+        "* 24 * * *",
+        "5-1 * * * *",
+        "*/0 * * * *",
+        "a * * * *",
+    ] {
+        assert!(CronExpr::parse(bad).is_err(), "{}", bad);
+    }
+    // Cron ORs day of month and day of week; systemd cannot.
+    assert!(
+        CronExpr::parse("0 0 1 * 1")
+            .unwrap()
+            .to_on_calendar()
+            .is_err()
+    );
+}
+
+#[test]
+fn test_generated_units_and_crontab() {
+    let expr = CronExpr::parse("30 1 * * *").unwrap();
+    let exe = Path::new("/usr/bin/rbackup");
+    let args: Vec<String> = [
+        "copy",
+        "/data",
+        "/mnt/my backup",
+        "--log",
+        "/var/log/%d.log",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect();
+
+    let (service, timer) = systemd_units("nightly", &expr, exe, &args).unwrap();
+    assert!(service.contains(
+        "ExecStart=/usr/bin/rbackup copy /data \"/mnt/my backup\" --log /var/log/%%d.log\n"
+    ));
+    assert!(timer.contains("OnCalendar=*-*-* 01:30:00\n"));
+
+    let line = crontab_line("nightly", &expr, exe, &args);
+    assert_eq!(
+        line,
+        "30 1 * * * /usr/bin/rbackup copy /data '/mnt/my backup' --log '/var/log/\\%d.log' # rbackup:nightly"
+    );
+
+    // Installing replaces the previous line of the same schedule only.
+    let existing = "MAILTO=me\n0 3 * * * other-job\n0 0 * * * old # rbackup:nightly\n";
+    let updated = crontab_with(existing, "nightly", Some(&line));
+    assert_eq!(
+        updated,
+        format!("MAILTO=me\n0 3 * * * other-job\n{}\n", line)
+    );
+
+    let entries = parse_crontab(&updated);
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].name, "nightly");
+    assert_eq!(entries[0].backend, Backend::Cron);
+    assert_eq!(entries[0].expression, "30 1 * * *");
+    assert!(
+        entries[0]
+            .command
+            .starts_with("/usr/bin/rbackup copy /data")
+    );
+
+    assert_eq!(
+        crontab_with(&updated, "nightly", None),
+        "MAILTO=me\n0 3 * * * other-job\n"
+    );
+}
+
+#[test]
+fn test_config_profiles() {
+    let conf: Config = serde_yaml::from_str(
+        "language: en\ntimestamp_format: '%H'\njobs: 2\nprofiles:\n  docs:\n    source: /home/me/docs\n    destination: /backup/docs\n    args: [\"--manifest\"]\n",
+    )
+    .unwrap();
+    assert_eq!(
+        conf.profiles["docs"].copy_args(),
+        vec!["copy", "/home/me/docs", "/backup/docs", "--manifest"]
+    );
+
+    // Profiles are optional.
+    let conf: Config =
+        serde_yaml::from_str("language: en\ntimestamp_format: '%H'\njobs: 2\n").unwrap();
+    assert!(conf.profiles.is_empty());
+}
+
+#[test]
+fn test_cron_next_after() {
+    let at = |s: &str| chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap();
+    let next = |cron: &str, after: &str| {
+        CronExpr::parse(cron)
+            .unwrap()
+            .next_after(at(after))
+            .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+    };
+
+    assert_eq!(
+        next("0 2 * * *", "2026-03-10 01:59").as_deref(),
+        Some("2026-03-10 02:00")
+    );
+    // Strictly after: a run at 02:00 schedules the next day.
+    assert_eq!(
+        next("0 2 * * *", "2026-03-10 02:00").as_deref(),
+        Some("2026-03-11 02:00")
+    );
+    assert_eq!(
+        next("*/15 * * * *", "2026-03-10 10:07").as_deref(),
+        Some("2026-03-10 10:15")
+    );
+    // 2026-03-13 is a Friday; weekdays only.
+    assert_eq!(
+        next("30 8 * * 1-5", "2026-03-13 09:00").as_deref(),
+        Some("2026-03-16 08:30")
+    );
+    assert_eq!(
+        next("0 0 * * 7", "2026-03-13 09:00").as_deref(),
+        Some("2026-03-15 00:00")
+    );
+    assert_eq!(
+        next("@yearly", "2026-03-13 09:00").as_deref(),
+        Some("2027-01-01 00:00")
+    );
+    assert_eq!(
+        next("0 12 31 * *", "2026-04-01 00:00").as_deref(),
+        Some("2026-05-31 12:00")
+    );
+    // Day of month or day of week (the 1st, or a Monday).
+    assert_eq!(
+        next("0 0 1 * 1", "2026-03-13 09:00").as_deref(),
+        Some("2026-03-16 00:00")
+    );
+    // Never matches.
+    assert_eq!(next("0 0 30 2 *", "2026-01-01 00:00"), None);
+}
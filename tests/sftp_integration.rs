@@ -0,0 +1,262 @@
+// The stand-in server reports Unix file modes.
+#![cfg(unix)]
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+use tempfile::tempdir;
+
+use rbackup::LogContext;
+use rbackup::destination::{Destination, Location};
+use rbackup::sftp::{SftpDestination, SftpUrl};
+use rbackup::utils::{CopyTarget, copy_targets, load_translations};
+
+/// A minimal SFTP version 3 server serving `root` over a pair of pipes, with
+/// just the requests `SftpDestination` sends.
+fn serve(root: PathBuf, mut requests: impl Read, mut replies: impl Write) {
+    fn u32_at(data: &[u8], pos: &mut usize) -> u32 {
+        let v = u32::from_be_bytes(data[*pos..*pos + 4].try_into().unwrap());
+        *pos += 4;
+        v
+    }
+    fn string_at(data: &[u8], pos: &mut usize) -> Vec<u8> {
+        let n = u32_at(data, pos) as usize;
+        *pos += n;
+        data[*pos - n..*pos].to_vec()
+    }
+    fn send(out: &mut impl Write, kind: u8, body: &[u8]) {
+        out.write_all(&(body.len() as u32 + 1).to_be_bytes())
+            .unwrap();
+        out.write_all(&[kind]).unwrap();
+        out.write_all(body).unwrap();
+        out.flush().unwrap();
+    }
+    fn status(id: u32, result: io::Result<()>) -> (u8, Vec<u8>) {
+        let code: u32 = match &result {
+            Ok(()) => 0,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => 2,
+            Err(_) => 4,
+        };
+        let mut body = id.to_be_bytes().to_vec();
+        body.extend(code.to_be_bytes());
+        let message = result.err().map(|e| e.to_string()).unwrap_or_default();
+        body.extend((message.len() as u32).to_be_bytes());
+        body.extend(message.as_bytes());
+        body.extend(0u32.to_be_bytes());
+        (101, body)
+    }
+
+    let local = |path: Vec<u8>| root.join(String::from_utf8(path).unwrap().trim_start_matches('/'));
+    let mut handles: HashMap<Vec<u8>, File> = HashMap::new();
+    let mut next_handle = 0u32;
+    loop {
+        let mut len = [0u8; 4];
+        if requests.read_exact(&mut len).is_err() {
+            return;
+        }
+        let mut packet = vec![0u8; u32::from_be_bytes(len) as usize];
+        requests.read_exact(&mut packet).unwrap();
+        let (kind, data) = (packet[0], &packet[1..]);
+        let mut pos = 0;
+        if kind == 1 {
+            let mut body = 3u32.to_be_bytes().to_vec();
+            for s in ["posix-rename@openssh.com", "1"] {
+                body.extend((s.len() as u32).to_be_bytes());
+                body.extend(s.as_bytes());
+            }
+            send(&mut replies, 2, &body);
+            continue;
+        }
+        let id = u32_at(data, &mut pos);
+        let (reply, body) = match kind {
+            // STAT
+            17 => match fs::metadata(local(string_at(data, &mut pos))) {
+                Ok(meta) => {
+                    let mut body = id.to_be_bytes().to_vec();
+                    body.extend((0x01u32 | 0x04 | 0x08).to_be_bytes());
+                    body.extend(meta.len().to_be_bytes());
+                    body.extend(meta.permissions().mode().to_be_bytes());
+                    let mtime = meta.modified().unwrap().duration_since(UNIX_EPOCH).unwrap();
+                    body.extend((mtime.as_secs() as u32).to_be_bytes());
+                    body.extend((mtime.as_secs() as u32).to_be_bytes());
+                    (105, body)
+                }
+                Err(e) => status(id, Err(e)),
+            },
+            // MKDIR
+            14 => status(id, fs::create_dir(local(string_at(data, &mut pos)))),
+            // OPEN
+            3 => {
+                let path = local(string_at(data, &mut pos));
+                match OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .open(path)
+                {
+                    Ok(file) => {
+                        next_handle += 1;
+                        let handle = next_handle.to_be_bytes().to_vec();
+                        handles.insert(handle.clone(), file);
+                        let mut body = id.to_be_bytes().to_vec();
+                        body.extend((handle.len() as u32).to_be_bytes());
+                        body.extend(handle);
+                        (102, body)
+                    }
+                    Err(e) => status(id, Err(e)),
+                }
+            }
+            // WRITE
+            6 => {
+                let handle = string_at(data, &mut pos);
+                let offset =
+                    (u64::from(u32_at(data, &mut pos)) << 32) | u64::from(u32_at(data, &mut pos));
+                let chunk = string_at(data, &mut pos);
+                let file = handles.get_mut(&handle).unwrap();
+                file.seek(SeekFrom::Start(offset)).unwrap();
+                status(id, file.write_all(&chunk))
+            }
+            // CLOSE
+            4 => {
+                handles.remove(&string_at(data, &mut pos));
+                status(id, Ok(()))
+            }
+            // REMOVE
+            13 => status(id, fs::remove_file(local(string_at(data, &mut pos)))),
+            // EXTENDED (posix-rename) and RENAME
+            200 | 18 => {
+                if kind == 200 {
+                    assert_eq!(string_at(data, &mut pos), b"posix-rename@openssh.com");
+                }
+                let from = local(string_at(data, &mut pos));
+                let to = local(string_at(data, &mut pos));
+                status(id, fs::rename(from, to))
+            }
+            other => panic!("unexpected request {}", other),
+        };
+        send(&mut replies, reply, &body);
+    }
+}
+
+/// An `SftpDestination` connected to a server thread serving `root`.
+fn connect(root: &Path) -> SftpDestination {
+    let (request_reader, request_writer) = io::pipe().unwrap();
+    let (reply_reader, reply_writer) = io::pipe().unwrap();
+    let root = root.to_path_buf();
+    std::thread::spawn(move || serve(root, request_reader, reply_writer));
+    SftpDestination::from_streams(reply_reader, request_writer).unwrap()
+}
+
+#[test]
+fn test_sftp_url_and_location_parsing() {
+    let url = SftpUrl::parse("backup@nas.local:2222/srv/backup").unwrap();
+    assert_eq!(url.user.as_deref(), Some("backup"));
+    assert_eq!(url.host, "nas.local");
+    assert_eq!(url.port, Some(2222));
+    assert_eq!(url.path, PathBuf::from("/srv/backup"));
+    assert_eq!(url.to_string(), "sftp://backup@nas.local:2222/srv/backup");
+
+    let v6 = SftpUrl::parse("[::1]:22/data").unwrap();
+    assert_eq!((v6.host.as_str(), v6.port), ("::1", Some(22)));
+    // Without a path, files go to the login directory.
+    assert_eq!(SftpUrl::parse("nas").unwrap().path, PathBuf::from("."));
+    assert!(SftpUrl::parse(":22/x").is_err());
+    assert!(SftpUrl::parse("nas:port/x").is_err());
+
+    assert!(matches!(
+        Location::parse(Path::new("sftp://nas/x")).unwrap(),
+        Location::Sftp(_)
+    ));
+    assert!(
+        Location::parse(Path::new("relative/dir"))
+            .unwrap()
+            .is_local()
+    );
+    let err = Location::parse(Path::new("ftp://nas/x")).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_sftp_destination_files() {
+    let server = tempdir().unwrap();
+    let dest = connect(server.path());
+    // The SFTP backend records nothing about the local file.
+    let source = PathBuf::from("local.bin");
+
+    assert!(dest.stat(Path::new("/a")).unwrap().is_none());
+    dest.create_dir_all(Path::new("/a/b/c")).unwrap();
+    assert!(dest.stat(Path::new("/a/b")).unwrap().unwrap().is_dir);
+
+    // Larger than one write request.
+    let data: Vec<u8> = (0..100_000u32).map(|i| i as u8).collect();
+    let mut file = dest.create(Path::new("/a/b/c/data.bin"), &source).unwrap();
+    file.write_all(&data).unwrap();
+    file.commit().unwrap();
+    assert_eq!(
+        fs::read(server.path().join("a/b/c/data.bin")).unwrap(),
+        data
+    );
+    let meta = dest.stat(Path::new("/a/b/c/data.bin")).unwrap().unwrap();
+    assert_eq!((meta.len, meta.is_dir), (100_000, false));
+
+    // Committing replaces the existing file.
+    let mut file = dest.create(Path::new("/a/b/c/data.bin"), &source).unwrap();
+    file.write_all(b"new").unwrap();
+    file.commit().unwrap();
+    assert_eq!(
+        fs::read(server.path().join("a/b/c/data.bin")).unwrap(),
+        b"new"
+    );
+
+    // An upload dropped before its commit leaves nothing behind.
+    let mut file = dest.create(Path::new("/a/partial.txt"), &source).unwrap();
+    file.write_all(b"half").unwrap();
+    drop(file);
+    let names: Vec<_> = fs::read_dir(server.path().join("a")).unwrap().collect();
+    assert_eq!(names.len(), 1);
+}
+
+#[test]
+fn test_copy_to_sftp_destination() {
+    let src_dir = tempdir().unwrap();
+    let server = tempdir().unwrap();
+    fs::create_dir(src_dir.path().join("sub")).unwrap();
+    fs::write(src_dir.path().join("one.txt"), b"one").unwrap();
+    fs::write(src_dir.path().join("sub").join("two.txt"), b"two").unwrap();
+
+    let msg = load_translations().unwrap().remove("en").unwrap();
+    let ctx = LogContext {
+        quiet: true,
+        on_log: false,
+        ..Default::default()
+    };
+    let remote: Arc<dyn Destination> = Arc::new(connect(server.path()));
+    let root = PathBuf::from("/backup");
+    let targets = [CopyTarget {
+        destination: root.clone(),
+        pairs: vec![(src_dir.path().to_path_buf(), root)],
+        options: LogContext {
+            remote: Some(remote),
+            ..ctx.clone()
+        },
+    }];
+
+    let results = copy_targets(&targets, &msg, &ctx, true, None);
+    assert_eq!(results[0].as_ref().unwrap().copied, 2);
+    let backup = server.path().join("backup");
+    assert_eq!(
+        fs::read(backup.join("sub").join("two.txt")).unwrap(),
+        b"two"
+    );
+    // Nothing is written to the local path of the same name.
+    assert!(!Path::new("/backup/one.txt").exists());
+
+    // Unchanged files are skipped on the next run.
+    let results = copy_targets(&targets, &msg, &ctx, true, None);
+    let counts = results[0].as_ref().unwrap();
+    assert_eq!((counts.copied, counts.skipped), (0, 2));
+}
@@ -0,0 +1,117 @@
+use std::fs;
+use std::time::{Duration, SystemTime};
+
+use filetime::{FileTime, set_file_mtime};
+use tempfile::tempdir;
+
+use rbackup::BackupJob;
+use rbackup::state::{StateIndex, default_dir};
+
+#[test]
+fn test_state_skips_unchanged_files() {
+    let src = tempdir().unwrap();
+    let dest = tempdir().unwrap();
+    let state = tempdir().unwrap();
+    fs::create_dir(src.path().join("sub")).unwrap();
+    fs::write(src.path().join("a.txt"), b"a").unwrap();
+    fs::write(src.path().join("sub").join("b.txt"), b"b").unwrap();
+
+    let job = BackupJob::new(src.path(), dest.path()).state(state.path());
+    let summary = job.run().unwrap();
+    assert_eq!((summary.copied, summary.skipped), (2, 0));
+
+    let path = StateIndex::path_in(state.path(), src.path(), dest.path(), dest.path());
+    let index = StateIndex::load(&path).unwrap().unwrap();
+    let keys: Vec<&str> = index.entries.keys().map(String::as_str).collect();
+    assert_eq!(keys, ["a.txt", "sub/b.txt"]);
+    assert_eq!(index.entries["a.txt"].size, 1);
+
+    let summary = job.run().unwrap();
+    assert_eq!((summary.copied, summary.skipped), (0, 2));
+
+    // Copies deleted from the destination are copied again, not only the
+    // first one.
+    fs::remove_file(dest.path().join("sub").join("b.txt")).unwrap();
+    let summary = job.run().unwrap();
+    assert_eq!((summary.copied, summary.skipped), (1, 1));
+    assert_eq!(
+        fs::read(dest.path().join("sub").join("b.txt")).unwrap(),
+        b"b"
+    );
+
+    // Copies recorded as unchanged are not compared with their source: an
+    // outdated copy is only noticed by a rescan.
+    set_file_mtime(dest.path().join("a.txt"), FileTime::zero()).unwrap();
+    let summary = job.run().unwrap();
+    assert_eq!((summary.copied, summary.skipped), (0, 2));
+    let summary = job.clone().rescan(true).run().unwrap();
+    assert_eq!((summary.copied, summary.skipped), (1, 1));
+
+    // Changed source files are compared and copied.
+    fs::write(src.path().join("sub").join("b.txt"), b"bb").unwrap();
+    let later = FileTime::from_system_time(SystemTime::now() + Duration::from_secs(60));
+    set_file_mtime(src.path().join("sub").join("b.txt"), later).unwrap();
+    let summary = job.run().unwrap();
+    assert_eq!((summary.copied, summary.skipped), (1, 1));
+    assert_eq!(
+        fs::read(dest.path().join("sub").join("b.txt")).unwrap(),
+        b"bb"
+    );
+}
+
+#[test]
+fn test_state_records_hashes_and_failures() {
+    let src = tempdir().unwrap();
+    let dest = tempdir().unwrap();
+    let state = tempdir().unwrap();
+    fs::write(src.path().join("good.txt"), b"good").unwrap();
+    fs::write(src.path().join("bad.txt"), b"bad").unwrap();
+    // A directory where the copy of `bad.txt` should go, older than it.
+    let blocked = dest.path().join("bad.txt");
+    fs::create_dir(&blocked).unwrap();
+    let past = FileTime::from_system_time(SystemTime::now() - Duration::from_secs(60));
+    set_file_mtime(&blocked, past).unwrap();
+
+    let job = BackupJob::new(src.path(), dest.path())
+        .manifest(true)
+        .state(state.path());
+    let summary = job.run().unwrap();
+    assert_eq!((summary.copied, summary.failed), (1, 1));
+
+    // Failed files are left out, so that the next run tries them again.
+    let path = StateIndex::path_in(state.path(), src.path(), dest.path(), dest.path());
+    let index = StateIndex::load(&path).unwrap().unwrap();
+    assert_eq!(index.entries.len(), 1);
+    let hash = index.entries["good.txt"].hash.as_deref().unwrap();
+    assert_eq!(hash, blake3::hash(b"good").to_hex().as_str());
+
+    fs::remove_dir(&blocked).unwrap();
+    let summary = job.run().unwrap();
+    assert_eq!((summary.copied, summary.skipped, summary.failed), (1, 1, 0));
+}
+
+#[test]
+fn test_state_needs_existing_destination_and_valid_index() {
+    let src = tempdir().unwrap();
+    let parent = tempdir().unwrap();
+    let dest = parent.path().join("backup");
+    let state = tempdir().unwrap();
+    fs::write(src.path().join("a.txt"), b"a").unwrap();
+
+    let job = BackupJob::new(src.path(), &dest).state(state.path());
+    assert_eq!(job.run().unwrap().copied, 1);
+
+    // A destination that is gone (e.g. an unmounted disk) is copied again.
+    fs::remove_dir_all(&dest).unwrap();
+    assert_eq!(job.run().unwrap().copied, 1);
+
+    // So is one whose index cannot be read.
+    let path = StateIndex::path_in(state.path(), src.path(), &dest, &dest);
+    fs::write(&path, b"RBSTATE\0garbage").unwrap();
+    assert!(StateIndex::load(&path).is_err());
+    fs::remove_file(dest.join("a.txt")).unwrap();
+    assert_eq!(job.run().unwrap().copied, 1);
+    assert!(StateIndex::load(&path).unwrap().is_some());
+
+    assert!(default_dir().ends_with(".rbackup/state") || default_dir().ends_with("rbackup\\state"));
+}
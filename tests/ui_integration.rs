@@ -0,0 +1,31 @@
+use std::time::{Duration, Instant};
+
+use rbackup::ui::ProgressStats;
+
+#[test]
+fn test_progress_stats_moving_window() {
+    let start = Instant::now();
+    let at = |secs| start + Duration::from_secs(secs);
+    let mut stats = ProgressStats::new_at(start, 100, 10_000);
+
+    // Fast first ten seconds, then a slowdown.
+    for s in 1..=10 {
+        stats.record_at(at(s), s as usize, s * 800);
+    }
+    for s in 11..=20 {
+        stats.record_at(at(s), s as usize, 8_000 + (s - 10) * 100);
+    }
+
+    assert_eq!((stats.done_files, stats.done_bytes), (20, 9_000));
+    assert_eq!(stats.average_rate(), 450.0);
+    // Only the last five seconds count for the current rate and the ETA.
+    assert_eq!(stats.window_rate(), 100.0);
+    assert_eq!(stats.eta(), Some(Duration::from_secs(10)));
+
+    // No progress at all: the ETA is unknown.
+    let idle = ProgressStats::new_at(start, 1, 10);
+    assert_eq!(idle.eta(), None);
+    // Everything done: nothing remains.
+    stats.record_at(at(21), 100, 10_000);
+    assert_eq!(stats.eta(), Some(Duration::ZERO));
+}
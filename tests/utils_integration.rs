@@ -62,8 +62,82 @@ fn test_copy_incremental_integration() {
         copy_progress: "".into(),
         copied_file: "copied".into(),
         skipped_file: "skipped".into(),
+        failed_file: "failed:".into(),
         generic_error: "".into(),
         error_exclude_parsing: "".into(),
+        restore_init: "".into(),
+        restore_ended: "".into(),
+        starting_restore: "".into(),
+        restoring_file: "".into(),
+        files_restored: "".into(),
+        files_failed: "".into(),
+        passphrase_prompt: "".into(),
+        passphrase_confirm: "".into(),
+        passphrase_mismatch: "".into(),
+        wrong_key: "".into(),
+        encryption_mismatch: "".into(),
+        check_init: "".into(),
+        check_ended: "".into(),
+        checking_repository: "".into(),
+        snapshots_checked: "".into(),
+        objects_checked: "".into(),
+        check_ok: "".into(),
+        check_failed: "".into(),
+        unreadable_snapshot: "".into(),
+        missing_object: "".into(),
+        corrupt_object: "".into(),
+        missing_file: "".into(),
+        extra_file: "".into(),
+        size_mismatch: "".into(),
+        hash_mismatch: "".into(),
+        files_checked: "".into(),
+        files_read: "".into(),
+        diff_new: "".into(),
+        diff_modified: "".into(),
+        diff_deleted: "".into(),
+        diff_excluded: "".into(),
+        dash_title: "".into(),
+        dash_paused: "".into(),
+        files_progress: "".into(),
+        dash_skipped: "".into(),
+        dash_errors: "".into(),
+        dash_workers: "".into(),
+        dash_idle: "".into(),
+        dash_recent: "".into(),
+        dash_keys: "".into(),
+        elapsed: "".into(),
+        throughput: "".into(),
+        eta: "".into(),
+        throughput_avg: "".into(),
+        schedule_installed: "".into(),
+        schedule_removed: "".into(),
+        schedule_not_found: "".into(),
+        schedule_none: "".into(),
+        profile_not_found: "".into(),
+        daemon_started: "".into(),
+        daemon_reloaded: "".into(),
+        daemon_next_run: "".into(),
+        daemon_invalid_schedule: "".into(),
+        daemon_run_started: "".into(),
+        daemon_run_finished: "".into(),
+        daemon_run_failed: "".into(),
+        daemon_run_overlap: "".into(),
+        watch_started: "".into(),
+        watch_batch: "".into(),
+        watch_rescan: "".into(),
+        watch_deleted: "".into(),
+        watch_error: "".into(),
+        lock_held: "".into(),
+        lock_waiting: "".into(),
+        lock_stale: "".into(),
+        hook_running: "".into(),
+        hook_failed: "".into(),
+        notify_success: "".into(),
+        notify_failure: "".into(),
+        notify_errors: "".into(),
+        notify_report: "".into(),
+        notify_failed: "".into(),
+        remote_unsupported: "".into(),
     };
 
     // build exclude matcher for skip.txt
@@ -115,8 +189,82 @@ fn test_copy_incremental_dry_run() {
         copy_progress: "".into(),
         copied_file: "copied".into(),
         skipped_file: "skipped".into(),
+        failed_file: "failed:".into(),
         generic_error: "".into(),
         error_exclude_parsing: "".into(),
+        restore_init: "".into(),
+        restore_ended: "".into(),
+        starting_restore: "".into(),
+        restoring_file: "".into(),
+        files_restored: "".into(),
+        files_failed: "".into(),
+        passphrase_prompt: "".into(),
+        passphrase_confirm: "".into(),
+        passphrase_mismatch: "".into(),
+        wrong_key: "".into(),
+        encryption_mismatch: "".into(),
+        check_init: "".into(),
+        check_ended: "".into(),
+        checking_repository: "".into(),
+        snapshots_checked: "".into(),
+        objects_checked: "".into(),
+        check_ok: "".into(),
+        check_failed: "".into(),
+        unreadable_snapshot: "".into(),
+        missing_object: "".into(),
+        corrupt_object: "".into(),
+        missing_file: "".into(),
+        extra_file: "".into(),
+        size_mismatch: "".into(),
+        hash_mismatch: "".into(),
+        files_checked: "".into(),
+        files_read: "".into(),
+        diff_new: "".into(),
+        diff_modified: "".into(),
+        diff_deleted: "".into(),
+        diff_excluded: "".into(),
+        dash_title: "".into(),
+        dash_paused: "".into(),
+        files_progress: "".into(),
+        dash_skipped: "".into(),
+        dash_errors: "".into(),
+        dash_workers: "".into(),
+        dash_idle: "".into(),
+        dash_recent: "".into(),
+        dash_keys: "".into(),
+        elapsed: "".into(),
+        throughput: "".into(),
+        eta: "".into(),
+        throughput_avg: "".into(),
+        schedule_installed: "".into(),
+        schedule_removed: "".into(),
+        schedule_not_found: "".into(),
+        schedule_none: "".into(),
+        profile_not_found: "".into(),
+        daemon_started: "".into(),
+        daemon_reloaded: "".into(),
+        daemon_next_run: "".into(),
+        daemon_invalid_schedule: "".into(),
+        daemon_run_started: "".into(),
+        daemon_run_finished: "".into(),
+        daemon_run_failed: "".into(),
+        daemon_run_overlap: "".into(),
+        watch_started: "".into(),
+        watch_batch: "".into(),
+        watch_rescan: "".into(),
+        watch_deleted: "".into(),
+        watch_error: "".into(),
+        lock_held: "".into(),
+        lock_waiting: "".into(),
+        lock_stale: "".into(),
+        hook_running: "".into(),
+        hook_failed: "".into(),
+        notify_success: "".into(),
+        notify_failure: "".into(),
+        notify_errors: "".into(),
+        notify_report: "".into(),
+        notify_failed: "".into(),
+        remote_unsupported: "".into(),
     };
 
     let ctx = LogContext {
@@ -0,0 +1,98 @@
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use notify::event::{AccessKind, CreateKind, ModifyKind, RenameMode};
+use notify::{Event, EventKind};
+use tempfile::tempdir;
+
+use rbackup::watch::{Batch, Step, WatchOptions, touched_paths};
+
+fn options() -> WatchOptions {
+    WatchOptions {
+        debounce: Duration::from_secs(2),
+        rescan: Duration::from_secs(60),
+    }
+}
+
+#[test]
+fn test_touched_paths_filter() {
+    let src = PathBuf::from("/data/src");
+    // The destination is inside the source.
+    let pairs = [(src.clone(), src.join("backup"))];
+    let event = |kind, paths: &[PathBuf]| {
+        paths
+            .iter()
+            .fold(Event::new(kind), |e, p| e.add_path(p.clone()))
+    };
+
+    let created = event(
+        EventKind::Create(CreateKind::File),
+        &[
+            src.join("a.txt"),
+            src.join("backup").join("a.txt"),
+            PathBuf::from("/data/other/b.txt"),
+        ],
+    );
+    assert_eq!(touched_paths(&created, &pairs), [src.join("a.txt")]);
+
+    // Reading the files to copy them is not a change.
+    let read = event(EventKind::Access(AccessKind::Any), &[src.join("a.txt")]);
+    assert!(touched_paths(&read, &pairs).is_empty());
+
+    let renamed = event(
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)),
+        &[src.join("old.txt"), src.join("new.txt")],
+    );
+    assert_eq!(
+        touched_paths(&renamed, &pairs),
+        [src.join("old.txt"), src.join("new.txt")]
+    );
+}
+
+#[test]
+fn test_batch_debounce_and_deletions() {
+    let dir = tempdir().unwrap();
+    let kept = dir.path().join("kept.txt");
+    let gone = dir.path().join("gone.txt");
+    std::fs::write(&kept, b"kept").unwrap();
+
+    let start = Instant::now();
+    let secs = |n| start + Duration::from_secs(n);
+    let mut batch = Batch::new(options(), start);
+    assert_eq!(batch.timeout(start), Duration::from_secs(60));
+    assert_eq!(batch.step(false, secs(1)), Step::Wait);
+
+    batch.add(vec![kept.clone()], secs(1));
+    assert_eq!(batch.timeout(secs(2)), Duration::from_secs(1));
+    // A later event postpones the copy.
+    batch.add(vec![gone.clone(), kept.clone()], secs(2));
+    assert_eq!(batch.step(false, secs(3)), Step::Wait);
+    // Events outside the sources do not.
+    batch.add(Vec::new(), secs(4));
+    assert_eq!(
+        batch.step(false, secs(4)),
+        Step::Copy {
+            paths: vec![gone, kept],
+            deleted: 1
+        }
+    );
+    assert_eq!(batch.step(false, secs(10)), Step::Wait);
+}
+
+#[test]
+fn test_batch_rescan() {
+    let start = Instant::now();
+    let secs = |n| start + Duration::from_secs(n);
+    let mut batch = Batch::new(options(), start);
+
+    // Lost events: the pending paths are covered by the full rescan.
+    batch.add(vec![PathBuf::from("/data/src/a.txt")], secs(1));
+    assert_eq!(batch.step(true, secs(1)), Step::Rescan);
+    assert_eq!(batch.step(false, secs(5)), Step::Wait);
+
+    // The periodic rescan is counted from the last one.
+    assert_eq!(batch.timeout(secs(1)), Duration::from_secs(60));
+    batch.add(vec![PathBuf::from("/data/src/b.txt")], secs(60));
+    assert_eq!(batch.timeout(secs(60)), Duration::from_secs(1));
+    assert_eq!(batch.step(false, secs(61)), Step::Rescan);
+}
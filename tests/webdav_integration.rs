@@ -0,0 +1,343 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tempfile::tempdir;
+
+use chrono::{DateTime, Utc};
+use rbackup::LogContext;
+use rbackup::destination::{Destination, Location};
+use rbackup::utils::{CopyTarget, copy_targets, load_translations};
+use rbackup::webdav::{WebDavDestination, WebDavLogin, WebDavUrl};
+
+/// `Authorization` header of `backup:secret`.
+const AUTHORIZATION: &str = "Basic YmFja3VwOnNlY3JldA==";
+
+fn decode(text: &str) -> String {
+    let mut out = Vec::new();
+    let bytes = text.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            out.push(u8::from_str_radix(&text[i + 1..i + 3], 16).unwrap());
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).unwrap()
+}
+
+fn read_body(reader: &mut impl BufRead, headers: &HashMap<String, String>) -> Vec<u8> {
+    if headers.get("transfer-encoding").map(String::as_str) != Some("chunked") {
+        let len = headers
+            .get("content-length")
+            .map_or(0, |l| l.parse().unwrap());
+        let mut body = vec![0u8; len];
+        reader.read_exact(&mut body).unwrap();
+        return body;
+    }
+    let mut body = Vec::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let len = usize::from_str_radix(line.trim(), 16).unwrap();
+        let mut chunk = vec![0u8; len + 2];
+        reader.read_exact(&mut chunk).unwrap();
+        if len == 0 {
+            return body;
+        }
+        body.extend_from_slice(&chunk[..len]);
+    }
+}
+
+/// `PROPFIND` entry of `path`, shown as `href`.
+fn prop_response(href: &str, path: &Path) -> String {
+    let meta = fs::metadata(path).unwrap();
+    let modified: DateTime<Utc> = meta.modified().unwrap().into();
+    let (href, kind) = match meta.is_dir() {
+        true => (
+            format!("{}/", href.trim_end_matches('/')),
+            "<D:collection/>",
+        ),
+        false => (href.to_string(), ""),
+    };
+    format!(
+        "<D:response><D:href>{}</D:href><D:propstat><D:prop>\
+         <D:resourcetype>{}</D:resourcetype>\
+         <D:getcontentlength>{}</D:getcontentlength>\
+         <D:getlastmodified>{}</D:getlastmodified>\
+         </D:prop><D:status>HTTP/1.1 200 OK</D:status></D:propstat></D:response>",
+        href.replace(' ', "%20"),
+        kind,
+        meta.len(),
+        modified.format("%a, %d %b %Y %H:%M:%S GMT")
+    )
+}
+
+/// Answer one request to the stand-in server: status and body.
+fn handle(
+    root: &Path,
+    method: &str,
+    target: &str,
+    headers: &HashMap<String, String>,
+    body: Vec<u8>,
+) -> (u16, String) {
+    if headers.get("authorization").map(String::as_str) != Some(AUTHORIZATION) {
+        return (401, String::new());
+    }
+    let href = decode(target);
+    let path = root.join(href.trim_start_matches('/'));
+    let status = |result: io::Result<()>, ok: u16| match result {
+        Ok(()) => (ok, String::new()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => (409, String::new()),
+        Err(_) => (500, String::new()),
+    };
+    match method {
+        "PROPFIND" => {
+            if !path.exists() {
+                return (404, String::new());
+            }
+            let mut responses = prop_response(&href, &path);
+            if path.is_dir() && headers["depth"] == "1" {
+                for entry in fs::read_dir(&path).unwrap() {
+                    let entry = entry.unwrap();
+                    let child = format!(
+                        "{}/{}",
+                        href.trim_end_matches('/'),
+                        entry.file_name().to_string_lossy()
+                    );
+                    responses.push_str(&prop_response(&child, &entry.path()));
+                }
+            }
+            let xml = format!(
+                "<?xml version=\"1.0\"?><D:multistatus xmlns:D=\"DAV:\">{}</D:multistatus>",
+                responses
+            );
+            (207, xml)
+        }
+        "MKCOL" if path.exists() => (405, String::new()),
+        "MKCOL" => status(fs::create_dir(&path), 201),
+        "PUT" => status(fs::write(&path, body), 201),
+        "MOVE" => {
+            assert_eq!(headers["overwrite"], "T");
+            let destination = headers["destination"].split_once("://").unwrap().1;
+            let destination = &destination[destination.find('/').unwrap()..];
+            let to = root.join(decode(destination).trim_start_matches('/'));
+            status(fs::rename(&path, to), 201)
+        }
+        "DELETE" => status(fs::remove_file(&path), 204),
+        other => panic!("unexpected request {}", other),
+    }
+}
+
+fn serve_connection(stream: TcpStream, root: PathBuf) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut writer = stream;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+            return;
+        }
+        let mut parts = line.split_whitespace();
+        let (method, target) = (
+            parts.next().unwrap().to_string(),
+            parts.next().unwrap().to_string(),
+        );
+        let mut headers = HashMap::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            let (name, value) = line.split_once(':').unwrap();
+            headers.insert(name.to_ascii_lowercase(), value.trim().to_string());
+        }
+        let body = read_body(&mut reader, &headers);
+        let (status, reply) = handle(&root, &method, &target, &headers, body);
+        let response = format!(
+            "HTTP/1.1 {} Reply\r\ncontent-length: {}\r\n\r\n{}",
+            status,
+            reply.len(),
+            reply
+        );
+        writer.write_all(response.as_bytes()).unwrap();
+    }
+}
+
+/// Start a stand-in WebDAV server serving `root`, returning its base URL.
+fn start_server(root: &Path) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    let root = root.to_path_buf();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let root = root.clone();
+            std::thread::spawn(move || serve_connection(stream.unwrap(), root));
+        }
+    });
+    base
+}
+
+fn login() -> Option<(String, String)> {
+    Some(("backup".to_string(), "secret".to_string()))
+}
+
+#[test]
+fn test_webdav_url_and_credentials() {
+    let url = WebDavUrl::parse("dms.example.com:8443/backup/laptop", true).unwrap();
+    assert_eq!(url.host, "dms.example.com:8443");
+    assert_eq!(url.base(), "https://dms.example.com:8443");
+    assert_eq!(url.path, PathBuf::from("/backup/laptop"));
+    assert_eq!(
+        url.to_string(),
+        "webdavs://dms.example.com:8443/backup/laptop"
+    );
+    assert!(WebDavUrl::parse("@/backup", false).is_err());
+    let location = Location::parse(Path::new("webdav://nas/backup")).unwrap();
+    assert!(matches!(&location, Location::WebDav(url) if !url.secure));
+    assert_eq!(location.root(), PathBuf::from("/backup"));
+
+    let logins = [
+        WebDavLogin {
+            url: "webdavs://dms.example.com:8443".to_string(),
+            username: "everyone".to_string(),
+            password: Some("one".to_string()),
+            password_env: None,
+        },
+        WebDavLogin {
+            url: "webdavs://dms.example.com:8443/backup/".to_string(),
+            username: "backup".to_string(),
+            password: Some("two".to_string()),
+            password_env: None,
+        },
+        WebDavLogin {
+            url: "webdavs://dms.example.com:8443/backup/other".to_string(),
+            username: "other".to_string(),
+            password: None,
+            password_env: Some("RBACKUP_TEST_UNSET_PASSWORD".to_string()),
+        },
+    ];
+    // The most specific entry wins.
+    assert_eq!(
+        url.credentials(&logins).unwrap(),
+        Some(("backup".to_string(), "two".to_string()))
+    );
+    // A user named in the URL selects the entries of that user.
+    let with_user = WebDavUrl::parse("everyone@dms.example.com:8443/backup", true).unwrap();
+    assert_eq!(
+        with_user.credentials(&logins).unwrap(),
+        Some(("everyone".to_string(), "one".to_string()))
+    );
+    // A password variable that is not set is an error.
+    let other = WebDavUrl::parse("dms.example.com:8443/backup/other", true).unwrap();
+    assert!(other.credentials(&logins).is_err());
+}
+
+#[test]
+fn test_webdav_destination_files() {
+    let server = tempdir().unwrap();
+    let dest = WebDavDestination::new(start_server(server.path()), login());
+    let source = PathBuf::from("local.bin");
+
+    assert!(dest.stat(Path::new("/a/b")).unwrap().is_none());
+    dest.create_dir_all(Path::new("/a/b/c")).unwrap();
+    assert!(server.path().join("a/b/c").is_dir());
+    assert!(dest.stat(Path::new("/a/b")).unwrap().unwrap().is_dir);
+
+    let data: Vec<u8> = (0..200_000u32).map(|i| i as u8).collect();
+    let mut file = dest
+        .create(Path::new("/a/b/c/my file.bin"), &source)
+        .unwrap();
+    file.write_all(&data).unwrap();
+    file.commit().unwrap();
+    assert_eq!(
+        fs::read(server.path().join("a/b/c/my file.bin")).unwrap(),
+        data
+    );
+    let meta = dest.stat(Path::new("/a/b/c/my file.bin")).unwrap().unwrap();
+    assert_eq!((meta.len, meta.is_dir), (200_000, false));
+
+    // A new connection lists what the server has.
+    let fresh = WebDavDestination::new(start_server(server.path()), login());
+    let meta = fresh
+        .stat(Path::new("/a/b/c/my file.bin"))
+        .unwrap()
+        .unwrap();
+    assert_eq!(meta.len, 200_000);
+    assert!(meta.modified.is_some());
+
+    // Moving replaces the existing file; a dropped upload leaves nothing.
+    let mut file = dest
+        .create(Path::new("/a/b/c/my file.bin"), &source)
+        .unwrap();
+    file.write_all(b"new").unwrap();
+    file.commit().unwrap();
+    assert_eq!(
+        fs::read(server.path().join("a/b/c/my file.bin")).unwrap(),
+        b"new"
+    );
+    let mut file = dest.create(Path::new("/a/partial.txt"), &source).unwrap();
+    file.write_all(b"half").unwrap();
+    drop(file);
+    assert_eq!(fs::read_dir(server.path().join("a")).unwrap().count(), 1);
+
+    // Rejected credentials are reported as such.
+    let wrong = WebDavDestination::new(
+        start_server(server.path()),
+        Some(("backup".to_string(), "wrong".to_string())),
+    );
+    let err = wrong.stat(Path::new("/a/b")).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+}
+
+#[test]
+fn test_copy_to_webdav_destination() {
+    let src_dir = tempdir().unwrap();
+    let server = tempdir().unwrap();
+    fs::create_dir(src_dir.path().join("sub")).unwrap();
+    fs::write(src_dir.path().join("one.txt"), b"one").unwrap();
+    fs::write(src_dir.path().join("sub").join("two.txt"), b"two").unwrap();
+
+    let msg = load_translations().unwrap().remove("en").unwrap();
+    let ctx = LogContext {
+        quiet: true,
+        on_log: false,
+        ..Default::default()
+    };
+    let base = start_server(server.path());
+    let target = |remote: Arc<dyn Destination>| {
+        let root = PathBuf::from("/dms/backup");
+        CopyTarget {
+            destination: root.clone(),
+            pairs: vec![(src_dir.path().to_path_buf(), root)],
+            options: LogContext {
+                remote: Some(remote),
+                ..ctx.clone()
+            },
+        }
+    };
+
+    let targets = [target(Arc::new(WebDavDestination::new(
+        base.clone(),
+        login(),
+    )))];
+    let results = copy_targets(&targets, &msg, &ctx, true, None);
+    assert_eq!(results[0].as_ref().unwrap().copied, 2);
+    let backup = server.path().join("dms").join("backup");
+    assert_eq!(
+        fs::read(backup.join("sub").join("two.txt")).unwrap(),
+        b"two"
+    );
+
+    // The next run lists the server again and finds nothing to copy.
+    let targets = [target(Arc::new(WebDavDestination::new(base, login())))];
+    let results = copy_targets(&targets, &msg, &ctx, true, None);
+    let counts = results[0].as_ref().unwrap();
+    assert_eq!((counts.copied, counts.skipped), (0, 2));
+}